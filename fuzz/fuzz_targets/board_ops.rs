@@ -0,0 +1,154 @@
+//! Plays arbitrary placement sequences, optionally on a preset board, and
+//! cross-checks `simulate`, `check` and `apply` against each other and
+//! against the board invariants.
+//!
+//! Run with `cargo fuzz run board_ops` from the repository root.
+#![no_main]
+#![allow(dead_code)]
+
+use libfuzzer_sys::fuzz_target;
+
+// The board modules are compiled in directly rather than taken from the
+// mortis library, which would pull the training and plotting dependencies
+// into the fuzz build.
+#[path = "../../src/board.rs"]
+mod board;
+// `tr!` for the board's parse errors
+#[path = "../../src/i18n.rs"]
+#[allow(unused_imports)]
+mod i18n;
+#[path = "../../src/piece.rs"]
+mod piece;
+// `SRS_SHAPES`, which the piece tables are built from
+#[path = "../../src/rotation.rs"]
+mod rotation;
+// The scoring rules every board carries
+#[path = "../../src/scoring.rs"]
+mod scoring;
+
+use board::{BOARD_HEIGHT, BOARD_WIDTH, Board};
+use piece::ALL_PIECES;
+
+/// A board with garbage in the bottom rows: each byte pair is one row with
+/// a single hole, shifted by a few empty cells when the high bit is set.
+fn preset(data: &[u8]) -> Board {
+    let mut rows: Vec<String> = data
+        .chunks(2)
+        .take(BOARD_HEIGHT - 4)
+        .map(|row| {
+            let hole = row[0] as usize % BOARD_WIDTH;
+            let extra = row
+                .get(1)
+                .map_or(0, |b| (b & 0x80 != 0) as usize * (*b as usize % 3));
+            (0..BOARD_WIDTH)
+                .map(|x| {
+                    if x != hole && (x + BOARD_WIDTH - hole) % BOARD_WIDTH > extra {
+                        '#'
+                    } else {
+                        '.'
+                    }
+                })
+                .collect()
+        })
+        .collect();
+    rows.reverse();
+    let rows: Vec<&str> = rows.iter().map(String::as_str).collect();
+    Board::from_rows(&rows).unwrap()
+}
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&header, rest)) = data.split_first() else {
+        return;
+    };
+    let garbage = (header as usize % 8) * 2;
+    let (rows, moves) = rest.split_at(garbage.min(rest.len()));
+
+    let mut board = preset(rows);
+    if board.check_invariants(0).is_err() {
+        // A preset row came out full; not a reachable position
+        return;
+    }
+
+    for mv in moves.chunks(2) {
+        let piece_type = ALL_PIECES[mv[0] as usize % 7];
+        let rotate = (mv[0] as usize / 7) % 4;
+        let x = mv.get(1).map_or(0, |&b| b as usize % BOARD_WIDTH);
+
+        let simulated = board.simulate(piece_type, x, rotate);
+        let checked = board.check(piece_type, x, rotate);
+        let score = board.get_score();
+        let lines = board.get_lines();
+        let listed = board
+            .legal_placements(piece_type)
+            .any(|p| (p.rotation, p.x) == (rotate, x));
+
+        // Trying the move and taking it back leaves the board as it was
+        let before = (board.dump(), board.to_ascii());
+        if let Ok(token) = board.apply_undoable(piece_type, x, rotate) {
+            board.undo(token);
+        }
+        assert_eq!((board.dump(), board.to_ascii()), before, "apply_undoable/undo");
+        if let Err(e) = board.check_invariants(score) {
+            panic!("undo: {}\n{}", e, board.dump());
+        }
+
+        let applied = board.apply(piece_type, x, rotate);
+
+        assert_eq!(
+            simulated.is_some(),
+            checked.is_ok(),
+            "simulate/check\n{}",
+            board.dump()
+        );
+        assert_eq!(
+            checked.is_ok(),
+            applied.is_ok(),
+            "check/apply\n{}",
+            board.dump()
+        );
+        assert_eq!(
+            checked.is_ok(),
+            listed,
+            "check/legal_placements\n{}",
+            board.dump()
+        );
+        if let Err(e) = board.check_invariants(score) {
+            panic!("{}\n{}", e, board.dump());
+        }
+
+        let Some((cleared, features)) = simulated else {
+            // Illegal moves must leave the board alone
+            assert_eq!(board.get_score(), score);
+            continue;
+        };
+        assert_eq!(
+            cleared as usize,
+            board.get_lines() - lines,
+            "{}",
+            board.dump()
+        );
+        let expected = [0, 100, 300, 500, 800][cleared as usize];
+        assert_eq!(board.get_score() - score, expected, "{}", board.dump());
+
+        // The reported result agrees with the board
+        let placed = applied.unwrap();
+        assert_eq!(placed.lines, cleared as usize, "{}", board.dump());
+        assert_eq!(placed.score_delta, expected, "{}", board.dump());
+        assert_eq!(
+            placed.max_height,
+            board.max_height(),
+            "{}",
+            board.dump()
+        );
+
+        if cleared == 0 {
+            // Without a clear the simulated stack is the applied one
+            let bumpiness: usize = board
+                .get_heights()
+                .windows(2)
+                .map(|w| w[0].abs_diff(w[1]))
+                .sum();
+            assert_eq!(features[8], bumpiness as f64, "{}", board.dump());
+        }
+    }
+});
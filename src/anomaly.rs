@@ -0,0 +1,225 @@
+//! Watching a training run for numerical blow-ups, so that a run that
+//! went wrong stops or recovers instead of burning hours on garbage.
+//!
+//! Every generation the search distribution and the best fitness of the
+//! generation are checked for non-finite numbers, a step size that ran
+//! away and a best fitness that fell far below its recent level. The
+//! first two mean the distribution is lost; the last one usually means
+//! the mean was dragged somewhere nothing scores.
+
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+
+use crate::checkpoint::Checkpoint;
+use crate::i18n::tr;
+use crate::logging::log_error;
+use crate::weights::format_weights;
+
+/// Largest step size still considered healthy. The search starts at 1 in
+/// whitened coordinates, and the policy only depends on the direction of
+/// the weights, so a healthy run has no reason to get anywhere near this.
+pub const MAX_SIGMA: f64 = 1e3;
+
+/// A generation whose best fitness is below this share of the recent
+/// median collapsed.
+pub const COLLAPSE_FRACTION: f64 = 0.1;
+
+/// Healthy generations the recent median is taken over.
+const WINDOW: usize = 10;
+
+/// Healthy generations needed before a collapse can be called: the first
+/// few are noisy and mostly near zero.
+const MIN_HISTORY: usize = 5;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Anomaly {
+    /// A NaN or infinity in the named part of the search state.
+    NonFinite {
+        what: &'static str,
+    },
+    SigmaExplosion {
+        sigma: f64,
+    },
+    FitnessCollapse {
+        fitness: f64,
+        recent: f64,
+    },
+}
+
+impl Anomaly {
+    pub fn describe(&self) -> String {
+        match self {
+            Anomaly::NonFinite { what } => {
+                tr!("{} 中出现 NaN 或无穷大", "NaN or infinity in the {}", what)
+            }
+            Anomaly::SigmaExplosion { sigma } => tr!(
+                "步长爆炸: {:.3e} 超过 {:.0e}",
+                "Step size explosion: {:.3e} exceeds {:.0e}",
+                sigma,
+                MAX_SIGMA
+            ),
+            Anomaly::FitnessCollapse { fitness, recent } => tr!(
+                "适应度崩溃: 本代最佳 {:.0}, 近期中位数 {:.0}",
+                "Fitness collapse: best of generation {:.0}, recent median {:.0}",
+                fitness,
+                recent
+            ),
+        }
+    }
+}
+
+/// What training does once an anomaly is detected. In every case the
+/// anomalous distribution goes to `anomaly-checkpoint.txt` and the report
+/// to `anomaly.txt`, while `checkpoint.txt` keeps the last healthy one.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum AnomalyAction {
+    /// Finish the run with what it has.
+    #[default]
+    Stop,
+    /// Restart the search from the last healthy checkpoint with a fresh
+    /// sampler, giving up after `MAX_ROLLBACKS` tries.
+    Rollback,
+    /// Report it and carry on.
+    Ignore,
+}
+
+impl AnomalyAction {
+    /// Rollbacks a run may take before it stops anyway.
+    pub const MAX_ROLLBACKS: usize = 3;
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "stop" => Some(AnomalyAction::Stop),
+            "rollback" => Some(AnomalyAction::Rollback),
+            "ignore" => Some(AnomalyAction::Ignore),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            AnomalyAction::Stop => "stop",
+            AnomalyAction::Rollback => "rollback",
+            AnomalyAction::Ignore => "ignore",
+        }
+    }
+}
+
+/// The best fitness of recent healthy generations, against which the
+/// next one is judged.
+#[derive(Debug, Clone, Default)]
+pub struct AnomalyDetector {
+    history: Vec<f64>,
+}
+
+impl AnomalyDetector {
+    pub fn new() -> Self {
+        AnomalyDetector::default()
+    }
+
+    /// Checks the distribution at the end of a generation, in weight
+    /// space, and the best fitness the generation found. Only healthy
+    /// generations join the history.
+    pub fn check(&mut self, checkpoint: &Checkpoint, fitness: f64) -> Option<Anomaly> {
+        let anomaly = Self::find(checkpoint, fitness, self.recent());
+        if anomaly.is_none() {
+            self.history.push(fitness);
+        }
+        anomaly
+    }
+
+    /// Median of the last `WINDOW` healthy generations, once there are
+    /// enough of them.
+    pub fn recent(&self) -> Option<f64> {
+        if self.history.len() < MIN_HISTORY {
+            return None;
+        }
+        let mut window = self.history[self.history.len().saturating_sub(WINDOW)..].to_vec();
+        window.sort_by(f64::total_cmp);
+        Some(window[window.len() / 2])
+    }
+
+    /// Forgets the fitness of the generations so far, once the later ones
+    /// are measured another way, as against a new opponent.
+    pub fn forget_fitness(&mut self) {
+        self.history.clear();
+    }
+
+    /// The best fitness of every healthy generation so far.
+    pub fn history(&self) -> &[f64] {
+        &self.history
+    }
+
+    fn find(checkpoint: &Checkpoint, fitness: f64, recent: Option<f64>) -> Option<Anomaly> {
+        if !checkpoint.sigma.is_finite() {
+            return Some(Anomaly::NonFinite { what: "sigma" });
+        }
+        if checkpoint.mean.iter().any(|v| !v.is_finite()) {
+            return Some(Anomaly::NonFinite { what: "mean" });
+        }
+        if checkpoint.cov.iter().flatten().any(|v| !v.is_finite()) {
+            return Some(Anomaly::NonFinite { what: "covariance" });
+        }
+        if !fitness.is_finite() {
+            return Some(Anomaly::NonFinite { what: "fitness" });
+        }
+        if checkpoint.sigma > MAX_SIGMA {
+            return Some(Anomaly::SigmaExplosion {
+                sigma: checkpoint.sigma,
+            });
+        }
+        match recent {
+            Some(recent) if recent > 0.0 && fitness < COLLAPSE_FRACTION * recent => {
+                Some(Anomaly::FitnessCollapse { fitness, recent })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Appends a diagnostic report of `anomaly` to `path`: where it happened,
+/// what was done about it, the recent fitness and the distribution's
+/// mean.
+pub fn report(
+    path: &Path,
+    generation: usize,
+    anomaly: &Anomaly,
+    action: &str,
+    detector: &AnomalyDetector,
+    checkpoint: &Checkpoint,
+) {
+    let history = detector.history();
+    let recent: Vec<String> = history[history.len().saturating_sub(WINDOW)..]
+        .iter()
+        .map(|f| format!("{:.0}", f))
+        .collect();
+    let mut text = tr!(
+        "# 第 {} 代: {}\n# 处理: {}\n",
+        "# generation {}: {}\n# action: {}\n",
+        generation,
+        anomaly.describe(),
+        action
+    );
+    text.push_str(&tr!(
+        "# 近期健康代的最佳适应度: {}\n",
+        "# best fitness of recent healthy generations: {}\n",
+        recent.join(" ")
+    ));
+    text.push_str(&tr!(
+        "# 步长 {}, 均值:\n",
+        "# sigma {}, mean:\n",
+        checkpoint.sigma
+    ));
+    text.push_str(&format_weights(&checkpoint.mean));
+    text.push_str("\n\n");
+
+    let written = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(text.as_bytes()));
+    if let Err(e) = written {
+        log_error!("无法写入 {}: {}", "Cannot write {}: {}", path.display(), e);
+    }
+}
@@ -0,0 +1,406 @@
+//! The `versus` command: matches of [`versus::play_match`] between the
+//! policy of one weight vector or network and another, or an external
+//! program, over a number of games, and who won them.
+//!
+//! A program plays the `check` protocol, as `serve` does, in the `simple`
+//! rotation system and with one piece of preview. The garbage pushed into
+//! its board comes as `G <rows> <hole>` lines ahead of the next piece, so
+//! the program has to add it to its own board to keep up; see `serve`.
+//! Each game starts a new process, and a program that takes longer than
+//! `--move-time` over a move loses the game, as it would fail `check`.
+//!
+//! [`versus::play_match`]: crate::versus::play_match
+
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::board::Board;
+use crate::check::{DEFAULT_MOVE_TIME, ProgramOutput, is_resign, parse_move, send};
+use crate::dimensions::{Dimensions, with_dimensions};
+use crate::evaluator::Model;
+use crate::generator::Randomizer;
+use crate::i18n::say;
+use crate::logging::{log_error, log_info, log_warn};
+use crate::piece::{BuiltinSet, PieceSet};
+use crate::policy::Visibility;
+use crate::versus::{
+    AttackTable, MATCH_PIECES, MatchOutcome, Player, PolicyPlayer, play_match_from, ratio, sequence,
+};
+
+/// How the matches of `versus` are played.
+#[derive(Debug, Clone)]
+pub struct VersusOptions {
+    pub games: usize,
+    /// Length of each game's piece sequence, at least 2. Both players
+    /// place all but the last piece unless one of them loses first.
+    pub pieces: usize,
+    pub attack: AttackTable,
+    pub piece_set: BuiltinSet,
+    /// How the sequences are drawn, parsed for `piece_set`.
+    pub randomizer: Randomizer,
+    /// Seed the games' sequences and garbage holes are drawn from; a
+    /// fresh one is drawn when unset.
+    pub seed: Option<u64>,
+    /// Whether the policies look ahead to the next piece.
+    pub visibility: Visibility,
+    /// Play the second side with this program instead of weights.
+    pub program: Option<String>,
+    /// Seconds the program may take over a move, as `check --move-time`.
+    pub move_time: f64,
+    /// Size of both boards. Set from the global `--width` and `--height`.
+    pub dimensions: Dimensions,
+}
+
+impl Default for VersusOptions {
+    fn default() -> Self {
+        VersusOptions {
+            games: 10,
+            pieces: MATCH_PIECES,
+            attack: AttackTable::default(),
+            piece_set: BuiltinSet::default(),
+            randomizer: Randomizer::default(),
+            seed: None,
+            visibility: Visibility::default(),
+            program: None,
+            move_time: DEFAULT_MOVE_TIME,
+            dimensions: Dimensions::default(),
+        }
+    }
+}
+
+impl VersusOptions {
+    pub fn from_args(args: &[String]) -> Self {
+        let mut options = VersusOptions::default();
+        let mut randomizer = None;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--games" => match args.next().and_then(|n| n.parse().ok()) {
+                    Some(games) if games > 0 => options.games = games,
+                    _ => log_warn!(
+                        "--games 需要一个正整数",
+                        "--games expects a positive integer"
+                    ),
+                },
+                "--pieces" => match args.next().and_then(|n| n.parse().ok()) {
+                    Some(n) if n >= 2 => options.pieces = n,
+                    _ => log_warn!(
+                        "--pieces 需要不小于 2 的长度",
+                        "--pieces expects a length of at least 2"
+                    ),
+                },
+                "--piece-set" => match args.next().and_then(|p| BuiltinSet::parse(p)) {
+                    Some(set) => options.piece_set = set,
+                    None => log_warn!(
+                        "--piece-set 需要 tetromino 或 pentomino",
+                        "--piece-set expects tetromino or pentomino"
+                    ),
+                },
+                "--attack" => match args.next().map(|a| AttackTable::parse(a)) {
+                    Some(Ok(attack)) => options.attack = attack,
+                    Some(Err(e)) => log_warn!("忽略 --attack: {}", "Ignoring --attack: {}", e),
+                    None => log_warn!(
+                        "--attack 需要用逗号分隔的行数",
+                        "--attack expects comma separated row counts"
+                    ),
+                },
+                "--randomizer" => randomizer = args.next(),
+                "--seed" => match args.next().and_then(|s| s.parse().ok()) {
+                    Some(seed) => options.seed = Some(seed),
+                    None => log_warn!("--seed 需要一个整数", "--seed expects an integer"),
+                },
+                "--visibility" => match args.next().and_then(|v| Visibility::parse(v)) {
+                    Some(visibility) => options.visibility = visibility,
+                    None => log_warn!(
+                        "--visibility 需要 current、next 或 expected",
+                        "--visibility expects current, next or expected"
+                    ),
+                },
+                "--program" => match args.next() {
+                    Some(program) => options.program = Some(program.clone()),
+                    None => log_warn!("--program 需要一个程序路径", "--program expects a path"),
+                },
+                "--move-time" => match args.next().and_then(|s| s.parse().ok()) {
+                    Some(s) if s > 0.0 => options.move_time = s,
+                    _ => log_warn!(
+                        "--move-time 需要一个正的秒数",
+                        "--move-time expects a positive number of seconds"
+                    ),
+                },
+                _ => log_warn!("忽略未知参数: {}", "Ignoring unknown argument: {}", arg),
+            }
+        }
+        if let Some(spec) = randomizer {
+            match Randomizer::parse(spec, options.piece_set.set()) {
+                Ok(Randomizer::Adversarial) => log_warn!(
+                    "忽略 --randomizer: 两边的方块序列相同, 无法针对某一方的棋盘选择方块",
+                    "Ignoring --randomizer: both sides share the sequence, so it cannot follow either board"
+                ),
+                Ok(randomizer) => options.randomizer = randomizer,
+                Err(e) => log_warn!("忽略 --randomizer: {}", "Ignoring --randomizer: {}", e),
+            }
+        }
+        options
+    }
+}
+
+/// An external program speaking the `check` protocol.
+struct ProgramPlayer {
+    child: Child,
+    stdin: Option<ChildStdin>,
+    output: ProgramOutput,
+    move_time: Duration,
+    /// Lines to send ahead of the next piece: the garbage since the last
+    /// move.
+    pending: String,
+    opened: bool,
+}
+
+impl ProgramPlayer {
+    fn spawn(path: &str, move_time: f64) -> std::io::Result<Self> {
+        let mut child = Command::new(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdin = child.stdin.take();
+        let stdout = child.stdout.take().ok_or(std::io::ErrorKind::BrokenPipe)?;
+        Ok(ProgramPlayer {
+            child,
+            stdin,
+            output: ProgramOutput::new(stdout),
+            move_time: Duration::from_secs_f64(move_time),
+            pending: String::new(),
+            opened: false,
+        })
+    }
+}
+
+impl<const H: usize, const W: usize> Player<H, W> for ProgramPlayer {
+    fn place(
+        &mut self,
+        _board: &Board<H, W>,
+        set: &PieceSet,
+        piece: usize,
+        next: usize,
+    ) -> Option<(usize, usize)> {
+        // The program saw `piece` as the preview of its last move
+        let mut text = std::mem::take(&mut self.pending);
+        if !self.opened {
+            text.push(set.letter(piece));
+            self.opened = true;
+        }
+        text.push(set.letter(next));
+        text.push('\n');
+        if let Err(e) = send(&mut self.stdin, &text) {
+            log_error!("写入方块失败: {}", "Failed to write the piece: {}", e);
+            return None;
+        }
+        let response = self.output.next_line(self.move_time).and_then(|line| {
+            if is_resign(&line) {
+                return Ok(None);
+            }
+            let mv = parse_move(&line)?;
+            // The score line, which only the program's own rules decide
+            self.output.next_line(self.move_time)?;
+            Ok(Some(mv))
+        });
+        match response {
+            Ok(mv) => mv
+                .filter(|mv| mv.x >= 0)
+                .map(|mv| (mv.rotation, mv.x as usize)),
+            Err(e) => {
+                log::error!("{}", e);
+                // The program is out of the game, and one that hangs
+                // would keep `drop` waiting
+                let _ = self.child.kill();
+                None
+            }
+        }
+    }
+
+    fn receive(&mut self, lines: usize, hole: usize) {
+        self.pending.push_str(&format!("G {} {}\n", lines, hole));
+    }
+}
+
+/// How long a program has to exit after the end of its game before it is
+/// killed.
+const EXIT_GRACE: Duration = Duration::from_secs(1);
+
+impl Drop for ProgramPlayer {
+    fn drop(&mut self) {
+        let _ = send(&mut self.stdin, "E\n");
+        self.stdin.take();
+        // As `check` does, but without waiting out the whole grace period
+        // for a program that exits at once
+        let deadline = Instant::now() + EXIT_GRACE;
+        loop {
+            match self.child.try_wait() {
+                Ok(Some(_)) => return,
+                Ok(None) if Instant::now() < deadline => sleep(Duration::from_millis(10)),
+                Ok(None) => {
+                    log_info!(
+                        "程序在对局结束后仍在运行, 正在终止...",
+                        "The program is still running after the game, killing it..."
+                    );
+                    break;
+                }
+                Err(e) => {
+                    log_error!(
+                        "检查程序状态时出错: {}",
+                        "Error checking the program status: {}",
+                        e
+                    );
+                    break;
+                }
+            }
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Plays `options.games` matches of `a` against `b`, or against
+/// `options.program` when it is set, and prints each one and the tally.
+/// The sides take turns at moving first. Returns the outcomes with `a` as
+/// player 0.
+pub fn versus(a: &Model, b: &Model, options: &VersusOptions) -> Vec<MatchOutcome> {
+    with_dimensions!(options.dimensions, |H, W| versus_in::<H, W>(a, b, options))
+}
+
+/// `versus` on boards of `H` rows and `W` columns.
+fn versus_in<const H: usize, const W: usize>(
+    a: &Model,
+    b: &Model,
+    options: &VersusOptions,
+) -> Vec<MatchOutcome> {
+    let seed = options.seed.unwrap_or_else(rand::random);
+    log_info!("种子: {}", "Seed: {}", seed);
+    log_info!("攻击表: {}", "Attack table: {}", options.attack.to_spec());
+    if !options.dimensions.is_default() {
+        log_info!("棋盘尺寸: {}", "Board size: {}", options.dimensions);
+    }
+    let set = options.piece_set.set();
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut outcomes = Vec::with_capacity(options.games);
+    for game in 0..options.games {
+        let game_seed: u64 = rng.random();
+        let pieces = sequence::<H, W>(&options.randomizer, set, options.pieces, game_seed);
+
+        let mut first = PolicyPlayer::new(a, options.visibility);
+        let mut second: Box<dyn Player<H, W>> = match &options.program {
+            Some(path) => match ProgramPlayer::spawn(path, options.move_time) {
+                Ok(program) => Box::new(program),
+                Err(e) => {
+                    log_error!(
+                        "无法启动程序 {}: {}",
+                        "Cannot start the program {}: {}",
+                        path,
+                        e
+                    );
+                    break;
+                }
+            },
+            None => Box::new(PolicyPlayer::new(b, options.visibility)),
+        };
+        // The sides swap who moves first every game
+        let outcome = play_match_from(
+            [&mut first, second.as_mut()],
+            game % 2,
+            set,
+            &pieces,
+            &options.attack,
+            !game_seed,
+        );
+        drop(second);
+        report_game(game + 1, &outcome);
+        outcomes.push(outcome);
+    }
+    report_tally(&outcomes);
+    outcomes
+}
+
+const SIDES: [&str; 2] = ["A", "B"];
+
+fn report_game(game: usize, outcome: &MatchOutcome) {
+    match outcome.winner {
+        Some(winner) => say!(
+            "第 {} 局: {} 在第 {} 回合获胜, 发送垃圾 A {} / B {}",
+            "Game {}: {} wins on turn {}, garbage sent A {} / B {}",
+            game,
+            SIDES[winner],
+            outcome.pieces,
+            outcome.sent[0],
+            outcome.sent[1]
+        ),
+        None => say!(
+            "第 {} 局: {} 回合后平局, 发送垃圾 A {} / B {}",
+            "Game {}: a draw after {} turns, garbage sent A {} / B {}",
+            game,
+            outcome.pieces,
+            outcome.sent[0],
+            outcome.sent[1]
+        ),
+    }
+}
+
+fn report_tally(outcomes: &[MatchOutcome]) {
+    let games = outcomes.len();
+    if games == 0 {
+        return;
+    }
+    let wins = |side: usize| outcomes.iter().filter(|o| o.winner == Some(side)).count();
+    let (a, b) = (wins(0), wins(1));
+    say!(
+        "\n{} 局: A 胜 {} 局, B 胜 {} 局, 平局 {} 局",
+        "\n{} games: A won {}, B won {}, {} draws",
+        games,
+        a,
+        b,
+        games - a - b
+    );
+    for (side, name) in SIDES.iter().enumerate() {
+        let mean = |count: fn(&MatchOutcome, usize) -> usize| {
+            outcomes.iter().map(|o| count(o, side)).sum::<usize>() as f64 / games as f64
+        };
+        let total = |count: fn(&MatchOutcome, usize) -> usize| {
+            outcomes.iter().map(|o| count(o, side)).sum::<usize>()
+        };
+        say!(
+            "  {}: 每局平均消行 {:.1}, 发送 {:.1}, 抵消 {:.1}, 收到 {:.1} 行垃圾",
+            "  {}: per game {:.1} lines, {:.1} garbage rows sent, {:.1} cancelled, {:.1} received",
+            name,
+            mean(|o, side| o.lines[side]),
+            mean(|o, side| o.sent[side]),
+            mean(|o, side| o.cancelled[side]),
+            mean(|o, side| o.received[side])
+        );
+        // Over all games pooled, as MatchOutcome's ratios of one game
+        say!(
+            "     每块攻击 {:.3}, 清除收到垃圾的 {:.0}%, 有垃圾时平均堆高 {:.1}",
+            "     {:.3} attack per piece, {:.0}% of received garbage dug out, mean stack height {:.1} under garbage",
+            ratio(
+                total(|o, side| o.sent[side] + o.cancelled[side]),
+                total(|o, side| o.placed[side])
+            ),
+            100.0
+                * ratio(
+                    total(|o, side| o.dug[side]),
+                    total(|o, side| o.received[side])
+                ),
+            ratio(
+                total(|o, side| o.pressured_height[side]),
+                total(|o, side| o.pressured[side])
+            )
+        );
+    }
+    match a.cmp(&b) {
+        std::cmp::Ordering::Greater => say!("胜者: A", "Winner: A"),
+        std::cmp::Ordering::Less => say!("胜者: B", "Winner: B"),
+        std::cmp::Ordering::Equal => say!("不分胜负", "No winner"),
+    }
+}
@@ -0,0 +1,318 @@
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+
+use crate::board::{Board, FEATURES};
+use crate::evaluator::Evaluator;
+use crate::generator::Randomizer;
+use crate::i18n::say;
+use crate::mode::{GameMode, Progress};
+use crate::observation::{ObservationMode, observed};
+use crate::piece::{ALL_PIECES, PieceSet};
+use crate::policy::{Visibility, choose};
+use crate::pool::{Schedule, play_all};
+use crate::visits::Visits;
+
+/// Bumped whenever a case, a reference score or anything that changes the
+/// piece sequences (such as the `rand` version) changes. Composite scores
+/// are only comparable within one suite version.
+pub const SUITE_VERSION: u32 = 1;
+
+/// One fixed game: the pieces come from `StdRng` seeded with `seed` and the
+/// game stops after `pieces` pieces if the policy has not topped out.
+pub struct Case {
+    pub name: &'static str,
+    pub seed: u64,
+    pub pieces: usize,
+    /// Score of the built-in `WEIGHTS` on this case, seeing only the
+    /// current piece.
+    pub reference: i64,
+}
+
+pub const SUITE: [Case; 8] = [
+    Case {
+        name: "sprint-1",
+        seed: 1,
+        pieces: 500,
+        reference: 20_500,
+    },
+    Case {
+        name: "sprint-2",
+        seed: 2,
+        pieces: 500,
+        reference: 20_200,
+    },
+    Case {
+        name: "sprint-3",
+        seed: 3,
+        pieces: 500,
+        reference: 21_000,
+    },
+    Case {
+        name: "sprint-4",
+        seed: 4,
+        pieces: 500,
+        reference: 21_400,
+    },
+    Case {
+        name: "marathon-1",
+        seed: 101,
+        pieces: 10_000,
+        reference: 49_500,
+    },
+    Case {
+        name: "marathon-2",
+        seed: 102,
+        pieces: 10_000,
+        reference: 67_300,
+    },
+    Case {
+        name: "marathon-3",
+        seed: 103,
+        pieces: 10_000,
+        reference: 25_200,
+    },
+    Case {
+        name: "marathon-4",
+        seed: 104,
+        pieces: 10_000,
+        reference: 173_800,
+    },
+];
+
+/// How much the policy is shown during a benchmark run.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Information {
+    pub visibility: Visibility,
+    pub observation: ObservationMode,
+}
+
+/// Plays one case. The sequence is the same whatever the information; with
+/// `Visibility::Next` the policy also sees the following piece, and with
+/// `ObservationMode::Heights` it plays on a board rebuilt from the height
+/// map.
+pub fn play_case<E: Evaluator + ?Sized>(case: &Case, evaluator: &E, info: Information) -> i64 {
+    play_case_visits(case, evaluator, info, &Randomizer::Uniform).0
+}
+
+/// `play_case` with the pieces drawn by `randomizer` from the case's
+/// seed, also keeping every position the game went through. Only the
+/// uniform draws are the suite's sequences.
+pub fn play_case_visits<E: Evaluator + ?Sized>(
+    case: &Case,
+    evaluator: &E,
+    info: Information,
+    randomizer: &Randomizer,
+) -> (i64, Visits) {
+    let rng = StdRng::seed_from_u64(case.seed);
+    let mut pieces = randomizer.generator(PieceSet::standard(), rng);
+    let mut board = Board::new();
+    let mut visits = Visits::default();
+    let mut next = ALL_PIECES[pieces.next_piece(&board)];
+    for _ in 0..case.pieces {
+        let piece = next;
+        next = ALL_PIECES[pieces.next_piece(&board)];
+        let seen = observed(&board, info.observation);
+        let Some((rotation, x, _)) = choose(&seen, piece, next, evaluator, info.visibility) else {
+            break;
+        };
+        board.apply(piece, x, rotation).unwrap();
+        visits.record(&board);
+    }
+    (board.get_score(), visits)
+}
+
+/// Plays one case for the goal of `mode` rather than for score, with the
+/// case's pieces drawn by `randomizer`. The starting board is drawn from
+/// the case's seed as well, apart from the pieces, and the game ends at
+/// the goal or after the case's pieces.
+pub fn play_case_goal<E: Evaluator + ?Sized>(
+    case: &Case,
+    evaluator: &E,
+    info: Information,
+    randomizer: &Randomizer,
+    mode: GameMode,
+) -> Progress {
+    let rng = StdRng::seed_from_u64(case.seed);
+    let mut pieces = randomizer.generator(PieceSet::standard(), rng);
+    let mut progress = Progress::new(mode, None, !case.seed);
+    let mut board = Board::new();
+    progress.start(&mut board);
+    let mut next = ALL_PIECES[pieces.next_piece(&board)];
+    for _ in 0..case.pieces {
+        let piece = next;
+        next = ALL_PIECES[pieces.next_piece(&board)];
+        let seen = observed(&board, info.observation);
+        let Some((rotation, x, _)) = choose(&seen, piece, next, evaluator, info.visibility) else {
+            break;
+        };
+        let placed = board.apply(piece, x, rotation).unwrap();
+        if !progress.after_piece(&placed, &mut board) {
+            break;
+        }
+    }
+    progress
+}
+
+/// Geometric mean of the score relative to the reference over all cases,
+/// times 100, so the built-in weights score exactly 100. One is added to
+/// both sides so that a zero score does not zero the whole composite.
+pub fn composite(scores: &[i64]) -> f64 {
+    let log_sum: f64 = SUITE
+        .iter()
+        .zip(scores)
+        .map(|(case, &score)| ((score as f64 + 1.0) / (case.reference as f64 + 1.0)).ln())
+        .sum();
+    100.0 * (log_sum / SUITE.len() as f64).exp()
+}
+
+/// Runs every weight vector on the suite and prints the per-case scores,
+/// the composite and how many distinct positions each vector reached, then
+/// how well `schedule` kept the cores busy. With another randomizer than
+/// `Uniform` the cases keep their seeds and lengths but not their
+/// sequences, so the composite only compares runs with that randomizer.
+pub fn benchmark(
+    entries: &[[f64; FEATURES]],
+    info: Information,
+    randomizer: &Randomizer,
+    schedule: Schedule,
+) {
+    say!(
+        "基准测试套件 v{}, {} 个用例, 可见方块: {}, 观察: {}",
+        "Benchmark suite v{}, {} cases, visible pieces: {}, observation: {}",
+        SUITE_VERSION,
+        SUITE.len(),
+        info.visibility.name(),
+        info.observation.name()
+    );
+    if *randomizer != Randomizer::Uniform {
+        say!(
+            "出块方式: {}; 参考分数基于均匀随机的方块序列, 综合得分只能与同一出块方式的结果比较",
+            "Randomizer: {}; the references are for uniform pieces, so the composite only compares with runs on the same randomizer",
+            randomizer.to_spec()
+        );
+    }
+    let (results, utilization) = play_all(entries, SUITE.len(), schedule, |weights, i| {
+        play_case_visits(&SUITE[i], weights, info, randomizer)
+    });
+    // A lookahead is only worth its cost by how much it beats the greedy
+    // policy on the same sequences
+    let greedy = (info.visibility != Visibility::Current).then(|| {
+        let greedy = Information {
+            visibility: Visibility::Current,
+            ..info
+        };
+        play_all(entries, SUITE.len(), schedule, |weights, i| {
+            play_case_visits(&SUITE[i], weights, greedy, randomizer).0
+        })
+        .0
+    });
+    for (i, games) in results.iter().enumerate() {
+        let scores: Vec<i64> = games.iter().map(|(score, _)| *score).collect();
+        if entries.len() > 1 {
+            say!("#{}", "#{}", i + 1);
+        }
+        for (case, score) in SUITE.iter().zip(&scores) {
+            println!("  {:<12} {:>10} ({:>10})", case.name, score, case.reference);
+        }
+        say!(
+            "  综合得分 (v{}, {}, {}): {:.1}",
+            "  Composite (v{}, {}, {}): {:.1}",
+            SUITE_VERSION,
+            info.visibility.name(),
+            info.observation.name(),
+            composite(&scores)
+        );
+        if let Some(greedy) = &greedy {
+            let greedy = composite(&greedy[i]);
+            let ratio = composite(&scores) / greedy;
+            say!(
+                "  贪心策略 (current) 的综合得分: {:.1}, {} 为其 {:.2} 倍",
+                "  Composite of the greedy policy (current): {:.1}; {} scores {:.2}x that",
+                greedy,
+                info.visibility.name(),
+                ratio
+            );
+        }
+        let mut batch = Visits::default();
+        for (_, visits) in games {
+            batch.merge(visits);
+        }
+        let per_game = |count: fn(&Visits) -> usize| {
+            games.iter().map(|(_, v)| count(v)).sum::<usize>() as f64 / games.len() as f64
+        };
+        say!(
+            "  不同局面: 每局平均 {:.0} 种地形 / {:.0} 个棋盘, 全部用例共 {} / {} (共 {} 步)",
+            "  Distinct positions: {:.0} surfaces / {:.0} boards per game, {} / {} over all cases ({} moves)",
+            per_game(Visits::surfaces),
+            per_game(Visits::boards),
+            batch.surfaces(),
+            batch.boards(),
+            batch.positions()
+        );
+    }
+    say!(
+        "括号内为内置权重的参考分数, 内置权重的综合得分为 100",
+        "Reference scores of the built-in weights in parentheses; they make a composite of 100"
+    );
+    say!(
+        "调度方式 {}: {} 个线程, 用时 {:.2}s, 核心利用率 {:.0}%",
+        "Scheduled per {}: {} threads, {:.2}s, core utilization {:.0}%",
+        schedule.name(),
+        utilization.threads,
+        utilization.wall.as_secs_f64(),
+        100.0 * utilization.ratio()
+    );
+}
+
+/// Runs every weight vector on the suite's cases playing for the goal of
+/// `mode` and prints how far each case got, then how many reached the
+/// goal and in how many pieces on average. The references and the
+/// composite are for score, so there are none here.
+pub fn benchmark_goal(
+    entries: &[[f64; FEATURES]],
+    info: Information,
+    randomizer: &Randomizer,
+    schedule: Schedule,
+    mode: GameMode,
+) {
+    say!(
+        "基准测试套件 v{}, {} 个用例, 模式: {}, 可见方块: {}, 观察: {}",
+        "Benchmark suite v{}, {} cases, mode: {}, visible pieces: {}, observation: {}",
+        SUITE_VERSION,
+        SUITE.len(),
+        mode.to_spec(),
+        info.visibility.name(),
+        info.observation.name()
+    );
+    let (results, _) = play_all(entries, SUITE.len(), schedule, |weights, i| {
+        play_case_goal(&SUITE[i], weights, info, randomizer, mode)
+    });
+    for (i, games) in results.iter().enumerate() {
+        if entries.len() > 1 {
+            say!("#{}", "#{}", i + 1);
+        }
+        for (case, progress) in SUITE.iter().zip(games) {
+            println!(
+                "  {:<12} {}",
+                case.name,
+                progress.summary().unwrap_or_default()
+            );
+        }
+        let reached: Vec<usize> = games
+            .iter()
+            .filter(|progress| progress.finished())
+            .map(|progress| progress.pieces())
+            .collect();
+        if reached.is_empty() {
+            say!("  没有用例达成目标", "  No case reached the goal");
+        } else {
+            say!(
+                "  {}/{} 个用例达成目标, 平均用了 {:.1} 个方块",
+                "  {} of {} cases reached the goal, in {:.1} pieces on average",
+                reached.len(),
+                SUITE.len(),
+                reached.iter().sum::<usize>() as f64 / reached.len() as f64
+            );
+        }
+    }
+}
@@ -1,10 +1,42 @@
+use std::str::FromStr;
+
 use anyhow::Result;
 
-use crate::piece::{PieceType, ROTATIONS};
+use crate::i18n::tr;
+use crate::piece::{ALL_PIECES, PIECE_SIZE, PieceSet, PieceType, ROTATIONS};
+use crate::scoring::{ScoreParts, ScoringRules};
+#[cfg(feature = "io")]
+use crate::theme::palette;
 pub const BOARD_HEIGHT: usize = 15;
 pub const BOARD_WIDTH: usize = 10;
+/// Number of features `simulate` reports for a placement.
 pub const FEATURES: usize = 13;
+/// Bumped whenever a feature changes meaning or order, so that weights
+/// trained for the old features are recognized as stale; see
+/// `weights::COMPATIBILITY`.
+pub const FEATURE_REVISION: u32 = 1;
+/// The leading features that are counts; the others are functions of the
+/// total stack height.
+pub const COUNT_FEATURES: usize = 9;
+
+/// Points for clearing 0 to 5 lines with one piece. Only pentominoes
+/// clear five.
+pub const LINE_CLEAR_SCORES: [i64; 6] = [0, 100, 300, 500, 800, 1200];
+
+/// A T-spin, as the three-corner rule tells it: a T whose last move was a
+/// turn and with at least three of the four cells diagonal to its center
+/// filled or off the board. It is a full one when both corners beside
+/// its point are among them and a mini otherwise.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Spin {
+    #[default]
+    None,
+    Mini,
+    Full,
+}
 
+/// The built-in evaluation: a placement scores the dot product of its
+/// features with these, and the lowest score is played.
 pub static WEIGHTS: [f64; FEATURES] = [
     1464772.166456,
     -2535297.130013,
@@ -21,26 +53,262 @@ pub static WEIGHTS: [f64; FEATURES] = [
     3233372.471683,
 ];
 
-pub struct Board {
-    pub grid: [[bool; BOARD_WIDTH]; BOARD_HEIGHT],
-    pub color_grid: [[Option<u8>; BOARD_WIDTH]; BOARD_HEIGHT],
-    pub heights: [usize; BOARD_WIDTH],
-    pub score: i32,
+/// Gaussians of the mean column height centered at 0, 1/3, 2/3 and all of
+/// the board height, the last features of `simulate`, for a board of
+/// `width` columns and `height` rows.
+pub fn height_rbf(
+    height_sum: usize,
+    width: usize,
+    height: usize,
+) -> [f64; FEATURES - COUNT_FEATURES] {
+    let c = height_sum as f64 / width as f64;
+    let h = height as f64;
+    let mut rbf = [0.0; FEATURES - COUNT_FEATURES];
+    for i in 0..rbf.len() {
+        let term = c - (i as f64 * h / 3.0);
+        rbf[i] = (-term.powi(2) / (2.0 * (h / 5.0).powi(2))).exp();
+    }
+    rbf
+}
+
+/// Cell characters of the ASCII layout by color, as `color_grid` numbers
+/// them. `#` is a filled cell of no particular piece.
+pub const CELL_CHARS: [char; 8] = {
+    let mut chars = ['#'; 8];
+    let mut i = 0;
+    while i < ALL_PIECES.len() {
+        chars[i] = ALL_PIECES[i].letter();
+        i += 1;
+    }
+    chars
+};
+
+/// The color of a filled cell of no particular piece, `#` in the ASCII
+/// layout: garbage, holes made up for a position and the cells of pieces
+/// outside the tetrominoes, which have no letters of their own.
+pub const PLAIN_COLOR: u8 = 7;
+
+/// A well of `H` rows and `W` columns, 15×10 unless given otherwise. Row
+/// 0 is the bottom. Every board size runs the same engine; only the
+/// default one has the built-in weights trained for it.
+///
+/// The fields are private so that the cached heights always match the
+/// grid: boards are read through the getters, changed by `apply` and set
+/// up with `from_rows`, `from_heights_with_holes` or `str::parse`.
+#[derive(Clone)]
+pub struct Board<const H: usize = BOARD_HEIGHT, const W: usize = BOARD_WIDTH> {
+    grid: [[bool; W]; H],
+    color_grid: [[Option<u8>; W]; H],
+    heights: [usize; W],
+    score: i64,
+    lines: usize,
+    /// Clears in a row up to the last placement, 0 if it cleared nothing.
+    combo: usize,
+    /// Whether the last clear was difficult, so that the next difficult
+    /// one is back to back.
+    back_to_back: bool,
+    scoring: ScoringRules,
+    /// Rows at the top above the playfield, where pieces spawn.
+    hidden: usize,
+}
+
+/// The 20×10 well of the guideline games.
+pub type StandardBoard = Board<20, 10>;
+
+/// The 20×10 playfield of the guideline games with the two hidden rows
+/// above it that the pieces spawn in, what `check` and `serve` play with
+/// `--width 10 --height 22 --hidden 2`. Boards of 23 and 24 rows take
+/// three or four with `with_hidden_rows`.
+pub type GuidelineBoard = Board<22, 10>;
+
+/// What `Board::apply` did, so that callers need not diff the board.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlacementResult {
+    /// The `(x, y)` of each cell of the piece where it locked, before any
+    /// line was cleared. Only the first `size` entries are meaningful;
+    /// `piece_cells` returns just those.
+    pub cells: [(usize, usize); PIECE_SIZE],
+    /// Number of cells of the piece: 4 unless a `PieceSet` says otherwise.
+    pub size: usize,
+    /// Rows that were full after the lock, bottom first, numbered as
+    /// before the clear. Only the first `lines` entries are meaningful;
+    /// `cleared_rows` returns just those.
+    pub rows: [usize; PIECE_SIZE],
+    pub lines: usize,
+    /// Points the placement added to the score by the rules of the board,
+    /// bonuses, level and drop points included.
+    pub score_delta: i64,
+    /// `score_delta` by the rule that gave each point.
+    pub points: ScoreParts,
+    /// Whether the piece locked as a T-spin. Only `apply_at_in` places a
+    /// piece after a turn, so the other ways to apply never report one.
+    pub spin: Spin,
+    /// Clears in a row this one makes, 0 if it cleared nothing.
+    pub combo: usize,
+    /// Whether the placement was a difficult clear following another.
+    pub back_to_back: bool,
+    /// Whether the clear left the board empty.
+    pub all_clear: bool,
+    /// Height of the tallest column after the clear.
+    pub max_height: usize,
+}
+
+/// What `Board::undo` needs to take a placement back: the result of the
+/// placement, the rows it landed in as they were before the lock, and the
+/// heights, score, line count and chain from before.
+#[must_use]
+#[derive(Debug, Clone, Copy)]
+pub struct UndoToken<const W: usize = BOARD_WIDTH> {
+    result: PlacementResult,
+    landing_y: usize,
+    band: [[Option<u8>; W]; PIECE_SIZE],
+    heights: [usize; W],
+    score: i64,
+    lines: usize,
+    combo: usize,
+    back_to_back: bool,
+}
+
+impl<const W: usize> UndoToken<W> {
+    pub fn result(&self) -> &PlacementResult {
+        &self.result
+    }
+}
+
+/// A board packed into one word per row, four bits per cell from column
+/// 0 up: 0 for an empty cell, the color plus one for a filled one. The
+/// heights are left out, `restore` derives them. Holds boards of up to 16
+/// columns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BoardSnapshot<const H: usize = BOARD_HEIGHT> {
+    rows: [u64; H],
+    score: i64,
+    lines: usize,
+    combo: usize,
+    back_to_back: bool,
+}
+
+impl<const H: usize> BoardSnapshot<H> {
+    /// `<score> <lines> <rows>`, the rows in hex from the bottom up to the
+    /// highest non-empty one, separated by commas, followed by
+    /// `combo=<n>` during a combo and `b2b` during a back-to-back chain.
+    pub fn to_text(&self) -> String {
+        let top = self.rows.iter().rposition(|&r| r != 0).map_or(0, |y| y + 1);
+        let rows: Vec<String> = self.rows[..top]
+            .iter()
+            .map(|r| format!("{:x}", r))
+            .collect();
+        let mut text = format!("{} {} {}", self.score, self.lines, rows.join(","));
+        if self.combo > 0 {
+            text += &format!(" combo={}", self.combo);
+        }
+        if self.back_to_back {
+            text += " b2b";
+        }
+        text
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut parts: Vec<&str> = text.split_whitespace().collect();
+        let number = |v: &str| tr!("无效的数字: {}", "Invalid number: {}", v);
+        // The chain goes last, and an empty board has no rows before it
+        let (mut combo, mut back_to_back) = (0, false);
+        while let Some(&last) = parts.last() {
+            if last == "b2b" {
+                back_to_back = true;
+            } else if let Some(n) = last.strip_prefix("combo=") {
+                combo = n.parse().map_err(|_| number(n))?;
+            } else {
+                break;
+            }
+            parts.pop();
+        }
+        let (score, lines, rows) = match parts.as_slice() {
+            [score, lines] => (score, lines, ""),
+            [score, lines, rows] => (score, lines, *rows),
+            _ => {
+                return Err(tr!(
+                    "快照应为 <分数> <行数> <各行>: {}",
+                    "A snapshot is <score> <lines> <rows>: {}",
+                    text
+                ));
+            }
+        };
+        let mut snapshot = BoardSnapshot {
+            rows: [0; H],
+            score: score.parse().map_err(|_| number(score))?,
+            lines: lines.parse().map_err(|_| number(lines))?,
+            combo,
+            back_to_back,
+        };
+        for (y, row) in rows.split(',').filter(|r| !r.is_empty()).enumerate() {
+            if y >= H {
+                return Err(tr!("快照超过{}行", "Snapshot has more than {} rows", H));
+            }
+            snapshot.rows[y] = u64::from_str_radix(row, 16).map_err(|_| number(row))?;
+        }
+        Ok(snapshot)
+    }
+}
+
+/// A legal placement: the rotation, the column of the piece's leftmost
+/// cell and the row its bottom row lands on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Placement {
+    pub rotation: usize,
+    pub x: usize,
+    pub landing_y: usize,
+    /// Whether the piece's last move into it was a turn, which a T-spin
+    /// needs. Straight drops never end with one.
+    pub turned: bool,
+}
+
+impl PlacementResult {
+    pub fn cleared_rows(&self) -> &[usize] {
+        &self.rows[..self.lines]
+    }
+
+    pub fn piece_cells(&self) -> &[(usize, usize)] {
+        &self.cells[..self.size]
+    }
 }
 
-impl Default for Board {
+/// The Zobrist key of `n`: splitmix64, so the keys are the same in every
+/// build without a table or a generator.
+pub fn zobrist_key(n: u64) -> u64 {
+    let mut z = n.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+impl<const H: usize, const W: usize> Default for Board<H, W> {
     fn default() -> Self {
-        Self::new()
+        Self::empty()
     }
 }
 
 impl Board {
+    /// An empty board of the default size. `Board::<H, W>::empty()` makes
+    /// one of any other.
     pub fn new() -> Self {
+        Self::empty()
+    }
+}
+
+impl<const H: usize, const W: usize> Board<H, W> {
+    pub fn empty() -> Self {
+        const { assert!(W >= 4 && H >= 4, "a board must fit every piece") };
         Board {
-            grid: [[false; BOARD_WIDTH]; BOARD_HEIGHT],
-            color_grid: [[None; BOARD_WIDTH]; BOARD_HEIGHT],
-            heights: [0; BOARD_WIDTH],
+            grid: [[false; W]; H],
+            color_grid: [[None; W]; H],
+            heights: [0; W],
             score: 0,
+            lines: 0,
+            combo: 0,
+            back_to_back: false,
+            scoring: ScoringRules::default(),
+            hidden: 0,
         }
     }
 
@@ -48,69 +316,431 @@ impl Board {
         self.heights[col]
     }
 
-    pub fn get_grid(&self) -> &[[bool; BOARD_WIDTH]; BOARD_HEIGHT] {
+    pub fn get_grid(&self) -> &[[bool; W]; H] {
         &self.grid
     }
 
-    pub fn get_color_grid(&self) -> &[[Option<u8>; BOARD_WIDTH]; BOARD_HEIGHT] {
+    pub fn get_color_grid(&self) -> &[[Option<u8>; W]; H] {
         &self.color_grid
     }
 
-    pub fn get_score(&self) -> i32 {
+    pub fn get_score(&self) -> i64 {
         self.score
     }
 
+    pub fn get_lines(&self) -> usize {
+        self.lines
+    }
+
+    /// Clears in a row up to the last placement, 0 if it cleared nothing.
+    pub fn get_combo(&self) -> usize {
+        self.combo
+    }
+
+    /// Whether the last clear was difficult, so that the next difficult
+    /// one scores as back to back.
+    pub fn get_back_to_back(&self) -> bool {
+        self.back_to_back
+    }
+
+    /// The level the next clear is scored on.
+    pub fn get_level(&self) -> i64 {
+        self.scoring.level(self.lines)
+    }
+
+    pub fn scoring(&self) -> &ScoringRules {
+        &self.scoring
+    }
+
+    pub fn get_heights(&self) -> &[usize; W] {
+        &self.heights
+    }
+
+    /// Height of the tallest column.
+    pub fn max_height(&self) -> usize {
+        self.heights.iter().copied().max().unwrap_or(0)
+    }
+
+    /// A board from ASCII rows, top to bottom, as `to_ascii` writes them:
+    /// `.` for empty and `#` or a piece letter, in either case, for filled.
+    /// Missing top rows are empty. Heights are recomputed; score and lines
+    /// start at zero.
+    pub fn from_rows(rows: &[&str]) -> Result<Self, String> {
+        if rows.len() > H {
+            return Err(tr!("棋盘超过{}行", "Board has more than {} rows", H));
+        }
+        let mut board = Self::empty();
+        for (i, row) in rows.iter().enumerate() {
+            let y = rows.len() - 1 - i;
+            if row.chars().count() != W {
+                return Err(tr!(
+                    "棋盘行宽度应为{}: {}",
+                    "Board row should be {} wide: {}",
+                    W,
+                    row
+                ));
+            }
+            for (x, c) in row.chars().enumerate() {
+                if c == '.' {
+                    continue;
+                }
+                let color = CELL_CHARS
+                    .iter()
+                    .position(|&cell| cell == c.to_ascii_uppercase())
+                    .ok_or_else(|| tr!("未知格子 '{}': {}", "Unknown cell '{}': {}", c, row))?;
+                board.grid[y][x] = true;
+                board.color_grid[y][x] = Some(color as u8);
+                board.heights[x] = board.heights[x].max(y + 1);
+            }
+        }
+        Ok(board)
+    }
+
+    /// Columns filled up to `heights` with `#` cells, except for the
+    /// `(x, y)` cells in `holes`. A hole has to lie under the top cell of
+    /// its column so that the heights hold.
+    pub fn from_heights_with_holes(
+        heights: &[usize; W],
+        holes: &[(usize, usize)],
+    ) -> Result<Self, String> {
+        let mut board = Self::empty();
+        for x in 0..W {
+            if heights[x] > H {
+                return Err(tr!(
+                    "第{}列高度{}超过棋盘高度{}",
+                    "Column {} is {} high, more than the board's {}",
+                    x,
+                    heights[x],
+                    H
+                ));
+            }
+            for y in 0..heights[x] {
+                board.grid[y][x] = true;
+                board.color_grid[y][x] = Some(PLAIN_COLOR);
+            }
+        }
+        board.heights = *heights;
+        for &(x, y) in holes {
+            if x >= W || y + 1 >= heights[x] {
+                return Err(tr!(
+                    "空洞 ({}, {}) 不在所在列的顶格之下",
+                    "Hole ({}, {}) is not under the top cell of its column",
+                    x,
+                    y
+                ));
+            }
+            board.grid[y][x] = false;
+            board.color_grid[y][x] = None;
+        }
+        Ok(board)
+    }
+
+    /// The board with the score and line count of a game in progress, for
+    /// restoring saved positions.
+    pub fn with_progress(mut self, score: i64, lines: usize) -> Self {
+        self.score = score;
+        self.lines = lines;
+        self
+    }
+
+    /// The board in the middle of a combo of `combo` clears, and of a
+    /// back-to-back chain with `back_to_back`, for restoring saved
+    /// positions.
+    pub fn with_chain(mut self, combo: usize, back_to_back: bool) -> Self {
+        self.combo = combo;
+        self.back_to_back = back_to_back;
+        self
+    }
+
+    /// The board with its top `rows` rows hidden above the playfield, as
+    /// in the guideline games. Pieces spawn with their bottom row in the
+    /// lowest hidden row, and the game is topped out when the spawn
+    /// position is taken, a block out, or when a piece would lock with all
+    /// of its cells above the playfield, a lock out: every placement of
+    /// the piece is then illegal. Without hidden rows a piece spawns at
+    /// the very top and the game only ends when it fits nowhere.
+    ///
+    /// Panics if fewer than 4 rows would be left visible.
+    pub fn with_hidden_rows(mut self, rows: usize) -> Self {
+        assert!(rows + 4 <= H, "a board must show at least 4 rows");
+        self.hidden = rows;
+        self
+    }
+
+    /// Rows of the playfield, below the hidden ones.
+    pub fn visible_height(&self) -> usize {
+        H - self.hidden
+    }
+
+    /// Row the bottom row of a piece `height` rows tall spawns on, the
+    /// lowest hidden row or as close above it as the piece fits.
+    pub fn spawn_y(&self, height: usize) -> usize {
+        (H - self.hidden).min(H - height)
+    }
+
+    /// Whether the spawn position of `piece` is taken, which tops the
+    /// game out on a board with hidden rows.
+    pub fn spawn_blocked_in(&self, set: &PieceSet, piece: usize) -> bool {
+        let shape = set.rotation(piece, 0);
+        let (x, y) = ((W - shape.width) / 2, self.spawn_y(shape.height));
+        (0..shape.height)
+            .any(|i| (0..shape.width).any(|j| shape.shape[i][j] != 0 && self.grid[y + i][x + j]))
+    }
+
+    /// Why locking a piece with its bottom row at `required_y` tops the
+    /// game out, if it does. Only boards with hidden rows top out.
+    #[inline]
+    fn top_out(&self, set: &PieceSet, piece: usize, required_y: usize) -> Option<&'static str> {
+        if self.hidden == 0 {
+            return None;
+        }
+        // The bottom row of a shape always has a cell
+        if required_y >= H - self.hidden {
+            return Some("Piece locks out above the playfield");
+        }
+        self.spawn_blocked_in(set, piece)
+            .then_some("Spawn position is blocked")
+    }
+
+    /// Pushes the stack up by `lines` rows of garbage, as an opponent's
+    /// attack does: every row is filled with `#` cells except in the
+    /// `hole_columns`, which must name at least one column of the board.
+    /// Fails when the stack would be pushed out of the top of the well,
+    /// which ends the game; the board is unchanged on error.
+    pub fn add_garbage(
+        &mut self,
+        lines: usize,
+        hole_columns: &[usize],
+    ) -> Result<(), &'static str> {
+        if hole_columns.is_empty() || hole_columns.iter().any(|&x| x >= W) {
+            return Err("Garbage needs a hole inside the board");
+        }
+        if self.max_height() + lines > H {
+            return Err("Garbage pushes the stack out of the well");
+        }
+        let mut row = [true; W];
+        for &x in hole_columns {
+            row[x] = false;
+        }
+        for y in (lines..H).rev() {
+            self.grid[y] = self.grid[y - lines];
+            self.color_grid[y] = self.color_grid[y - lines];
+        }
+        for y in 0..lines {
+            self.grid[y] = row;
+            self.color_grid[y] = row.map(|filled| filled.then_some(PLAIN_COLOR));
+        }
+        for x in 0..W {
+            self.heights[x] = match self.heights[x] {
+                0 if row[x] => lines,
+                0 => 0,
+                h => h + lines,
+            };
+        }
+
+        #[cfg(feature = "invariants")]
+        if let Err(e) = self.check_invariants(self.score) {
+            panic!("棋盘不变量被破坏: {}\n{}", e, self.dump());
+        }
+        Ok(())
+    }
+
+    /// The board scoring by `rules` instead of the default ones.
+    pub fn with_scoring(mut self, rules: ScoringRules) -> Self {
+        self.scoring = rules;
+        self
+    }
+
+    /// Row the piece's bottom row comes to rest on when dropped at column
+    /// `x`. The caller must have checked that the piece fits horizontally.
+    #[inline]
+    fn landing_y(&self, set: &PieceSet, piece: usize, x: usize, rotate: usize) -> usize {
+        let offsets = set.drop_offsets(piece, rotate);
+        let piece = set.rotation(piece, rotate);
+        (0..piece.width)
+            .map(|dx| self.heights[x + dx].saturating_sub(offsets[dx]))
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Every legal placement of `piece_type`, by rotation and then from
+    /// left to right. The policy breaks ties by this order.
+    pub fn legal_placements(&self, piece_type: PieceType) -> impl Iterator<Item = Placement> + '_ {
+        self.legal_placements_in(PieceSet::standard(), piece_type as usize)
+    }
+
+    /// `legal_placements` of a piece of `set`.
+    pub fn legal_placements_in<'a>(
+        &'a self,
+        set: &'a PieceSet,
+        piece: usize,
+    ) -> impl Iterator<Item = Placement> + 'a {
+        self.candidates(set, piece)
+            .filter(move |p| self.check_in(set, piece, p.x, p.rotation).is_ok())
+    }
+
+    /// `legal_placements` with the features `simulate` reports for each,
+    /// for searches that rate every placement anyway.
+    pub fn legal_placements_with_features(
+        &self,
+        piece_type: PieceType,
+    ) -> impl Iterator<Item = (Placement, [f64; FEATURES])> + '_ {
+        self.legal_placements_with_features_in(PieceSet::standard(), piece_type as usize)
+    }
+
+    /// `legal_placements_with_features` of a piece of `set`.
+    pub fn legal_placements_with_features_in<'a>(
+        &'a self,
+        set: &'a PieceSet,
+        piece: usize,
+    ) -> impl Iterator<Item = (Placement, [f64; FEATURES])> + 'a {
+        self.candidates(set, piece).filter_map(move |p| {
+            let (_, features) = self.simulate_in(set, piece, p.x, p.rotation)?;
+            Some((p, features))
+        })
+    }
+
+    /// Every placement that fits horizontally, legal or not.
+    fn candidates<'a>(
+        &'a self,
+        set: &'a PieceSet,
+        piece: usize,
+    ) -> impl Iterator<Item = Placement> + 'a {
+        (0..4).flat_map(move |rotation| {
+            let width = set.rotation(piece, rotation).width;
+            (0..=W.saturating_sub(width)).map(move |x| Placement {
+                rotation,
+                x,
+                landing_y: self.landing_y(set, piece, x, rotation),
+                turned: false,
+            })
+        })
+    }
+
+    /// Rates dropping `piece_type` in rotation `rotate` at column `x`
+    /// without changing the board. Returns the number of lines it would
+    /// clear and the features of the board after the drop, or `None` if
+    /// the placement is illegal. `BoardFeatures` names the entries.
     pub fn simulate(
         &self,
         piece_type: PieceType,
         x: usize,
         rotate: usize,
     ) -> Option<(i32, [f64; FEATURES])> {
-        let piece = &ROTATIONS[piece_type as usize][rotate];
+        self.simulate_in(PieceSet::standard(), piece_type as usize, x, rotate)
+    }
+
+    /// `simulate` for a piece of `set`.
+    #[inline]
+    pub fn simulate_in(
+        &self,
+        set: &PieceSet,
+        piece: usize,
+        x: usize,
+        rotate: usize,
+    ) -> Option<(i32, [f64; FEATURES])> {
+        let (cleared, counts, height_sum) = self.simulate_counts_in(set, piece, x, rotate)?;
+        Some((cleared, Self::features(&counts, height_sum)))
+    }
+
+    /// `simulate_in` for a placement that need not be where the piece
+    /// drops to, such as one of `moves::reachable_placements_in`. `None`
+    /// if it does not fit or does not rest on anything.
+    pub fn simulate_at_in(
+        &self,
+        set: &PieceSet,
+        piece: usize,
+        placement: Placement,
+    ) -> Option<(i32, [f64; FEATURES])> {
+        self.check_at_in(set, piece, placement).ok()?;
+        let Placement {
+            rotation,
+            x,
+            landing_y,
+            ..
+        } = placement;
+        let (cleared, counts, height_sum) =
+            self.simulate_counts_at(set, piece, x, rotation, landing_y)?;
+        Some((cleared, Self::features(&counts, height_sum)))
+    }
+
+    /// The features of `simulate` from the counts and the height sum of
+    /// `simulate_counts`.
+    #[inline]
+    fn features(counts: &[i64; COUNT_FEATURES], height_sum: usize) -> [f64; FEATURES] {
+        let mut features = [0.0; FEATURES];
+        for i in 0..COUNT_FEATURES {
+            features[i] = counts[i] as f64;
+        }
+        features[COUNT_FEATURES..].copy_from_slice(&height_rbf(height_sum, W, H));
+        features
+    }
 
+    /// The integer part of `simulate`: the lines cleared, the first
+    /// `COUNT_FEATURES` features, which are all counts, and the sum of the
+    /// column heights after the drop, from which the rest are derived.
+    #[inline]
+    pub fn simulate_counts(
+        &self,
+        piece_type: PieceType,
+        x: usize,
+        rotate: usize,
+    ) -> Option<(i32, [i64; COUNT_FEATURES], usize)> {
+        self.simulate_counts_in(PieceSet::standard(), piece_type as usize, x, rotate)
+    }
+
+    /// `simulate_counts` for a piece of `set`.
+    #[inline]
+    pub fn simulate_counts_in(
+        &self,
+        set: &PieceSet,
+        piece_index: usize,
+        x: usize,
+        rotate: usize,
+    ) -> Option<(i32, [i64; COUNT_FEATURES], usize)> {
         // Check x boundaries
-        if x + piece.width > BOARD_WIDTH {
+        if x + set.rotation(piece_index, rotate).width > W {
             return None;
         }
 
         // Calculate required y position
-        let mut required_y = 0;
-        for dx in 0..piece.width {
-            let col = x + dx;
-            let h_col = self.heights[col];
-            let mut max_i_for_dx = 0;
-            let mut has_block = false;
-
-            for i in 0..piece.height {
-                if piece.shape[i][dx] != 0 {
-                    has_block = true;
-                    let current_required_y = h_col as i32 - i as i32;
-                    if current_required_y > max_i_for_dx {
-                        max_i_for_dx = current_required_y;
-                    }
-                }
-            }
+        let required_y = self.landing_y(set, piece_index, x, rotate);
+        self.simulate_counts_at(set, piece_index, x, rotate, required_y)
+    }
 
-            if has_block && max_i_for_dx > required_y {
-                required_y = max_i_for_dx;
-            }
-        }
-        let required_y = required_y as usize;
+    /// `simulate_counts_in` with the piece's bottom row at `required_y`
+    /// rather than where it drops to. The piece must fit horizontally.
+    #[inline]
+    fn simulate_counts_at(
+        &self,
+        set: &PieceSet,
+        piece_index: usize,
+        x: usize,
+        rotate: usize,
+        required_y: usize,
+    ) -> Option<(i32, [i64; COUNT_FEATURES], usize)> {
+        let piece = set.rotation(piece_index, rotate);
 
         // Check if piece fits
-        let mut blocks = Vec::new();
+        let mut blocks = [(0, 0); PIECE_SIZE];
+        let mut block_count = 0;
         for i in 0..piece.height {
             for j in 0..piece.width {
                 if piece.shape[i][j] != 0 {
                     let y = required_y + i;
                     let col = x + j;
-                    if y >= BOARD_HEIGHT || self.grid[y][col] {
+                    if y >= H || self.grid[y][col] {
                         return None;
                     }
-                    blocks.push((y, col));
+                    blocks[block_count] = (y, col);
+                    block_count += 1;
                 }
             }
         }
+        if self.top_out(set, piece_index, required_y).is_some() {
+            return None;
+        }
+        let blocks = &blocks[..block_count];
 
         // Create temporary grid and heights
         let mut temp_grid = self.grid;
@@ -118,34 +748,37 @@ impl Board {
 
         // Place the piece
         let mut max_h = 0;
-        for &(y, col) in &blocks {
+        for &(y, col) in blocks {
             temp_grid[y][col] = true;
             temp_heights[col] = temp_heights[col].max(y + 1);
             max_h = max_h.max(y + 1);
         }
 
         // Check for full rows
-        let mut full_rows = Vec::new();
-        for y in 0..BOARD_HEIGHT {
-            if (0..BOARD_WIDTH).all(|x| temp_grid[y][x]) {
-                full_rows.push(y);
+        let mut full_rows = [0; PIECE_SIZE];
+        let mut full_count = 0;
+        for y in 0..H {
+            if (0..W).all(|x| temp_grid[y][x]) {
+                full_rows[full_count] = y;
+                full_count += 1;
             }
         }
+        let full_rows = &full_rows[..full_count];
         let cleared = full_rows.len() as i32;
 
         // Clear full rows if any
         if !full_rows.is_empty() {
-            let mut new_grid = [[false; BOARD_WIDTH]; BOARD_HEIGHT];
+            let mut new_grid = [[false; W]; H];
             let mut shift = 0;
 
-            for y in (0..BOARD_HEIGHT).rev() {
+            for y in (0..H).rev() {
                 if shift < full_rows.len() && y == full_rows[full_rows.len() - 1 - shift] {
                     shift += 1;
                     continue;
                 }
 
                 let new_y = y + shift;
-                if new_y < BOARD_HEIGHT {
+                if new_y < H {
                     new_grid[new_y] = temp_grid[y];
                 }
             }
@@ -153,9 +786,9 @@ impl Board {
             temp_grid = new_grid;
 
             // Recalculate heights
-            temp_heights = [0; BOARD_WIDTH];
-            for x in 0..BOARD_WIDTH {
-                for y in (0..BOARD_HEIGHT).rev() {
+            temp_heights = [0; W];
+            for x in 0..W {
+                for y in (0..H).rev() {
                     if temp_grid[y][x] {
                         temp_heights[x] = y + 1;
                         break;
@@ -165,36 +798,37 @@ impl Board {
         }
 
         // Calculate features
-        let mut features = [0.0; FEATURES];
+        let mut counts = [0; COUNT_FEATURES];
 
         // 1. landing_height (highest block's y coordinate)
         let landing_height = blocks.iter().map(|&(y, _)| y).max().unwrap_or(0);
-        features[0] = landing_height as f64;
+        counts[0] = landing_height as i64;
 
         // 2. eroded_piece_cells (number of blocks in cleared rows × cleared rows)
         let mut eroded = 0;
-        for &(y, _) in &blocks {
+        for &(y, _) in blocks {
             if full_rows.contains(&y) {
                 eroded += 1;
             }
         }
-        
+
         let eroded_value = match cleared {
             1 => eroded * 100,
-            2 => eroded * 150,  // 300 / 2 = 150
-            3 => eroded * 166,  // 500 / 3 ≈ 166
-            4 => eroded * 200,  // 800 / 4 = 200
+            2 => eroded * 150, // 300 / 2 = 150
+            3 => eroded * 166, // 500 / 3 ≈ 166
+            4 => eroded * 200, // 800 / 4 = 200
+            5 => eroded * 240, // 1200 / 5 = 240
             _ => 0,
         };
-        
-        features[1] = eroded_value as f64;
+
+        counts[1] = eroded_value as i64;
 
         // 3. row_transitions (row transitions)
         let mut row_trans = 0;
-        for y in 0..BOARD_HEIGHT {
+        for y in 0..H {
             let mut prev = true;
             let mut cnt = 0;
-            for x in 0..BOARD_WIDTH {
+            for x in 0..W {
                 let curr = temp_grid[y][x];
                 if curr != prev {
                     cnt += 1;
@@ -206,14 +840,14 @@ impl Board {
             }
             row_trans += cnt;
         }
-        features[2] = row_trans as f64;
+        counts[2] = row_trans as i64;
 
         // 4. column_transitions (column transitions)
         let mut col_trans = 0;
-        for x in 0..BOARD_WIDTH {
+        for x in 0..W {
             let mut prev = true;
             let mut cnt = 0;
-            for y in 0..BOARD_HEIGHT {
+            for y in 0..H {
                 let curr = temp_grid[y][x];
                 if curr != prev {
                     cnt += 1;
@@ -225,13 +859,13 @@ impl Board {
             }
             col_trans += cnt;
         }
-        features[3] = col_trans as f64;
+        counts[3] = col_trans as i64;
 
         // 5. holes (number of holes)
         let mut holes = 0;
-        for x in 0..BOARD_WIDTH {
+        for x in 0..W {
             let mut top = None;
-            for y in (0..BOARD_HEIGHT).rev() {
+            for y in (0..H).rev() {
                 if temp_grid[y][x] {
                     top = Some(y);
                     break;
@@ -245,17 +879,17 @@ impl Board {
                 }
             }
         }
-        features[4] = holes as f64;
+        counts[4] = holes as i64;
 
         // 6. board_wells (well sums)
         let mut wells = 0;
-        for x in 0..BOARD_WIDTH {
+        for x in 0..W {
             let left = if x > 0 {
                 temp_heights[x - 1]
             } else {
                 temp_heights[x]
             };
-            let right = if x < BOARD_WIDTH - 1 {
+            let right = if x < W - 1 {
                 temp_heights[x + 1]
             } else {
                 temp_heights[x]
@@ -265,11 +899,11 @@ impl Board {
                 wells += left.min(right) - current;
             }
         }
-        features[5] = wells as f64;
+        counts[5] = wells as i64;
 
         // 7. hole_depth (hole depth)
         let mut hole_depth = 0;
-        for x in 0..BOARD_WIDTH {
+        for x in 0..W {
             let current_h = temp_heights[x];
             for y in 0..current_h {
                 if !temp_grid[y][x] {
@@ -277,13 +911,13 @@ impl Board {
                 }
             }
         }
-        features[6] = hole_depth as f64;
+        counts[6] = hole_depth as i64;
 
         // 8. rows_with_holes (rows with holes)
         let mut rows_with_holes = 0;
-        for y in 0..BOARD_HEIGHT {
+        for y in 0..H {
             let mut has_hole = false;
-            for x in 0..BOARD_WIDTH {
+            for x in 0..W {
                 if !temp_grid[y][x] && temp_heights[x] > y {
                     has_hole = true;
                     break;
@@ -293,138 +927,250 @@ impl Board {
                 rows_with_holes += 1;
             }
         }
-        features[7] = rows_with_holes as f64;
+        counts[7] = rows_with_holes as i64;
 
         // 9. diversity
         let mut diversity = 0;
         let mut prev_h = temp_heights[0];
-        for x in 1..BOARD_WIDTH {
+        for x in 1..W {
             diversity += (temp_heights[x] as i32 - prev_h as i32).abs();
             prev_h = temp_heights[x];
         }
-        features[8] = diversity as f64;
+        counts[8] = diversity as i64;
 
-        // 10. RFB
-        let c =
-            (0..BOARD_WIDTH).map(|i| temp_heights[i]).sum::<usize>() as f64 / BOARD_WIDTH as f64;
-        let h = BOARD_HEIGHT as f64;
-        for i in 0..4 {
-            let term = c - (i as f64 * h / 3.0);
-            features[9 + i] = (-term.powi(2) / (2.0 * (h / 5.0).powi(2))).exp();
-        }
+        // 10. RFB, see `height_rbf`
+        let height_sum = temp_heights.iter().sum::<usize>();
 
-        Some((cleared, features))
+        Some((cleared, counts, height_sum))
     }
 
+    /// Whether the placement is legal, with the reason if it is not.
     pub fn check(
         &self,
         piece_type: PieceType,
         x: usize,
         rotate: usize,
     ) -> Result<(), &'static str> {
-        let piece = &ROTATIONS[piece_type as usize][rotate];
+        self.check_in(PieceSet::standard(), piece_type as usize, x, rotate)
+    }
+
+    /// `check` for a piece of `set`.
+    pub fn check_in(
+        &self,
+        set: &PieceSet,
+        piece_index: usize,
+        x: usize,
+        rotate: usize,
+    ) -> Result<(), &'static str> {
+        let piece = set.rotation(piece_index, rotate);
 
         // Check x boundaries
-        if x + piece.width > BOARD_WIDTH {
+        if x + piece.width > W {
             return Err("Piece out of bounds");
         }
 
         // Calculate required y position
-        let mut required_y = 0;
-        for dx in 0..piece.width {
-            let col = x + dx;
-            let h_col = self.heights[col];
-            let mut max_i_for_dx = 0;
-            let mut has_block = false;
-
-            for i in 0..piece.height {
-                if piece.shape[i][dx] != 0 {
-                    has_block = true;
-                    let current_required_y = h_col as i32 - i as i32;
-                    if current_required_y > max_i_for_dx {
-                        max_i_for_dx = current_required_y;
+        let required_y = self.landing_y(set, piece_index, x, rotate);
+
+        // Check if piece fits
+        for i in 0..piece.height {
+            for j in 0..piece.width {
+                if piece.shape[i][j] != 0 {
+                    let y = required_y + i;
+                    let col = x + j;
+                    if y >= H || self.grid[y][col] {
+                        return Err("Piece doesn't fit");
                     }
                 }
             }
+        }
 
-            if has_block && max_i_for_dx > required_y {
-                required_y = max_i_for_dx;
-            }
+        match self.top_out(set, piece_index, required_y) {
+            Some(e) => Err(e),
+            None => Ok(()),
         }
-        let required_y = required_y as usize;
+    }
 
-        // Check if piece fits
+    /// `check_in` for a placement that need not be where the piece drops
+    /// to: it must fit and rest on the floor or a filled cell. Whether
+    /// the piece can get there is up to whoever found it.
+    pub fn check_at_in(
+        &self,
+        set: &PieceSet,
+        piece_index: usize,
+        placement: Placement,
+    ) -> Result<(), &'static str> {
+        let piece = set.rotation(piece_index, placement.rotation);
+        let (x, y) = (placement.x, placement.landing_y);
+        if x + piece.width > W || y + piece.height > H {
+            return Err("Piece out of bounds");
+        }
+        let mut rests = y == 0;
         for i in 0..piece.height {
             for j in 0..piece.width {
                 if piece.shape[i][j] != 0 {
-                    let y = required_y + i;
-                    let col = x + j;
-                    if y >= BOARD_HEIGHT || self.grid[y][col] {
+                    if self.grid[y + i][x + j] {
                         return Err("Piece doesn't fit");
                     }
+                    rests |= y + i > 0 && self.grid[y + i - 1][x + j];
                 }
             }
         }
-
-        Ok(())
+        if !rests {
+            return Err("Piece is floating");
+        }
+        match self.top_out(set, piece_index, y) {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
     }
 
+    /// Plays the placement: locks the piece, clears full lines and adds
+    /// their points to the score. The board is unchanged on error.
     pub fn apply(
         &mut self,
         piece_type: PieceType,
         x: usize,
         rotate: usize,
-    ) -> Result<(), &'static str> {
-        let piece = &ROTATIONS[piece_type as usize][rotate];
-        let color = piece_type as u8;
+    ) -> Result<PlacementResult, &'static str> {
+        self.apply_in(PieceSet::standard(), piece_type as usize, x, rotate)
+    }
 
+    /// `apply` for a piece of `set`; its cells get `set.color` of it as
+    /// their color.
+    pub fn apply_in(
+        &mut self,
+        set: &PieceSet,
+        piece_index: usize,
+        x: usize,
+        rotate: usize,
+    ) -> Result<PlacementResult, &'static str> {
         // Check x boundaries
-        if x + piece.width > BOARD_WIDTH {
+        if x + set.rotation(piece_index, rotate).width > W {
             return Err("Piece out of bounds");
         }
 
         // Calculate required y position
-        let mut required_y = 0;
-        for dx in 0..piece.width {
-            let col = x + dx;
-            let h_col = self.heights[col];
-            let mut max_i_for_dx = 0;
-            let mut has_block = false;
-
-            for i in 0..piece.height {
-                if piece.shape[i][dx] != 0 {
-                    has_block = true;
-                    let current_required_y = h_col as i32 - i as i32;
-                    if current_required_y > max_i_for_dx {
-                        max_i_for_dx = current_required_y;
-                    }
-                }
-            }
+        let required_y = self.landing_y(set, piece_index, x, rotate);
+        self.lock(set, piece_index, x, rotate, required_y, Spin::None)
+    }
+
+    /// `apply_in` for a placement that need not be where the piece drops
+    /// to, as `check_at_in` takes it. A T of the standard set that got
+    /// there by a turn may lock as a T-spin, which scores its bonus on top
+    /// of the lines. The board is unchanged on error.
+    pub fn apply_at_in(
+        &mut self,
+        set: &PieceSet,
+        piece_index: usize,
+        placement: Placement,
+    ) -> Result<PlacementResult, &'static str> {
+        self.check_at_in(set, piece_index, placement)?;
+        let spin = if placement.turned && set.is_standard() && piece_index == PieceType::T as usize
+        {
+            self.t_spin(placement)
+        } else {
+            Spin::None
+        };
+        self.lock(
+            set,
+            piece_index,
+            placement.x,
+            placement.rotation,
+            placement.landing_y,
+            spin,
+        )
+    }
 
-            if has_block && max_i_for_dx > required_y {
-                required_y = max_i_for_dx;
+    /// The spin a T locking at `placement` makes by the three-corner rule,
+    /// assuming it got there by a turn. Cells off the board count as
+    /// filled.
+    fn t_spin(&self, placement: Placement) -> Spin {
+        let shape = &ROTATIONS[PieceType::T as usize][placement.rotation];
+        let mut cells = [(0, 0); 4];
+        let mut filled_cells = (0..shape.height)
+            .flat_map(|i| (0..shape.width).map(move |j| (j, i)))
+            .filter(|&(j, i)| shape.shape[i][j] != 0);
+        for cell in &mut cells {
+            let (j, i) = filled_cells.next().unwrap_or_default();
+            *cell = ((placement.x + j) as i32, (placement.landing_y + i) as i32);
+        }
+        let adjacent =
+            |(x, y): (i32, i32), (cx, cy): (i32, i32)| x.abs_diff(cx) + y.abs_diff(cy) == 1;
+        // The center touches the other three cells, and the point is the
+        // one with no cell across the center from it
+        let Some(&center) = cells
+            .iter()
+            .find(|&&c| cells.iter().filter(|&&o| adjacent(o, c)).count() == 3)
+        else {
+            return Spin::None;
+        };
+        let Some(&point) = cells.iter().find(|&&(x, y)| {
+            adjacent((x, y), center) && !cells.contains(&(2 * center.0 - x, 2 * center.1 - y))
+        }) else {
+            return Spin::None;
+        };
+        let (dx, dy) = (point.0 - center.0, point.1 - center.1);
+        let filled = |(x, y): (i32, i32)| {
+            x < 0 || y < 0 || x >= W as i32 || y >= H as i32 || self.grid[y as usize][x as usize]
+        };
+        let (mut corners, mut front) = (0, 0);
+        for (cx, cy) in [(-1, -1), (-1, 1), (1, -1), (1, 1)] {
+            if filled((center.0 + cx, center.1 + cy)) {
+                corners += 1;
+                if cx * dx + cy * dy > 0 {
+                    front += 1;
+                }
             }
         }
-        let required_y = required_y as usize;
+        match (corners, front) {
+            (3.., 2) => Spin::Full,
+            (3.., _) => Spin::Mini,
+            _ => Spin::None,
+        }
+    }
+
+    /// Locks the piece with its bottom row at `required_y`, clears the
+    /// lines it fills and scores them by the rules of the board, with the
+    /// bonus of `spin` if it is one, those of the chain it keeps up and
+    /// that of an all clear. The piece must fit horizontally.
+    fn lock(
+        &mut self,
+        set: &PieceSet,
+        piece_index: usize,
+        x: usize,
+        rotate: usize,
+        required_y: usize,
+        spin: Spin,
+    ) -> Result<PlacementResult, &'static str> {
+        let piece = set.rotation(piece_index, rotate);
+        let color = set.color(piece_index);
+        #[cfg(feature = "invariants")]
+        let previous_score = self.score;
 
         // Check if piece fits and collect blocks
-        let mut blocks = Vec::new();
+        let mut blocks = [(0, 0); PIECE_SIZE];
+        let mut block_count = 0;
         for i in 0..piece.height {
             for j in 0..piece.width {
                 if piece.shape[i][j] != 0 {
                     let y = required_y + i;
                     let col = x + j;
-                    if y >= BOARD_HEIGHT || self.grid[y][col] {
+                    if y >= H || self.grid[y][col] {
                         return Err("Piece doesn't fit");
                     }
-                    blocks.push((y, col));
+                    blocks[block_count] = (y, col);
+                    block_count += 1;
                 }
             }
         }
+        if let Some(e) = self.top_out(set, piece_index, required_y) {
+            return Err(e);
+        }
 
         // Place the piece
         let mut max_h = 0;
-        for &(y, col) in &blocks {
+        for &(y, col) in &blocks[..block_count] {
             self.grid[y][col] = true;
             self.color_grid[y][col] = Some(color);
             self.heights[col] = self.heights[col].max(y + 1);
@@ -432,27 +1178,53 @@ impl Board {
         }
 
         // Check for full rows
-        let mut full_rows = Vec::new();
-        for y in 0..BOARD_HEIGHT {
-            if (0..BOARD_WIDTH).all(|x| self.grid[y][x]) {
-                full_rows.push(y);
+        let mut full_rows = [0; PIECE_SIZE];
+        let mut full_count = 0;
+        for y in 0..H {
+            if (0..W).all(|x| self.grid[y][x]) {
+                full_rows[full_count] = y;
+                full_count += 1;
             }
         }
+        let rows = full_rows;
+        let full_rows = &full_rows[..full_count];
+        let rules = &self.scoring;
+        let level = rules.level(self.lines);
+        let lines = rules.lines[full_rows.len()];
+        let spin_points = rules.spin.points(spin, full_rows.len());
+        let mut back_to_back = false;
+        if full_rows.is_empty() {
+            self.combo = 0;
+        } else {
+            let difficult = full_rows.len() >= 4 || spin != Spin::None;
+            back_to_back = difficult && self.back_to_back;
+            self.combo += 1;
+            self.back_to_back = difficult;
+        }
+        let mut points = ScoreParts {
+            lines,
+            spin: spin_points,
+            combo: rules.chain.combo_points(self.combo),
+            back_to_back: rules
+                .chain
+                .back_to_back_points(back_to_back, lines.saturating_add(spin_points)),
+            ..ScoreParts::default()
+        };
 
         // Clear full rows if any
         if !full_rows.is_empty() {
-            let mut new_grid = [[false; BOARD_WIDTH]; BOARD_HEIGHT];
-            let mut new_color_grid = [[None; BOARD_WIDTH]; BOARD_HEIGHT];
+            let mut new_grid = [[false; W]; H];
+            let mut new_color_grid = [[None; W]; H];
             let mut shift = 0;
 
-            for y in 0..BOARD_HEIGHT {
+            for y in 0..H {
                 if full_rows.contains(&y) {
                     shift += 1;
                     continue;
                 }
 
                 let new_y = y - shift;
-                if new_y < BOARD_HEIGHT {
+                if new_y < H {
                     new_grid[new_y] = self.grid[y];
                     new_color_grid[new_y] = self.color_grid[y];
                 }
@@ -462,9 +1234,9 @@ impl Board {
             self.color_grid = new_color_grid;
 
             // Recalculate heights
-            self.heights = [0; BOARD_WIDTH];
-            for x in 0..BOARD_WIDTH {
-                for y in (0..BOARD_HEIGHT).rev() {
+            self.heights = [0; W];
+            for x in 0..W {
+                for y in (0..H).rev() {
                     if self.grid[y][x] {
                         self.heights[x] = y + 1;
                         break;
@@ -472,32 +1244,281 @@ impl Board {
                 }
             }
 
-            // Update score
-            let add_score = match full_rows.len() {
-                1 => 100,
-                2 => 300,
-                3 => 500,
-                4 => 800,
-                _ => 0,
-            };
-            self.score += add_score;
+            self.lines += full_rows.len();
+        }
+        let all_clear = !full_rows.is_empty() && self.heights.iter().all(|&h| h == 0);
+        if all_clear {
+            points.all_clear = self.scoring.all_clear.lines[full_rows.len()];
+        }
+        // Clears score on their level, drops do not
+        for part in [
+            &mut points.lines,
+            &mut points.spin,
+            &mut points.combo,
+            &mut points.back_to_back,
+            &mut points.all_clear,
+        ] {
+            *part = part.saturating_mul(level);
+        }
+        // The piece comes down from where it spawns, see `spawn_y`
+        let dropped = self.spawn_y(piece.height).saturating_sub(required_y) as i64;
+        points.drop = self.scoring.drop.saturating_mul(dropped);
+        let score_delta = points.total();
+        // Update score; a T-spin scores even when it clears nothing
+        self.score = self.score.saturating_add(score_delta);
+
+        #[cfg(feature = "invariants")]
+        if let Err(e) = self.check_invariants(previous_score) {
+            panic!("棋盘不变量被破坏: {}\n{}", e, self.dump());
+        }
+
+        Ok(PlacementResult {
+            cells: blocks.map(|(y, col)| (col, y)),
+            size: block_count,
+            rows,
+            lines: full_rows.len(),
+            score_delta,
+            points,
+            spin,
+            combo: self.combo,
+            back_to_back,
+            all_clear,
+            max_height: self.heights.iter().copied().max().unwrap_or(0),
+        })
+    }
+
+    /// `apply`, keeping what `undo` needs to take the placement back. A
+    /// search can then try moves on one board instead of cloning it for
+    /// each.
+    pub fn apply_undoable(
+        &mut self,
+        piece_type: PieceType,
+        x: usize,
+        rotate: usize,
+    ) -> Result<UndoToken<W>, &'static str> {
+        self.apply_undoable_in(PieceSet::standard(), piece_type as usize, x, rotate)
+    }
+
+    /// `apply_undoable` for a piece of `set`.
+    pub fn apply_undoable_in(
+        &mut self,
+        set: &PieceSet,
+        piece_index: usize,
+        x: usize,
+        rotate: usize,
+    ) -> Result<UndoToken<W>, &'static str> {
+        let piece = set.rotation(piece_index, rotate);
+        if x + piece.width > W {
+            return Err("Piece out of bounds");
+        }
+        // Only the rows the piece lands in can fill up
+        let landing_y = self.landing_y(set, piece_index, x, rotate);
+        let mut band = [[None; W]; PIECE_SIZE];
+        for (i, row) in band.iter_mut().enumerate().take(piece.height) {
+            if landing_y + i < H {
+                *row = self.color_grid[landing_y + i];
+            }
         }
+        let heights = self.heights;
+        let (score, lines) = (self.score, self.lines);
+        let (combo, back_to_back) = (self.combo, self.back_to_back);
+
+        let result = self.apply_in(set, piece_index, x, rotate)?;
+        Ok(UndoToken {
+            result,
+            landing_y,
+            band,
+            heights,
+            score,
+            lines,
+            combo,
+            back_to_back,
+        })
+    }
+
+    /// Takes back the placement `token` came from: puts cleared rows back
+    /// where they were, removes the piece and restores the heights, score,
+    /// line count and chain. Tokens must be undone last first, on the board
+    /// that made them.
+    pub fn undo(&mut self, token: UndoToken<W>) {
+        let cleared = token.result.cleared_rows();
+        if !cleared.is_empty() {
+            let mut new_grid = [[false; W]; H];
+            let mut new_color_grid = [[None; W]; H];
+            let mut from = 0;
+            for y in 0..H {
+                if cleared.contains(&y) {
+                    new_color_grid[y] = token.band[y - token.landing_y];
+                    new_grid[y] = new_color_grid[y].map(|c| c.is_some());
+                } else {
+                    new_grid[y] = self.grid[from];
+                    new_color_grid[y] = self.color_grid[from];
+                    from += 1;
+                }
+            }
+            self.grid = new_grid;
+            self.color_grid = new_color_grid;
+        }
+
+        // The band rows hold the cells from before the lock, so only the
+        // rows that stayed still have the piece in them
+        for &(x, y) in token.result.piece_cells() {
+            self.grid[y][x] = false;
+            self.color_grid[y][x] = None;
+        }
+        self.heights = token.heights;
+        self.score = token.score;
+        self.lines = token.lines;
+        self.combo = token.combo;
+        self.back_to_back = token.back_to_back;
+
+        #[cfg(feature = "invariants")]
+        if let Err(e) = self.check_invariants(self.score) {
+            panic!("棋盘不变量被破坏: {}\n{}", e, self.dump());
+        }
+    }
+
+    /// The board in the compact form of `BoardSnapshot`, to branch a
+    /// search from or to keep a game for later. Cells keep their colors,
+    /// which are at most `PLAIN_COLOR`.
+    pub fn snapshot(&self) -> BoardSnapshot<H> {
+        const { assert!(W <= 16, "a snapshot holds at most 16 columns") };
+        let mut rows = [0; H];
+        for y in 0..H {
+            for x in 0..W {
+                if let Some(color) = self.color_grid[y][x] {
+                    rows[y] |= (color as u64 + 1) << (4 * x);
+                }
+            }
+        }
+        BoardSnapshot {
+            rows,
+            score: self.score,
+            lines: self.lines,
+            combo: self.combo,
+            back_to_back: self.back_to_back,
+        }
+    }
 
+    /// Puts the board back to the state `snapshot` was taken in. Cells
+    /// beyond the board's width are ignored.
+    pub fn restore(&mut self, snapshot: &BoardSnapshot<H>) {
+        self.heights = [0; W];
+        for y in 0..H {
+            for x in 0..W {
+                let cell = (snapshot.rows[y] >> (4 * x)) & 0xF;
+                self.grid[y][x] = cell != 0;
+                self.color_grid[y][x] = cell.checked_sub(1).map(|c| c as u8);
+                if cell != 0 {
+                    self.heights[x] = y + 1;
+                }
+            }
+        }
+        self.score = snapshot.score;
+        self.lines = snapshot.lines;
+        self.combo = snapshot.combo;
+        self.back_to_back = snapshot.back_to_back;
+    }
+
+    /// A Zobrist hash of the filled cells, the score, the lines and the
+    /// chain, for recognizing a board reached along different paths.
+    /// Colors are left out: boards that differ only in them play the same.
+    pub fn zobrist(&self) -> u64 {
+        // Cells take the keys from 0, the score, lines and chain ones far
+        // above
+        let mut hash = zobrist_key(1 << 63 | self.score as u64)
+            ^ zobrist_key(1 << 62 | self.lines as u64)
+            ^ zobrist_key(1 << 61 | self.combo as u64)
+            ^ zobrist_key(1 << 60 | self.back_to_back as u64);
+        for y in 0..H {
+            for x in 0..W {
+                if self.grid[y][x] {
+                    hash ^= zobrist_key((y * W + x) as u64);
+                }
+            }
+        }
+        hash
+    }
+
+    /// Verifies that the cached state agrees with the grid: heights match
+    /// the highest filled cell, colors exist exactly on filled cells, no
+    /// full row is left behind and the score did not decrease.
+    pub fn check_invariants(&self, previous_score: i64) -> Result<(), String> {
+        for x in 0..W {
+            let top = (0..H).rev().find(|&y| self.grid[y][x]).map_or(0, |y| y + 1);
+            if self.heights[x] != top {
+                return Err(format!("第{}列高度为{}, 实际为{}", x, self.heights[x], top));
+            }
+        }
+        for y in 0..H {
+            for x in 0..W {
+                if self.grid[y][x] != self.color_grid[y][x].is_some() {
+                    return Err(format!("({}, {}) 的颜色与占用不一致", x, y));
+                }
+            }
+            if self.grid[y].iter().all(|&c| c) {
+                return Err(format!("第{}行已满但未消除", y));
+            }
+        }
+        if self.score < previous_score {
+            return Err(format!("分数从{}下降到{}", previous_score, self.score));
+        }
         Ok(())
     }
 
+    /// The grid as text, top row first, with the cached heights and score.
+    pub fn dump(&self) -> String {
+        let mut text = String::new();
+        for y in (0..H).rev() {
+            for x in 0..W {
+                text.push(match (self.grid[y][x], self.color_grid[y][x]) {
+                    (true, Some(_)) => '#',
+                    (true, None) => '?',
+                    (false, Some(_)) => '!',
+                    (false, None) => '.',
+                });
+            }
+            text.push('\n');
+        }
+        text.push_str(&format!(
+            "heights {:?}, score {}, lines {}",
+            self.heights, self.score, self.lines
+        ));
+        text
+    }
+
+    /// The stack as ASCII, top row first, one line per row up to the
+    /// highest filled cell: `.` for empty, the letter of the tetromino
+    /// that left a cell and `#` for any other filled cell. `str::parse`
+    /// reads it back.
+    pub fn to_ascii(&self) -> String {
+        let top = self.heights.iter().copied().max().unwrap_or(0);
+        let mut text = String::new();
+        for y in (0..top).rev() {
+            for x in 0..W {
+                text.push(match self.color_grid[y][x] {
+                    _ if !self.grid[y][x] => '.',
+                    Some(c) => CELL_CHARS.get(c as usize).copied().unwrap_or('#'),
+                    None => '#',
+                });
+            }
+            text.push('\n');
+        }
+        text
+    }
+
     pub fn get_start_y(&mut self, piece_type: PieceType, x: usize, rotate: usize) -> usize {
         let piece = &ROTATIONS[piece_type as usize][rotate];
 
         // Check x boundaries using leftmost and rightmost
         let left = x as i32 + piece.leftmost[rotate];
         let right = x as i32 + piece.rightmost[rotate];
-        if left < 0 || right >= BOARD_WIDTH as i32 {
+        if left < 0 || right >= W as i32 {
             return 0;
         }
 
         // Find maximum i where shape has non-zero elements
-        let i_max = (0..4)
+        let i_max = (0..PIECE_SIZE)
             .rev()
             .find(|&i| piece.shape[i].iter().any(|&v| v != 0))
             .unwrap_or(0);
@@ -508,6 +1529,7 @@ impl Board {
         min_height - i_max
     }
 
+    #[cfg(feature = "io")]
     #[allow(dead_code)]
     pub fn draw(&self) {
         print!("\x1B[2J\x1B[1;1H");
@@ -522,28 +1544,20 @@ impl Board {
         println!("--------------------------");
     }
 
+    #[cfg(feature = "io")]
     pub fn draw_colored(&self) {
         print!("\x1B[2J\x1B[1;1H");
         println!("Score: {}", self.score);
 
         // Print top border
-        println!("╔{}╗", "═".repeat(BOARD_WIDTH));
+        println!("╔{}╗", "═".repeat(W));
 
         // Print each row
-        for y in (0..BOARD_HEIGHT).rev() {
+        for y in (0..H).rev() {
             print!("║");
-            for x in 0..BOARD_WIDTH {
+            for x in 0..W {
                 if self.grid[y][x] {
-                    let color_code = match self.color_grid[y][x] {
-                        Some(0) => "\x1B[36m", // Cyan - I type
-                        Some(1) => "\x1B[35m", // Purple - T type
-                        Some(2) => "\x1B[33m", // Yellow - O type
-                        Some(3) => "\x1B[34m", // Blue - J type
-                        Some(4) => "\x1B[31m", // Red - L type
-                        Some(5) => "\x1B[32m", // Green - S type
-                        Some(6) => "\x1B[91m", // Bright Red - Z type
-                        _ => "\x1B[37m",       // White - Default
-                    };
+                    let color_code = palette().ansi(self.color_grid[y][x]);
                     print!("{}\u{25A0}\x1B[0m", color_code);
                 } else {
                     print!(" ");
@@ -553,6 +1567,199 @@ impl Board {
         }
 
         // Print bottom border
-        println!("╚{}╝", "═".repeat(BOARD_WIDTH));
+        println!("╚{}╝", "═".repeat(W));
+    }
+}
+
+/// `Board::from_rows` over the lines of the text, skipping blank ones.
+impl<const H: usize, const W: usize> FromStr for Board<H, W> {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, String> {
+        let rows: Vec<&str> = text
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty())
+            .collect();
+        Self::from_rows(&rows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::piece::BuiltinSet;
+
+    #[test]
+    fn drop_points_count_from_the_spawn_row() {
+        let rules = ScoringRules::GUIDELINE;
+        // An O spawns with its bottom row in the lowest hidden row, 20,
+        // and falls to the floor
+        let mut hidden = Board::<24, 10>::empty()
+            .with_scoring(rules)
+            .with_hidden_rows(4);
+        let placed = hidden.apply(PieceType::O, 0, 0).unwrap();
+        assert_eq!(placed.points.drop, 2 * 20);
+        // Without hidden rows it spawns at the very top, row 22
+        let mut open = Board::<24, 10>::empty().with_scoring(rules);
+        let placed = open.apply(PieceType::O, 0, 0).unwrap();
+        assert_eq!(placed.points.drop, 2 * 22);
+    }
+
+    /// Locks a T turned into `rotation` at column `x`, its bottom row on
+    /// the floor, in the board of `rows`.
+    fn lock_turned_t(rows: &[&str], rotation: usize, x: usize) -> PlacementResult {
+        let mut board = Board::<20, 10>::from_rows(rows).unwrap();
+        let placement = Placement {
+            rotation,
+            x,
+            landing_y: 0,
+            turned: true,
+        };
+        board
+            .apply_at_in(PieceSet::standard(), PieceType::T as usize, placement)
+            .unwrap()
+    }
+
+    #[test]
+    fn three_corners_with_both_in_front_make_a_full_t_spin() {
+        // The T points down into the slot, under the overhang on its left
+        let placed = lock_turned_t(&["...#......", "###...####", "####.#####"], 2, 3);
+        assert_eq!(placed.spin, Spin::Full);
+        assert_eq!(placed.lines, 2);
+        assert_eq!(placed.points.spin, 900);
+        assert_eq!(placed.score_delta, 1200);
+    }
+
+    #[test]
+    fn three_corners_with_one_in_front_make_a_mini() {
+        let placed = lock_turned_t(&["...#.#....", "###...####", "###..#####"], 2, 3);
+        assert_eq!(placed.spin, Spin::Mini);
+        assert_eq!(placed.lines, 1);
+        assert_eq!(placed.score_delta, 200);
+    }
+
+    #[test]
+    fn two_corners_make_no_spin() {
+        // The slot of the full T-spin without its overhang
+        let placed = lock_turned_t(&["..........", "###...####", "####.#####"], 2, 3);
+        assert_eq!(placed.spin, Spin::None);
+        assert_eq!(placed.score_delta, 300);
+    }
+
+    #[test]
+    fn a_drop_makes_no_spin() {
+        let mut board = Board::<20, 10>::from_rows(&["###...####", "####.#####"]).unwrap();
+        let placed = board.apply(PieceType::T, 3, 2).unwrap();
+        assert_eq!(placed.spin, Spin::None);
+        assert_eq!(placed.score_delta, 300);
+    }
+
+    #[test]
+    fn the_wall_counts_as_filled_corners() {
+        // Pointing right with its back to the left wall, which gives the
+        // two corners behind it
+        let placed = lock_turned_t(&[".#........", "..########", ".#########"], 1, 0);
+        assert_eq!(placed.spin, Spin::Full);
+        assert_eq!(placed.score_delta, 1200);
+        let placed = lock_turned_t(&[".#........", "..########", "..########"], 1, 0);
+        assert_eq!(placed.spin, Spin::Mini);
+        assert_eq!(placed.lines, 1);
+        assert_eq!(placed.score_delta, 200);
+    }
+
+    #[test]
+    fn pentominoes_lock_as_plain_cells() {
+        let set = BuiltinSet::Pentomino.set();
+        let mut board = Board::<20, 10>::empty();
+        // The last pieces of the set would read as other colors if they
+        // kept their index
+        for piece in [0, set.len() - 1] {
+            board.apply_in(set, piece, 0, 0).unwrap();
+        }
+        assert!(board.to_ascii().chars().all(|c| "#.\n".contains(c)));
+        let mut restored = Board::<20, 10>::empty();
+        restored.restore(&board.snapshot());
+        assert_eq!(restored.get_color_grid(), board.get_color_grid());
+    }
+
+    /// The board of `rows` scoring by the guideline rules, less the drop
+    /// points, which depend only on where the piece lands.
+    fn guideline(rows: &[&str]) -> Board<20, 10> {
+        let rules = ScoringRules {
+            drop: 0,
+            ..ScoringRules::GUIDELINE
+        };
+        Board::from_rows(rows).unwrap().with_scoring(rules)
+    }
+
+    const TETRIS: [&str; 5] = [
+        "#.........",
+        "#########.",
+        "#########.",
+        "#########.",
+        "#########.",
+    ];
+
+    #[test]
+    fn back_to_back_adds_its_percent_of_the_clear() {
+        let mut board = guideline(&TETRIS).with_chain(1, true);
+        let placed = board.apply(PieceType::I, 9, 1).unwrap();
+        assert!(placed.back_to_back);
+        assert_eq!(placed.points.back_to_back, 400);
+        // The T-spin bonus is part of the clear it takes the percent of
+        let mut board = guideline(&["...#......", "###...####", "####.#####"]).with_chain(1, true);
+        let placement = Placement {
+            rotation: 2,
+            x: 3,
+            landing_y: 0,
+            turned: true,
+        };
+        let placed = board
+            .apply_at_in(PieceSet::standard(), PieceType::T as usize, placement)
+            .unwrap();
+        assert_eq!(placed.points.back_to_back, (300 + 900) / 2);
+    }
+
+    #[test]
+    fn clears_that_are_not_difficult_break_back_to_back() {
+        let mut board = guideline(&["#######...", "########.."]).with_chain(1, true);
+        let placed = board.apply(PieceType::O, 8, 0).unwrap();
+        assert_eq!(placed.lines, 1);
+        assert!(!placed.back_to_back);
+        assert!(!board.get_back_to_back());
+        // It keeps the combo going though
+        assert_eq!(placed.combo, 2);
+        assert_eq!(placed.points.combo, 50);
+    }
+
+    #[test]
+    fn placements_that_clear_nothing_only_break_the_combo() {
+        let mut board = guideline(&TETRIS).with_chain(3, true);
+        let placed = board.apply(PieceType::O, 0, 0).unwrap();
+        assert_eq!(placed.lines, 0);
+        assert_eq!(board.get_combo(), 0);
+        assert!(board.get_back_to_back());
+        let placed = board.apply(PieceType::I, 9, 1).unwrap();
+        assert!(placed.back_to_back);
+        assert_eq!(placed.points.combo, 0);
+    }
+
+    #[test]
+    fn all_clears_score_their_bonus_by_the_lines() {
+        let mut board = guideline(&["######...."]);
+        let placed = board.apply(PieceType::I, 6, 0).unwrap();
+        assert!(placed.all_clear);
+        assert_eq!(placed.points.all_clear, 800);
+        assert_eq!(placed.score_delta, 100 + 800);
+        let mut board = guideline(&TETRIS[1..]);
+        let placed = board.apply(PieceType::I, 9, 1).unwrap();
+        assert!(placed.all_clear);
+        assert_eq!(placed.score_delta, 800 + 2000);
+        // Clearing lines with cells left above is no all clear
+        let mut board = guideline(&TETRIS);
+        let placed = board.apply(PieceType::I, 9, 1).unwrap();
+        assert!(!placed.all_clear);
+        assert_eq!(placed.points.all_clear, 0);
     }
 }
@@ -0,0 +1,1346 @@
+use std::fmt;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread::{self, sleep};
+use std::time::{Duration, Instant};
+
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+
+use crate::board::{Board, PlacementResult};
+use crate::dimensions::{Dimensions, with_dimensions};
+use crate::generator::Randomizer;
+use crate::hold::HoldSlot;
+use crate::i18n::{say, tr};
+use crate::logging::{log_error, log_info, log_warn};
+use crate::mode::{GameMode, Progress};
+use crate::observation::{HeightMap, ObservationMode};
+use crate::piece::{ALL_PIECES, BuiltinSet, PieceSet};
+use crate::render::save_board;
+use crate::rotation::{RotationSystem, Unrepresentable};
+use crate::scoring::ScoringRules;
+use crate::search::SearchConfig;
+use crate::stats::GameStats;
+
+/// A placement as reported by the program under test.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Move {
+    pub rotation: usize,
+    pub x: i32,
+}
+
+#[derive(Debug)]
+pub enum ProtocolError {
+    /// Reading from the child failed.
+    Io(std::io::Error),
+    /// The child closed its stdout.
+    Closed,
+    /// The child printed something that is not a valid message.
+    Format(String),
+    /// The child printed nothing for this long.
+    Timeout(Duration),
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProtocolError::Io(e) => write!(
+                f,
+                "{}",
+                tr!(
+                    "读取程序输出错误: {}",
+                    "Failed to read program output: {}",
+                    e
+                )
+            ),
+            ProtocolError::Closed => write!(
+                f,
+                "{}",
+                tr!("程序已退出，游戏结束", "Program exited, game over")
+            ),
+            ProtocolError::Format(line) => write!(
+                f,
+                "{}",
+                tr!("程序输出格式错误: {}", "Malformed program output: {}", line)
+            ),
+            ProtocolError::Timeout(limit) => write!(
+                f,
+                "{}",
+                tr!(
+                    "程序在 {:.1} 秒内没有回应",
+                    "The program did not answer within {:.1}s",
+                    limit.as_secs_f64()
+                )
+            ),
+        }
+    }
+}
+
+/// Seconds a program may take over a move unless told otherwise, in
+/// `check` and against `versus`.
+pub const DEFAULT_MOVE_TIME: f64 = 5.0;
+
+/// Options for the optional protocol extensions of `check`.
+#[derive(Debug, Clone)]
+pub struct CheckOptions {
+    /// After the end marker, expect a `<score> <lines> <pieces>` summary line.
+    pub summary: bool,
+    /// Coordinate convention the child reports its moves in.
+    pub rotation: RotationSystem,
+    /// Where to save an image of the final board.
+    pub image: Option<PathBuf>,
+    /// Also save the board every this many pieces, next to `image`.
+    pub keyframes: Option<usize>,
+    /// Move lines carry a third field: how many legal placements the
+    /// program saw for the current piece.
+    pub legal_count: bool,
+    /// Every this many pieces, expect a board line after the score line.
+    pub board_every: Option<usize>,
+    /// Length of the piece sequence, at least 2.
+    pub pieces: usize,
+    /// The pieces the sequence is drawn from. Other sets than the
+    /// tetrominoes only come in the `Simple` rotation system.
+    pub piece_set: BuiltinSet,
+    /// How the sequence is drawn, parsed for `piece_set`.
+    pub randomizer: Randomizer,
+    /// Seconds after which the game is ended.
+    pub time_limit: f64,
+    /// Seconds the program may take over each line it owes us, a move, a
+    /// score or a board, before it is at fault.
+    pub move_time: f64,
+    /// How the end of the game is signalled.
+    pub end: EndSignal,
+    /// Offer the sequence length to the program before the first piece and
+    /// let it ask for fewer pieces.
+    pub negotiate: bool,
+    /// With `Heights`, every piece line is followed by an `O` line with the
+    /// column heights and hole count of our board.
+    pub observation: ObservationMode,
+    /// Seed of the piece sequence; a fresh one is drawn when unset.
+    pub seed: Option<u64>,
+    /// Let the program send `H` before a move to hold the current piece
+    /// and place the held one instead, or the next one while nothing is
+    /// held. Holding into an empty slot is answered with the new preview
+    /// piece.
+    pub hold: bool,
+    /// Deal the `lookahead` of this search as the preview instead of one
+    /// piece, for programs that search the queue, like `serve --search`.
+    pub search: Option<SearchConfig>,
+    /// How our board scores the game, which the program's score lines
+    /// must match.
+    pub scoring: ScoringRules,
+    /// Size of our board, which the program has to play on too. Set from
+    /// the global `--width` and `--height`.
+    pub dimensions: Dimensions,
+    /// Rows at the top of our board that pieces spawn in, above the
+    /// playfield. A game with hidden rows tops out on a block out or a
+    /// lock out, not only when a piece fits nowhere.
+    pub hidden: usize,
+    /// With a sprint the game ends once its lines are cleared, and the
+    /// pieces and simulated time it took are reported. The program is not
+    /// told, it just gets the end of the game.
+    pub mode: GameMode,
+}
+
+impl Default for CheckOptions {
+    fn default() -> Self {
+        CheckOptions {
+            summary: false,
+            rotation: RotationSystem::default(),
+            image: None,
+            keyframes: None,
+            legal_count: false,
+            board_every: None,
+            pieces: 1_000_000,
+            piece_set: BuiltinSet::default(),
+            randomizer: Randomizer::default(),
+            time_limit: 10.0,
+            move_time: DEFAULT_MOVE_TIME,
+            end: EndSignal::default(),
+            negotiate: false,
+            observation: ObservationMode::default(),
+            seed: None,
+            hold: false,
+            search: None,
+            scoring: ScoringRules::default(),
+            dimensions: Dimensions::default(),
+            hidden: 0,
+            mode: GameMode::default(),
+        }
+    }
+}
+
+impl CheckOptions {
+    pub fn from_args(args: &[String]) -> Self {
+        let mut options = CheckOptions::default();
+        let mut randomizer = None;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--summary" => options.summary = true,
+                "--negotiate" => options.negotiate = true,
+                "--legal-count" => options.legal_count = true,
+                "--hold" => options.hold = true,
+                "--board-every" => {
+                    options.board_every =
+                        args.next().and_then(|n| n.parse().ok()).filter(|&n| n > 0)
+                }
+                "--rotation" => match args.next().and_then(|r| RotationSystem::parse(r)) {
+                    Some(rotation) => options.rotation = rotation,
+                    None => log_warn!(
+                        "--rotation 需要 simple 或 srs",
+                        "--rotation expects simple or srs"
+                    ),
+                },
+                "--pieces" => match args.next().and_then(|n| n.parse().ok()) {
+                    Some(n) if n >= 2 => options.pieces = n,
+                    _ => log_warn!(
+                        "--pieces 需要不小于 2 的长度",
+                        "--pieces expects a length of at least 2"
+                    ),
+                },
+                "--piece-set" => match args.next().and_then(|p| BuiltinSet::parse(p)) {
+                    Some(set) => options.piece_set = set,
+                    None => log_warn!(
+                        "--piece-set 需要 tetromino 或 pentomino",
+                        "--piece-set expects tetromino or pentomino"
+                    ),
+                },
+                "--randomizer" => randomizer = args.next(),
+                "--search" => match args.next().map(|s| SearchConfig::parse(s)) {
+                    Some(Ok(search)) => options.search = Some(search),
+                    Some(Err(e)) => log_warn!("忽略 --search: {}", "Ignoring --search: {}", e),
+                    None => log_warn!(
+                        "--search 需要 depth=<n>,beam=<k>",
+                        "--search expects depth=<n>,beam=<k>"
+                    ),
+                },
+                "--scoring" => match args.next().map(|s| ScoringRules::load(s)) {
+                    Some(Ok(scoring)) => options.scoring = scoring,
+                    Some(Err(e)) => log_warn!("忽略 --scoring: {}", "Ignoring --scoring: {}", e),
+                    None => log_warn!(
+                        "--scoring 需要 guideline 或规则文件",
+                        "--scoring expects guideline or a rules file"
+                    ),
+                },
+                "--time-limit" => {
+                    if let Some(s) = args
+                        .next()
+                        .and_then(|s| s.parse().ok())
+                        .filter(|&s| s > 0.0)
+                    {
+                        options.time_limit = s;
+                    }
+                }
+                "--move-time" => match args.next().and_then(|s| s.parse().ok()) {
+                    Some(s) if s > 0.0 => options.move_time = s,
+                    _ => log_warn!(
+                        "--move-time 需要一个正的秒数",
+                        "--move-time expects a positive number of seconds"
+                    ),
+                },
+                "--hidden" => match args.next().and_then(|n| n.parse().ok()) {
+                    Some(rows) => options.hidden = rows,
+                    None => log_warn!("--hidden 需要一个整数", "--hidden expects an integer"),
+                },
+                "--end" => match args.next().map(String::as_str) {
+                    Some("marker") => options.end = EndSignal::Marker,
+                    Some("close") => options.end = EndSignal::Close,
+                    _ => log_warn!(
+                        "--end 需要 marker 或 close",
+                        "--end expects marker or close"
+                    ),
+                },
+                "--observation" => match args.next().and_then(|m| ObservationMode::parse(m)) {
+                    Some(mode) => options.observation = mode,
+                    None => log_warn!(
+                        "--observation 需要 full 或 heights",
+                        "--observation expects full or heights"
+                    ),
+                },
+                "--image" => options.image = args.next().map(PathBuf::from),
+                "--seed" => match args.next().and_then(|s| s.parse().ok()) {
+                    Some(seed) => options.seed = Some(seed),
+                    None => log_warn!("--seed 需要一个整数", "--seed expects an integer"),
+                },
+                "--mode" => match args.next().map(|m| GameMode::parse(m)) {
+                    // The protocol has no way to deal the program a board
+                    Some(Ok(GameMode::Dig { .. })) => log_warn!(
+                        "忽略 --mode: 程序总是从空棋盘开始, 无法 dig",
+                        "Ignoring --mode: the program always starts on an empty board, so it cannot dig"
+                    ),
+                    Some(Ok(mode)) => options.mode = mode,
+                    Some(Err(e)) => log_warn!("忽略 --mode: {}", "Ignoring --mode: {}", e),
+                    None => log_warn!(
+                        "--mode 需要 endless 或 sprint[=<行数>]",
+                        "--mode expects endless or sprint[=<lines>]"
+                    ),
+                },
+                "--keyframes" => {
+                    options.keyframes = args.next().and_then(|n| n.parse().ok()).filter(|&n| n > 0)
+                }
+                _ => log_warn!("忽略未知参数: {}", "Ignoring unknown argument: {}", arg),
+            }
+        }
+        // The sequence letters depend on the set, wherever --piece-set was
+        if let Some(spec) = randomizer {
+            match Randomizer::parse(spec, options.piece_set.set()) {
+                Ok(Randomizer::Adversarial) => log_warn!(
+                    "忽略 --randomizer: 方块序列在开局前就已生成, 无法针对棋盘选择方块",
+                    "Ignoring --randomizer: the sequence is drawn before the game, so it cannot follow the board"
+                ),
+                Ok(randomizer) => options.randomizer = randomizer,
+                Err(e) => log_warn!("忽略 --randomizer: {}", "Ignoring --randomizer: {}", e),
+            }
+        }
+        options
+    }
+}
+
+/// How the end of the game reaches the child.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum EndSignal {
+    /// An `E` line in place of the next piece.
+    #[default]
+    Marker,
+    /// Closing the child's stdin, for programs that stop at end of input.
+    Close,
+}
+
+/// How the game came to an end. Grading often treats these differently.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Finish {
+    /// The sequence ran out or the time limit was reached.
+    Completed,
+    /// No legal placement was left for piece `at` (counted from 1).
+    TopOut { at: usize },
+    /// The program gave up instead of placing piece `at`.
+    Resigned { at: usize },
+    /// The program made an illegal move or broke the protocol at piece `at`.
+    Fault { at: usize },
+}
+
+/// What a finished check reports back, for callers that grade several
+/// games.
+#[derive(Debug, Clone)]
+pub struct CheckOutcome {
+    pub seed: u64,
+    pub finish: Finish,
+    pub stats: GameStats,
+}
+
+impl CheckOutcome {
+    pub fn score(&self) -> i64 {
+        self.stats.total()
+    }
+}
+
+/// The line a program sends instead of a move to give up.
+pub fn is_resign(line: &str) -> bool {
+    line.trim() == "R"
+}
+
+/// The line a program sends before its move to hold the current piece,
+/// with `--hold`.
+pub fn is_hold(line: &str) -> bool {
+    line.trim() == "H"
+}
+
+/// Parses the program's `P <n>` answer to the offered sequence length.
+/// It may only shorten the game.
+pub fn parse_negotiation(line: &str, offered: usize) -> Result<usize, ProtocolError> {
+    let mut parts = line.split_whitespace();
+    match (
+        parts.next(),
+        parts.next().map(str::parse::<usize>),
+        parts.next(),
+    ) {
+        (Some("P"), Some(Ok(n)), None) if (2..=offered).contains(&n) => Ok(n),
+        _ => Err(ProtocolError::Format(line.to_string())),
+    }
+}
+
+/// What to send the child after a move was accepted.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Feed {
+    /// The letter of the new preview piece.
+    Piece(char),
+    /// No further pieces: signal the end of the game.
+    End,
+}
+
+/// Where the game stands in the piece sequence. The child is always told
+/// the preview as well: it plays `pieces[current]` while seeing the next
+/// piece, or the next few with `--search`, and each accepted move is
+/// answered with the piece after those. The last pieces of the sequence
+/// are therefore only ever previews and never played. With `--hold`,
+/// holding while the slot is empty also moves on to the next piece,
+/// without a placement.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Sequence {
+    Playing {
+        current: usize,
+    },
+    /// The end was signalled after `placed` pieces.
+    Ended {
+        placed: usize,
+    },
+}
+
+pub struct PieceFeed<'a> {
+    set: &'a PieceSet,
+    pieces: Vec<usize>,
+    state: Sequence,
+    /// Pieces that went into an empty hold slot instead of being placed.
+    held: usize,
+    /// Pieces the child sees beyond the current one.
+    preview: usize,
+}
+
+impl<'a> PieceFeed<'a> {
+    pub fn new(set: &'a PieceSet, pieces: Vec<usize>) -> Self {
+        assert!(
+            pieces.len() >= 2,
+            "need at least a current and a next piece"
+        );
+        PieceFeed {
+            set,
+            pieces,
+            state: Sequence::Playing { current: 0 },
+            held: 0,
+            preview: 1,
+        }
+    }
+
+    /// Shows the child `preview` pieces beyond the current one instead of
+    /// one, or as many as the sequence has after its first piece.
+    pub fn with_preview(mut self, preview: usize) -> Self {
+        self.preview = preview.clamp(1, self.pieces.len() - 1);
+        self
+    }
+
+    /// The first line of the protocol: the current piece and the preview.
+    pub fn opening(&self) -> String {
+        let mut line: String = self.pieces[..=self.preview]
+            .iter()
+            .map(|&p| self.set.letter(p))
+            .collect();
+        line.push('\n');
+        line
+    }
+
+    /// Index of the piece the child has to place, until the game ended.
+    pub fn current(&self) -> Option<usize> {
+        match self.state {
+            Sequence::Playing { current } => Some(current),
+            Sequence::Ended { .. } => None,
+        }
+    }
+
+    pub fn piece(&self, index: usize) -> usize {
+        self.pieces[index]
+    }
+
+    /// Pieces the child placed and we accepted.
+    pub fn placed(&self) -> usize {
+        match self.state {
+            Sequence::Playing { current } => current - self.held,
+            Sequence::Ended { placed } => placed,
+        }
+    }
+
+    pub fn ended(&self) -> bool {
+        matches!(self.state, Sequence::Ended { .. })
+    }
+
+    /// Moves on after the current piece was placed. With `stop`, or when the
+    /// sequence has no piece left to preview, the game ends instead.
+    pub fn advance(&mut self, stop: bool) -> Feed {
+        let Sequence::Playing { current } = self.state else {
+            return Feed::End;
+        };
+        let preview = current + 1 + self.preview;
+        if stop || preview >= self.pieces.len() {
+            self.state = Sequence::Ended {
+                placed: current + 1 - self.held,
+            };
+            Feed::End
+        } else {
+            self.state = Sequence::Playing {
+                current: current + 1,
+            };
+            Feed::Piece(self.set.letter(self.pieces[preview]))
+        }
+    }
+
+    /// Moves on to the next piece after the current one went into an
+    /// empty hold slot. The game ends when there is no piece left to
+    /// preview, as in `advance`.
+    pub fn hold(&mut self) -> Feed {
+        let Sequence::Playing { current } = self.state else {
+            return Feed::End;
+        };
+        let preview = current + 1 + self.preview;
+        if preview >= self.pieces.len() {
+            self.abort();
+            return Feed::End;
+        }
+        self.held += 1;
+        self.state = Sequence::Playing {
+            current: current + 1,
+        };
+        Feed::Piece(self.set.letter(self.pieces[preview]))
+    }
+
+    /// Ends the game without accepting the current piece.
+    pub fn abort(&mut self) {
+        self.state = Sequence::Ended {
+            placed: self.placed(),
+        };
+    }
+}
+
+/// Final bookkeeping reported by the child after the end marker.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Summary {
+    pub score: i64,
+    pub lines: usize,
+    pub pieces: usize,
+}
+
+/// Outcome of comparing the number of legal placements the child saw with
+/// ours.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LegalCheck {
+    Match,
+    Mismatch { program: usize, actual: usize },
+}
+
+/// Outcome of comparing the score reported by the child with ours.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScoreCheck {
+    Match,
+    Mismatch { program: i64, actual: i64 },
+}
+
+/// Parses a `<rotation> <x>` move line.
+pub fn parse_move(line: &str) -> Result<Move, ProtocolError> {
+    let mut parts = line.split_whitespace();
+    let (Some(rotation), Some(x)) = (parts.next(), parts.next()) else {
+        return Err(ProtocolError::Format(line.to_string()));
+    };
+    match (rotation.parse(), x.parse()) {
+        (Ok(rotation), Ok(x)) => Ok(Move { rotation, x }),
+        _ => Err(ProtocolError::Format(line.to_string())),
+    }
+}
+
+/// Parses the legal placement count, the third field of a move line.
+pub fn parse_legal_count(line: &str) -> Result<usize, ProtocolError> {
+    line.split_whitespace()
+        .nth(2)
+        .and_then(|count| count.parse().ok())
+        .ok_or_else(|| ProtocolError::Format(line.to_string()))
+}
+
+/// Number of `(rotation, x)` placements of `piece` the rules accept, in our
+/// rotation system.
+pub fn count_legal<const H: usize, const W: usize>(
+    board: &Board<H, W>,
+    set: &PieceSet,
+    piece: usize,
+) -> usize {
+    board.legal_placements_in(set, piece).count()
+}
+
+pub fn compare_legal<const H: usize, const W: usize>(
+    board: &Board<H, W>,
+    set: &PieceSet,
+    piece: usize,
+    program_count: usize,
+) -> LegalCheck {
+    let actual = count_legal(board, set, piece);
+    if actual == program_count {
+        LegalCheck::Match
+    } else {
+        LegalCheck::Mismatch {
+            program: program_count,
+            actual,
+        }
+    }
+}
+
+/// Compact board encoding for the `--board-every` extension: each row as
+/// three hex digits, bit `x` set when column `x` is filled, from the bottom
+/// row up, with empty rows at the top left out. An empty board is `-`.
+/// Three digits hold rows of up to 12 columns.
+pub fn encode_board<const H: usize, const W: usize>(board: &Board<H, W>) -> String {
+    let grid = board.get_grid();
+    let rows: Vec<u16> = grid
+        .iter()
+        .map(|row| {
+            row.iter()
+                .enumerate()
+                .fold(0, |bits, (x, &filled)| bits | (filled as u16) << x)
+        })
+        .collect();
+    let used = rows.iter().rposition(|&r| r != 0).map_or(0, |top| top + 1);
+    if used == 0 {
+        return "-".to_string();
+    }
+    rows[..used].iter().map(|r| format!("{:03x}", r)).collect()
+}
+
+/// Rows (from 0 at the bottom) where two encodings disagree, or `None`
+/// if the program's line is not a valid encoding.
+pub fn diff_encoding(program: &str, actual: &str) -> Option<Vec<usize>> {
+    let decode = |text: &str| -> Option<Vec<u16>> {
+        if text == "-" {
+            return Some(Vec::new());
+        }
+        if !text.len().is_multiple_of(3) || !text.is_ascii() {
+            return None;
+        }
+        (0..text.len())
+            .step_by(3)
+            .map(|i| u16::from_str_radix(&text[i..i + 3], 16).ok())
+            .collect()
+    };
+    let (program, actual) = (decode(program.trim())?, decode(actual)?);
+    let rows = program.len().max(actual.len());
+    Some(
+        (0..rows)
+            .filter(|&y| program.get(y).unwrap_or(&0) != actual.get(y).unwrap_or(&0))
+            .collect(),
+    )
+}
+
+/// Parses the score line that follows every move.
+pub fn parse_score(line: &str) -> Result<i64, ProtocolError> {
+    line.trim()
+        .parse()
+        .map_err(|_| ProtocolError::Format(line.to_string()))
+}
+
+/// Translates a parsed move from the child's rotation system into ours.
+/// `Srs` only describes the tetrominoes; `piece` is a `PieceType` then.
+pub fn translate_move(
+    rotation: RotationSystem,
+    piece: usize,
+    mv: Move,
+) -> Result<Move, Unrepresentable> {
+    if rotation == RotationSystem::Simple {
+        return Ok(mv);
+    }
+    let (rotation, x) = rotation.to_simple(ALL_PIECES[piece], mv.rotation, mv.x)?;
+    Ok(Move { rotation, x })
+}
+
+/// Checks the move against the rules and, if legal, plays it on `board`.
+pub fn validate_move<const H: usize, const W: usize>(
+    board: &mut Board<H, W>,
+    set: &PieceSet,
+    piece: usize,
+    mv: Move,
+) -> Result<PlacementResult, &'static str> {
+    if mv.rotation >= 4 {
+        return Err("Invalid rotation");
+    }
+    if mv.x < 0 {
+        return Err("Piece out of bounds");
+    }
+    let x = mv.x as usize;
+    board.check_in(set, piece, x, mv.rotation)?;
+    board.apply_in(set, piece, x, mv.rotation)
+}
+
+pub fn compare_score<const H: usize, const W: usize>(
+    board: &Board<H, W>,
+    program_score: i64,
+) -> ScoreCheck {
+    let actual = board.get_score();
+    if actual == program_score {
+        ScoreCheck::Match
+    } else {
+        ScoreCheck::Mismatch {
+            program: program_score,
+            actual,
+        }
+    }
+}
+
+/// Parses the `<score> <lines> <pieces>` summary line.
+pub fn parse_summary(line: &str) -> Result<Summary, ProtocolError> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    let [score, lines, pieces] = fields[..] else {
+        return Err(ProtocolError::Format(line.to_string()));
+    };
+    match (score.parse(), lines.parse(), pieces.parse()) {
+        (Ok(score), Ok(lines), Ok(pieces)) => Ok(Summary {
+            score,
+            lines,
+            pieces,
+        }),
+        _ => Err(ProtocolError::Format(line.to_string())),
+    }
+}
+
+/// Returns the `(field, program, actual)` triples that disagree.
+pub fn compare_summary(program: &Summary, actual: &Summary) -> Vec<(&'static str, i64, i64)> {
+    let fields = [
+        ("score", program.score, actual.score),
+        ("lines", program.lines as i64, actual.lines as i64),
+        ("pieces", program.pieces as i64, actual.pieces as i64),
+    ];
+    fields.into_iter().filter(|(_, p, a)| p != a).collect()
+}
+
+/// The line that deals the new preview piece, followed by the `O` line
+/// of our board with `ObservationMode::Heights`.
+fn piece_line<const H: usize, const W: usize>(
+    next: char,
+    board: &Board<H, W>,
+    observation: ObservationMode,
+) -> String {
+    let mut text = format!("{}\n", next);
+    if observation == ObservationMode::Heights {
+        text.push_str(&HeightMap::from_board(board).to_line());
+        text.push('\n');
+    }
+    text
+}
+
+/// `<stem>-<pieces>.<ext>` next to the final image.
+fn keyframe_path(image: &Path, pieces: usize) -> PathBuf {
+    let stem = image
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("board");
+    let ext = image.extension().and_then(|e| e.to_str()).unwrap_or("png");
+    image.with_file_name(format!("{}-{}.{}", stem, pieces, ext))
+}
+
+/// The lines a program prints, read on a thread of their own so that a
+/// wait for the next one can give up.
+pub(crate) struct ProgramOutput {
+    lines: Receiver<std::io::Result<String>>,
+}
+
+impl ProgramOutput {
+    pub(crate) fn new(stdout: ChildStdout) -> Self {
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let failed = line.is_err();
+                if sender.send(line).is_err() || failed {
+                    break;
+                }
+            }
+        });
+        ProgramOutput { lines }
+    }
+
+    /// The next line, if the program prints it within `limit`.
+    pub(crate) fn next_line(&self, limit: Duration) -> Result<String, ProtocolError> {
+        match self.lines.recv_timeout(limit) {
+            Ok(Ok(line)) => Ok(line),
+            Ok(Err(e)) => Err(ProtocolError::Io(e)),
+            Err(RecvTimeoutError::Timeout) => Err(ProtocolError::Timeout(limit)),
+            Err(RecvTimeoutError::Disconnected) => Err(ProtocolError::Closed),
+        }
+    }
+}
+
+pub(crate) fn send(stdin: &mut Option<ChildStdin>, text: &str) -> std::io::Result<()> {
+    let Some(stdin) = stdin else {
+        return Err(std::io::ErrorKind::BrokenPipe.into());
+    };
+    stdin.write_all(text.as_bytes())?;
+    stdin.flush()
+}
+
+/// Sends a line that comes before the first move. A program that has
+/// exited already is not a fault yet: what it printed is still read, and
+/// the game ends where that runs out.
+fn send_initial(stdin: &mut Option<ChildStdin>, text: &str) {
+    if let Err(e) = send(stdin, text) {
+        log_error!(
+            "写入初始输入失败: {}",
+            "Failed to write the initial input: {}",
+            e
+        );
+    }
+}
+
+fn signal_end(stdin: &mut Option<ChildStdin>, end: EndSignal) {
+    let result = match end {
+        EndSignal::Marker => send(stdin, "E\n"),
+        EndSignal::Close => {
+            stdin.take();
+            Ok(())
+        }
+    };
+    match result {
+        Ok(()) => log_info!("已发送游戏结束标记", "Sent game over marker"),
+        Err(e) => log_error!("写入结束标记失败: {}", "Failed to write end marker: {}", e),
+    }
+}
+
+pub fn check(executable_path: String, options: CheckOptions) -> CheckOutcome {
+    check_command(Command::new(executable_path), options)
+}
+
+/// `check` on the program `command` starts, arguments and all.
+fn check_command(command: Command, options: CheckOptions) -> CheckOutcome {
+    with_dimensions!(options.dimensions, |H, W| check_in::<H, W>(
+        command, options
+    ))
+}
+
+/// `check` on a board of `H` rows and `W` columns.
+fn check_in<const H: usize, const W: usize>(
+    mut command: Command,
+    options: CheckOptions,
+) -> CheckOutcome {
+    let start_time = Instant::now();
+
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("无法启动目标程序");
+
+    let mut stdin = Some(child.stdin.take().expect("无法获取子进程stdin"));
+    let stdout = child.stdout.take().expect("无法获取子进程stdout");
+    let output = ProgramOutput::new(stdout);
+    let move_time = Duration::from_secs_f64(options.move_time);
+
+    let seed = options.seed.unwrap_or_else(rand::random);
+    log_info!("方块序列种子: {}", "Piece sequence seed: {}", seed);
+    if !options.dimensions.is_default() {
+        log_info!("棋盘尺寸: {}", "Board size: {}", options.dimensions);
+    }
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut board = Board::<H, W>::empty().with_scoring(options.scoring);
+    if options.hidden + 4 <= H {
+        board = board.with_hidden_rows(options.hidden);
+    } else {
+        log_warn!(
+            "忽略 --hidden: {} 行的棋盘至少要露出 4 行",
+            "Ignoring --hidden: a board of {} rows must show at least 4",
+            H
+        );
+    }
+    let set = options.piece_set.set();
+    let mut rotation = options.rotation;
+    if options.piece_set != BuiltinSet::Tetromino && rotation != RotationSystem::Simple {
+        log_warn!(
+            "{} 只适用于四格方块, 改用 simple",
+            "{} only describes tetrominoes, using simple",
+            rotation.name()
+        );
+        rotation = RotationSystem::Simple;
+    }
+
+    let mut length = options.pieces;
+    if options.negotiate {
+        send_initial(&mut stdin, &format!("P {}\n", length));
+        match output
+            .next_line(move_time)
+            .and_then(|l| parse_negotiation(&l, length))
+        {
+            Ok(n) => {
+                log_info!("约定方块数: {}", "Agreed number of pieces: {}", n);
+                length = n;
+            }
+            Err(e) => {
+                log_error!(
+                    "协商方块数失败: {}",
+                    "Failed to negotiate the number of pieces: {}",
+                    e
+                );
+                let _ = child.kill();
+                let _ = child.wait();
+                return CheckOutcome {
+                    seed,
+                    finish: Finish::Fault { at: 1 },
+                    stats: GameStats::default(),
+                };
+            }
+        }
+    }
+    let mut generator = options.randomizer.generator(set, &mut rng);
+    let pieces = (0..length).map(|_| generator.next_piece(&board)).collect();
+    let lookahead = options.search.map_or(1, |search| search.lookahead());
+    let mut feed = PieceFeed::new(set, pieces).with_preview(lookahead);
+    send_initial(&mut stdin, &feed.opening());
+    if options.observation == ObservationMode::Heights {
+        let line = format!("{}\n", HeightMap::from_board(&board).to_line());
+        send_initial(&mut stdin, &line);
+    }
+    let mut finish = None;
+    let mut stats = GameStats::default();
+    let mut progress = Progress::new(options.mode, None, seed);
+
+    let mut legal_mismatches = 0;
+    let mut first_desync: Option<usize> = None;
+
+    let mut slot = HoldSlot::new();
+    // The held piece, while it is placed instead of the dealt one
+    let mut swapped = None;
+    let mut held = false;
+
+    while let Some(current_idx) = feed.current() {
+        let current_piece = swapped.unwrap_or_else(|| feed.piece(current_idx));
+        let at = feed.placed() + 1;
+
+        // Protocol: one move line followed by one score line, or a
+        // resignation
+        let response = match output.next_line(move_time) {
+            Ok(response) => response,
+            Err(e) => {
+                log::error!("{}", e);
+                break;
+            }
+        };
+        log::debug!("{}", response);
+        if is_resign(&response) {
+            log_info!(
+                "程序在第{}个方块认输",
+                "The program resigned at piece {}",
+                at
+            );
+            finish = Some(Finish::Resigned { at });
+            feed.abort();
+            signal_end(&mut stdin, options.end);
+            break;
+        }
+        if options.hold && is_hold(&response) {
+            if held {
+                log_warn!(
+                    "警告: 程序对第{}个方块暂存了两次",
+                    "Warning: the program held twice at piece {}",
+                    at
+                );
+                finish = Some(Finish::Fault { at });
+                feed.abort();
+                signal_end(&mut stdin, options.end);
+                break;
+            }
+            held = true;
+            let (piece, took_next) = slot.hold(current_piece, Some(feed.piece(current_idx + 1)));
+            if !took_next {
+                swapped = Some(piece);
+                continue;
+            }
+            swapped = None;
+            match feed.hold() {
+                Feed::Piece(next) => {
+                    if let Err(e) = send(&mut stdin, &piece_line(next, &board, options.observation))
+                    {
+                        log_error!("写入下一方块失败: {}", "Failed to write next piece: {}", e);
+                        break;
+                    }
+                }
+                Feed::End => {
+                    finish = Some(Finish::Completed);
+                    signal_end(&mut stdin, options.end);
+                    break;
+                }
+            }
+            continue;
+        }
+        let mv = parse_move(&response).and_then(|mv| {
+            let legal = if options.legal_count {
+                Some(parse_legal_count(&response)?)
+            } else {
+                None
+            };
+            Ok((mv, legal))
+        });
+        let (mv, program_legal) = match mv {
+            Ok(mv) => mv,
+            Err(e) => {
+                log::error!("{}", e);
+                break;
+            }
+        };
+
+        let program_score = match output.next_line(move_time).and_then(|l| parse_score(&l)) {
+            Ok(score) => score,
+            Err(ProtocolError::Closed) => {
+                log_error!(
+                    "程序在提供分数前退出，游戏结束",
+                    "Program exited before reporting a score, game over"
+                );
+                break;
+            }
+            Err(e) => {
+                log_error!("读取分数错误: {}", "Failed to read score: {}", e);
+                break;
+            }
+        };
+
+        let mv = match translate_move(rotation, current_piece, mv) {
+            Ok(mv) => mv,
+            Err(Unrepresentable) => {
+                log_warn!(
+                    "警告: 程序的行动无法在本棋盘的旋转系统中表示 (旋转={}, 位置={})",
+                    "Warning: the program's move has no counterpart in this board's rotation system (rotation={}, x={})",
+                    mv.rotation,
+                    mv.x
+                );
+                finish = Some(Finish::Fault { at });
+                feed.abort();
+                signal_end(&mut stdin, options.end);
+                break;
+            }
+        };
+
+        // What the program saw, checked before the move changes the board
+        let legal =
+            program_legal.map(|count| (count, compare_legal(&board, set, current_piece, count)));
+        if let Some((_, LegalCheck::Mismatch { program, actual })) = legal {
+            legal_mismatches += 1;
+            if legal_mismatches == 1 {
+                log_warn!(
+                    "警告: 第{}个方块的合法位置数不一致, 程序={}, 实际={}, 程序的棋盘可能与实际不同",
+                    "Warning: legal placement count differs at piece {}, program={}, actual={}; the program's board may differ",
+                    at,
+                    program,
+                    actual
+                );
+            }
+        }
+
+        // Rules
+        let any_legal = count_legal(&board, set, current_piece) > 0;
+        let placed = validate_move(&mut board, set, current_piece, mv);
+        let Ok(placed) = placed else {
+            log_warn!(
+                "警告: 程序选择了无效的行动 (旋转={}, 位置={})",
+                "Warning: the program chose an invalid move (rotation={}, x={})",
+                mv.rotation,
+                mv.x
+            );
+            match legal {
+                Some((0, LegalCheck::Match)) => log_warn!(
+                    "程序与检查器都认为没有合法位置, 属于正常结束",
+                    "Neither the program nor the checker found a legal placement: a regular top-out"
+                ),
+                Some((0, _)) => log_warn!(
+                    "程序认为没有合法位置, 但实际存在, 程序的棋盘与实际不同",
+                    "The program saw no legal placement although there are some: its board differs"
+                ),
+                Some((_, LegalCheck::Match)) => log_warn!(
+                    "程序看到的合法位置数正确, 但选择了非法位置",
+                    "The program saw the right number of legal placements but chose an illegal one"
+                ),
+                Some((_, LegalCheck::Mismatch { .. })) => log_warn!(
+                    "程序的合法位置数与实际不同, 程序的棋盘可能与实际不同",
+                    "The program's legal placement count differs: its board probably differs"
+                ),
+                None => {}
+            }
+            finish = Some(if any_legal {
+                Finish::Fault { at }
+            } else {
+                Finish::TopOut { at }
+            });
+            feed.abort();
+            signal_end(&mut stdin, options.end);
+            break;
+        };
+
+        stats.record(&placed);
+        progress.after_piece(&placed, &mut board);
+        swapped = None;
+        held = false;
+
+        // Scoring
+        if let ScoreCheck::Mismatch { program, actual } = compare_score(&board, program_score) {
+            log_warn!(
+                "警告: 分数不匹配！程序={}, 实际={}",
+                "Warning: score mismatch! program={}, actual={}",
+                program,
+                actual
+            );
+        }
+
+        let placed = at;
+
+        if let Some(every) = options.board_every
+            && placed.is_multiple_of(every)
+        {
+            let line = match output.next_line(move_time) {
+                Ok(line) => line,
+                Err(e) => {
+                    log_error!(
+                        "读取棋盘编码失败: {}",
+                        "Failed to read board encoding: {}",
+                        e
+                    );
+                    break;
+                }
+            };
+            match diff_encoding(&line, &encode_board(&board)) {
+                Some(rows) if rows.is_empty() => {}
+                Some(rows) => {
+                    if first_desync.is_none() {
+                        first_desync = Some(placed);
+                        log_warn!(
+                            "警告: 第{}个方块后棋盘不同步, 不一致的行 (自底向上): {:?}",
+                            "Warning: boards out of sync after piece {}, differing rows (from the bottom): {:?}",
+                            placed,
+                            rows
+                        );
+                    }
+                }
+                None => {
+                    log_error!("{}", "{}", ProtocolError::Format(line));
+                    break;
+                }
+            }
+        }
+
+        if let (Some(image), Some(every)) = (&options.image, options.keyframes)
+            && placed.is_multiple_of(every)
+        {
+            let path = keyframe_path(image, placed);
+            let title = format!("{} pieces", placed);
+            if let Err(e) = save_board(&board, &title, &path) {
+                log::error!("{}", e);
+            }
+        }
+
+        let elapsed = start_time.elapsed().as_secs_f64();
+        let timed_out = elapsed > options.time_limit;
+        if timed_out {
+            log_info!(
+                "当前放置了 {} 个方块，平均速度: {:.2} 个方块/秒",
+                "Placed {} pieces so far, average speed: {:.2} pieces/s",
+                placed,
+                placed as f64 / elapsed
+            );
+            log_info!("正在发送结束标记...", "Sending end marker...");
+        }
+
+        if progress.finished() {
+            log_info!(
+                "达成 {} 的目标, 正在发送结束标记...",
+                "Reached the goal of {}, sending end marker...",
+                options.mode.to_spec()
+            );
+        }
+
+        match feed.advance(timed_out || progress.finished()) {
+            Feed::Piece(next) => {
+                if let Err(e) = send(&mut stdin, &piece_line(next, &board, options.observation)) {
+                    log_error!("写入下一方块失败: {}", "Failed to write next piece: {}", e);
+                    log_error!(
+                        "程序可能已退出，游戏结束",
+                        "The program may have exited, game over"
+                    );
+                    break;
+                }
+            }
+            Feed::End => {
+                finish = Some(Finish::Completed);
+                signal_end(&mut stdin, options.end);
+            }
+        }
+    }
+    let current_idx = feed.placed();
+    let finish = finish.unwrap_or(Finish::Fault {
+        at: current_idx + 1,
+    });
+
+    if options.summary && feed.ended() {
+        let actual = Summary {
+            score: board.get_score(),
+            lines: board.get_lines(),
+            pieces: current_idx,
+        };
+        match output.next_line(move_time).and_then(|l| parse_summary(&l)) {
+            Ok(program) => {
+                let mismatches = compare_summary(&program, &actual);
+                if mismatches.is_empty() {
+                    say!("结算信息一致", "Summary matches");
+                }
+                for (field, program, actual) in mismatches {
+                    log_warn!(
+                        "警告: 结算{}不匹配！程序={}, 实际={}",
+                        "Warning: summary {} mismatch! program={}, actual={}",
+                        field,
+                        program,
+                        actual
+                    );
+                }
+            }
+            Err(e) => log_error!("读取结算信息失败: {}", "Failed to read summary: {}", e),
+        }
+    }
+
+    sleep(Duration::from_secs(1));
+    log_info!(
+        "正在检查目标程序状态...",
+        "Checking target program status..."
+    );
+
+    match child.try_wait() {
+        Ok(Some(status)) => log_info!(
+            "目标程序已退出，状态码: {}",
+            "Target program exited with status: {}",
+            status
+        ),
+        Ok(None) => {
+            log_info!(
+                "目标程序仍在运行，正在终止...",
+                "Target program still running, killing it..."
+            );
+            let _ = child.kill();
+            let _ = child.wait();
+            log_info!("目标程序已终止", "Target program killed");
+        }
+        Err(e) => log_error!(
+            "检查目标程序状态时出错: {}",
+            "Error checking target program status: {}",
+            e
+        ),
+    }
+
+    say!(
+        "验证完成！总共放置了 {} 个方块",
+        "Check finished! {} pieces placed in total",
+        current_idx
+    );
+    say!("最终分数: {}", "Final score: {}", board.get_score());
+    for (name, count, points) in stats.components() {
+        if points != 0 {
+            say!(
+                "  {}: {} 次, {} 分",
+                "  {}: {} times, {} points",
+                name,
+                count,
+                points
+            );
+        }
+    }
+    debug_assert_eq!(stats.total(), board.get_score());
+    if let Some(summary) = progress.summary() {
+        say!("{}: {}", "{}: {}", options.mode.to_spec(), summary);
+    }
+    match finish {
+        Finish::Completed => say!("结果: 完成", "Result: completed"),
+        Finish::TopOut { at } => say!(
+            "结果: 第{}个方块无处可放",
+            "Result: topped out at piece {}",
+            at
+        ),
+        Finish::Resigned { at } => say!("结果: 第{}个方块认输", "Result: resigned at piece {}", at),
+        Finish::Fault { at } => say!("结果: 第{}个方块出错", "Result: fault at piece {}", at),
+    }
+    if options.board_every.is_some() {
+        match first_desync {
+            Some(piece) => say!(
+                "棋盘首次不同步于第{}个方块之后",
+                "Boards first went out of sync after piece {}",
+                piece
+            ),
+            None => say!("棋盘始终同步", "Boards stayed in sync"),
+        }
+    }
+    if options.legal_count {
+        say!(
+            "合法位置数不一致的方块: {}",
+            "Pieces with a differing legal placement count: {}",
+            legal_mismatches
+        );
+    }
+
+    if let Some(image) = &options.image {
+        let title = format!("final, {} pieces", current_idx);
+        match save_board(&board, &title, image) {
+            Ok(()) => log_info!(
+                "最终棋盘已保存到 {}",
+                "Final board saved to {}",
+                image.display()
+            ),
+            Err(e) => log::error!("{}", e),
+        }
+    }
+
+    CheckOutcome {
+        seed,
+        finish,
+        stats,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks a fake program that ignores its input and prints `script`'s
+    /// output, on a fixed sequence.
+    fn check_script(script: &str, time_limit: f64) -> CheckOutcome {
+        let mut command = Command::new("sh");
+        command.args(["-c", script]);
+        let options = CheckOptions {
+            rotation: RotationSystem::Simple,
+            pieces: 20,
+            seed: Some(1),
+            time_limit,
+            ..CheckOptions::default()
+        };
+        check_command(command, options)
+    }
+
+    #[test]
+    fn time_limit_completes_the_game() {
+        // Every piece goes to the left wall, slowly enough that the limit
+        // comes before the stack tops out
+        let outcome = check_script("while true; do echo 0 0; echo 0; sleep 0.2; done", 0.5);
+        assert_eq!(outcome.finish, Finish::Completed);
+        assert!((1..20).contains(&outcome.stats.pieces));
+    }
+
+    #[test]
+    fn slow_move_is_a_fault() {
+        let mut command = Command::new("sh");
+        command.args(["-c", "echo 0 0; echo 0; sleep 5"]);
+        let options = CheckOptions {
+            rotation: RotationSystem::Simple,
+            pieces: 20,
+            seed: Some(1),
+            move_time: 0.2,
+            ..CheckOptions::default()
+        };
+        let outcome = check_command(command, options);
+        assert_eq!(outcome.finish, Finish::Fault { at: 2 });
+        assert_eq!(outcome.stats.pieces, 1);
+    }
+
+    #[test]
+    fn garbage_output_is_a_fault() {
+        let outcome = check_script("echo hello; sleep 5", 10.0);
+        assert_eq!(outcome.finish, Finish::Fault { at: 1 });
+        assert_eq!(outcome.stats.pieces, 0);
+    }
+
+    #[test]
+    fn early_exit_is_a_fault_at_the_next_piece() {
+        let outcome = check_script("echo 0 0; echo 0", 10.0);
+        assert_eq!(outcome.finish, Finish::Fault { at: 2 });
+        assert_eq!(outcome.stats.pieces, 1);
+    }
+
+    #[test]
+    fn exit_before_the_score_is_a_fault() {
+        let outcome = check_script("echo 0 0", 10.0);
+        assert_eq!(outcome.finish, Finish::Fault { at: 1 });
+        assert_eq!(outcome.stats.pieces, 0);
+    }
+}
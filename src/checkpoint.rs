@@ -0,0 +1,472 @@
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+use crate::board::FEATURES;
+use crate::dimensions::Dimensions;
+use crate::episode::MAX_PIECES;
+use crate::evaluator::ModelKind;
+use crate::garbage::GarbageConfig;
+use crate::i18n::tr;
+use crate::logging::log_error;
+use crate::mode::GameMode;
+use crate::moves::Moves;
+use crate::piece::BuiltinSet;
+use crate::policy::Visibility;
+use crate::scoring::ScoringRules;
+use crate::train::POPULATION_GAMES;
+use crate::versus::{AttackTable, Objective};
+
+/// The search distribution at the end of a generation, in weight space:
+/// samples are drawn from N(mean, sigma^2 * cov).
+pub struct Checkpoint {
+    pub sigma: f64,
+    pub mean: Vec<f64>,
+    pub cov: Vec<Vec<f64>>,
+    /// Where the sampler of the run that saved it stands, `None` for a
+    /// distribution saved outside of a run and in older files.
+    pub sampler: Option<SamplerState>,
+}
+
+/// The state of the random streams of a run after some generation. The
+/// sampler is a ChaCha stream private to the cmaes crate, so it is saved
+/// as the seed it was built with and the generations drawn from it since,
+/// which is all it takes to draw the same samples again. The games need
+/// nothing: each point's games are seeded from the run seed and the point
+/// alone, whichever rayon thread plays them, see `rollout_seed`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SamplerState {
+    /// The run seed plus the number of rollbacks, as the sampler of each
+    /// rollback is seeded.
+    pub seed: u64,
+    pub generation: usize,
+}
+
+impl Checkpoint {
+    pub fn save(&self, path: &Path) {
+        let row = |values: &[f64]| {
+            values
+                .iter()
+                .map(|v| v.to_string())
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        let mut text = tr!("# CMA-ES 检查点\n", "# CMA-ES checkpoint\n");
+        text.push_str(&format!("dim {}\n", self.mean.len()));
+        text.push_str(&format!("sigma {}\n", self.sigma));
+        text.push_str(&format!("mean {}\n", row(&self.mean)));
+        for r in &self.cov {
+            text.push_str(&format!("cov {}\n", row(r)));
+        }
+        if let Some(sampler) = self.sampler {
+            text.push_str(&format!(
+                "sampler {} {}\n",
+                sampler.seed, sampler.generation
+            ));
+        }
+        if let Err(e) = fs::write(path, text) {
+            log_error!(
+                "无法保存检查点 {}: {}",
+                "Cannot save checkpoint {}: {}",
+                path.display(),
+                e
+            );
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut dim = None;
+        let mut sigma = None;
+        let mut mean = None;
+        let mut cov = Vec::new();
+        let mut sampler = None;
+
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, rest) = line.split_once(' ').unwrap_or((line, ""));
+            let values = || -> Result<Vec<f64>, String> {
+                rest.split_whitespace()
+                    .map(|v| {
+                        v.parse()
+                            .map_err(|_| tr!("无效的数值: {}", "Invalid number: {}", v))
+                    })
+                    .collect()
+            };
+            match key {
+                "dim" => {
+                    dim = Some(
+                        rest.trim()
+                            .parse()
+                            .map_err(|_| tr!("无效的维度", "Invalid dimension"))?,
+                    )
+                }
+                "sigma" => {
+                    sigma = Some(
+                        rest.trim()
+                            .parse()
+                            .map_err(|_| tr!("无效的步长", "Invalid step size"))?,
+                    )
+                }
+                "mean" => mean = Some(values()?),
+                "cov" => cov.push(values()?),
+                "sampler" => {
+                    let invalid = || tr!("无效的采样器状态", "Invalid sampler state");
+                    let (seed, generation) = rest.trim().split_once(' ').ok_or_else(invalid)?;
+                    sampler = Some(SamplerState {
+                        seed: seed.parse().map_err(|_| invalid())?,
+                        generation: generation.trim().parse().map_err(|_| invalid())?,
+                    });
+                }
+                _ => return Err(tr!("无法识别的行: {}", "Unrecognized line: {}", line)),
+            }
+        }
+
+        let dim: usize = dim.ok_or_else(|| tr!("缺少 dim", "Missing dim"))?;
+        let mean = mean.ok_or_else(|| tr!("缺少 mean", "Missing mean"))?;
+        if mean.len() != dim || cov.len() != dim || cov.iter().any(|r| r.len() != dim) {
+            return Err(tr!(
+                "均值或协方差的维度与 {} 不符",
+                "Mean or covariance does not match dimension {}",
+                dim
+            ));
+        }
+        Ok(Checkpoint {
+            sigma: sigma.ok_or_else(|| tr!("缺少 sigma", "Missing sigma"))?,
+            mean,
+            cov,
+            sampler,
+        })
+    }
+
+    /// Loads a checkpoint file, or `checkpoint.txt` inside a run directory.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let path = if path.is_dir() {
+            path.join("checkpoint.txt")
+        } else {
+            path.to_path_buf()
+        };
+        let text = fs::read_to_string(&path)
+            .map_err(|e| tr!("无法读取 {}: {}", "Cannot read {}: {}", path.display(), e))?;
+        Self::parse(&text)
+    }
+}
+
+/// Affine map from the space CMA-ES searches to weight space,
+/// `w = origin + transform * z`. Since the crate always starts from an
+/// identity covariance, a warm start is done by whitening: the previous
+/// run's distribution becomes N(0, I) in `z`.
+#[derive(Debug, Clone, Copy)]
+pub struct WarmStart {
+    origin: [f64; FEATURES],
+    transform: [[f64; FEATURES]; FEATURES],
+}
+
+impl Default for WarmStart {
+    fn default() -> Self {
+        let mut transform = [[0.0; FEATURES]; FEATURES];
+        for i in 0..FEATURES {
+            transform[i][i] = 1.0;
+        }
+        WarmStart {
+            origin: [0.0; FEATURES],
+            transform,
+        }
+    }
+}
+
+impl WarmStart {
+    /// Starts from `checkpoint` for the features both runs share, matched
+    /// by index. Features the checkpoint does not know start at zero with
+    /// unit variance, as in a fresh run.
+    pub fn from_checkpoint(checkpoint: &Checkpoint) -> Result<Self, String> {
+        let shared = checkpoint.mean.len().min(FEATURES);
+        let mut origin = [0.0; FEATURES];
+        let mut cov = [[0.0; FEATURES]; FEATURES];
+        for i in 0..FEATURES {
+            cov[i][i] = 1.0;
+        }
+        for i in 0..shared {
+            origin[i] = checkpoint.mean[i];
+            for j in 0..shared {
+                cov[i][j] = checkpoint.sigma.powi(2) * checkpoint.cov[i][j];
+            }
+        }
+        let transform = cholesky(&cov).ok_or_else(|| {
+            tr!(
+                "检查点的协方差不是正定矩阵",
+                "Checkpoint covariance is not positive definite"
+            )
+        })?;
+        Ok(WarmStart { origin, transform })
+    }
+
+    pub fn to_weights(&self, z: &[f64]) -> [f64; FEATURES] {
+        let mut w = self.origin;
+        for i in 0..FEATURES {
+            for j in 0..=i {
+                w[i] += self.transform[i][j] * z[j];
+            }
+        }
+        w
+    }
+
+    /// Expresses a distribution over `z` in weight space, for saving.
+    pub fn checkpoint(
+        &self,
+        sigma: f64,
+        mean: &[f64],
+        cov: impl Fn(usize, usize) -> f64,
+    ) -> Checkpoint {
+        let t = &self.transform;
+        // cov_w = T * cov_z * T^T
+        let mut tc = [[0.0; FEATURES]; FEATURES];
+        for i in 0..FEATURES {
+            for j in 0..FEATURES {
+                tc[i][j] = (0..FEATURES).map(|k| t[i][k] * cov(k, j)).sum();
+            }
+        }
+        let cov_w = (0..FEATURES)
+            .map(|i| {
+                (0..FEATURES)
+                    .map(|j| (0..FEATURES).map(|k| tc[i][k] * t[j][k]).sum())
+                    .collect()
+            })
+            .collect();
+        Checkpoint {
+            sigma,
+            mean: self.to_weights(mean).to_vec(),
+            cov: cov_w,
+            sampler: None,
+        }
+    }
+}
+
+/// Lower triangular `L` with `L * L^T = a`, or `None` if `a` is not
+/// positive definite.
+fn cholesky(a: &[[f64; FEATURES]; FEATURES]) -> Option<[[f64; FEATURES]; FEATURES]> {
+    let mut l = [[0.0; FEATURES]; FEATURES];
+    for i in 0..FEATURES {
+        for j in 0..=i {
+            let sum: f64 = (0..j).map(|k| l[i][k] * l[j][k]).sum();
+            if i == j {
+                let d = a[i][i] - sum;
+                if d <= 0.0 || !d.is_finite() {
+                    return None;
+                }
+                l[i][j] = d.sqrt();
+            } else {
+                l[i][j] = (a[i][j] - sum) / l[j][j];
+            }
+        }
+    }
+    Some(l)
+}
+
+/// Every fitness value of a run, keyed by the exact bits of the sampled
+/// point, plus the seed of the CMA-ES sampler. The crate keeps its sampler
+/// and evolution paths private, so a run is resumed by starting over with
+/// the same seed and answering the logged points from here: the search
+/// then retraces the original run bit for bit up to where the log ends.
+///
+/// The file is `evaluations.txt` in the run directory:
+///
+/// ```text
+/// seed <u64>
+/// population <n>
+/// games <n>
+/// max_pieces <n>
+/// pieces tetromino|pentomino
+/// randomizer uniform|bag|history|file:<path>
+/// hold true|false
+/// visibility current|next|expected
+/// moves drop|full
+/// board <width>x<height>
+/// garbage off|every=<n>,lines=<k>
+/// mode endless|dig=<rows>|sprint=<lines>|marathon=<lines per level>
+/// objective score|wins|damage
+/// attack <rows for 1 line>,<rows for 2 lines>,...
+/// model linear|mlp|bonus
+/// scoring <rules file lines, separated by ;>
+/// <point as hex f64 bits, space separated> <fitness as hex f64 bits>
+/// ```
+pub struct EvaluationLog {
+    pub seed: u64,
+    pub population: usize,
+    /// Games per fitness evaluation. Logs from before it was recorded
+    /// played `POPULATION_GAMES`.
+    pub games: usize,
+    /// Cap on the length of each game, `MAX_PIECES` in older logs.
+    pub max_pieces: usize,
+    /// Tetrominoes in older logs.
+    pub pieces: BuiltinSet,
+    /// The `--randomizer` spec, `uniform` in older logs.
+    pub randomizer: String,
+    /// Whether the games had a hold slot, never in older logs.
+    pub hold: bool,
+    /// What the policy saw of the queue, `current` in older logs.
+    pub visibility: Visibility,
+    /// The placements the policy chose from, `drop` in older logs.
+    pub moves: Moves,
+    /// Size of the board, the default in older logs.
+    pub dimensions: Dimensions,
+    /// Garbage pushed into the games, none in older logs.
+    pub garbage: Option<GarbageConfig>,
+    /// What the games were played for, endless in older logs.
+    pub mode: GameMode,
+    /// What the candidates were ranked by, their score in older logs.
+    pub objective: Objective,
+    /// The attack table of the matches, the default in older logs.
+    pub attack: AttackTable,
+    /// What the points were the parameters of, weights in older logs.
+    pub model: ModelKind,
+    /// How the games scored, by the default rules in older logs.
+    pub scoring: ScoringRules,
+    pub values: HashMap<Vec<u64>, f64>,
+    /// Every fitness in the order the run logged it, `population` to a
+    /// generation.
+    pub history: Vec<f64>,
+}
+
+impl EvaluationLog {
+    pub fn key(point: &[f64]) -> Vec<u64> {
+        point.iter().map(|v| v.to_bits()).collect()
+    }
+
+    /// Starts a log at `path` with the header of this one; its values
+    /// are not written.
+    pub fn create(&self, path: &Path) -> Result<(), String> {
+        fs::write(
+            path,
+            format!(
+                "seed {}\npopulation {}\ngames {}\nmax_pieces {}\npieces {}\nrandomizer {}\nhold {}\nvisibility {}\nmoves {}\nboard {}\ngarbage {}\nmode {}\nobjective {}\nattack {}\nmodel {}\nscoring {}\n",
+                self.seed,
+                self.population,
+                self.games,
+                self.max_pieces,
+                self.pieces.name(),
+                self.randomizer,
+                self.hold,
+                self.visibility.name(),
+                self.moves.name(),
+                self.dimensions,
+                self.garbage.map_or("off".to_string(), |g| g.to_spec()),
+                self.mode.to_spec(),
+                self.objective.name(),
+                self.attack.to_spec(),
+                self.model.name(),
+                self.scoring.to_text().trim_end().replace('\n', ";")
+            ),
+        )
+        .map_err(|e| tr!("无法写入 {}: {}", "Cannot write {}: {}", path.display(), e))
+    }
+
+    /// Appends the evaluations of one generation.
+    pub fn append(path: &Path, evaluations: &[(Vec<u64>, f64)]) -> Result<(), String> {
+        let mut text = String::new();
+        for (key, value) in evaluations {
+            for bits in key {
+                text.push_str(&format!("{:016x} ", bits));
+            }
+            text.push_str(&format!("{:016x}\n", value.to_bits()));
+        }
+        OpenOptions::new()
+            .append(true)
+            .open(path)
+            .and_then(|mut file| file.write_all(text.as_bytes()))
+            .map_err(|e| tr!("无法写入 {}: {}", "Cannot write {}: {}", path.display(), e))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| tr!("无法读取 {}: {}", "Cannot read {}: {}", path.display(), e))?;
+        let mut seed = None;
+        let mut population = None;
+        let mut games = POPULATION_GAMES;
+        let mut max_pieces = MAX_PIECES;
+        let mut pieces = BuiltinSet::default();
+        let mut randomizer = "uniform".to_string();
+        let mut hold = false;
+        let mut visibility = Visibility::default();
+        let mut moves = Moves::default();
+        let mut dimensions = Dimensions::default();
+        let mut garbage = None;
+        let mut mode = GameMode::default();
+        let mut objective = Objective::default();
+        let mut attack = AttackTable::default();
+        let mut model = ModelKind::default();
+        let mut scoring = ScoringRules::default();
+        let mut values = HashMap::new();
+        let mut history = Vec::new();
+        for line in text.lines() {
+            let invalid = || tr!("无法识别的行: {}", "Unrecognized line: {}", line);
+            match line.split_once(' ') {
+                Some(("seed", s)) => seed = Some(s.trim().parse().map_err(|_| invalid())?),
+                Some(("population", n)) => {
+                    population = Some(n.trim().parse().map_err(|_| invalid())?)
+                }
+                Some(("games", n)) => games = n.trim().parse().map_err(|_| invalid())?,
+                Some(("max_pieces", n)) => max_pieces = n.trim().parse().map_err(|_| invalid())?,
+                Some(("pieces", name)) => {
+                    pieces = BuiltinSet::parse(name.trim()).ok_or_else(invalid)?
+                }
+                Some(("randomizer", spec)) => randomizer = spec.trim().to_string(),
+                Some(("hold", flag)) => hold = flag.trim().parse().map_err(|_| invalid())?,
+                Some(("visibility", name)) => {
+                    visibility = Visibility::parse(name.trim()).ok_or_else(invalid)?
+                }
+                Some(("moves", name)) => moves = Moves::parse(name.trim()).ok_or_else(invalid)?,
+                Some(("board", size)) => dimensions = Dimensions::parse(size.trim())?,
+                Some(("garbage", spec)) => {
+                    garbage = match spec.trim() {
+                        "off" => None,
+                        spec => Some(GarbageConfig::parse(spec)?),
+                    }
+                }
+                Some(("mode", spec)) => mode = GameMode::parse(spec.trim())?,
+                Some(("objective", name)) => {
+                    objective = Objective::parse(name.trim()).ok_or_else(invalid)?
+                }
+                Some(("attack", spec)) => attack = AttackTable::parse(spec.trim())?,
+                Some(("model", name)) => {
+                    model = ModelKind::parse(name.trim()).ok_or_else(invalid)?
+                }
+                Some(("scoring", rules)) => {
+                    scoring = ScoringRules::parse(&rules.replace(';', "\n"))?
+                }
+                _ => {
+                    let mut bits = line
+                        .split_whitespace()
+                        .map(|h| u64::from_str_radix(h, 16).map_err(|_| invalid()))
+                        .collect::<Result<Vec<u64>, String>>()?;
+                    let value = f64::from_bits(bits.pop().ok_or_else(invalid)?);
+                    values.insert(bits, value);
+                    history.push(value);
+                }
+            }
+        }
+        Ok(EvaluationLog {
+            seed: seed.ok_or_else(|| tr!("缺少 seed", "Missing seed"))?,
+            population: population.ok_or_else(|| tr!("缺少 population", "Missing population"))?,
+            games,
+            max_pieces,
+            pieces,
+            randomizer,
+            hold,
+            visibility,
+            moves,
+            dimensions,
+            garbage,
+            mode,
+            objective,
+            attack,
+            model,
+            scoring,
+            values,
+            history,
+        })
+    }
+}
@@ -0,0 +1,263 @@
+//! Short games of every new best during training, to look back on how the
+//! policy's style changed over a run.
+//!
+//! Whenever the hall of fame gets a new leader, one game is played with it
+//! on a background thread, always on the pieces of the first validation
+//! game, and written to `clips/gen-<generation>.txt` in the run directory:
+//!
+//! ```text
+//! # generation 12, validation score 81234.50, clip score 4200
+//! # weights [1464772.166456, ...]
+//! T 0 4
+//! ```
+//!
+//! followed by one `<letter> <rotation> <x>` line per placement in the
+//! `simple` rotation system, which is also how `sandbox` places a piece:
+//! a tetromino clip can be piped into it and stepped back with `undo`.
+//! With `--moves full` the lines end with the row the piece's bottom row
+//! rests on, which `sandbox` takes as well, since a tucked piece is not
+//! where it drops to, and then with `spin` for a T-spin. With
+//! `--clips gif` every clip also gets a GIF next to it.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::path::PathBuf;
+use std::thread::{self, JoinHandle};
+
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+
+use crate::board::{Board, FEATURES, Placement, Spin};
+use crate::generator::Randomizer;
+use crate::hold::HoldSlot;
+use crate::i18n::tr;
+use crate::logging::{log_debug, log_warn};
+use crate::moves::Moves;
+use crate::piece::BuiltinSet;
+use crate::policy::{Visibility, best_move_in, choose_in};
+use crate::render::save_boards_gif;
+use crate::train::{VALIDATION_SEED, move_with_hold};
+use crate::weights::format_weights;
+
+/// Pieces after which a clip stops. Long enough to show how the policy
+/// builds and clears, short enough to watch.
+pub const CLIP_PIECES: usize = 200;
+
+/// What is saved for every new best.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Clips {
+    Off,
+    /// The moves, as text.
+    #[default]
+    Replay,
+    /// The moves and a GIF of the game.
+    Gif,
+}
+
+impl Clips {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "off" => Some(Clips::Off),
+            "replay" => Some(Clips::Replay),
+            "gif" => Some(Clips::Gif),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Clips::Off => "off",
+            Clips::Replay => "replay",
+            Clips::Gif => "gif",
+        }
+    }
+}
+
+/// A clip: the placements with their pieces and the boards after each of
+/// them.
+pub struct Clip {
+    pub moves: Vec<(usize, Placement)>,
+    pub boards: Vec<Board>,
+}
+
+impl Clip {
+    pub fn score(&self) -> i64 {
+        self.boards.last().map_or(0, Board::get_score)
+    }
+}
+
+/// Plays the clip game of `weights` as the training games are played,
+/// with a hold slot or seeing `visibility` of the queue, choosing from
+/// `moves`.
+pub fn play_clip(
+    weights: &[f64; FEATURES],
+    pieces: BuiltinSet,
+    randomizer: &Randomizer,
+    hold: bool,
+    visibility: Visibility,
+    moves: Moves,
+) -> Clip {
+    let set = pieces.set();
+    let generator = &mut *randomizer.generator(set, StdRng::seed_from_u64(VALIDATION_SEED));
+    let mut board = Board::new();
+    let mut slot = HoldSlot::new();
+    let mut next = generator.next_piece(&board);
+    let mut clip = Clip {
+        moves: Vec::new(),
+        boards: vec![board.clone()],
+    };
+
+    for _ in 0..CLIP_PIECES {
+        let placed = if moves.is_full() {
+            let piece = std::mem::replace(&mut next, generator.next_piece(&board));
+            best_move_in(&board, set, piece, weights, moves).map(|(placement, _)| {
+                let spin = board.apply_at_in(set, piece, placement).unwrap().spin;
+                // The turn only needs replaying where it made a spin
+                let turned = spin != Spin::None;
+                (
+                    piece,
+                    Placement {
+                        turned,
+                        ..placement
+                    },
+                )
+            })
+        } else {
+            let action = if hold {
+                move_with_hold(&board, set, weights, generator, &mut next, &mut slot)
+            } else {
+                let piece = std::mem::replace(&mut next, generator.next_piece(&board));
+                choose_in(&board, set, piece, next, weights, visibility)
+                    .map(|(rotation, x, _)| (piece, rotation, x))
+            };
+            action.map(|(piece, rotation, x)| {
+                let placed = board.apply_in(set, piece, x, rotation).unwrap();
+                // The piece's bottom row is the lowest of its cells
+                let landing_y = placed.piece_cells().iter().map(|&(_, y)| y).min();
+                let placement = Placement {
+                    rotation,
+                    x,
+                    landing_y: landing_y.unwrap_or(0),
+                    turned: false,
+                };
+                (piece, placement)
+            })
+        };
+        let Some(placed) = placed else {
+            break;
+        };
+        clip.moves.push(placed);
+        clip.boards.push(board.clone());
+    }
+    clip
+}
+
+/// Saves a clip of every new best on background threads, so that the
+/// search does not wait for them.
+pub struct ClipRecorder {
+    clips: Clips,
+    dir: PathBuf,
+    pieces: BuiltinSet,
+    randomizer: Randomizer,
+    hold: bool,
+    visibility: Visibility,
+    moves: Moves,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl ClipRecorder {
+    /// A recorder writing into `dir`, playing as the training does.
+    pub fn new(
+        clips: Clips,
+        dir: PathBuf,
+        pieces: BuiltinSet,
+        randomizer: Randomizer,
+        hold: bool,
+        visibility: Visibility,
+        moves: Moves,
+    ) -> Self {
+        ClipRecorder {
+            clips,
+            dir,
+            pieces,
+            randomizer,
+            hold,
+            visibility,
+            moves,
+            threads: Vec::new(),
+        }
+    }
+
+    /// Starts recording the clip of a new best found in `generation`.
+    pub fn record(&mut self, generation: usize, score: f64, weights: [f64; FEATURES]) {
+        if self.clips == Clips::Off {
+            return;
+        }
+        if let Err(e) = fs::create_dir_all(&self.dir) {
+            log_warn!(
+                "无法创建片段目录 {}: {}",
+                "Cannot create the clip directory {}: {}",
+                self.dir.display(),
+                e
+            );
+            return;
+        }
+        let (clips, pieces, randomizer, hold, visibility, moves) = (
+            self.clips,
+            self.pieces,
+            self.randomizer.clone(),
+            self.hold,
+            self.visibility,
+            self.moves,
+        );
+        let path = self.dir.join(format!("gen-{:04}.txt", generation));
+        self.threads.push(thread::spawn(move || {
+            let clip = play_clip(&weights, pieces, &randomizer, hold, visibility, moves);
+            let mut text = tr!(
+                "# 第 {} 代, 验证分数 {:.2}, 片段分数 {}\n",
+                "# generation {}, validation score {:.2}, clip score {}\n",
+                generation,
+                score,
+                clip.score()
+            );
+            let _ = writeln!(text, "# weights {}", format_weights(&weights));
+            let set = pieces.set();
+            for &(piece, p) in &clip.moves {
+                let _ = match moves {
+                    Moves::Drop => writeln!(text, "{} {} {}", set.letter(piece), p.rotation, p.x),
+                    Moves::Full | Moves::FullHalfTurns => writeln!(
+                        text,
+                        "{} {} {} {}{}",
+                        set.letter(piece),
+                        p.rotation,
+                        p.x,
+                        p.landing_y,
+                        if p.turned { " spin" } else { "" }
+                    ),
+                };
+            }
+            let saved = fs::write(&path, text)
+                .map_err(|e| tr!("无法写入 {}: {}", "Cannot write {}: {}", path.display(), e))
+                .and_then(|()| match clips {
+                    Clips::Gif => save_boards_gif(&clip.boards, &path.with_extension("gif")),
+                    _ => Ok(()),
+                });
+            match saved {
+                Ok(()) => log_debug!(
+                    "第 {} 代的片段已保存到 {}",
+                    "Clip of generation {} saved to {}",
+                    generation,
+                    path.display()
+                ),
+                Err(e) => log_warn!("无法保存片段: {}", "Cannot save the clip: {}", e),
+            }
+        }));
+    }
+
+    /// Waits for the clips still being recorded.
+    pub fn finish(&mut self) {
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,298 @@
+use std::fs;
+
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+
+use crate::board::{Board, FEATURES};
+use crate::i18n::{say, tr};
+use crate::logging::{log_error, log_info, log_warn};
+use crate::piece::get_random_piece;
+use crate::policy::best_action;
+use crate::quantize::{QuantizedWeights, best_action_quantized};
+use crate::weights::{format_weights, parse_weights};
+
+/// How the policy evaluates placements in a reference game.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Mode {
+    /// The usual `f64` evaluation. Sums and `exp` may round differently
+    /// on other platforms or compilers.
+    #[default]
+    Float,
+    /// The 16-bit fixed-point path of `quantize`, which involves no
+    /// floating point at run time and plays the same everywhere.
+    Integer,
+}
+
+impl Mode {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "float" => Some(Mode::Float),
+            "integer" => Some(Mode::Integer),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Mode::Float => "float",
+            Mode::Integer => "integer",
+        }
+    }
+}
+
+/// Bits per weight on the integer path. At 16 bits the built-in weights
+/// play exactly like the float policy.
+const INTEGER_BITS: u32 = 16;
+
+/// One decision of the reference game: the placement and the raw bits of
+/// its evaluation, so that a rounding difference shows up even when it
+/// does not change the move.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Decision {
+    pub rotation: usize,
+    pub x: usize,
+    pub bits: u64,
+}
+
+impl Decision {
+    /// Parses a `<rotation> <x> <evaluation bits in hex>` line.
+    fn parse(line: &str) -> Option<Self> {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        match parts.as_slice() {
+            [rotation, x, bits] => Some(Decision {
+                rotation: rotation.parse().ok()?,
+                x: x.parse().ok()?,
+                bits: u64::from_str_radix(bits, 16).ok()?,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// A game stored for comparison. The file is plain text:
+///
+/// ```text
+/// mode float
+/// seed 42
+/// pieces 500
+/// weights [1464772.166456, ...]
+/// score 20500
+/// 0 4 c1a3...
+/// ```
+///
+/// followed by one `<rotation> <x> <evaluation bits in hex>` line per
+/// placement, until the sequence ends or the policy tops out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reference {
+    pub mode: Mode,
+    pub seed: u64,
+    pub pieces: usize,
+    pub weights: [f64; FEATURES],
+    pub score: i64,
+    pub decisions: Vec<Decision>,
+}
+
+impl Reference {
+    /// Plays the game the reference describes on this machine.
+    pub fn play(
+        mode: Mode,
+        seed: u64,
+        pieces: usize,
+        weights: &[f64; FEATURES],
+    ) -> (i64, Vec<Decision>) {
+        let quantized = QuantizedWeights::quantize(weights, INTEGER_BITS);
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut board = Board::new();
+        let mut decisions = Vec::new();
+        for _ in 0..pieces {
+            let piece = get_random_piece(&mut rng);
+            let decision =
+                match mode {
+                    Mode::Float => {
+                        best_action(&board, piece, weights).map(|(rotation, x, score)| Decision {
+                            rotation,
+                            x,
+                            bits: score.to_bits(),
+                        })
+                    }
+                    Mode::Integer => best_action_quantized(&board, piece, &quantized).map(
+                        |(rotation, x, score)| Decision {
+                            rotation,
+                            x,
+                            bits: score as u64,
+                        },
+                    ),
+                };
+            let Some(decision) = decision else {
+                break;
+            };
+            board.apply(piece, decision.x, decision.rotation).unwrap();
+            decisions.push(decision);
+        }
+        (board.get_score(), decisions)
+    }
+
+    /// Plays and records a game. The weights are written with a fixed
+    /// number of decimals, so the game is played with the weights as they
+    /// will read back.
+    pub fn record(mode: Mode, seed: u64, pieces: usize, weights: &[f64; FEATURES]) -> Self {
+        let weights = parse_weights(&format_weights(weights))
+            .ok()
+            .and_then(|w| w.first().copied())
+            .unwrap_or(*weights);
+        let (score, decisions) = Self::play(mode, seed, pieces, &weights);
+        Reference {
+            mode,
+            seed,
+            pieces,
+            weights,
+            score,
+            decisions,
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = format!(
+            "mode {}\nseed {}\npieces {}\nweights {}\nscore {}\n",
+            self.mode.name(),
+            self.seed,
+            self.pieces,
+            format_weights(&self.weights),
+            self.score
+        );
+        for d in &self.decisions {
+            text.push_str(&format!("{} {} {:016x}\n", d.rotation, d.x, d.bits));
+        }
+        text
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut lines = text.lines().map(str::trim).filter(|l| !l.is_empty());
+        let mut header = |key: &str| {
+            lines
+                .next()
+                .and_then(|l| l.strip_prefix(key))
+                .map(str::trim)
+                .ok_or_else(|| tr!("缺少 {} 行", "Missing {} line", key))
+        };
+        let mode = header("mode")?;
+        let mode =
+            Mode::parse(mode).ok_or_else(|| tr!("未知模式: {}", "Unknown mode: {}", mode))?;
+        let number = |v: &str| {
+            v.parse::<i64>()
+                .map_err(|_| tr!("无效的数字: {}", "Invalid number: {}", v))
+        };
+        let seed = number(header("seed")?)? as u64;
+        let pieces = number(header("pieces")?)? as usize;
+        let weights = *parse_weights(header("weights")?)?
+            .first()
+            .ok_or_else(|| tr!("缺少权重", "Missing weights"))?;
+        let score = number(header("score")?)?;
+
+        let decisions = lines
+            .map(|line| {
+                Decision::parse(line)
+                    .ok_or_else(|| tr!("无法识别的行: {}", "Unrecognized line: {}", line))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Reference {
+            mode,
+            seed,
+            pieces,
+            weights,
+            score,
+            decisions,
+        })
+    }
+}
+
+/// Records a reference game to `path`.
+pub fn record(path: &str, mode: Mode, seed: u64, pieces: usize, weights: &[f64; FEATURES]) {
+    let reference = Reference::record(mode, seed, pieces, weights);
+    match fs::write(path, reference.to_text()) {
+        Ok(()) => log_info!(
+            "参考对局 ({}, {} 步, 分数 {}) 已保存到 {}",
+            "Reference game ({}, {} moves, score {}) saved to {}",
+            mode.name(),
+            reference.decisions.len(),
+            reference.score,
+            path
+        ),
+        Err(e) => log_error!(
+            "无法保存参考对局 {}: {}",
+            "Cannot save reference game {}: {}",
+            path,
+            e
+        ),
+    }
+}
+
+/// Replays the reference game in `path` and compares it move for move.
+/// Returns whether every placement matched; evaluation bits that differ
+/// under the same placements are reported but do not fail the check.
+pub fn verify(path: &str) -> bool {
+    let reference = match fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|text| Reference::parse(&text))
+    {
+        Ok(reference) => reference,
+        Err(e) => {
+            log_error!(
+                "无法读取参考对局 {}: {}",
+                "Cannot read reference game {}: {}",
+                path,
+                e
+            );
+            return false;
+        }
+    };
+    let (score, decisions) = Reference::play(
+        reference.mode,
+        reference.seed,
+        reference.pieces,
+        &reference.weights,
+    );
+
+    let mut bits_differ = None;
+    for (i, (expected, actual)) in reference.decisions.iter().zip(&decisions).enumerate() {
+        if (expected.rotation, expected.x) != (actual.rotation, actual.x) {
+            say!(
+                "[不一致] 第 {} 步: 参考为 (旋转={}, 位置={}), 本机为 (旋转={}, 位置={})",
+                "[mismatch] move {}: reference (rotation={}, x={}), here (rotation={}, x={})",
+                i + 1,
+                expected.rotation,
+                expected.x,
+                actual.rotation,
+                actual.x
+            );
+            return false;
+        }
+        if expected.bits != actual.bits && bits_differ.is_none() {
+            bits_differ = Some(i);
+        }
+    }
+    if decisions.len() != reference.decisions.len() {
+        say!(
+            "[不一致] 参考对局有 {} 步, 本机为 {} 步",
+            "[mismatch] the reference game has {} moves, here it has {}",
+            reference.decisions.len(),
+            decisions.len()
+        );
+        return false;
+    }
+    if let Some(i) = bits_differ {
+        log_warn!(
+            "第 {} 步起评估值的位不同, 但落点一致; 浮点运算在本机上的舍入与参考不同",
+            "Evaluation bits differ from move {} on although the placements agree; floating point rounds differently here",
+            i + 1
+        );
+    }
+    say!(
+        "[一致] {} 模式, {} 步, 分数 {}",
+        "[match] {} mode, {} moves, score {}",
+        reference.mode.name(),
+        decisions.len(),
+        score
+    );
+    true
+}
@@ -0,0 +1,149 @@
+//! Board sizes picked on the command line. A `Board` takes its size as
+//! const parameters, so the commands are built for the sizes in [`SIZES`]
+//! and [`with_dimensions!`] calls the instance that matches a
+//! [`Dimensions`] read at run time.
+
+use std::fmt;
+
+use crate::board::{BOARD_HEIGHT, BOARD_WIDTH};
+use crate::i18n::tr;
+
+/// The `(width, height)` of every size the commands are built for, the
+/// default first. `with_dimensions!` has an arm for each.
+pub const SIZES: [(usize, usize); 6] = [
+    (BOARD_WIDTH, BOARD_HEIGHT),
+    (10, 20),
+    (10, 22),
+    (10, 24),
+    (12, 20),
+    (12, 24),
+];
+
+/// Width and height of the board a command plays on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Dimensions {
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Default for Dimensions {
+    fn default() -> Self {
+        Dimensions {
+            width: BOARD_WIDTH,
+            height: BOARD_HEIGHT,
+        }
+    }
+}
+
+impl fmt::Display for Dimensions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}", self.width, self.height)
+    }
+}
+
+impl Dimensions {
+    /// The size, if it is one of `SIZES`.
+    pub fn new(width: usize, height: usize) -> Result<Self, String> {
+        if !SIZES.contains(&(width, height)) {
+            let sizes: Vec<String> = SIZES.iter().map(|(w, h)| format!("{}x{}", w, h)).collect();
+            return Err(tr!(
+                "不支持 {}x{} 的棋盘, 可用: {}",
+                "No {}x{} board is built in, available: {}",
+                width,
+                height,
+                sizes.join(", ")
+            ));
+        }
+        Ok(Dimensions { width, height })
+    }
+
+    /// Parses `<width>x<height>`, as `Display` writes it.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let invalid = || tr!("无效的棋盘尺寸: {}", "Invalid board size: {}", text);
+        let (width, height) = text.split_once('x').ok_or_else(invalid)?;
+        let width = width.trim().parse().map_err(|_| invalid())?;
+        let height = height.trim().parse().map_err(|_| invalid())?;
+        Self::new(width, height)
+    }
+
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Takes `--width <n>` and `--height <n>` out of `args`; either one
+    /// left out keeps its default.
+    pub fn extract(args: &mut Vec<String>) -> Result<Self, String> {
+        let mut dimensions = Dimensions::default();
+        let mut i = 0;
+        while i < args.len() {
+            let field = match args[i].as_str() {
+                "--width" => &mut dimensions.width,
+                "--height" => &mut dimensions.height,
+                _ => {
+                    i += 1;
+                    continue;
+                }
+            };
+            let flag = args.remove(i);
+            let value = (i < args.len()).then(|| args.remove(i));
+            *field = value
+                .as_deref()
+                .and_then(|v| v.parse().ok())
+                .ok_or_else(|| tr!("{} 需要一个正整数", "{} expects a positive integer", flag))?;
+        }
+        Self::new(dimensions.width, dimensions.height)
+    }
+}
+
+/// Evaluates `$body` with the consts `$h` and `$w` set to the height and
+/// width of `$dimensions`, which must be one of `SIZES`:
+///
+/// ```
+/// use mortis::board::Board;
+/// use mortis::dimensions::Dimensions;
+/// use mortis::with_dimensions;
+///
+/// let dimensions = Dimensions::new(12, 24).unwrap();
+/// let heights = with_dimensions!(dimensions, |H, W| Board::<H, W>::empty().get_heights().len());
+/// assert_eq!(heights, 12);
+/// ```
+#[macro_export]
+macro_rules! with_dimensions {
+    ($dimensions:expr, |$h:ident, $w:ident| $body:expr) => {{
+        let dimensions: $crate::dimensions::Dimensions = $dimensions;
+        match (dimensions.width, dimensions.height) {
+            (10, 20) => {
+                const $h: usize = 20;
+                const $w: usize = 10;
+                $body
+            }
+            (10, 22) => {
+                const $h: usize = 22;
+                const $w: usize = 10;
+                $body
+            }
+            (10, 24) => {
+                const $h: usize = 24;
+                const $w: usize = 10;
+                $body
+            }
+            (12, 20) => {
+                const $h: usize = 20;
+                const $w: usize = 12;
+                $body
+            }
+            (12, 24) => {
+                const $h: usize = 24;
+                const $w: usize = 12;
+                $body
+            }
+            _ => {
+                const $h: usize = $crate::board::BOARD_HEIGHT;
+                const $w: usize = $crate::board::BOARD_WIDTH;
+                $body
+            }
+        }
+    }};
+}
+
+pub use crate::with_dimensions;
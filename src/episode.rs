@@ -0,0 +1,55 @@
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+
+use crate::board::Board;
+use crate::evaluator::Evaluator;
+use crate::piece::get_random_piece;
+use crate::policy::{Visibility, choose};
+use crate::stats::GameStats;
+
+/// Default cap on the pieces of one game. Good weights seldom top out
+/// before it, so it mostly bounds how long a rollout can take.
+pub const MAX_PIECES: usize = 1_000_000;
+
+/// How `run_episode` plays a game.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EpisodeConfig {
+    pub max_pieces: usize,
+    pub visibility: Visibility,
+}
+
+impl Default for EpisodeConfig {
+    fn default() -> Self {
+        EpisodeConfig {
+            max_pieces: MAX_PIECES,
+            visibility: Visibility::Current,
+        }
+    }
+}
+
+/// Plays one game on the pieces of `StdRng` seeded with `seed` and returns
+/// where its points came from; `GameStats::total` is the score. Nothing
+/// outside the arguments is read or written, so the same inputs always
+/// give the same result, and it builds without the `io` feature. The
+/// sequence does not depend on the visibility: the next piece is drawn
+/// either way.
+pub fn run_episode<E: Evaluator + ?Sized>(
+    policy: &E,
+    seed: u64,
+    config: &EpisodeConfig,
+) -> GameStats {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut board = Board::new();
+    let mut stats = GameStats::default();
+    let mut next = get_random_piece(&mut rng);
+    for _ in 0..config.max_pieces {
+        let piece = next;
+        next = get_random_piece(&mut rng);
+        let Some((rotation, x, _)) = choose(&board, piece, next, policy, config.visibility) else {
+            break;
+        };
+        let placed = board.apply(piece, x, rotation).unwrap();
+        stats.record(&placed);
+    }
+    stats
+}
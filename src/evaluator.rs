@@ -0,0 +1,282 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::board::{BOARD_HEIGHT, BOARD_WIDTH, Board, FEATURES};
+use crate::features::{BONUS_FEATURES, bonus_features};
+use crate::mlp::{MlpEvaluator, PARAMETERS};
+
+/// Rates a placement for the policy; lower is better.
+///
+/// `board` is the position before the placement and `features` are what
+/// `Board::simulate` reports for the position after it. The weight array
+/// is the built-in linear evaluator, so anything that takes an
+/// `&impl Evaluator` also takes `&WEIGHTS` or a trained weight vector.
+///
+/// Evaluators are shared between the rayon workers of training and the
+/// lookahead, hence `Sync`. The parameters are the size of the board they
+/// rate, the default one unless given.
+pub trait Evaluator<const H: usize = BOARD_HEIGHT, const W: usize = BOARD_WIDTH>: Sync {
+    fn evaluate(&self, board: &Board<H, W>, features: &[f64; FEATURES]) -> f64;
+}
+
+/// The linear evaluator: the dot product of the features with the weights.
+impl<const H: usize, const W: usize> Evaluator<H, W> for [f64; FEATURES] {
+    fn evaluate(&self, _board: &Board<H, W>, features: &[f64; FEATURES]) -> f64 {
+        features.iter().zip(self.iter()).map(|(f, w)| f * w).sum()
+    }
+}
+
+/// An evaluator with the features of `features::bonus_features` as
+/// extra linear terms, for boards that score combos, back-to-back and
+/// all clears.
+pub struct BonusTerms<'a, E: ?Sized> {
+    evaluator: &'a E,
+    weights: [f64; BONUS_FEATURES],
+}
+
+impl<'a, E: ?Sized> BonusTerms<'a, E> {
+    pub fn new(evaluator: &'a E, weights: [f64; BONUS_FEATURES]) -> Self {
+        BonusTerms { evaluator, weights }
+    }
+}
+
+impl<const H: usize, const W: usize, E: Evaluator<H, W> + ?Sized> Evaluator<H, W>
+    for BonusTerms<'_, E>
+{
+    fn evaluate(&self, board: &Board<H, W>, features: &[f64; FEATURES]) -> f64 {
+        let bonus = bonus_features(board, features);
+        self.evaluator.evaluate(board, features)
+            + bonus
+                .iter()
+                .zip(self.weights.iter())
+                .map(|(f, w)| f * w)
+                .sum::<f64>()
+    }
+}
+
+/// Parameters of a `Model::Bonus`: the weights, then the weights of the
+/// bonus features.
+pub const BONUS_PARAMETERS: usize = FEATURES + BONUS_FEATURES;
+
+/// The kinds of evaluator training can search the parameters of.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ModelKind {
+    /// A weight vector, see `Board::simulate`.
+    #[default]
+    Linear,
+    /// An `MlpEvaluator`.
+    Mlp,
+    /// A weight vector with `BonusTerms`.
+    Bonus,
+}
+
+impl ModelKind {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "linear" => Some(ModelKind::Linear),
+            "mlp" => Some(ModelKind::Mlp),
+            "bonus" => Some(ModelKind::Bonus),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ModelKind::Linear => "linear",
+            ModelKind::Mlp => "mlp",
+            ModelKind::Bonus => "bonus",
+        }
+    }
+
+    /// Length of the parameter vector of a model of this kind.
+    pub fn parameters(&self) -> usize {
+        match self {
+            ModelKind::Linear => FEATURES,
+            ModelKind::Mlp => PARAMETERS,
+            ModelKind::Bonus => BONUS_PARAMETERS,
+        }
+    }
+}
+
+/// An evaluator of either kind, as a weights file holds them.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Model {
+    Linear([f64; FEATURES]),
+    Mlp(MlpEvaluator),
+    /// Weights followed by the weights of their `BonusTerms`.
+    Bonus([f64; BONUS_PARAMETERS]),
+}
+
+impl Model {
+    pub fn kind(&self) -> ModelKind {
+        match self {
+            Model::Linear(_) => ModelKind::Linear,
+            Model::Mlp(_) => ModelKind::Mlp,
+            Model::Bonus(_) => ModelKind::Bonus,
+        }
+    }
+
+    pub fn parameters(&self) -> &[f64] {
+        match self {
+            Model::Linear(weights) => weights,
+            Model::Mlp(network) => network.parameters(),
+            Model::Bonus(parameters) => parameters,
+        }
+    }
+
+    pub fn parameters_mut(&mut self) -> &mut [f64] {
+        match self {
+            Model::Linear(weights) => weights,
+            Model::Mlp(network) => network.parameters_mut(),
+            Model::Bonus(parameters) => parameters,
+        }
+    }
+}
+
+impl<const H: usize, const W: usize> Evaluator<H, W> for Model {
+    fn evaluate(&self, board: &Board<H, W>, features: &[f64; FEATURES]) -> f64 {
+        match self {
+            Model::Linear(weights) => weights.evaluate(board, features),
+            Model::Mlp(network) => network.evaluate(board, features),
+            Model::Bonus(parameters) => {
+                let (weights, bonus) = parameters.split_at(FEATURES);
+                let weights: &[f64; FEATURES] = weights.try_into().unwrap();
+                BonusTerms::new(weights, bonus.try_into().unwrap()).evaluate(board, features)
+            }
+        }
+    }
+}
+
+/// What to do about a NaN or an infinity in a weight vector or an
+/// evaluation. Comparisons with NaN are false both ways, so left alone
+/// it would quietly win or lose every comparison it takes part in.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum NonFinite {
+    /// Do not play weights with such a value, and only take a placement
+    /// with such an evaluation when nothing else is left.
+    #[default]
+    Skip,
+    /// Replace NaN with 0 in weights and with the worst score in
+    /// evaluations, and infinities with the largest finite value of their
+    /// sign.
+    Clamp,
+    /// Count it like `Skip`, and let the caller stop at the next chance.
+    Error,
+}
+
+impl NonFinite {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "skip" => Some(NonFinite::Skip),
+            "clamp" => Some(NonFinite::Clamp),
+            "error" => Some(NonFinite::Error),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            NonFinite::Skip => "skip",
+            NonFinite::Clamp => "clamp",
+            NonFinite::Error => "error",
+        }
+    }
+}
+
+/// Non-finite values a `NonFiniteGuard` met.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct NonFiniteCounts {
+    /// Weight vectors with at least one.
+    pub weights: usize,
+    /// Evaluations that were one.
+    pub evaluations: usize,
+}
+
+impl NonFiniteCounts {
+    pub fn is_empty(&self) -> bool {
+        self.weights == 0 && self.evaluations == 0
+    }
+}
+
+/// Applies a `NonFinite` mode to weights and evaluations and counts what
+/// it had to fix. Shared by all the workers of a training run.
+#[derive(Debug, Default)]
+pub struct NonFiniteGuard {
+    mode: NonFinite,
+    weights: AtomicUsize,
+    evaluations: AtomicUsize,
+}
+
+impl NonFiniteGuard {
+    pub fn new(mode: NonFinite) -> Self {
+        NonFiniteGuard {
+            mode,
+            ..NonFiniteGuard::default()
+        }
+    }
+
+    pub fn mode(&self) -> NonFinite {
+        self.mode
+    }
+
+    /// Checks `weights`, or any other parameters, before they are played,
+    /// clamping them in `Clamp` mode. Returns whether they may be played.
+    pub fn sanitize(&self, weights: &mut [f64]) -> bool {
+        if weights.iter().all(|w| w.is_finite()) {
+            return true;
+        }
+        self.weights.fetch_add(1, Ordering::Relaxed);
+        if self.mode != NonFinite::Clamp {
+            return false;
+        }
+        for w in weights.iter_mut() {
+            *w = if w.is_nan() {
+                0.0
+            } else {
+                w.clamp(-f64::MAX, f64::MAX)
+            };
+        }
+        true
+    }
+
+    /// `evaluator` with its evaluations checked.
+    pub fn guard<'a, E: ?Sized>(&'a self, evaluator: &'a E) -> Guarded<'a, E> {
+        Guarded {
+            evaluator,
+            guard: self,
+        }
+    }
+
+    /// What was met since the last call.
+    pub fn take(&self) -> NonFiniteCounts {
+        NonFiniteCounts {
+            weights: self.weights.swap(0, Ordering::Relaxed),
+            evaluations: self.evaluations.swap(0, Ordering::Relaxed),
+        }
+    }
+}
+
+/// An evaluator whose NaN and infinite evaluations are counted and
+/// replaced by its `NonFiniteGuard`, see `NonFiniteGuard::guard`.
+pub struct Guarded<'a, E: ?Sized> {
+    evaluator: &'a E,
+    guard: &'a NonFiniteGuard,
+}
+
+impl<const H: usize, const W: usize, E: Evaluator<H, W> + ?Sized> Evaluator<H, W>
+    for Guarded<'_, E>
+{
+    fn evaluate(&self, board: &Board<H, W>, features: &[f64; FEATURES]) -> f64 {
+        let score = self.evaluator.evaluate(board, features);
+        if score.is_finite() {
+            return score;
+        }
+        self.guard.evaluations.fetch_add(1, Ordering::Relaxed);
+        match self.guard.mode {
+            NonFinite::Clamp if score == f64::NEG_INFINITY => -f64::MAX,
+            NonFinite::Clamp => f64::MAX,
+            // Loses to every finite score, so it is only taken when nothing
+            // else is left
+            NonFinite::Skip | NonFinite::Error => f64::INFINITY,
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use std::fmt;
+
+use crate::board::{Board, COUNT_FEATURES, FEATURES};
+
+/// Names of the entries of the flat feature array, in order.
+pub const FEATURE_NAMES: [&str; FEATURES] = [
+    "landing_height",
+    "eroded_piece_cells",
+    "row_transitions",
+    "column_transitions",
+    "holes",
+    "board_wells",
+    "hole_depth",
+    "rows_with_holes",
+    "diversity",
+    "height_rbf_0",
+    "height_rbf_1",
+    "height_rbf_2",
+    "height_rbf_3",
+];
+
+/// The features `Board::simulate` reports, by name. The flat array stays
+/// the form the policy and the weights use; this is for code that wants
+/// to read or print single features without counting indices.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BoardFeatures {
+    /// Row of the highest cell of the placed piece.
+    pub landing_height: f64,
+    /// Cells of the piece in cleared rows, weighted by the line clear
+    /// points per row.
+    pub eroded_piece_cells: f64,
+    /// Filled/empty changes along the rows, walls counting as filled.
+    pub row_transitions: f64,
+    /// Filled/empty changes up the columns, the floor and the top of the
+    /// board counting as filled.
+    pub column_transitions: f64,
+    /// Empty cells with a filled cell somewhere above them.
+    pub holes: f64,
+    /// How far each column lies below the lower of its two neighbours,
+    /// summed over the columns lower than both. Edge columns never count.
+    pub board_wells: f64,
+    /// Distance of every hole below the top of its column, summed.
+    pub hole_depth: f64,
+    /// Rows with at least one hole.
+    pub rows_with_holes: f64,
+    /// Sum of the height differences between neighbouring columns.
+    pub diversity: f64,
+    /// Gaussians of the mean column height centered at `0`, `h/3`, `2h/3`
+    /// and `h` for a board of height `h`.
+    pub height_rbf: [f64; 4],
+}
+
+impl BoardFeatures {
+    pub fn to_array(&self) -> [f64; FEATURES] {
+        [
+            self.landing_height,
+            self.eroded_piece_cells,
+            self.row_transitions,
+            self.column_transitions,
+            self.holes,
+            self.board_wells,
+            self.hole_depth,
+            self.rows_with_holes,
+            self.diversity,
+            self.height_rbf[0],
+            self.height_rbf[1],
+            self.height_rbf[2],
+            self.height_rbf[3],
+        ]
+    }
+
+    /// `(name, value)` for every feature, in array order.
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, f64)> + use<> {
+        FEATURE_NAMES.into_iter().zip(self.to_array())
+    }
+}
+
+impl From<[f64; FEATURES]> for BoardFeatures {
+    fn from(f: [f64; FEATURES]) -> Self {
+        BoardFeatures {
+            landing_height: f[0],
+            eroded_piece_cells: f[1],
+            row_transitions: f[2],
+            column_transitions: f[3],
+            holes: f[4],
+            board_wells: f[5],
+            hole_depth: f[6],
+            rows_with_holes: f[7],
+            diversity: f[8],
+            height_rbf: [f[9], f[10], f[11], f[12]],
+        }
+    }
+}
+
+impl From<BoardFeatures> for [f64; FEATURES] {
+    fn from(features: BoardFeatures) -> Self {
+        features.to_array()
+    }
+}
+
+/// One `name: value` line per feature.
+impl fmt::Display for BoardFeatures {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, value) in self.iter() {
+            writeln!(f, "{:>20}: {:.3}", name, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Number of optional bonus features, see `bonus_features`.
+pub const BONUS_FEATURES: usize = 3;
+
+/// Names of the bonus features, in order.
+pub const BONUS_FEATURE_NAMES: [&str; BONUS_FEATURES] = ["combo", "back_to_back", "all_clear"];
+
+/// The features of a placement on `board` that bear on the bonuses it
+/// scores, from the `features` `simulate` reported for it: the length of
+/// the combo after it, whether `board` is in a back-to-back chain, so
+/// that a difficult clear would keep it up, and whether it leaves the
+/// board empty. They are not among `FEATURES`, so that weights stay what
+/// they are for the boards that score no bonuses; evaluators that want
+/// them add them, as `evaluator::BonusTerms` does.
+pub fn bonus_features<const H: usize, const W: usize>(
+    board: &Board<H, W>,
+    features: &[f64; FEATURES],
+) -> [f64; BONUS_FEATURES] {
+    // Only placements that clear lines erode any of their cells
+    let combo = if features[1] > 0.0 {
+        board.get_combo() + 1
+    } else {
+        0
+    };
+    // The first height Gaussian is exactly 1 when no column has a cell
+    let all_clear = features[COUNT_FEATURES] == 1.0;
+    [
+        combo as f64,
+        board.get_back_to_back() as u8 as f64,
+        all_clear as u8 as f64,
+    ]
+}
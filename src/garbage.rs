@@ -0,0 +1,93 @@
+//! Garbage rows pushed into a game from below, as an opponent's attacks
+//! would send them. The rows come from [`Board::add_garbage`]; this module
+//! decides when and where their holes are.
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::board::Board;
+use crate::i18n::tr;
+
+/// How often garbage arrives and how much of it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GarbageConfig {
+    /// Pieces between two injections.
+    pub every: usize,
+    /// Rows added by one injection. They share a hole, as a single attack
+    /// does in most versus games.
+    pub lines: usize,
+}
+
+impl Default for GarbageConfig {
+    fn default() -> Self {
+        GarbageConfig {
+            every: 10,
+            lines: 1,
+        }
+    }
+}
+
+impl GarbageConfig {
+    /// Parses `every=<n>,lines=<k>`; a key left out keeps its default.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut config = GarbageConfig::default();
+        for part in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let invalid = || {
+                tr!(
+                    "需要 every=<n>,lines=<k>, 实际为 '{}'",
+                    "expected every=<n>,lines=<k>, got '{}'",
+                    spec
+                )
+            };
+            let (key, value) = part.split_once('=').ok_or_else(invalid)?;
+            let value: usize = value.trim().parse().map_err(|_| invalid())?;
+            match key.trim() {
+                "every" if value > 0 => config.every = value,
+                "lines" if value > 0 => config.lines = value,
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(config)
+    }
+
+    /// The config in the form `parse` reads.
+    pub fn to_spec(&self) -> String {
+        format!("every={},lines={}", self.every, self.lines)
+    }
+}
+
+/// The garbage of one game: counts the pieces and adds `lines` rows after
+/// every `every` of them, with a hole in a random column. The holes come
+/// from their own generator, so that a game's pieces do not change when
+/// garbage is turned on.
+pub struct Garbage {
+    config: GarbageConfig,
+    rng: StdRng,
+    pieces: usize,
+}
+
+impl Garbage {
+    pub fn new(config: GarbageConfig, seed: u64) -> Self {
+        Garbage {
+            config,
+            rng: StdRng::seed_from_u64(seed),
+            pieces: 0,
+        }
+    }
+
+    /// Counts a placed piece and adds the garbage due after it to `board`.
+    /// Returns the rows added, or an error when they top the stack out,
+    /// which ends the game.
+    pub fn after_piece<const H: usize, const W: usize>(
+        &mut self,
+        board: &mut Board<H, W>,
+    ) -> Result<usize, &'static str> {
+        self.pieces += 1;
+        if !self.pieces.is_multiple_of(self.config.every) {
+            return Ok(0);
+        }
+        let hole = self.rng.random_range(0..W);
+        board.add_garbage(self.config.lines, &[hole])?;
+        Ok(self.config.lines)
+    }
+}
@@ -0,0 +1,289 @@
+//! Where the pieces of a game come from. The games used to draw every
+//! piece uniformly; a [`PieceGenerator`] lets them use the randomizers of
+//! real games instead, or a fixed sequence.
+//!
+//! [`Randomizer`] is the choice as the command line gives it and makes a
+//! fresh generator for every game.
+//!
+//! Generators are shown the board before every piece. Only [`Adversarial`]
+//! looks at it; when the policy sees the next piece, that piece is chosen
+//! before the current one is placed, so the adversary judges a board one
+//! piece behind.
+
+use std::collections::VecDeque;
+#[cfg(feature = "io")]
+use std::fs;
+
+use rand::Rng;
+use rand::seq::SliceRandom;
+
+use crate::board::{BOARD_HEIGHT, BOARD_WIDTH, Board, WEIGHTS};
+use crate::evaluator::Evaluator;
+use crate::i18n::tr;
+use crate::piece::PieceSet;
+use crate::policy::best_action_in;
+
+/// Hands out the pieces of one game in order, as indices into the set it
+/// was made for, for games on boards of `H` rows and `W` columns.
+pub trait PieceGenerator<const H: usize = BOARD_HEIGHT, const W: usize = BOARD_WIDTH> {
+    fn next_piece(&mut self, board: &Board<H, W>) -> usize;
+}
+
+/// Every piece drawn independently and uniformly. This draws from the
+/// random numbers exactly as `PieceSet::random` does.
+pub struct Uniform<R> {
+    rng: R,
+    count: usize,
+}
+
+impl<R: Rng> Uniform<R> {
+    pub fn new(rng: R, count: usize) -> Self {
+        Uniform { rng, count }
+    }
+}
+
+impl<R: Rng, const H: usize, const W: usize> PieceGenerator<H, W> for Uniform<R> {
+    fn next_piece(&mut self, _board: &Board<H, W>) -> usize {
+        self.rng.random_range(0..self.count)
+    }
+}
+
+/// The modern guideline randomizer: every piece of the set once, in a
+/// shuffled order, then the next shuffle. Never more than twice the set
+/// size between two of the same piece.
+pub struct Bag<R> {
+    rng: R,
+    count: usize,
+    bag: Vec<usize>,
+}
+
+impl<R: Rng> Bag<R> {
+    pub fn new(rng: R, count: usize) -> Self {
+        Bag {
+            rng,
+            count,
+            bag: Vec::with_capacity(count),
+        }
+    }
+}
+
+impl<R: Rng, const H: usize, const W: usize> PieceGenerator<H, W> for Bag<R> {
+    fn next_piece(&mut self, _board: &Board<H, W>) -> usize {
+        if self.bag.is_empty() {
+            self.bag.extend(0..self.count);
+            self.bag.shuffle(&mut self.rng);
+        }
+        self.bag.pop().unwrap()
+    }
+}
+
+/// The history randomizer of TGM2: a draw that is among the last four
+/// pieces is rerolled, up to six times. The history starts as Z S Z S, and
+/// the first piece is never S, Z or O, so a game does not open on an
+/// overhang. Letters a set lacks are left out of both rules.
+pub struct History<R> {
+    rng: R,
+    count: usize,
+    history: VecDeque<usize>,
+    /// Pieces the first draw avoids, empty after it.
+    opening: Vec<usize>,
+}
+
+impl<R: Rng> History<R> {
+    const LENGTH: usize = 4;
+    const ROLLS: usize = 6;
+
+    pub fn new(rng: R, set: &PieceSet) -> Self {
+        let find =
+            |letters: &str| -> Vec<usize> { letters.chars().filter_map(|c| set.find(c)).collect() };
+        let mut opening = find("SZO");
+        // A set of only those pieces has no safe opening
+        if opening.len() == set.len() {
+            opening.clear();
+        }
+        History {
+            rng,
+            count: set.len(),
+            history: find("ZSZS").into(),
+            opening,
+        }
+    }
+}
+
+impl<R: Rng, const H: usize, const W: usize> PieceGenerator<H, W> for History<R> {
+    fn next_piece(&mut self, _board: &Board<H, W>) -> usize {
+        let piece = if self.opening.is_empty() {
+            let mut piece = self.rng.random_range(0..self.count);
+            for _ in 1..Self::ROLLS {
+                if !self.history.contains(&piece) {
+                    break;
+                }
+                piece = self.rng.random_range(0..self.count);
+            }
+            piece
+        } else {
+            let allowed: Vec<usize> = (0..self.count)
+                .filter(|p| !self.opening.contains(p))
+                .collect();
+            self.opening.clear();
+            allowed[self.rng.random_range(0..allowed.len())]
+        };
+        if self.history.len() == Self::LENGTH {
+            self.history.pop_front();
+        }
+        self.history.push_back(piece);
+        piece
+    }
+}
+
+/// The same pieces every game, from the start again once they run out.
+pub struct Sequence<'a> {
+    pieces: &'a [usize],
+    next: usize,
+}
+
+impl<'a> Sequence<'a> {
+    /// `pieces` must not be empty.
+    pub fn new(pieces: &'a [usize]) -> Self {
+        Sequence { pieces, next: 0 }
+    }
+}
+
+impl<const H: usize, const W: usize> PieceGenerator<H, W> for Sequence<'_> {
+    fn next_piece(&mut self, _board: &Board<H, W>) -> usize {
+        let piece = self.pieces[self.next];
+        self.next = (self.next + 1) % self.pieces.len();
+        piece
+    }
+}
+
+/// The hatetris idea: always the piece whose best placement is worst.
+/// Every piece of the set is tried with `evaluator` and the one whose
+/// lowest evaluation is highest comes next, earlier pieces winning ties; a
+/// piece that fits nowhere beats them all. No randomness at all, so the
+/// same policy always gets the same game.
+pub struct Adversarial<'a, E: ?Sized> {
+    set: &'a PieceSet,
+    evaluator: &'a E,
+}
+
+impl<'a, E: ?Sized> Adversarial<'a, E> {
+    pub fn new(set: &'a PieceSet, evaluator: &'a E) -> Self {
+        Adversarial { set, evaluator }
+    }
+}
+
+impl<const H: usize, const W: usize, E: Evaluator<H, W> + ?Sized> PieceGenerator<H, W>
+    for Adversarial<'_, E>
+{
+    fn next_piece(&mut self, board: &Board<H, W>) -> usize {
+        let mut worst = (0, f64::NEG_INFINITY);
+        for piece in 0..self.set.len() {
+            let Some((_, _, score)) = best_action_in(board, self.set, piece, self.evaluator) else {
+                return piece;
+            };
+            if score > worst.1 {
+                worst = (piece, score);
+            }
+        }
+        worst.0
+    }
+}
+
+/// The randomizers `--randomizer` offers.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum Randomizer {
+    #[default]
+    Uniform,
+    Bag,
+    History,
+    /// `Adversarial` against the built-in `WEIGHTS`, whatever policy is
+    /// playing, as hatetris uses a fixed heuristic of its own.
+    Adversarial,
+    /// The pieces of a file, read when the randomizer is parsed.
+    Sequence {
+        path: String,
+        pieces: Vec<usize>,
+    },
+}
+
+impl Randomizer {
+    /// Parses `uniform`, `bag`, `history`, `adversarial` or `file:<path>`
+    /// for pieces of `set`. A sequence file holds piece letters, with
+    /// whitespace and `//` comments ignored.
+    #[cfg(feature = "io")]
+    pub fn parse(spec: &str, set: &PieceSet) -> Result<Self, String> {
+        match spec {
+            "uniform" => Ok(Randomizer::Uniform),
+            "bag" => Ok(Randomizer::Bag),
+            "history" => Ok(Randomizer::History),
+            "adversarial" => Ok(Randomizer::Adversarial),
+            _ => {
+                let path = spec.strip_prefix("file:").ok_or_else(|| {
+                    tr!(
+                        "需要 uniform、bag、history、adversarial 或 file:<路径>, 实际为 '{}'",
+                        "expected uniform, bag, history, adversarial or file:<path>, got '{}'",
+                        spec
+                    )
+                })?;
+                let text = fs::read_to_string(path)
+                    .map_err(|e| tr!("无法读取 {}: {}", "Cannot read {}: {}", path, e))?;
+                let pieces = Self::parse_sequence(&text, set)?;
+                Ok(Randomizer::Sequence {
+                    path: path.to_string(),
+                    pieces,
+                })
+            }
+        }
+    }
+
+    /// The pieces of the text of a sequence file.
+    pub fn parse_sequence(text: &str, set: &PieceSet) -> Result<Vec<usize>, String> {
+        let mut pieces = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split("//").next().unwrap_or("");
+            for letter in line.chars().filter(|c| !c.is_whitespace()) {
+                let piece = set.find(letter).ok_or_else(|| {
+                    tr!(
+                        "第 {} 行: 未知方块 '{}'",
+                        "line {}: unknown piece '{}'",
+                        number + 1,
+                        letter
+                    )
+                })?;
+                pieces.push(piece);
+            }
+        }
+        if pieces.is_empty() {
+            return Err(tr!("序列中没有方块", "the sequence has no pieces"));
+        }
+        Ok(pieces)
+    }
+
+    /// The randomizer in the form `parse` reads.
+    pub fn to_spec(&self) -> String {
+        match self {
+            Randomizer::Uniform => "uniform".to_string(),
+            Randomizer::Bag => "bag".to_string(),
+            Randomizer::History => "history".to_string(),
+            Randomizer::Adversarial => "adversarial".to_string(),
+            Randomizer::Sequence { path, .. } => format!("file:{}", path),
+        }
+    }
+
+    /// A generator for a new game with pieces of `set`, drawing from
+    /// `rng`. A sequence ignores both, the adversary the `rng`.
+    pub fn generator<'a, R: Rng + 'a, const H: usize, const W: usize>(
+        &'a self,
+        set: &'a PieceSet,
+        rng: R,
+    ) -> Box<dyn PieceGenerator<H, W> + 'a> {
+        match self {
+            Randomizer::Uniform => Box::new(Uniform::new(rng, set.len())),
+            Randomizer::Bag => Box::new(Bag::new(rng, set.len())),
+            Randomizer::History => Box::new(History::new(rng, set)),
+            Randomizer::Adversarial => Box::new(Adversarial::new(set, &WEIGHTS)),
+            Randomizer::Sequence { pieces, .. } => Box::new(Sequence::new(pieces)),
+        }
+    }
+}
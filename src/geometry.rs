@@ -0,0 +1,92 @@
+use std::fs;
+use std::path::Path;
+
+use crate::board::{BOARD_HEIGHT, BOARD_WIDTH};
+use crate::logging::{log_error, log_info};
+use crate::piece::PieceType;
+use crate::rotation::RotationSystem;
+
+const PIECES: [(PieceType, char); 7] = [
+    (PieceType::I, 'I'),
+    (PieceType::T, 'T'),
+    (PieceType::O, 'O'),
+    (PieceType::J, 'J'),
+    (PieceType::L, 'L'),
+    (PieceType::S, 'S'),
+    (PieceType::Z, 'Z'),
+];
+
+/// The action space of the `check` protocol in `system` as JSON: for each
+/// piece and rotation the occupied cells, their mask, the size and the
+/// columns a move line may name, plus the matching `ROTATIONS` index.
+///
+/// ```text
+/// {"system": "srs", "board": {"width": 10, "height": 15},
+///  "pieces": {"I": [{"rotation": 0, "simple_rotation": 0, "cells": [[0, 0], ...],
+///                    "mask": 15, "width": 4, "height": 1, "x_min": 0, "x_max": 6}, ...]}}
+/// ```
+///
+/// Cells are `[dx, dy]` from the reported column and the lowest occupied
+/// row; `mask` has bit `4 * dy + dx` set for each of them.
+pub fn geometry_json(system: RotationSystem) -> String {
+    let mut json = format!(
+        "{{\n  \"system\": \"{}\",\n  \"board\": {{\"width\": {}, \"height\": {}}},\n  \"pieces\": {{\n",
+        system.name(),
+        BOARD_WIDTH,
+        BOARD_HEIGHT
+    );
+    for (p, &(piece, name)) in PIECES.iter().enumerate() {
+        json.push_str(&format!("    \"{}\": [\n", name));
+        for rotation in 0..4 {
+            let shape = system.shape(piece, rotation);
+            let simple = system
+                .to_simple(piece, rotation, 0)
+                .map_or("null".to_string(), |(r, _)| r.to_string());
+            let cells: Vec<String> = shape
+                .cells
+                .iter()
+                .map(|(dx, dy)| format!("[{}, {}]", dx, dy))
+                .collect();
+            json.push_str(&format!(
+                "      {{\"rotation\": {}, \"simple_rotation\": {}, \"cells\": [{}], \"mask\": {}, \"width\": {}, \"height\": {}, \"x_min\": {}, \"x_max\": {}}}{}\n",
+                rotation,
+                simple,
+                cells.join(", "),
+                shape.mask(),
+                shape.width,
+                shape.height,
+                shape.x_range.0,
+                shape.x_range.1,
+                if rotation < 3 { "," } else { "" }
+            ));
+        }
+        json.push_str(if p + 1 < PIECES.len() {
+            "    ],\n"
+        } else {
+            "    ]\n"
+        });
+    }
+    json.push_str("  }\n}\n");
+    json
+}
+
+/// Writes the geometry to `out`, or prints it when no file is given.
+pub fn export(system: RotationSystem, out: Option<&Path>) {
+    let json = geometry_json(system);
+    match out {
+        None => print!("{}", json),
+        Some(path) => match fs::write(path, json) {
+            Ok(()) => log_info!(
+                "几何数据已保存到 {}",
+                "Geometry saved to {}",
+                path.display()
+            ),
+            Err(e) => log_error!(
+                "无法保存几何数据 {}: {}",
+                "Cannot save geometry {}: {}",
+                path.display(),
+                e
+            ),
+        },
+    }
+}
@@ -0,0 +1,386 @@
+//! Grading a batch of submissions in one go, for course use.
+//!
+//! Every submission plays the same seeds through `check`, so the grades
+//! are comparable. The results directory doubles as the archive: each
+//! submission gets a folder with its per-game results and final boards,
+//! and `grades.csv` and `grades.html` sum up the whole batch.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::check::{CheckOptions, CheckOutcome, Finish, check};
+use crate::i18n::{Lang, lang, say, tr};
+use crate::logging::{log_error, log_info, log_warn};
+use crate::report::escape;
+
+/// Used when `--scale` is not given.
+pub const DEFAULT_SCALE: &str = "A=200000,B=100000,C=50000,D=10000,F=0";
+
+/// Maps a mean score to a grade: the first band whose threshold the
+/// score reaches, checked from the highest threshold down.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GradeScale {
+    bands: Vec<(String, i64)>,
+}
+
+impl GradeScale {
+    /// Parses `<grade>=<threshold>` pairs separated by commas, in any
+    /// order. A score below every threshold gets the lowest grade.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut bands = Vec::new();
+        for band in spec.split(',') {
+            let (name, threshold) = band.split_once('=').ok_or_else(|| {
+                tr!(
+                    "需要 <等级>=<分数线>, 实际为 '{}'",
+                    "expected <grade>=<threshold>, got '{}'",
+                    band
+                )
+            })?;
+            let threshold = threshold
+                .trim()
+                .parse()
+                .map_err(|_| tr!("无效的分数线 '{}'", "invalid threshold '{}'", threshold))?;
+            let name = name.trim();
+            if name.is_empty() {
+                return Err(tr!("'{}' 缺少等级名", "missing grade name in '{}'", band));
+            }
+            bands.push((name.to_string(), threshold));
+        }
+        bands.sort_by_key(|&(_, threshold)| std::cmp::Reverse(threshold));
+        Ok(GradeScale { bands })
+    }
+
+    pub fn grade(&self, score: f64) -> &str {
+        self.bands
+            .iter()
+            .find(|&&(_, threshold)| score >= threshold as f64)
+            .or(self.bands.last())
+            .map_or("-", |(name, _)| name)
+    }
+
+    /// The scale in the form `parse` reads.
+    pub fn to_spec(&self) -> String {
+        self.bands
+            .iter()
+            .map(|(name, threshold)| format!("{}={}", name, threshold))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+impl Default for GradeScale {
+    fn default() -> Self {
+        GradeScale::parse(DEFAULT_SCALE).unwrap()
+    }
+}
+
+pub struct GradeOptions {
+    pub games: usize,
+    /// Game `i` of every submission uses seed `seed + i`.
+    pub seed: u64,
+    pub out_dir: PathBuf,
+    pub scale: GradeScale,
+    /// Passed on to every `check`; its seed and image are set per game.
+    pub check: CheckOptions,
+}
+
+impl GradeOptions {
+    /// Parses `<executable>...` followed by optional flags. Flags that are
+    /// not grading flags go to `CheckOptions::from_args`.
+    pub fn from_args(args: &[String]) -> (Vec<String>, Self) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let mut options = GradeOptions {
+            games: 5,
+            seed: 1,
+            out_dir: PathBuf::from(format!("runs/grade-{}", timestamp)),
+            scale: GradeScale::default(),
+            check: CheckOptions::default(),
+        };
+
+        let submissions: Vec<String> = args
+            .iter()
+            .take_while(|a| !a.starts_with("--"))
+            .cloned()
+            .collect();
+        let mut rest = Vec::new();
+        let mut args = args[submissions.len()..].iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--games" => {
+                    if let Some(n) = args.next().and_then(|n| n.parse().ok()).filter(|&n| n > 0) {
+                        options.games = n;
+                    }
+                }
+                "--seed" => {
+                    if let Some(seed) = args.next().and_then(|s| s.parse().ok()) {
+                        options.seed = seed;
+                    }
+                }
+                "--out" => {
+                    if let Some(dir) = args.next() {
+                        options.out_dir = PathBuf::from(dir);
+                    }
+                }
+                "--scale" => match args.next().map(|s| GradeScale::parse(s)) {
+                    Some(Ok(scale)) => options.scale = scale,
+                    Some(Err(e)) => log_warn!("忽略 --scale: {}", "Ignoring --scale: {}", e),
+                    None => log_warn!(
+                        "--scale 需要 <等级>=<分数>,...",
+                        "--scale expects <grade>=<score>,..."
+                    ),
+                },
+                _ => rest.push(arg.clone()),
+            }
+        }
+        options.check = CheckOptions::from_args(&rest);
+        (submissions, options)
+    }
+}
+
+/// One submission's games and the grade they earned.
+#[derive(Debug, Clone)]
+pub struct Submission {
+    pub path: String,
+    /// Folder name in the results directory.
+    pub name: String,
+    pub games: Vec<CheckOutcome>,
+    pub grade: String,
+}
+
+impl Submission {
+    pub fn mean(&self) -> f64 {
+        let total: f64 = self.games.iter().map(|g| g.score() as f64).sum();
+        total / self.games.len().max(1) as f64
+    }
+
+    pub fn min(&self) -> i64 {
+        self.games
+            .iter()
+            .map(CheckOutcome::score)
+            .min()
+            .unwrap_or(0)
+    }
+
+    pub fn max(&self) -> i64 {
+        self.games
+            .iter()
+            .map(CheckOutcome::score)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Games that did not end in a fault.
+    pub fn clean(&self) -> usize {
+        self.games
+            .iter()
+            .filter(|g| !matches!(g.finish, Finish::Fault { .. }))
+            .count()
+    }
+}
+
+fn finish_name(finish: Finish) -> String {
+    match finish {
+        Finish::Completed => "completed".to_string(),
+        Finish::TopOut { at } => format!("top-out@{}", at),
+        Finish::Resigned { at } => format!("resigned@{}", at),
+        Finish::Fault { at } => format!("fault@{}", at),
+    }
+}
+
+fn lines(outcome: &CheckOutcome) -> usize {
+    outcome
+        .stats
+        .clears
+        .iter()
+        .enumerate()
+        .map(|(i, &n)| (i + 1) * n)
+        .sum()
+}
+
+/// A folder name for `path` that no earlier submission has taken.
+fn folder_name(path: &str, taken: &[Submission]) -> String {
+    let stem = Path::new(path)
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("submission");
+    let mut name = stem.to_string();
+    let mut n = 2;
+    while taken.iter().any(|s| s.name == name) {
+        name = format!("{}-{}", stem, n);
+        n += 1;
+    }
+    name
+}
+
+fn games_csv(submission: &Submission) -> String {
+    let mut csv = String::from("game,seed,finish,pieces,lines,score\n");
+    for (i, game) in submission.games.iter().enumerate() {
+        csv.push_str(&format!(
+            "{},{},{},{},{},{}\n",
+            i + 1,
+            game.seed,
+            finish_name(game.finish),
+            game.stats.pieces,
+            lines(game),
+            game.score()
+        ));
+    }
+    csv
+}
+
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+pub fn grades_csv(submissions: &[Submission]) -> String {
+    let mut csv = String::from("submission,folder,games,clean,mean,min,max,grade\n");
+    for s in submissions {
+        csv.push_str(&format!(
+            "{},{},{},{},{:.2},{},{},{}\n",
+            csv_field(&s.path),
+            csv_field(&s.name),
+            s.games.len(),
+            s.clean(),
+            s.mean(),
+            s.min(),
+            s.max(),
+            csv_field(&s.grade)
+        ));
+    }
+    csv
+}
+
+pub fn grades_html(submissions: &[Submission], options: &GradeOptions) -> String {
+    let (code, title) = match lang() {
+        Lang::Zh => ("zh", "评分结果"),
+        Lang::En => ("en", "Grades"),
+    };
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html lang=\"{}\">\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n",
+        code, title
+    );
+    html.push_str(
+        "<style>\n\
+         body { font-family: sans-serif; margin: 2em; }\n\
+         table { border-collapse: collapse; }\n\
+         td, th { border: 1px solid #ccc; padding: 4px 8px; text-align: right; }\n\
+         img { width: 120px; }\n\
+         </style>\n</head>\n<body>\n",
+    );
+    html.push_str(&tr!(
+        "<h1>评分结果</h1>\n<p>每份提交 {} 局, 种子 {} 起, 等级划分 <code>{}</code></p>\n",
+        "<h1>Grades</h1>\n<p>{} games per submission on seeds from {}, scale <code>{}</code></p>\n",
+        options.games,
+        options.seed,
+        escape(&options.scale.to_spec())
+    ));
+    html.push_str(&tr!(
+        "<table>\n<tr><th>提交</th><th>正常结束</th><th>平均</th><th>最低</th><th>最高</th><th>等级</th><th>最终棋盘</th></tr>\n",
+        "<table>\n<tr><th>Submission</th><th>Clean</th><th>Mean</th><th>Min</th><th>Max</th><th>Grade</th><th>Final boards</th></tr>\n"
+    ));
+    for s in submissions {
+        let boards: String = (1..=s.games.len())
+            .map(|i| {
+                format!(
+                    "<a href=\"{0}/game-{1}.png\"><img src=\"{0}/game-{1}.png\" alt=\"{1}\"></a>",
+                    escape(&s.name),
+                    i
+                )
+            })
+            .collect();
+        html.push_str(&format!(
+            "<tr><td><a href=\"{}/games.csv\"><code>{}</code></a></td><td>{}/{}</td><td>{:.2}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+            escape(&s.name),
+            escape(&s.path),
+            s.clean(),
+            s.games.len(),
+            s.mean(),
+            s.min(),
+            s.max(),
+            escape(&s.grade),
+            boards
+        ));
+    }
+    html.push_str("</table>\n</body>\n</html>\n");
+    html
+}
+
+fn write(path: &Path, contents: &str) {
+    match fs::write(path, contents) {
+        Ok(()) => log_info!("已写入 {}", "Wrote {}", path.display()),
+        Err(e) => log_error!(
+            "无法写入 {}: {}",
+            "Failed to write {}: {}",
+            path.display(),
+            e
+        ),
+    }
+}
+
+/// Checks every submission on the same seeds, archives the games in the
+/// results directory and prints the grades.
+pub fn grade(submissions: &[String], options: &GradeOptions) -> Vec<Submission> {
+    let mut results: Vec<Submission> = Vec::new();
+    for path in submissions {
+        let name = folder_name(path, &results);
+        let dir = options.out_dir.join(&name);
+        if let Err(e) = fs::create_dir_all(&dir) {
+            log_error!(
+                "无法创建目录 {}: {}",
+                "Failed to create directory {}: {}",
+                dir.display(),
+                e
+            );
+        }
+        let mut games = Vec::with_capacity(options.games);
+        for i in 0..options.games {
+            say!(
+                "== {} 第 {}/{} 局 ==",
+                "== {} game {}/{} ==",
+                path,
+                i + 1,
+                options.games
+            );
+            let mut check_options = options.check.clone();
+            check_options.seed = Some(options.seed + i as u64);
+            check_options.image = Some(dir.join(format!("game-{}.png", i + 1)));
+            games.push(check(path.clone(), check_options));
+        }
+        let mut submission = Submission {
+            path: path.clone(),
+            name,
+            games,
+            grade: String::new(),
+        };
+        submission.grade = options.scale.grade(submission.mean()).to_string();
+        write(&dir.join("games.csv"), &games_csv(&submission));
+        results.push(submission);
+    }
+
+    write(&options.out_dir.join("grades.csv"), &grades_csv(&results));
+    write(
+        &options.out_dir.join("grades.html"),
+        &grades_html(&results, options),
+    );
+
+    say!("\n评分结果:", "\nGrades:");
+    for s in &results {
+        say!(
+            "  {}: 平均 {:.2} (正常结束 {}/{} 局) -> {}",
+            "  {}: mean {:.2} ({} of {} games clean) -> {}",
+            s.path,
+            s.mean(),
+            s.clean(),
+            s.games.len(),
+            s.grade
+        );
+    }
+    results
+}
@@ -0,0 +1,45 @@
+//! The hold slot. Instead of placing the current piece, the player may
+//! put it aside and place the held piece, or the next one while nothing
+//! is held yet. As in the guideline games a piece taken out of hold
+//! cannot go straight back, so every move is one choice between two
+//! pieces; see [`best_action_with_hold_in`](crate::policy::best_action_with_hold_in).
+
+/// The piece put aside, if any, as an index into the set of the game.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct HoldSlot {
+    piece: Option<usize>,
+}
+
+impl HoldSlot {
+    pub fn new() -> Self {
+        HoldSlot::default()
+    }
+
+    pub fn piece(&self) -> Option<usize> {
+        self.piece
+    }
+
+    /// The piece that is placed instead of the current one when holding:
+    /// the held one, or `next` while the slot is empty. `None` when the
+    /// slot is empty and the next piece is not known.
+    pub fn alternative(&self, next: Option<usize>) -> Option<usize> {
+        self.piece.or(next)
+    }
+
+    /// Puts `current` aside and returns the piece to place instead. When
+    /// the slot was empty that is `next`, which the caller then has to
+    /// replace from its queue, and the second field is `true`.
+    ///
+    /// # Panics
+    ///
+    /// If the slot is empty and `next` is `None`.
+    pub fn hold(&mut self, current: usize, next: Option<usize>) -> (usize, bool) {
+        match self.piece.replace(current) {
+            Some(held) => (held, false),
+            None => (
+                next.expect("holding into an empty slot needs the next piece"),
+                true,
+            ),
+        }
+    }
+}
@@ -0,0 +1,76 @@
+//! Language selection for user-facing messages.
+//!
+//! Messages are written next to their use as a Chinese/English pair with
+//! `tr!` (returns a `String`) or `say!` (prints a line); both take the
+//! same format arguments for either language.
+
+use std::env;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lang {
+    Zh,
+    En,
+}
+
+static LANG: AtomicU8 = AtomicU8::new(Lang::Zh as u8);
+
+impl Lang {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "zh" => Some(Lang::Zh),
+            "en" => Some(Lang::En),
+            _ => None,
+        }
+    }
+
+    /// Picks the language from the usual locale variables. Chinese stays
+    /// the default when they are unset or say nothing (`C`, `POSIX`).
+    pub fn from_env() -> Self {
+        let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|v| env::var(v).ok())
+            .find(|v| !v.is_empty())
+            .unwrap_or_default();
+        if locale.is_empty() || locale == "C" || locale == "POSIX" || locale.starts_with("zh") {
+            Lang::Zh
+        } else {
+            Lang::En
+        }
+    }
+}
+
+pub fn set_lang(lang: Lang) {
+    LANG.store(lang as u8, Ordering::Relaxed);
+}
+
+pub fn lang() -> Lang {
+    if LANG.load(Ordering::Relaxed) == Lang::En as u8 {
+        Lang::En
+    } else {
+        Lang::Zh
+    }
+}
+
+/// Formats the message in the current language.
+#[macro_export]
+#[doc(hidden)]
+macro_rules! tr {
+    ($zh:literal, $en:literal $(, $arg:expr)* $(,)?) => {
+        match $crate::i18n::lang() {
+            $crate::i18n::Lang::Zh => format!($zh $(, $arg)*),
+            $crate::i18n::Lang::En => format!($en $(, $arg)*),
+        }
+    };
+}
+
+/// Prints the message in the current language, like `println!`.
+#[macro_export]
+#[doc(hidden)]
+macro_rules! say {
+    ($($t:tt)*) => {
+        println!("{}", $crate::i18n::tr!($($t)*))
+    };
+}
+
+pub use crate::{say, tr};
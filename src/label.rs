@@ -0,0 +1,263 @@
+//! Labelled positions for distilling a slow planner into the fast policy.
+//!
+//! Fast games are played with the one-piece policy, and every few pieces
+//! the position is handed to a deep beam search, the teacher, whose move
+//! and path score become the label. The student only ever sees positions
+//! its own games reach, which is where its mistakes matter.
+//!
+//! The dataset is plain text, one position per line after `#` comments:
+//!
+//! ```text
+//! <board> <pieces> <rotation> <x> <value>
+//! ```
+//!
+//! `<board>` is the encoding of `check --board-every`, `<pieces>` the
+//! letters of the current piece and the queue the teacher saw, and
+//! `<rotation> <x> <value>` the teacher's move in the `simple` rotation
+//! system and the score of its best path, lower being better.
+
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::PathBuf;
+
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use rayon::prelude::*;
+
+use crate::board::Board;
+use crate::check::encode_board;
+use crate::evaluator::Evaluator;
+use crate::generator::Randomizer;
+use crate::i18n::tr;
+use crate::logging::{log_info, log_warn};
+use crate::piece::{BuiltinSet, PieceSet};
+use crate::policy::best_action_in;
+use crate::search::{SearchConfig, TranspositionTable, best_search_action_with_table_in};
+
+#[derive(Debug, Clone)]
+pub struct LabelOptions {
+    pub out: PathBuf,
+    pub games: usize,
+    /// Seed of the first game; game `i` uses `seed + i`.
+    pub seed: u64,
+    /// Label every this many pieces of a game.
+    pub every: usize,
+    /// Pieces after which a game is cut short. Good weights rarely top
+    /// out, and later positions look much like earlier ones.
+    pub max_pieces: usize,
+    /// The teacher. Its lookahead is dealt as the queue.
+    pub search: SearchConfig,
+    pub piece_set: BuiltinSet,
+    /// How the pieces are drawn, parsed for `piece_set`.
+    pub randomizer: Randomizer,
+}
+
+impl Default for LabelOptions {
+    fn default() -> Self {
+        LabelOptions {
+            out: PathBuf::from("labels.txt"),
+            games: 8,
+            seed: 1,
+            every: 10,
+            max_pieces: 2000,
+            search: SearchConfig {
+                depth: 3,
+                beam_width: 64,
+                ..SearchConfig::default()
+            },
+            piece_set: BuiltinSet::default(),
+            randomizer: Randomizer::default(),
+        }
+    }
+}
+
+impl LabelOptions {
+    pub fn from_args(args: &[String]) -> Self {
+        let mut options = LabelOptions::default();
+        let mut randomizer = None;
+        let mut args = args.iter();
+        let count = |value: Option<&String>| value.and_then(|n| n.parse().ok()).filter(|&n| n > 0);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--out" => match args.next() {
+                    Some(path) => options.out = PathBuf::from(path),
+                    None => log_warn!("--out 需要一个文件", "--out expects a file"),
+                },
+                "--games" => match count(args.next()) {
+                    Some(n) => options.games = n,
+                    None => log_warn!("--games 需要正整数", "--games expects a positive integer"),
+                },
+                "--every" => match count(args.next()) {
+                    Some(n) => options.every = n,
+                    None => log_warn!("--every 需要正整数", "--every expects a positive integer"),
+                },
+                "--max-pieces" => match count(args.next()) {
+                    Some(n) => options.max_pieces = n,
+                    None => log_warn!(
+                        "--max-pieces 需要正整数",
+                        "--max-pieces expects a positive integer"
+                    ),
+                },
+                "--seed" => match args.next().and_then(|s| s.parse().ok()) {
+                    Some(seed) => options.seed = seed,
+                    None => log_warn!("--seed 需要一个整数", "--seed expects an integer"),
+                },
+                "--search" => match args.next().map(|s| SearchConfig::parse(s)) {
+                    Some(Ok(search)) => options.search = search,
+                    Some(Err(e)) => log_warn!("忽略 --search: {}", "Ignoring --search: {}", e),
+                    None => log_warn!(
+                        "--search 需要 depth=<n>,beam=<k>",
+                        "--search expects depth=<n>,beam=<k>"
+                    ),
+                },
+                "--piece-set" => match args.next().and_then(|p| BuiltinSet::parse(p)) {
+                    Some(set) => options.piece_set = set,
+                    None => log_warn!(
+                        "--piece-set 需要 tetromino 或 pentomino",
+                        "--piece-set expects tetromino or pentomino"
+                    ),
+                },
+                "--randomizer" => randomizer = args.next(),
+                _ => log_warn!("忽略未知参数: {}", "Ignoring unknown argument: {}", arg),
+            }
+        }
+        // The sequence letters depend on the set, wherever --piece-set was
+        if let Some(spec) = randomizer {
+            match Randomizer::parse(spec, options.piece_set.set()) {
+                Ok(randomizer) => options.randomizer = randomizer,
+                Err(e) => log_warn!("忽略 --randomizer: {}", "Ignoring --randomizer: {}", e),
+            }
+        }
+        options
+    }
+}
+
+/// One labelled position.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Label {
+    /// The board as `encode_board` writes it.
+    pub board: String,
+    /// The current piece followed by the queue the teacher saw.
+    pub pieces: Vec<usize>,
+    pub rotation: usize,
+    pub x: usize,
+    pub value: f64,
+    /// Whether the student played the teacher's move here.
+    pub agreed: bool,
+}
+
+impl Label {
+    /// The dataset line of the label, without the newline.
+    pub fn to_line(&self, set: &PieceSet) -> String {
+        let pieces: String = self.pieces.iter().map(|&p| set.letter(p)).collect();
+        format!(
+            "{} {} {} {} {}",
+            self.board, pieces, self.rotation, self.x, self.value
+        )
+    }
+}
+
+/// Plays one game of the student on the pieces of `seed` and labels every
+/// `options.every`-th position with the teacher's move. The game follows
+/// the student; the teacher's moves are never played.
+pub fn label_game<E: Evaluator + ?Sized>(
+    student: &E,
+    set: &PieceSet,
+    seed: u64,
+    options: &LabelOptions,
+) -> Vec<Label> {
+    let mut board = Board::new();
+    let mut pieces = options
+        .randomizer
+        .generator(set, StdRng::seed_from_u64(seed));
+    let mut queue: VecDeque<usize> = (0..=options.search.lookahead())
+        .map(|_| pieces.next_piece(&board))
+        .collect();
+    let mut labels = Vec::new();
+    // Only every few positions are searched, but the boards between them
+    // still come back along the queue
+    let mut table = TranspositionTable::new(options.search.table);
+
+    for placed in 0..options.max_pieces {
+        let piece = queue[0];
+        let Some((rotation, x, _)) = best_action_in(&board, set, piece, student) else {
+            break;
+        };
+        if placed.is_multiple_of(options.every) {
+            let path: Vec<usize> = queue.iter().copied().collect();
+            // The student's move fits, so the teacher has one too
+            let (teacher_rotation, teacher_x, value) = best_search_action_with_table_in(
+                &board,
+                set,
+                &path,
+                student,
+                options.search,
+                &mut table,
+            )
+            .unwrap();
+            labels.push(Label {
+                board: encode_board(&board),
+                pieces: path,
+                rotation: teacher_rotation,
+                x: teacher_x,
+                value,
+                agreed: (teacher_rotation, teacher_x) == (rotation, x),
+            });
+        }
+        board.apply_in(set, piece, x, rotation).unwrap();
+        queue.pop_front();
+        queue.push_back(pieces.next_piece(&board));
+    }
+    labels
+}
+
+/// Labels `options.games` games in parallel and writes the dataset, game
+/// by game in seed order. Returns the labels.
+pub fn label<E: Evaluator + ?Sized>(
+    student: &E,
+    options: &LabelOptions,
+) -> Result<Vec<Label>, String> {
+    let set = options.piece_set.set();
+    log_info!(
+        "用 {1} 的搜索标注 {0} 局, 每 {2} 个方块一个局面",
+        "Labelling {} games with a {} search, one position every {} pieces",
+        options.games,
+        options.search.to_spec(),
+        options.every
+    );
+    let labels: Vec<Label> = (0..options.games as u64)
+        .into_par_iter()
+        .map(|i| label_game(student, set, options.seed + i, options))
+        .flatten()
+        .collect();
+
+    let mut text = tr!(
+        "# 标注: 教师 {0}, 种子 {2} 起 {1} 局\n# <棋盘> <方块> <旋转> <x> <评估>\n",
+        "# labels: teacher {}, {} games from seed {}\n# <board> <pieces> <rotation> <x> <value>\n",
+        options.search.to_spec(),
+        options.games,
+        options.seed
+    );
+    for label in &labels {
+        let _ = writeln!(text, "{}", label.to_line(set));
+    }
+    fs::write(&options.out, text).map_err(|e| {
+        tr!(
+            "无法写入 {}: {}",
+            "Cannot write {}: {}",
+            options.out.display(),
+            e
+        )
+    })?;
+
+    let agreed = labels.iter().filter(|l| l.agreed).count();
+    log_info!(
+        "已写入 {} 个局面到 {}, 学生与教师一致 {:.1}%",
+        "Wrote {} positions to {}, the student agreeing with the teacher on {:.1}%",
+        labels.len(),
+        options.out.display(),
+        100.0 * agreed as f64 / labels.len().max(1) as f64
+    );
+    Ok(labels)
+}
@@ -0,0 +1,130 @@
+//! A Tetris placement engine and the tools built on it.
+//!
+//! The core API is small:
+//!
+//! - [`board::Board`] is a 10x15 grid unless its const parameters say
+//!   otherwise, as in [`board::StandardBoard`], or the guideline well with
+//!   hidden spawn rows, [`board::GuidelineBoard`]. The commands pick a
+//!   size at run time from [`dimensions::SIZES`]. [`Board::simulate`] rates
+//!   a placement without changing the board, [`Board::check`] tells
+//!   whether it is legal and [`Board::apply`] plays it, clearing lines and
+//!   adding to the score.
+//! - [`piece::PieceType`] names the seven pieces and [`piece::ROTATIONS`]
+//!   holds their shapes. A placement is a piece, a rotation index into
+//!   `ROTATIONS` and the column of its leftmost cell. Other pieces, such
+//!   as the built-in pentominoes, come from a [`piece::PieceSet`] and are
+//!   played with the `_in` methods of the board and the policy.
+//! - `simulate` returns the [`board::FEATURES`] numbers the evaluation
+//!   uses. A placement's score is their dot product with a weight vector
+//!   such as [`board::WEIGHTS`]; lower is better. [`policy::best_action`]
+//!   finds the placement with the lowest score.
+//! - Other scoring rules plug in through [`evaluator::Evaluator`], which
+//!   the weight arrays implement, as does the small network of
+//!   [`mlp::MlpEvaluator`]. The policy, the rollouts and the preview modes
+//!   all take any evaluator.
+//!
+//! ```
+//! use mortis::board::{Board, WEIGHTS};
+//! use mortis::piece::PieceType;
+//! use mortis::policy::best_action;
+//!
+//! let mut board = Board::new();
+//! let (rotate, x, _score) = best_action(&board, PieceType::T, &WEIGHTS).unwrap();
+//! let (cleared, _features) = board.simulate(PieceType::T, x, rotate).unwrap();
+//! assert_eq!(cleared, 0);
+//! board.apply(PieceType::T, x, rotate).unwrap();
+//! ```
+//!
+//! [`episode::run_episode`] plays a whole game from a seed and returns its
+//! statistics, with no printing or file access along the way.
+//!
+//! The remaining modules are the training, grading and reporting code
+//! behind the `mortis` command. They are public so that other tools can
+//! reuse them, but they are shaped by the command line first. They need
+//! the default `io` feature; without it the crate is only the engine, for
+//! benchmarks and embedding.
+//!
+//! [`Board::simulate`]: board::Board::simulate
+//! [`Board::check`]: board::Board::check
+//! [`Board::apply`]: board::Board::apply
+#![allow(clippy::needless_range_loop)]
+
+#[cfg(feature = "io")]
+pub mod anomaly;
+#[cfg(feature = "io")]
+pub mod arena;
+#[cfg(feature = "io")]
+pub mod benchmark;
+pub mod board;
+#[cfg(feature = "io")]
+pub mod check;
+#[cfg(feature = "io")]
+pub mod checkpoint;
+#[cfg(feature = "io")]
+pub mod clips;
+#[cfg(feature = "io")]
+pub mod determinism;
+pub mod dimensions;
+pub mod episode;
+pub mod evaluator;
+pub mod features;
+pub mod garbage;
+pub mod generator;
+#[cfg(feature = "io")]
+pub mod geometry;
+#[cfg(feature = "io")]
+pub mod grade;
+pub mod hold;
+pub mod i18n;
+#[cfg(feature = "io")]
+pub mod label;
+#[cfg(feature = "io")]
+pub mod live;
+#[cfg(feature = "io")]
+pub mod logging;
+pub mod mcts;
+pub mod mlp;
+pub mod mode;
+pub mod moves;
+#[cfg(feature = "io")]
+pub mod multiview;
+pub mod observation;
+pub mod piece;
+#[cfg(feature = "io")]
+pub mod plots;
+pub mod policy;
+pub mod pool;
+#[cfg(feature = "io")]
+pub mod preview;
+#[cfg(feature = "io")]
+pub mod puzzle;
+#[cfg(feature = "io")]
+pub mod qd;
+#[cfg(feature = "io")]
+pub mod quantize;
+#[cfg(feature = "io")]
+pub mod render;
+#[cfg(feature = "io")]
+pub mod report;
+pub mod rotation;
+#[cfg(feature = "io")]
+pub mod sandbox;
+#[cfg(feature = "io")]
+pub mod scenario;
+pub mod scoring;
+pub mod search;
+#[cfg(feature = "io")]
+pub mod serve;
+#[cfg(feature = "serde")]
+pub mod state;
+pub mod stats;
+#[cfg(feature = "io")]
+pub mod terminal;
+pub mod theme;
+pub mod timing;
+#[cfg(feature = "io")]
+pub mod train;
+pub mod versus;
+pub mod visits;
+#[cfg(feature = "io")]
+pub mod weights;
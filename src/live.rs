@@ -0,0 +1,273 @@
+use std::thread;
+use std::time::Instant;
+
+use crate::board::{BOARD_HEIGHT, BOARD_WIDTH, Board};
+use crate::evaluator::Evaluator;
+use crate::i18n::{say, tr};
+use crate::piece::{PieceType, ROTATIONS, get_random_piece};
+use crate::policy::select_action;
+use crate::rotation::Transition;
+use crate::terminal;
+use crate::theme::palette;
+use crate::timing::{FRAME, FRAMES_PER_INPUT, Timing, gravity, pieces_per_second};
+
+const SPAWN_X: usize = 3;
+
+/// Columns of the status line under the board, the widest line of a
+/// frame.
+const FRAME_COLUMNS: usize = 56;
+
+#[derive(Debug, Clone, Copy)]
+enum Input {
+    /// A clockwise quarter turn, with the SRS kicks when it is blocked.
+    Rotate,
+    /// A 180° turn, with the SRS+ kicks when it is blocked.
+    RotateHalf,
+    Left,
+    Right,
+}
+
+/// The key that takes `falling` one step towards `rotation` at column
+/// `x`: turns first, then shifts, as the kicks may move the piece. Gravity
+/// does the rest. With `half_turns`, two clockwise turns are one 180° turn
+/// instead.
+fn next_key(falling: &Falling, rotation: usize, x: usize, half_turns: bool) -> Option<Input> {
+    let turns = (rotation + 4 - falling.rotation) % 4;
+    let x = x as i32;
+    if half_turns && turns >= 2 {
+        Some(Input::RotateHalf)
+    } else if turns > 0 {
+        Some(Input::Rotate)
+    } else if falling.x > x {
+        Some(Input::Left)
+    } else if falling.x < x {
+        Some(Input::Right)
+    } else {
+        None
+    }
+}
+
+/// The piece under the player's control; `y` is the row of its lowest
+/// shape row.
+#[derive(Debug, Clone, Copy)]
+struct Falling {
+    piece: PieceType,
+    rotation: usize,
+    x: i32,
+    y: i32,
+}
+
+impl Falling {
+    fn spawn(piece: PieceType) -> Self {
+        let height = ROTATIONS[piece as usize][0].height;
+        Falling {
+            piece,
+            rotation: 0,
+            x: SPAWN_X as i32,
+            y: (BOARD_HEIGHT - height) as i32,
+        }
+    }
+
+    /// Board cells covered by the piece, or `None` if it sticks out of the
+    /// board or overlaps a filled cell.
+    fn cells(&self, board: &Board) -> Option<Vec<(usize, usize)>> {
+        let shape = &ROTATIONS[self.piece as usize][self.rotation];
+        let (x, y) = (usize::try_from(self.x).ok()?, usize::try_from(self.y).ok()?);
+        if x + shape.width > BOARD_WIDTH || y + shape.height > BOARD_HEIGHT {
+            return None;
+        }
+        let grid = board.get_grid();
+        let mut cells = Vec::with_capacity(4);
+        for i in 0..shape.height {
+            for j in 0..shape.width {
+                if shape.shape[i][j] != 0 {
+                    let (y, x) = (y + i, x + j);
+                    if grid[y][x] {
+                        return None;
+                    }
+                    cells.push((y, x));
+                }
+            }
+        }
+        Some(cells)
+    }
+
+    /// The piece after `input`, or `None` if the key is blocked.
+    fn press(&self, input: Input, board: &Board) -> Option<Self> {
+        let mut moved = *self;
+        match input {
+            Input::Rotate => return self.turned(1, board),
+            Input::RotateHalf => return self.turned(2, board),
+            Input::Left => moved.x -= 1,
+            Input::Right => moved.x += 1,
+        }
+        moved.cells(board).map(|_| moved)
+    }
+
+    /// Turned clockwise `quarters` times at the first kick of the turn
+    /// that fits. A position sticking out of the top is pushed down first,
+    /// as the board has no rows above it to turn into.
+    fn turned(&self, quarters: usize, board: &Board) -> Option<Self> {
+        let rotation = (self.rotation + quarters) % 4;
+        let transition = Transition::find(self.piece, self.rotation, rotation)?;
+        let top = (BOARD_HEIGHT - ROTATIONS[self.piece as usize][rotation].height) as i32;
+        transition
+            .positions(self.piece, self.x, self.y)
+            .find_map(|(x, y)| {
+                let turned = Falling {
+                    rotation,
+                    x,
+                    y: y.min(top),
+                    ..*self
+                };
+                turned.cells(board).map(|_| turned)
+            })
+    }
+
+    /// One row down, or `None` if it has landed.
+    fn fall(&self, board: &Board) -> Option<Self> {
+        let fallen = Falling {
+            y: self.y - 1,
+            ..*self
+        };
+        fallen.cells(board).map(|_| fallen)
+    }
+}
+
+fn frame(board: &Board, falling: &Falling, next: PieceType, level: usize, pps: f64) -> String {
+    let grid = board.get_grid();
+    let colors = board.get_color_grid();
+    let active = falling.cells(board).unwrap_or_default();
+
+    let mut out = String::from("\x1B[2J\x1B[1;1H");
+    out.push_str(&tr!(
+        "Tetris AI Preview - 实时 (按Ctrl+C退出)\n",
+        "Tetris AI Preview - real time (Ctrl+C to quit)\n"
+    ));
+    let color = |y: usize, x: usize| {
+        if active.contains(&(y, x)) {
+            Some(falling.piece as u8)
+        } else if grid[y][x] {
+            Some(colors[y][x].unwrap_or(7))
+        } else {
+            None
+        }
+    };
+    out.push_str(&format!("╔{}╗\n", "═".repeat(BOARD_WIDTH)));
+    if !terminal::half(FRAME_COLUMNS, BOARD_HEIGHT + 4) {
+        for y in (0..BOARD_HEIGHT).rev() {
+            out.push('║');
+            for x in 0..BOARD_WIDTH {
+                match color(y, x) {
+                    Some(c) => out.push_str(&format!("{}\u{25A0}\x1B[0m", palette().ansi(Some(c)))),
+                    None => out.push(' '),
+                }
+            }
+            out.push_str("║\n");
+        }
+    } else {
+        let lines = terminal::half_blocks(BOARD_WIDTH, BOARD_HEIGHT, |y, x| color(y, x).map(Some));
+        for line in lines {
+            out.push_str(&format!("║{}║\n", line));
+        }
+    }
+    out.push_str(&format!("╚{}╝\n", "═".repeat(BOARD_WIDTH)));
+    out.push_str(&tr!(
+        "分数 {}  行数 {}  等级 {}  下一个 {}  每秒 {:.2} 块\n",
+        "Score {}  lines {}  level {}  next {}  {:.2} pieces/s\n",
+        board.get_score(),
+        board.get_lines(),
+        level,
+        next,
+        pps
+    ));
+    out
+}
+
+/// Demo mode: plays the policy's placements as a person would, pressing
+/// the keys at a steady pace while the piece falls under the classic
+/// gravity for the current level. The level goes up every 10 lines. After
+/// each lock the game waits as `timing` says, and the pieces per second
+/// shown count those pauses. With a `temperature` the placements are
+/// sampled, see `sample_action`. With `half_turns` the player has a 180°
+/// rotation key.
+pub fn live<E: Evaluator + ?Sized>(
+    start_level: usize,
+    evaluator: &E,
+    timing: Timing,
+    temperature: Option<f64>,
+    half_turns: bool,
+) {
+    let mut board = Board::new();
+    let mut rng = rand::rng();
+    let mut next = get_random_piece(&mut rng);
+    let mut clock = Instant::now();
+    let mut frames = 0u64;
+    let mut pieces = 0;
+
+    loop {
+        let piece = next;
+        next = get_random_piece(&mut rng);
+        let level = start_level + board.get_lines() / 10;
+        let gravity = gravity(level);
+
+        let mut falling = Falling::spawn(piece);
+        let placement = select_action(&board, piece, evaluator, temperature, &mut rng);
+        let (Some((rotation, x, _)), Some(_)) = (placement, falling.cells(&board)) else {
+            let pps = pieces_per_second(pieces, frames);
+            print!("{}", frame(&board, &falling, next, level, pps));
+            say!(
+                "游戏结束！最终分数: {}",
+                "Game over! Final score: {}",
+                board.get_score()
+            );
+            return;
+        };
+
+        let mut pending = next_key(&falling, rotation, x, half_turns);
+        let mut tick = 0u32;
+        loop {
+            tick += 1;
+            let mut changed = false;
+            if let Some(input) = pending
+                && tick.is_multiple_of(FRAMES_PER_INPUT)
+            {
+                // A blocked key leaves the piece where it is; it is put in
+                // its place when it locks
+                pending = match falling.press(input, &board) {
+                    Some(moved) => {
+                        falling = moved;
+                        next_key(&falling, rotation, x, half_turns)
+                    }
+                    None => None,
+                };
+                changed = true;
+            }
+            if tick.is_multiple_of(gravity) {
+                match falling.fall(&board) {
+                    Some(moved) => {
+                        falling = moved;
+                        changed = true;
+                    }
+                    None if pending.is_none() => break,
+                    None => {}
+                }
+            }
+            if changed {
+                let pps = pieces_per_second(pieces, frames);
+                print!("{}", frame(&board, &falling, next, level, pps));
+            }
+
+            frames += 1;
+            clock += FRAME;
+            thread::sleep(clock.saturating_duration_since(Instant::now()));
+        }
+
+        let cleared = board.apply(piece, x, rotation).unwrap().lines;
+        pieces += 1;
+        let delay = timing.after_lock(cleared);
+        frames += delay as u64;
+        clock += FRAME * delay;
+        thread::sleep(clock.saturating_duration_since(Instant::now()));
+    }
+}
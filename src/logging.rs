@@ -0,0 +1,221 @@
+//! Diagnostics go through `log` to stderr (and optionally a file), so that
+//! results printed to stdout can be piped without progress noise.
+//!
+//! `log_info!`, `log_warn!`, `log_error!` and `log_debug!` take the same
+//! Chinese/English pair as `tr!`.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+// For the macros below, which expand in other crates too
+#[doc(hidden)]
+pub use log;
+
+/// Command line settings for the logger.
+#[derive(Debug, Clone)]
+pub struct LogOptions {
+    pub level: LevelFilter,
+    /// `(module, level)` overrides, e.g. `("mortis::check", Debug)`.
+    pub filters: Vec<(String, LevelFilter)>,
+    pub file: Option<String>,
+}
+
+impl Default for LogOptions {
+    fn default() -> Self {
+        LogOptions {
+            level: LevelFilter::Info,
+            filters: Vec::new(),
+            file: None,
+        }
+    }
+}
+
+impl LogOptions {
+    /// Takes the global logging flags out of `args`: `-v`/`-vv` for more
+    /// detail, `-q`/`-qq` for less, `--log <filters>` and `--log-file <path>`.
+    pub fn extract(args: &mut Vec<String>) -> Self {
+        let mut options = LogOptions::default();
+        let mut verbosity = 0i32;
+        let mut i = 0;
+        while i < args.len() {
+            match args[i].as_str() {
+                "-v" => verbosity += 1,
+                "-vv" => verbosity += 2,
+                "-q" => verbosity -= 1,
+                "-qq" => verbosity -= 2,
+                "--log" | "--log-file" if i + 1 < args.len() => {
+                    let value = args.remove(i + 1);
+                    if args[i] == "--log" {
+                        options.add_filters(&value);
+                    } else {
+                        options.file = Some(value);
+                    }
+                }
+                _ => {
+                    i += 1;
+                    continue;
+                }
+            }
+            args.remove(i);
+        }
+
+        if verbosity != 0 {
+            options.level = match verbosity {
+                ..=-2 => LevelFilter::Error,
+                -1 => LevelFilter::Warn,
+                1 => LevelFilter::Debug,
+                _ => LevelFilter::Trace,
+            };
+        }
+        options
+    }
+
+    /// Parses `level` or `module=level` items separated by commas. Module
+    /// names may omit the crate prefix.
+    fn add_filters(&mut self, spec: &str) {
+        for item in spec.split(',').filter(|s| !s.is_empty()) {
+            match item.split_once('=') {
+                Some((module, level)) => match level.parse() {
+                    Ok(level) => {
+                        let module = if module.starts_with("mortis") {
+                            module.to_string()
+                        } else {
+                            format!("mortis::{}", module)
+                        };
+                        self.filters.push((module, level));
+                    }
+                    Err(_) => eprintln!("--log: {}?", item),
+                },
+                None => match item.parse() {
+                    Ok(level) => self.level = level,
+                    Err(_) => eprintln!("--log: {}?", item),
+                },
+            }
+        }
+    }
+}
+
+struct Logger {
+    level: LevelFilter,
+    filters: Vec<(String, LevelFilter)>,
+    file: Mutex<Option<File>>,
+    start: Instant,
+}
+
+impl Logger {
+    /// The level for `target`: the longest matching module filter, or the
+    /// global level.
+    fn level_for(&self, target: &str) -> LevelFilter {
+        self.filters
+            .iter()
+            .filter(|(module, _)| {
+                target == module
+                    || target
+                        .strip_prefix(module.as_str())
+                        .is_some_and(|rest| rest.starts_with("::"))
+            })
+            .max_by_key(|(module, _)| module.len())
+            .map_or(self.level, |&(_, level)| level)
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level_for(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        match record.level() {
+            Level::Info | Level::Warn | Level::Error => eprintln!("{}", record.args()),
+            _ => eprintln!(
+                "[{} {}] {}",
+                record.level().as_str().to_lowercase(),
+                record.target(),
+                record.args()
+            ),
+        }
+        if let Some(file) = self.file.lock().unwrap().as_mut() {
+            let _ = writeln!(
+                file,
+                "[{:>10.3}s {:<5} {}] {}",
+                self.start.elapsed().as_secs_f64(),
+                record.level(),
+                record.target(),
+                record.args()
+            );
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(file) = self.file.lock().unwrap().as_mut() {
+            let _ = file.flush();
+        }
+    }
+}
+
+static LOGGER: OnceLock<Logger> = OnceLock::new();
+
+pub fn init(options: &LogOptions) {
+    let max = options
+        .filters
+        .iter()
+        .map(|&(_, level)| level)
+        .fold(options.level, |a, b| a.max(b));
+    let logger = LOGGER.get_or_init(|| Logger {
+        level: options.level,
+        filters: options.filters.clone(),
+        file: Mutex::new(None),
+        start: Instant::now(),
+    });
+    if log::set_logger(logger).is_ok() {
+        log::set_max_level(max);
+    }
+    if let Some(path) = &options.file
+        && let Err(e) = log_to_file(Path::new(path))
+    {
+        eprintln!("--log-file {}: {}", path, e);
+    }
+}
+
+/// Appends every log record from now on to `path` as well.
+pub fn log_to_file(path: &Path) -> io::Result<()> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    if let Some(logger) = LOGGER.get() {
+        *logger.file.lock().unwrap() = Some(file);
+    }
+    Ok(())
+}
+
+#[macro_export]
+#[doc(hidden)]
+macro_rules! log_error {
+    ($($t:tt)*) => { $crate::logging::log::error!("{}", $crate::i18n::tr!($($t)*)) };
+}
+
+#[macro_export]
+#[doc(hidden)]
+macro_rules! log_warn {
+    ($($t:tt)*) => { $crate::logging::log::warn!("{}", $crate::i18n::tr!($($t)*)) };
+}
+
+#[macro_export]
+#[doc(hidden)]
+macro_rules! log_info {
+    ($($t:tt)*) => { $crate::logging::log::info!("{}", $crate::i18n::tr!($($t)*)) };
+}
+
+#[macro_export]
+#[doc(hidden)]
+macro_rules! log_debug {
+    ($($t:tt)*) => { $crate::logging::log::debug!("{}", $crate::i18n::tr!($($t)*)) };
+}
+
+pub use crate::{log_debug, log_error, log_info, log_warn};
@@ -1,564 +1,960 @@
-pub mod board;
-pub mod piece;
-use board::{BOARD_HEIGHT, BOARD_WIDTH, Board, FEATURES, WEIGHTS};
-use cmaes::{CMAESOptions, DVector, Mode, PlotOptions};
-use piece::{PieceType, ROTATIONS};
-use rand::Rng;
-use rand::seq::IndexedRandom;
+use mortis::board::WEIGHTS;
+use mortis::dimensions::{Dimensions, with_dimensions};
+use mortis::evaluator::Model;
+use mortis::garbage::GarbageConfig;
+use mortis::generator::Randomizer;
+use mortis::i18n::{Lang, say};
+use mortis::logging::{log_error, log_warn};
+use mortis::mode::{GameMode, Progress};
+use mortis::moves::Moves;
+use mortis::piece::{BuiltinSet, PieceSet};
+use mortis::policy::Visibility;
+use mortis::search::SearchConfig;
+use mortis::{
+    arena, benchmark, check, determinism, geometry, grade, i18n, label, live, logging, multiview,
+    observation, plots, pool, preview, puzzle, qd, quantize, rotation, sandbox, scenario, serve,
+    terminal, theme, timing, train, weights,
+};
 use std::env;
 use std::process::exit;
-use std::thread::sleep;
-use std::{thread, time::Duration};
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let mut args: Vec<String> = env::args().collect();
+
+    logging::init(&logging::LogOptions::extract(&mut args));
+    i18n::set_lang(Lang::from_env());
+    if let Some(i) = args.iter().position(|a| a == "--lang") {
+        args.remove(i);
+        let name = if i < args.len() {
+            args.remove(i)
+        } else {
+            String::new()
+        };
+        match Lang::parse(&name) {
+            Some(lang) => i18n::set_lang(lang),
+            None => log_warn!("--lang 需要 zh 或 en", "--lang expects zh or en"),
+        }
+    }
+    if let Some(rendering) = terminal::Rendering::from_env() {
+        terminal::set_rendering(rendering);
+    }
+    if let Some(i) = args.iter().position(|a| a == "--render") {
+        args.remove(i);
+        let name = if i < args.len() {
+            args.remove(i)
+        } else {
+            String::new()
+        };
+        match terminal::Rendering::parse(&name) {
+            Some(rendering) => terminal::set_rendering(rendering),
+            None => log_warn!(
+                "--render 需要 auto、full 或 half",
+                "--render expects auto, full or half"
+            ),
+        }
+    }
+    if let Some(theme) = theme::Theme::from_env() {
+        theme::set_theme(theme);
+    }
+    if let Some(i) = args.iter().position(|a| a == "--theme") {
+        args.remove(i);
+        let name = if i < args.len() {
+            args.remove(i)
+        } else {
+            String::new()
+        };
+        match theme::Theme::parse(&name) {
+            Some(theme) => theme::set_theme(theme),
+            None => log_warn!(
+                "--theme 需要 classic、high-contrast、colorblind 或 monochrome",
+                "--theme expects classic, high-contrast, colorblind or monochrome"
+            ),
+        }
+    }
+
+    // Applies to every command that loads a weights file
+    let allow_stale_weights = match args.iter().position(|a| a == "--allow-stale-weights") {
+        Some(i) => {
+            args.remove(i);
+            true
+        }
+        None => false,
+    };
+    let dimensions = match Dimensions::extract(&mut args) {
+        Ok(dimensions) => dimensions,
+        Err(e) => {
+            log_error!("{}", "{}", e);
+            exit(1);
+        }
+    };
+    // The commands that can play on a board of another size; the others
+    // are built for the default one only
+    let fixed_size = match (
+        args.get(1).map(String::as_str),
+        args.get(2).map(String::as_str),
+    ) {
+        (Some("preview"), Some(view @ ("grid" | "live"))) => Some(format!("preview {}", view)),
+        (Some("preview" | "train" | "check" | "grade" | "serve" | "versus"), _) => None,
+        (Some(command), _) => Some(command.to_string()),
+        (None, _) => None,
+    };
+    if let Some(command) = fixed_size.filter(|_| !dimensions.is_default()) {
+        log_error!(
+            "{} 只使用 {} 的棋盘, 不支持 --width 和 --height ({}); 支持的命令有 preview、train、check、grade、serve 和 versus",
+            "{} only uses the {} board and does not take --width and --height ({}); preview, train, check, grade, serve and versus do",
+            command,
+            Dimensions::default(),
+            dimensions
+        );
+        exit(1);
+    }
 
     if args.len() <= 1 {
-        println!("Usage: tetris [preview|train <generations>|check <executable>]");
-        println!("  preview: Show AI gameplay visualization");
-        println!("  train: Train the AI with specified generations");
-        println!("  check: Check the AI's performance against a given executable");
+        print_usage();
         return;
     }
 
     match args[1].as_str() {
-        "preview" => preview(),
-        "train" => {
-            let generations = if args.len() > 2 {
-                args[2].parse().unwrap_or(20)
-            } else {
-                20
+        "preview" if args.get(2).is_some_and(|a| a == "grid") => {
+            let count = args.get(3).and_then(|n| n.parse().ok()).unwrap_or(4);
+            let weights = match args.get(4) {
+                Some(path) => match weights::load_weights(path, allow_stale_weights) {
+                    Ok(weights) if !weights.is_empty() => weights,
+                    Ok(_) => {
+                        log_error!("权重文件为空: {}", "Weights file is empty: {}", path);
+                        return;
+                    }
+                    Err(e) => {
+                        log::error!("{}", e);
+                        return;
+                    }
+                },
+                None => vec![WEIGHTS],
             };
-            let target = if args.len() > 3 {
-                args[3].parse().unwrap_or(1_000_000.0)
-            } else {
-                1_000_000.0
+            let Some(temperature) = temperature(&args) else {
+                return;
             };
-            train(generations, target);
+            multiview::multiview(count, &weights, temperature);
         }
-        "check" => {
-            let executable_path = args[2].clone();
-            check(executable_path);
+        "preview" if args.get(2).is_some_and(|a| a == "live") => {
+            let level = args.get(3).and_then(|n| n.parse().ok()).unwrap_or(5);
+            let timing = match flag_value(&args, "--timing") {
+                None => timing::Timing::NES,
+                Some(spec) => match timing::Timing::parse(spec) {
+                    Some(timing) => timing,
+                    None => {
+                        log_error!(
+                            "--timing 需要 instant、nes 或 <出块延迟>,<消行延迟>",
+                            "--timing expects instant, nes or <entry delay>,<line clear delay>"
+                        );
+                        return;
+                    }
+                },
+            };
+            let Some(temperature) = temperature(&args) else {
+                return;
+            };
+            let half_turns = args.iter().any(|a| a == "--rotate-180");
+            live::live(level, &WEIGHTS, timing, temperature, half_turns);
         }
-        "--help" | "-h" | "help" => {
-            println!("Usage: tetris [preview|train <generations>|check <executable>]");
-            println!("  preview: Show AI gameplay visualization");
-            println!("  train: Train the AI with specified generations");
-            println!("  check: Check the AI's performance against a given executable");
+        "preview" => {
+            if let (Some(temperature), Some(pieces), Some(visibility), Some(search)) = (
+                temperature(&args),
+                piece_set(&args),
+                visibility(&args),
+                search(&args),
+            ) && let (Some(garbage), Some(mode)) = (garbage(&args), mode(&args, dimensions))
+                && let (Some(randomizer), Some(moves)) =
+                    (randomizer(&args, pieces.set()), moves(&args))
+                && let Some(model) = model(&args, allow_stale_weights)
+            {
+                let config = train::GameConfig {
+                    max_pieces: usize::MAX,
+                    visibility,
+                    hold: args.iter().any(|a| a == "--hold"),
+                    moves,
+                };
+                with_dimensions!(dimensions, |H, W| preview::preview::<H, W>(
+                    &model,
+                    temperature,
+                    pieces.set(),
+                    &randomizer,
+                    &config,
+                    search,
+                    Progress::new(mode, garbage, rand::random()),
+                ));
+            }
         }
-        _ => {
-            println!("Unknown command. Use 'preview', 'train' or 'check'");
+        "train" => train::train(train::TrainOptions {
+            dimensions,
+            ..train::TrainOptions::from_args(&args[2..])
+        }),
+        "qd" => qd::qd(qd::QdOptions::from_args(&args[2..])),
+        "hof" => {
+            let html = flag_value(&args, "--html").map(std::path::PathBuf::from);
+            let Some(visibility) = visibility(&args) else {
+                return;
+            };
+            let path = args.get(2).map_or("hall_of_fame.txt", |p| p.as_str());
+            let games = args.get(3).and_then(|g| g.parse().ok()).unwrap_or(100);
+            train::tournament(
+                path,
+                games,
+                html.as_deref(),
+                visibility,
+                allow_stale_weights,
+            );
         }
-    }
-}
-
-fn train(generations: usize, target: f64) {
-    println!("开始使用CMAES训练俄罗斯方块AI参数...");
-
-    let running = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(true));
-    let r = running.clone();
-    ctrlc::set_handler(move || {
-        println!("\n接收到Ctrl+C, 正在结束训练...");
-        r.store(false, std::sync::atomic::Ordering::SeqCst);
-    })
-    .expect("Error setting Ctrl+C handler");
-
-    let objective_function = |weights: &DVector<f64>| {
-        let mut weights_array = [0.0; FEATURES];
-        for i in 0..FEATURES {
-            weights_array[i] = weights[i];
+        "puzzle" if args.get(2).is_some_and(|a| a == "generate") => {
+            let out = args.get(3).map_or("puzzles/generated.txt", |p| p.as_str());
+            let games = args.get(4).and_then(|g| g.parse().ok()).unwrap_or(10);
+            let count = args.get(5).and_then(|c| c.parse().ok()).unwrap_or(20);
+            puzzle::generate(out, games, count, &WEIGHTS);
         }
-
-        let norm = weights_array.iter().map(|w| w.powi(2)).sum::<f64>().sqrt();
-        if norm > 0.0 {
-            for w in &mut weights_array {
-                *w /= norm;
+        "puzzle" => {
+            let path = args.get(2).map_or("puzzles/basic.txt", |p| p.as_str());
+            puzzle::puzzle(path, &WEIGHTS);
+        }
+        "sandbox" => sandbox::sandbox(&WEIGHTS),
+        "quantize" => {
+            let bits = match flag_value(&args, "--bits") {
+                None => 16,
+                Some(bits) => match bits.parse() {
+                    Ok(bits) => bits,
+                    Err(_) => {
+                        log_error!("无效的位数: {}", "Invalid bit count: {}", bits);
+                        return;
+                    }
+                },
+            };
+            let weights = match args.get(2).filter(|a| !a.starts_with("--")) {
+                Some(path) => match weights::load_weights(path, allow_stale_weights) {
+                    Ok(weights) if !weights.is_empty() => weights,
+                    Ok(_) => {
+                        log_error!("权重文件为空: {}", "Weights file is empty: {}", path);
+                        return;
+                    }
+                    Err(e) => {
+                        log::error!("{}", e);
+                        return;
+                    }
+                },
+                None => vec![WEIGHTS],
+            };
+            quantize::report(&weights, bits);
+        }
+        "determinism" if args.get(2).is_some_and(|a| a == "verify") => {
+            let path = args
+                .get(3)
+                .map_or("reference/determinism-float.txt", |p| p.as_str());
+            if !determinism::verify(path) {
+                exit(1);
             }
         }
-
-        let num_games = 100;
-        let mut total_score = 0.0;
-
-        for _ in 0..num_games {
-            let score = simulate_game(&weights_array);
-            total_score += score as f64;
+        "determinism" if args.get(2).is_some_and(|a| a == "record") => {
+            let Some(path) = args.get(3).filter(|a| !a.starts_with("--")) else {
+                log_error!(
+                    "用法: determinism record <文件>",
+                    "Usage: determinism record <file>"
+                );
+                return;
+            };
+            let mode = match flag_value(&args, "--mode") {
+                None => determinism::Mode::default(),
+                Some(name) => match determinism::Mode::parse(name) {
+                    Some(mode) => mode,
+                    None => {
+                        log_error!("未知模式: {}", "Unknown mode: {}", name);
+                        return;
+                    }
+                },
+            };
+            let number = |flag: &str, default: u64| match flag_value(&args, flag) {
+                None => Some(default),
+                Some(value) => value.parse().ok().or_else(|| {
+                    log_error!("无效的数字: {}", "Invalid number: {}", value);
+                    None
+                }),
+            };
+            let (Some(seed), Some(pieces)) = (number("--seed", 42), number("--pieces", 500)) else {
+                return;
+            };
+            determinism::record(path, mode, seed, pieces as usize, &WEIGHTS);
         }
-
-        total_score / num_games as f64
-    };
-
-    let initial_weights = DVector::from_vec(vec![0.0; FEATURES]);
-    // let initial_weights = WEIGHTS.to_vec();
-    let initial_step_size = 1.0;
-
-    let mut cmaes_states = CMAESOptions::new(initial_weights, initial_step_size)
-        .mode(Mode::Maximize)
-        .max_generations(generations)
-        .cm(0.8)
-        .weights(cmaes::Weights::Positive)
-        .parallel_update(true)
-        .population_size(240)
-        .enable_plot(PlotOptions::new(0, false))
-        .enable_printing(50)
-        .build(objective_function)
-        .unwrap();
-
-    println!("正在运行CMAES优化, 总共{}代...", generations);
-
-    'main: loop {
-        let result = loop {
-            if let Some(data) = cmaes_states.next_parallel() {
-                break data;
+        "scenario" if args.get(2).is_some_and(|a| a == "run") => {
+            let path = args.get(3).map_or("scenarios/basic.txt", |p| p.as_str());
+            let weights = match args.get(4) {
+                Some(path) => match weights::load_weights(path, allow_stale_weights) {
+                    Ok(weights) if !weights.is_empty() => weights,
+                    Ok(_) => {
+                        log_error!("权重文件为空: {}", "Weights file is empty: {}", path);
+                        exit(1);
+                    }
+                    Err(e) => {
+                        log::error!("{}", e);
+                        exit(1);
+                    }
+                },
+                None => vec![WEIGHTS],
+            };
+            let mut all_passed = true;
+            for (i, weights) in weights.iter().enumerate() {
+                if i > 0 {
+                    println!();
+                }
+                if args.get(4).is_some() {
+                    say!("权重 #{}", "Weights #{}", i + 1);
+                }
+                all_passed &= scenario::run(path, weights);
             }
-
-            if !running.load(std::sync::atomic::Ordering::SeqCst) {
-                cmaes_states
-                    .get_plot()
-                    .unwrap()
-                    .save_to_file("plot.png", true)
-                    .unwrap();
-                println!("优化完成！");
-                print_results(cmaes_states.current_best_individual().unwrap());
-                break 'main;
+            if !all_passed {
+                exit(1);
             }
-        };
-
-        if !running.load(std::sync::atomic::Ordering::SeqCst) {
-            cmaes_states
-                .get_plot()
-                .unwrap()
-                .save_to_file("plot.png", true)
-                .unwrap();
-            println!("优化完成！");
-            print_results(cmaes_states.current_best_individual().unwrap());
-            break 'main;
         }
-
-        if cmaes_states.generation() > generations {
-            cmaes_states
-                .get_plot()
-                .unwrap()
-                .save_to_file("plot.png", true)
-                .unwrap();
-
-            println!("优化完成！");
-            print_results(&result.current_best.unwrap());
-            break 'main;
-        }
-
-        if let Some(ref best) = result.overall_best {
-            if best.value > target {
-                cmaes_states
-                    .get_plot()
-                    .unwrap()
-                    .save_to_file("plot.png", true)
-                    .unwrap();
-
-                println!("优化完成！");
-                print_results(&result.current_best.unwrap());
-                break 'main;
+        "benchmark" => {
+            let Some(visibility) = visibility(&args) else {
+                return;
+            };
+            let Some(randomizer) = randomizer(&args, PieceSet::standard()) else {
+                return;
+            };
+            let observation = match flag_value(&args, "--observation") {
+                None => observation::ObservationMode::default(),
+                Some(name) => match observation::ObservationMode::parse(name) {
+                    Some(mode) => mode,
+                    None => {
+                        log_error!(
+                            "--observation 需要 full 或 heights",
+                            "--observation expects full or heights"
+                        );
+                        return;
+                    }
+                },
+            };
+            let weights = match args.get(2).filter(|a| !a.starts_with("--")) {
+                Some(path) => match weights::load_weights(path, allow_stale_weights) {
+                    Ok(weights) if !weights.is_empty() => weights,
+                    Ok(_) => {
+                        log_error!("权重文件为空: {}", "Weights file is empty: {}", path);
+                        return;
+                    }
+                    Err(e) => {
+                        log::error!("{}", e);
+                        return;
+                    }
+                },
+                None => vec![WEIGHTS],
+            };
+            let schedule = match flag_value(&args, "--schedule") {
+                None => pool::Schedule::default(),
+                Some(name) => match pool::Schedule::parse(name) {
+                    Some(schedule) => schedule,
+                    None => {
+                        log_error!(
+                            "--schedule 需要 game 或 candidate",
+                            "--schedule expects game or candidate"
+                        );
+                        return;
+                    }
+                },
+            };
+            let Some(mode) = mode(&args, dimensions) else {
+                return;
+            };
+            if let GameMode::Marathon { .. } = mode {
+                log_error!(
+                    "基准测试只用直落, 不受重力限制, 无法 marathon",
+                    "The benchmark only drops pieces straight down, which gravity does not limit, so it cannot play a marathon"
+                );
+                return;
+            }
+            let info = benchmark::Information {
+                visibility,
+                observation,
+            };
+            if mode.has_goal() {
+                benchmark::benchmark_goal(&weights, info, &randomizer, schedule, mode);
+            } else {
+                benchmark::benchmark(&weights, info, &randomizer, schedule);
             }
-        };
-    }
-}
-
-fn print_results(best: &cmaes::Individual) {
-    println!("最佳分数: {:.2}", best.value);
-
-    println!("最佳权重数组形式:");
-    print!("[");
-    for (i, &w) in best.point.iter().enumerate() {
-        if i > 0 {
-            print!(", ");
         }
-        print!("{:.6}", w);
-    }
-    println!("]");
-}
-
-fn simulate_game(weights: &[f64; FEATURES]) -> i32 {
-    let mut board = Board::new();
-    let mut rng = rand::rng();
-
-    let num_pieces = 1_000_000;
-
-    for _ in 0..num_pieces {
-        let piece_type = match rng.random_range(0..7) {
-            0 => PieceType::I,
-            1 => PieceType::T,
-            2 => PieceType::O,
-            3 => PieceType::J,
-            4 => PieceType::L,
-            5 => PieceType::S,
-            _ => PieceType::Z,
-        };
-
-        let mut possible_actions = Vec::new();
-        for rotate in 0..4 {
-            let p = &ROTATIONS[piece_type as usize][rotate];
-            for x in 0..=(BOARD_WIDTH - p.width) {
-                if let Some((_, features)) = board.simulate(piece_type, x, rotate) {
-                    let action_score = features
-                        .iter()
-                        .zip(weights.iter())
-                        .map(|(f, w)| f * w)
-                        .sum::<f64>();
-                    possible_actions.push((rotate, x, action_score));
+        "geometry" => {
+            let system = match args.get(2).filter(|a| !a.starts_with("--")) {
+                Some(name) => match rotation::RotationSystem::parse(name) {
+                    Some(system) => system,
+                    None => {
+                        log_error!(
+                            "旋转系统应为 simple 或 srs",
+                            "Rotation system must be simple or srs"
+                        );
+                        return;
+                    }
+                },
+                None => rotation::RotationSystem::default(),
+            };
+            geometry::export(system, flag_value(&args, "--out").map(std::path::Path::new));
+        }
+        "check" => {
+            let executable_path = args[2].clone();
+            let options = check::CheckOptions {
+                dimensions,
+                ..check::CheckOptions::from_args(&args[3..])
+            };
+            check::check(executable_path, options);
+        }
+        "versus" => {
+            let paths: Vec<&String> = args[2..]
+                .iter()
+                .take_while(|a| !a.starts_with("--"))
+                .take(2)
+                .collect();
+            let options = arena::VersusOptions {
+                dimensions,
+                ..arena::VersusOptions::from_args(&args[2 + paths.len()..])
+            };
+            let mut sides = [Model::Linear(WEIGHTS), Model::Linear(WEIGHTS)];
+            for (side, path) in sides.iter_mut().zip(&paths) {
+                match weights::load_models(path, allow_stale_weights) {
+                    Ok(mut models) if !models.is_empty() => *side = models.swap_remove(0),
+                    Ok(_) => {
+                        log_error!("权重文件为空: {}", "Weights file is empty: {}", path);
+                        return;
+                    }
+                    Err(e) => {
+                        log::error!("{}", e);
+                        return;
+                    }
                 }
             }
+            if options.program.is_some() && paths.len() > 1 {
+                log_warn!(
+                    "B 方由 --program 的程序执行, 忽略第二个权重文件",
+                    "Side B is played by the --program, ignoring the second weights file"
+                );
+            }
+            arena::versus(&sides[0], &sides[1], &options);
         }
-
-        if possible_actions.is_empty() {
-            break;
+        "serve" => {
+            let path = args.get(2).filter(|a| !a.starts_with("--"));
+            let flags = &args[2 + path.is_some() as usize..];
+            let options = serve::ServeOptions {
+                dimensions,
+                ..serve::ServeOptions::from_args(flags)
+            };
+            let model = match path {
+                Some(path) => match weights::load_models(path, allow_stale_weights) {
+                    Ok(mut models) if !models.is_empty() => models.swap_remove(0),
+                    Ok(_) => {
+                        log_error!("权重文件为空: {}", "Weights file is empty: {}", path);
+                        return;
+                    }
+                    Err(e) => {
+                        log::error!("{}", e);
+                        return;
+                    }
+                },
+                None => Model::Linear(WEIGHTS),
+            };
+            if let Err(e) = serve::serve(&model, &options) {
+                log_error!("输入输出错误: {}", "I/O error: {}", e);
+            }
         }
-
-        possible_actions.sort_by(|a, b| a.2.partial_cmp(&b.2).unwrap_or(std::cmp::Ordering::Equal));
-        let best_action = possible_actions[0];
-
-        board
-            .apply(piece_type, best_action.1, best_action.0)
-            .unwrap();
-    }
-
-    board.get_score()
-}
-
-fn preview() {
-    let mut board = Board::new();
-    let mut rng = rand::rng();
-    let piece_symbols = ['I', 'T', 'O', 'J', 'L', 'S', 'Z'];
-    let piece_colors = [
-        "\x1B[36m", "\x1B[35m", "\x1B[33m", "\x1B[34m", "\x1B[31m", "\x1B[32m", "\x1B[91m",
-    ];
-
-    println!("Tetris AI Preview (按Ctrl+C退出)");
-
-    let mut current_piece_type = get_random_piece(&mut rng);
-    let mut next_piece_type = get_random_piece(&mut rng);
-
-    let mut last_score = 0;
-
-    loop {
-        let mut possible_actions = Vec::new();
-        for rotate in 0..4 {
-            let p = &ROTATIONS[current_piece_type as usize][rotate];
-            for x in 0..=(BOARD_WIDTH - p.width) {
-                if let Some((_, features)) = board.simulate(current_piece_type, x, rotate) {
-                    let action_score = features
-                        .iter()
-                        .zip(WEIGHTS.iter())
-                        .map(|(f, w)| f * w)
-                        .sum::<f64>();
-                    possible_actions.push((rotate, x, action_score));
-                }
+        "label" => {
+            let path = args.get(2).filter(|a| !a.starts_with("--"));
+            let flags = &args[2 + path.is_some() as usize..];
+            let options = label::LabelOptions::from_args(flags);
+            let weights = match path {
+                Some(path) => match weights::load_weights(path, allow_stale_weights) {
+                    Ok(weights) if !weights.is_empty() => weights[0],
+                    Ok(_) => {
+                        log_error!("权重文件为空: {}", "Weights file is empty: {}", path);
+                        return;
+                    }
+                    Err(e) => {
+                        log::error!("{}", e);
+                        return;
+                    }
+                },
+                None => WEIGHTS,
+            };
+            if let Err(e) = label::label(&weights, &options) {
+                log::error!("{}", e);
             }
         }
-
-        if possible_actions.is_empty() {
-            println!("游戏结束！无法放置方块: {:?}", current_piece_type);
-            break;
+        "grade" => {
+            let (submissions, mut options) = grade::GradeOptions::from_args(&args[2..]);
+            options.check.dimensions = dimensions;
+            if submissions.is_empty() {
+                log_error!(
+                    "grade 需要至少一个可执行文件",
+                    "grade needs at least one executable"
+                );
+                return;
+            }
+            grade::grade(&submissions, &options);
         }
-
-        possible_actions.sort_by(|a, b| a.2.partial_cmp(&b.2).unwrap_or(std::cmp::Ordering::Equal));
-        let best_action = possible_actions[0];
-
-        board
-            .apply(current_piece_type, best_action.1, best_action.0)
-            .unwrap();
-
-        // print!("\x1B[2J\x1B[1;1H");
-
-        let score = board.get_score();
-
-        println!("╔══════════════════════════════════════╗");
-        println!("║ Tetris AI Preview - Score: {:<9} ║", score);
-        println!("╚══════════════════════════════════════╝");
-
-        display_game_with_next_piece(
-            &board,
-            current_piece_type,
-            next_piece_type,
-            best_action,
-            piece_symbols,
-            piece_colors,
-        );
-
-        current_piece_type = next_piece_type;
-        next_piece_type = get_random_piece(&mut rng);
-
-        if score - last_score > 100 {
-            exit(0);
+        "plots" if args.get(2).is_some_and(|a| a == "compare") => {
+            let (runs, options) = plots::CompareOptions::from_args(&args[3..]);
+            if runs.is_empty() {
+                log_error!(
+                    "plots compare 需要至少一个运行目录",
+                    "plots compare needs at least one run directory"
+                );
+                return;
+            }
+            if let Err(e) = plots::compare(&runs, &options) {
+                log_error!("{}", "{}", e);
+                exit(1);
+            }
+        }
+        "--help" | "-h" | "help" => print_usage(),
+        _ => {
+            log_error!(
+                "未知命令。可用命令: 'preview'、'train'、'qd'、'hof'、'puzzle'、'scenario'、'sandbox'、'benchmark'、'quantize'、'determinism'、'geometry'、'check'、'versus'、'serve'、'label'、'grade' 或 'plots compare'",
+                "Unknown command. Use 'preview', 'train', 'qd', 'hof', 'puzzle', 'scenario', 'sandbox', 'benchmark', 'quantize', 'determinism', 'geometry', 'check', 'versus', 'serve', 'label', 'grade' or 'plots compare'"
+            );
         }
-        last_score = score;
-
-        thread::sleep(Duration::from_millis(100));
     }
 }
 
-fn get_random_piece(rng: &mut impl Rng) -> PieceType {
-    match rng.random_range(0..7) {
-        0 => PieceType::I,
-        1 => PieceType::T,
-        2 => PieceType::O,
-        3 => PieceType::J,
-        4 => PieceType::L,
-        5 => PieceType::S,
-        _ => PieceType::Z,
-    }
+const USAGE_ZH: &str =
+    "用法: tetris [--lang zh|en] [--theme <主题>] [--render <方式>] [-v|-q] [--log <过滤>] [--log-file <路径>] [--allow-stale-weights] [--width <n>] [--height <n>] <命令> [选项]
+  -v/-vv, -q/-qq: 增加或减少输出到stderr的诊断信息
+  --log <过滤>: 按模块设置级别, 例如 'warn,check=debug'
+  --log-file <路径>: 同时将诊断信息追加到文件
+  --theme classic|high-contrast|colorblind|monochrome: 预览和导出图片的配色, 也可用环境变量 MORTIS_THEME 设置 (默认 classic)
+  --render auto|full|half: 预览中的棋盘每行一格, 或用半格字符每行两格以便放进 24 行的终端; auto 只在终端放不下时使用半格, 也可用环境变量 MORTIS_RENDER 设置 (默认 auto)
+  --allow-stale-weights: 权重文件标记的特征版本与当前程序不同时只发出警告而不拒绝; 名人堂和 qd 写出的文件带有此标记
+  --width <n>, --height <n>: preview (grid 和 live 除外)、train、check、grade、serve 和 versus 所用棋盘的列数和行数, 可选 10x15、10x20、10x22、10x24、12x20 和 12x24; check 和 versus 的程序须使用同样的棋盘, 恢复训练时沿用日志中的值 (默认 10x15)
+  preview: 预览AI对局, 结束或按Ctrl+C时打印总结: 方块数、分数、各类消行、最大高度走势和决策差距
+    --piece-set tetromino|pentomino: 使用四格方块或 18 种单面五格方块 (默认 tetromino)
+    --randomizer uniform|bag|history|adversarial|file:<路径>: 出块方式: 均匀随机、整袋洗牌 (四格方块即 7-bag)、TGM 式历史重抽、总是给出对内置权重最不利的方块 (hatetris), 或循环使用文件中的方块字母序列 (默认 uniform)
+    --hold: 允许把当前方块放入暂存格, 换出暂存的方块或在暂存格为空时换出下一个方块; 暂存格显示在 NEXT 下方
+    --visibility current|next|expected: next 时每步对当前方块的每个落点再加上下一个方块的最佳落点来评估; expected 时下一个方块未知, 改为加上所有方块最佳落点评估的平均 (expectimax); 与 --temperature 同用时按这一评估随机选择 (默认 current)
+    --search depth=<n>,beam=<k>[,table=<t>]: 对接下来 n 个方块做束搜索, 每层只保留最好的 k 个棋盘; 预览随之显示 n-1 个方块中的第一个, 同时忽略 --visibility 和 --temperature; 置换表记住最近展开的 t 个棋盘, 供之后的搜索复用, 0 表示不用 (默认 depth=1,beam=16,table=4096)
+    --garbage every=<n>,lines=<k>: 每放 n 个方块, 从底部推入 k 行垃圾, 各行的空洞在同一个随机列; 垃圾把方块堆推出棋盘时游戏结束 (默认 every=10,lines=1)
+    --mode endless|dig[=<行数>]|sprint[=<行数>]|marathon[=<每级行数>]: endless 一直玩到顶出; dig 时棋盘开局铺着若干行垃圾 (默认 10 行), 每行一个随机空洞, 与下一行错开, 全部挖完时结束; sprint 消除若干行 (默认 40 行) 时结束; marathon 从 0 级开始, 每消若干行 (默认 10 行) 升一级, 方块按 NES 该级的重力下落, 在 --moves full 时限制来得及移动到的落点, 一直玩到顶出; 总结中显示用了多少方块, sprint 还显示模拟用时: 每个方块 24 帧加上 NES 的出块和消行延迟, marathon 显示到达的等级 (默认 endless)
+    --moves drop|full|full-180: 落点的来源, 见 train; full 和 full-180 时总是选择最佳落点, 忽略 --hold、--search 和 --temperature (默认 drop)
+    --weights <文件>: 用文件中的第一组权重或神经网络评估落点, 如 train --model mlp 的名人堂 (默认内置权重)
+  preview grid [数量] [权重文件]: 同步并排进行1-9局对局
+  preview live [等级]: 以经典重力实时演示, 模拟按键操作 (默认等级 5)
+    --timing instant|nes|<出块>,<消行>: 锁定后的出块延迟和消行延迟, 单位为帧 (默认 nes, 即 10,18)
+    --rotate-180: 允许一次按键旋转 180°, 受阻时按 SRS+ 的 180° 踢墙表尝试偏移
+    --temperature <t>: 三种预览均可用, 按评估值的 softmax 随机选择落点, t 以候选评估值的标准差为单位; 0 表示只在并列最佳中随机选择 (默认总是选最佳)
+  train [代数] [目标分数]: 按指定代数训练AI参数
+    --run-dir <目录>: plot.png、hall_of_fame.txt 和 checkpoint.txt 的保存位置 (默认 runs/<时间>)
+    --save-log: 同时将诊断信息写入运行目录中的 train.log
+    --clips off|replay|gif: 每当出现新的最佳权重时, 在后台用它在第一局验证对局的方块上下 200 个方块, 把落点写入运行目录的 clips/gen-<代>.txt, gif 时另存一张动图; 俄罗斯方块的片段可以输入 sandbox 逐步查看 (默认 replay)
+    --seed <n>: 随机种子, 相同种子和参数的运行结果完全相同
+    --resume <目录>: 用相同参数继续该目录中的运行, 结果与未中断的运行一致
+    --on-anomaly stop|rollback|ignore: 出现 NaN、步长爆炸或适应度崩溃时停止训练、回滚到上一个健康的检查点 (最多3次) 或只记录; 报告写入运行目录中的 anomaly.txt (默认 stop)
+    --non-finite skip|clamp|error: 权重或评估出现 NaN 或无穷大时: 跳过这组权重并且不选这个落点、把它截断到有限值, 或在本代结束时停止训练; 每代的次数写入日志 (默认 skip)
+    --cpu-fraction <f>: 使用的CPU核心比例, 种群大小随线程数调整 (默认 1)
+    --hof-size <k>: 名人堂保存的不同精英数量 (默认 10)
+    --validation-games <n>: 重新评估精英所用的局数 (默认 100)
+    --population-games <n>: 每个候选的评估局数, 恢复运行时沿用日志中的值 (默认 100)
+    --elite-games <n>: 名人堂第一名重新评估的局数, 报告的最佳分数和目标判断都以此为准 (默认 1000)
+    --max-pieces <n>: 训练和验证中每局最多放置的方块数, 恢复运行时沿用日志中的值 (默认 1000000)
+    --piece-set tetromino|pentomino: 训练所用的方块, 恢复运行时沿用日志中的值 (默认 tetromino)
+    --randomizer uniform|bag|history|adversarial|file:<路径>: 训练和验证对局的出块方式, 见 preview; adversarial 每个方块要多评估整套方块, 对局慢得多; 恢复运行时沿用日志中的值 (默认 uniform)
+    --hold: 训练和验证对局带暂存格, 见 preview; 恢复运行时沿用日志中的值
+    --visibility current|next|expected: 训练和验证对局中策略是否参考下一个方块, 见 preview; next 和 expected 每局慢得多, 使用 --hold 时不起作用; 恢复运行时沿用日志中的值 (默认 current)
+    --moves drop|full|full-180: 落点的来源; drop 时方块从上方直落, full 时从出生位置开始左右移动、下落和旋转 (俄罗斯方块按 SRS 踢墙), full-180 时还可一次旋转 180° (俄罗斯方块按 SRS+ 踢墙), 可以塞到悬空部分下面或转进缝隙, T 旋按三角规则判定并按指南另加分数; full 每局慢得多, 只看当前方块, 忽略 --hold 和 --visibility, 片段每行多记一个落点行号, T 旋时再记 spin; 恢复运行时沿用日志中的值 (默认 drop)
+    --garbage every=<n>,lines=<k>: 训练和验证对局中推入垃圾行, 见 preview; 不录制片段; 恢复运行时沿用日志中的值 (默认没有)
+    --mode endless|dig[=<行数>]|sprint[=<行数>]|marathon[=<每级行数>]: 训练和验证对局的玩法, 见 preview; dig 和 sprint 时适应度为每挖掉一行垃圾或每消一行 1000 分减去所用方块数, 以最少方块达成目标; marathon 的适应度仍为分数, 重力只在 --moves full 时起作用; 非 endless 时不录制片段; 恢复运行时沿用日志中的值 (默认 endless)
+    --objective score|wins|damage: 候选的适应度; score 为单人对局的分数或目标进度; wins 和 damage 时每个候选在 --population-games 局对战中与名人堂第一名 (最初为内置权重) 交换垃圾行, 轮流先手, 每局最多 --max-pieces 且不超过 1000 个方块, 见 versus; wins 按胜率 (百分比, 平局算半场), damage 按平均送出减去收到的垃圾行数; 对战不使用 --mode、--garbage、--hold、--moves full 和 --novelty, 名人堂仍按单人对局验证; 恢复运行时沿用日志中的值 (默认 score)
+    --attack <单消>,<双消>,...[/<延迟>]: wins 和 damage 对战的攻击表, 见 versus; 恢复运行时沿用日志中的值 (默认 0,1,2,4)
+    --model linear|mlp|bonus: linear 训练特征的线性权重; mlp 训练 13-32-1 的 tanh 神经网络, 其 481 个参数作为一个向量由 CMA-ES 搜索, 计数特征先除以棋盘宽度; 名人堂每行存一个网络, 以 mlp 开头, preview --weights、serve 和 versus 都可读取; mlp 每局慢得多, 不能与 --freeze、--sign、--bound 和 --warm-start 同用, 不录制片段, 异常时不回滚; bonus 在线性权重之外再训练连击长度、背靠背和全清三个奖励项的权重, 名人堂每行以 bonus 开头, 与 --scoring 一起使用, 限制同 mlp; 恢复运行时沿用日志中的值 (默认 linear)
+    --scoring guideline|<文件>: 训练和验证对局的计分方式, 与 check --scoring 一致; 恢复运行时沿用日志中的规则 (默认每消 1 到 4 行 100/300/500/800 分)
+    --novelty <w>: 奖励与先前候选风格不同的策略 (默认 0)
+    --warm-start <路径>: 从之前运行的 checkpoint.txt 或运行目录热启动
+    --freeze <i>=<v>: 将特征 i 的归一化权重固定为 v
+    --sign <i>=+|-: 保持特征 i 的权重非负或非正
+    --bound <i>=<lo>:<hi>: 将特征 i 的权重限制在范围内, 任一端可省略
+  qd [轮数]: 为每种对局风格找出最佳策略, 填满风格网格
+    --out <目录>: grid.txt 和各格权重的保存位置 (默认 runs/qd-<时间>)
+    --games <n>: 每次评估的局数 (默认 20)
+    --resolution <n>: 每个行为维度的分格数 (默认 10)
+  hof <文件> [局数]: 在相同种子上重新评估文件中的每组权重
+    --html <文件>: 同时生成包含分数分布和最终棋盘的报告
+    --visibility current|next|expected: 内置策略是否利用下一个方块, 见 preview (默认 current)
+  puzzle [文件]: 用精选局面测试AI (默认 puzzles/basic.txt)
+  puzzle generate [输出] [局数] [数量]: 从AI对局中挖掘明确的局面
+  scenario run [文件] [权重文件]: 按脚本化场景检查AI的行为, 有断言失败时退出码为 1 (默认 scenarios/basic.txt)
+  sandbox: 自由模式, 逐个选择方块并手动放置或交给AI, 可导出为谜题棋盘格式, 也可保存对局稍后继续
+  benchmark [权重文件]: 在固定的基准套件上评估权重, 输出可比较的综合得分, 以及到达的不同地形和棋盘数
+    --visibility current|next|expected: 内置策略是否利用下一个方块, 见 preview; 不是 current 时同时给出贪心策略 (current) 的综合得分作对比 (默认 current)
+    --observation full|heights: 内置策略看到完整棋盘或只有列高和空洞数 (默认 full)
+    --schedule game|candidate: 每局棋作为一个任务由空闲线程窃取, 或每组权重的所有对局在一个线程上依次进行; 结束时显示核心利用率 (默认 game)
+    --randomizer uniform|bag|history|adversarial|file:<路径>: 用例保留种子和长度, 但按此方式出块; 综合得分只能与同一出块方式的结果比较 (默认 uniform)
+    --mode endless|dig[=<行数>]|sprint[=<行数>]: 以 dig 或 sprint 方式玩每个用例, 开局垃圾由用例种子决定, 报告各用例的进度、所用方块和 sprint 的模拟用时, 不计算综合得分 (默认 endless)
+  quantize [权重文件]: 将权重量化为定点整数, 用纯整数评估在基准套件上与浮点策略比较
+    --bits <n>: 每个权重的位数, 2 到 24 (默认 16)
+  determinism verify [文件]: 重放参考对局并逐步比较落点, 检测与平台相关的浮点差异, 不一致时退出码为 1 (默认 reference/determinism-float.txt)
+  determinism record <文件>: 用内置权重记录参考对局
+    --mode float|integer: 浮点评估或不含浮点运算的 16 位定点评估, 后者在任何平台上结果相同, 适合评分 (默认 float)
+    --seed <n>, --pieces <n>: 方块序列的种子和长度 (默认 42, 500)
+  geometry [simple|srs]: 以JSON输出各方块各旋转的格子、宽度和合法x范围
+    --out <文件>: 写入文件而不是stdout
+  check <可执行文件>: 检查给定程序的实现和表现, 程序可用 'R' 代替行动认输
+    --summary: 在结束标记后读取 '<分数> <行数> <方块数>' 结算行
+    --negotiate: 开局前发送 'P <n>', 程序以 'P <m>' (m <= n) 回应约定方块数
+    --legal-count: 行动行末尾附带程序看到的合法位置数
+    --board-every <k>: 每 k 个方块, 在分数行后读取程序的棋盘编码
+    --observation heights: 每个方块行后附带 'O <10个列高> <空洞数>' 行
+    --pieces <n>: 方块序列长度, 最后一个方块只作为预览 (默认 1000000)
+    --piece-set tetromino|pentomino: 方块组; 五格方块以大写字母表示, 其镜像以小写字母表示, 只能使用 simple 旋转系统 (默认 tetromino)
+    --randomizer uniform|bag|history|file:<路径>: 方块序列的出块方式, 见 preview; 序列在开局前生成, 因此不支持 adversarial (默认 uniform)
+    --hold: 程序可在行动行前发送 'H' 暂存当前方块, 之后的行动放置换出的方块; 暂存格为空时换出的是预览方块, 随后发送新的预览方块行; 每个方块只能暂存一次
+    --search depth=<n>,beam=<k>: 预览 n-1 个方块而不是 1 个, 供做搜索的程序使用, 如 serve --search; 序列最后 n-1 个方块只作为预览
+    --scoring guideline|<文件>: 按指南规则 (等级倍数、硬降分、T 旋、连击、背靠背和全清奖励) 或规则文件计分, 程序的分数须与之一致; 文件每行一个 '<键> <值>', 键为 lines、level、drop、spin-mini、spin、combo、back-to-back、all-clear (默认每消 1 到 4 行 100/300/500/800 分)
+    --hidden <行数>: 棋盘顶部在可见区域之上的隐藏行数, 方块在其中生成; 生成位置被占或方块整个锁定在隐藏行中时对局结束, 如 --width 10 --height 22 --hidden 2 即指南的 20 行棋盘 (默认 0)
+    --time-limit <秒>: 超时后结束对局 (默认 10)
+    --move-time <秒>: 程序每次回应 (行动、分数或棋盘行) 的时限, 超时即在该方块出错 (默认 5)
+    --end marker|close: 以 'E' 行或关闭stdin通知对局结束 (默认 marker)
+    --rotation simple|srs: 程序输出所用的坐标约定
+    --image <file.png|file.svg>: 将最终棋盘保存为图片
+    --keyframes <n>: 配合 --image, 每 n 个方块额外保存一张棋盘图片
+    --seed <n>: 方块序列的种子, 用于复现某局 (默认每次随机, 并在日志中给出)
+    --mode endless|sprint[=<行数>]: sprint 时消除若干行 (默认 40 行) 后发送结束标记, 并报告所用方块和模拟用时, 见 preview (默认 endless)
+  grade <可执行文件>...: 在相同种子上逐个检查提交, 按平均分给出等级, 并将每局结果和最终棋盘存档
+    --games <n>: 每份提交的局数 (默认 5)
+    --seed <n>: 第一局的种子, 第 i 局使用 n+i (默认 1)
+    --out <目录>: 存档目录, 包含 grades.csv、grades.html 和每份提交的子目录 (默认 runs/grade-<时间>)
+    --scale <等级>=<分数>,...: 平均分达到阈值即得该等级, 低于所有阈值得最低等级 (默认 A=200000,B=100000,C=50000,D=10000,F=0)
+    其余 check 选项原样用于每一局
+  serve [权重文件]: check 的反向, 文件中的第一组权重或神经网络做决定: 从标准输入逐行读取方块字母, 用自己的棋盘选择落点并输出 '<旋转> <x>' 和分数, 无处可放时输出 'R'; 'E' 或输入结束时停止
+    --preview <n>: 当前方块之外再等待 n 个方块才落子 (默认 1, 与 check 一致; 0 表示收到即落子)
+    --visibility current|next|expected: 已知下一个方块时是否参考它, expected 时对所有可能取平均 (默认 current)
+    --hold: 使用暂存格, 暂存时在行动行前输出 'H', 与 check --hold 配合
+    --search depth=<n>,beam=<k>: 用对整个队列的束搜索选择落点, 忽略 --visibility; 未给出 --preview 时等待 n-1 个预览方块, 与 check --search 一致
+    --mcts nodes=<n>|time=<毫秒>ms[,depth=<d>,c=<x>]: 用蒙特卡洛树搜索选择落点, 每条路径放置 d 个方块, 队列之后的方块随机抽取, 树外的部分贪心放置; 优先于 --search, 忽略 --visibility (默认 nodes=400,depth=2,c=1)
+    --piece-set tetromino|pentomino: 方块集合 (默认 tetromino)
+    --scoring guideline|<文件>: 分数行的计分方式, 与 check --scoring 一致
+    --hidden <行数>: 隐藏行数, 与 check --hidden 一致
+    --legal-count, --board-every <k>, --summary: 与 check 的同名选项配合
+    收到 'G <行数> <空洞列>' 时把对手 (见 versus) 的垃圾行推入自己的棋盘
+  versus [权重文件 A] [权重文件 B]: 两方由文件中的第一组权重或神经网络执行, 两方在相同的方块序列上轮流落子, 消行按攻击表变成送给对手的垃圾行, 先抵消自己待收的垃圾; 垃圾在不消行的落子之后进入棋盘 (可设延迟), 无处可放或被垃圾顶出的一方输; 两方每局交替先手, 打印每局结果、双方平均消行和收发垃圾、每块攻击、清除收到垃圾的比例、有垃圾时的平均堆高以及总胜者 (默认内置权重)
+    --games <n>: 局数 (默认 10)
+    --pieces <n>: 每局方块序列的长度 (默认 1000)
+    --piece-set tetromino|pentomino: 方块集合 (默认 tetromino)
+    --attack <单消>,<双消>,...[/<延迟>]: 消除 1、2... 行送出的垃圾行数, 超出部分按最后一个值; 带 /n 时垃圾要等收方再落 n 块才进入棋盘, 期间可被抵消 (默认 0,1,2,4)
+    --program <可执行文件>: B 方改由按 check 协议对局的程序执行, 每局启动一次; 程序收到 'G <行数> <空洞列>' 时须把垃圾推入自己的棋盘, 如 serve
+    --move-time <秒>: 程序每步的时限, 超时即输掉该局, 与 check 一致 (默认 5)
+    --visibility current|next|expected, --randomizer <方式>: 见 preview
+    --seed <n>: 方块序列和垃圾空洞的种子 (默认随机)
+  label [权重文件]: 用快速策略对局, 每隔几个方块把局面交给深度束搜索作为教师标注, 输出 '<棋盘> <方块> <旋转> <x> <评估>' 数据集, 用于把搜索蒸馏进快速策略; 日志给出学生与教师一致的比例
+    --search depth=<n>,beam=<k>: 教师的搜索, 见 preview (默认 depth=3,beam=64)
+    --games <n>, --seed <n>: 局数和第一局的种子, 第 i 局使用 n+i (默认 8, 1)
+    --every <k>: 每 k 个方块标注一个局面 (默认 10)
+    --max-pieces <n>: 每局最多的方块数 (默认 2000)
+    --piece-set tetromino|pentomino, --randomizer <方式>: 见 preview
+    --out <文件>: 数据集的保存位置 (默认 labels.txt)
+  plots compare <运行目录>...: 读取各次训练的 evaluations.txt, 把每代最佳适应度和目前最佳适应度随评估次数的曲线叠加画在一张图上, 并打印每次运行的代数、评估次数和最佳适应度
+    --out <文件>: PNG 图片的保存位置 (默认 compare.png)
+    --labels <名称>,...: 按顺序为各次运行命名 (默认用目录名)
+";
+
+const USAGE_EN: &str =
+    "Usage: tetris [--lang zh|en] [--theme <theme>] [--render <mode>] [-v|-q] [--log <filters>] [--log-file <path>] [--allow-stale-weights] [--width <n>] [--height <n>] <command> [options]
+  -v/-vv, -q/-qq: more or less diagnostic output on stderr
+  --log <filters>: levels per module, e.g. 'warn,check=debug'
+  --log-file <path>: also append diagnostics to a file
+  --theme classic|high-contrast|colorblind|monochrome: colors for the previews and exported images, also settable with the MORTIS_THEME environment variable (default classic)
+  --render auto|full|half: draw the preview boards one row per line, or two per line with half block characters so that they fit a 24-line terminal; auto only uses half blocks when the terminal is too small, also settable with the MORTIS_RENDER environment variable (default auto)
+  --allow-stale-weights: only warn instead of refusing when a weights file is stamped for a different feature set than this build has; hall of fame and qd files carry the stamp
+  --width <n>, --height <n>: columns and rows of the board for preview (but not grid or live), train, check, grade, serve and versus, one of 10x15, 10x20, 10x22, 10x24, 12x20 and 12x24; the program under check or versus must play on the same board, and a resumed training run keeps the size of its log (default 10x15)
+  preview: Show AI gameplay visualization, with a summary at game over or Ctrl+C: pieces, score, line clears, max height over time and decision margins
+    --piece-set tetromino|pentomino: play with the tetrominoes or the 18 one-sided pentominoes (default tetromino)
+    --randomizer uniform|bag|history|adversarial|file:<path>: how pieces are drawn: uniformly, from shuffled bags, with TGM-style history rerolls, always the worst piece for the built-in weights (hatetris), or cycling through the piece letters of a file (default uniform)
+    --hold: allow putting the current piece in a hold slot and playing the held one, or the next one while the slot is empty; the slot is shown below NEXT
+    --visibility current|next|expected: with next, every placement of the current piece is rated together with the best placement of the next piece that follows it; with expected the next piece stays unknown and the mean over every piece's best placement is added instead (expectimax); --temperature samples by that rating (default current)
+    --search depth=<n>,beam=<k>[,table=<t>]: beam search over the next n pieces, keeping the best k boards after each; the first of the n-1 previewed pieces is shown, and --visibility and --temperature are ignored; a transposition table keeps the last t boards expanded for later searches to reuse, 0 for none (default depth=1,beam=16,table=4096)
+    --garbage every=<n>,lines=<k>: after every n pieces, push k rows of garbage into the bottom of the board, sharing a hole in a random column; the game ends when the garbage pushes the stack out of the well (default every=10,lines=1)
+    --mode endless|dig[=<rows>]|sprint[=<lines>]|marathon[=<lines per level>]: endless plays until the stack tops out; dig starts on rows of garbage (10 by default), each with one hole in a random column unlike the row below, and ends once they are all cleared; sprint ends once that many lines are cleared (40 by default); marathon starts at level 0, goes a level up every that many lines (10 by default) and plays until the stack tops out, with the piece falling at the NES gravity of the level, which with --moves full limits the placements it can be moved to in time; the summary gives the pieces it took, for sprint the time on a simulated clock of 24 frames a piece plus the NES entry and line clear delays, and for marathon the level reached (default endless)
+    --moves drop|full|full-180: where placements come from, see train; full and full-180 always plays the best placement and ignores --hold, --search and --temperature (default drop)
+    --weights <file>: rate the placements with the first weight vector or network of the file, such as the hall of fame of train --model mlp (default built-in weights)
+  preview grid [count] [weights file]: Play 1-9 games side by side in lockstep
+  preview live [level]: Real-time demo with classic gravity and animated key presses (default level 5)
+    --timing instant|nes|<entry>,<clear>: entry delay and line clear delay after a lock, in frames (default nes, i.e. 10,18)
+    --rotate-180: allow 180° rotations as a single key press, trying the SRS+ 180° kicks when blocked
+    --temperature <t>: for all three previews, sample placements by a softmax over their evaluations, t in standard deviations of the candidates' scores; 0 picks at random among tied best placements (default always the best)
+  train [generations] [target]: Train the AI with specified generations
+    --run-dir <dir>: where plot.png, hall_of_fame.txt and checkpoint.txt go (default runs/<time>)
+    --save-log: also write diagnostics to train.log in the run dir
+    --clips off|replay|gif: whenever a new best turns up, play it in the background for 200 pieces of the first validation game and write the moves to clips/gen-<generation>.txt in the run dir, with gif also as an animation; tetromino clips can be piped into sandbox to step through (default replay)
+    --seed <n>: random seed; runs with the same seed and flags are identical
+    --resume <dir>: continue the run in dir with the same flags, matching an uninterrupted run
+    --on-anomaly stop|rollback|ignore: on NaNs, a step size explosion or a fitness collapse, stop, roll back to the last healthy checkpoint (at most 3 times) or only report it; reports go to anomaly.txt in the run dir (default stop)
+    --non-finite skip|clamp|error: on a NaN or infinity in weights or an evaluation, skip those weights and avoid that placement, clamp it to a finite value, or stop training at the end of the generation; counts are logged every generation (default skip)
+    --cpu-fraction <f>: share of CPU cores to use; the population scales with the threads (default 1)
+    --hof-size <k>: number of distinct elites to archive (default 10)
+    --validation-games <n>: games used to re-evaluate elites (default 100)
+    --population-games <n>: games per candidate evaluation; a resumed run keeps the value from its log (default 100)
+    --elite-games <n>: games to re-evaluate the hall of fame leader on; the reported best score and the target check use them (default 1000)
+    --max-pieces <n>: pieces after which a training or validation game ends; a resumed run keeps the value from its log (default 1000000)
+    --piece-set tetromino|pentomino: the pieces to train with; a resumed run keeps the value from its log (default tetromino)
+    --randomizer uniform|bag|history|adversarial|file:<path>: how training and validation games draw their pieces, see preview; adversarial tries the whole set before every piece, so games get much slower; a resumed run keeps the value from its log (default uniform)
+    --hold: training and validation games have a hold slot, see preview; a resumed run keeps the value from its log
+    --visibility current|next|expected: whether the policy looks ahead to the next piece in training and validation games, see preview; next and expected make games much slower and does nothing with --hold; a resumed run keeps the value from its log (default current)
+    --moves drop|full|full-180: where placements come from: with drop the piece falls straight from above, with full it is shifted, soft-dropped and turned from its spawn (tetrominoes with the SRS kicks), and full-180 also turns it 180° in one go (tetrominoes with the SRS+ kicks), so it can tuck under overhangs and spin into slots, with T-spins found by the three-corner rule and scoring the guideline bonus; full makes games much slower, sees only the current piece, ignores --hold and --visibility and adds the landing row to every clip line, followed by spin for a T-spin; a resumed run keeps the value from its log (default drop)
+    --garbage every=<n>,lines=<k>: push garbage rows into training and validation games, see preview; no clips are recorded; a resumed run keeps the value from its log (default none)
+    --mode endless|dig[=<rows>]|sprint[=<lines>]|marathon[=<lines per level>]: what training and validation games are played for, see preview; with dig and sprint the fitness is 1000 for every garbage row dug out or line cleared less a point per piece, so that reaching the goal in the fewest pieces wins; a marathon is still played for score, and its gravity only matters with --moves full; no clips are recorded but for endless; a resumed run keeps the value from its log (default endless)
+    --objective score|wins|damage: the fitness of a candidate; score is that of games played alone, the score or the progress towards the goal; with wins and damage every candidate plays --population-games matches of garbage exchange against the hall of fame leader (the built-in weights at first), taking turns to move first, each at most --max-pieces and no more than 1000 pieces long, see versus; wins ranks by the share of matches won in percent, a draw counting half, damage by the garbage rows sent less those received on average; matches ignore --mode, --garbage, --hold, --moves full and --novelty, and the hall of fame still validates on games played alone; a resumed run keeps the value from its log (default score)
+    --attack <single>,<double>,...[/<delay>]: the attack table of the wins and damage matches, see versus; a resumed run keeps the value from its log (default 0,1,2,4)
+    --model linear|mlp|bonus: linear trains linear weights over the features; mlp trains a 13-32-1 network of tanh units, whose 481 parameters CMA-ES searches as one vector, with the count features divided by the board width first; the hall of fame holds one network per line, starting with mlp, which preview --weights, serve and versus read; mlp makes games much slower, cannot be combined with --freeze, --sign, --bound or --warm-start, records no clips and does not roll back on anomalies; bonus trains linear weights plus weights for three bonus terms, the combo length, the back-to-back chain and all clears, stored one per line starting with bonus, for use with --scoring and with the same limits as mlp; a resumed run keeps the value from its log (default linear)
+    --scoring guideline|<file>: how the training and validation games score, as for check --scoring; a resumed run keeps the rules from its log (default 100/300/500/800 points for 1 to 4 lines)
+    --novelty <w>: reward playing styles unlike earlier candidates (default 0)
+    --warm-start <path>: start from a previous run's checkpoint.txt or run dir
+    --freeze <i>=<v>: fix feature i's normalized weight at v
+    --sign <i>=+|-: keep feature i's weight non-negative or non-positive
+    --bound <i>=<lo>:<hi>: keep feature i's weight in a range, either end optional
+  qd [iterations]: Fill a grid of playing styles with the best policy for each
+    --out <dir>: where grid.txt and the per-cell weights go (default runs/qd-<time>)
+    --games <n>: games per evaluation (default 20)
+    --resolution <n>: bins per behavior axis (default 10)
+  hof <file> [games]: Re-evaluate every weight vector in an archive on shared seeds
+    --html <file>: also write a report with score histograms and final boards
+    --visibility current|next|expected: whether the built-in policy uses the next piece, see preview (default current)
+  puzzle [file]: Test the AI on curated positions (default puzzles/basic.txt)
+  puzzle generate [out] [games] [count]: Mine clear-cut positions from AI games
+  scenario run [file] [weights file]: Check the AI's behavior against scripted scenarios; exits with 1 if an assertion fails (default scenarios/basic.txt)
+  sandbox: Freeplay: choose each piece and place it yourself or let the AI, with export to puzzle board notation and save/load to continue a game later
+  benchmark [weights file]: Score weights on the fixed benchmark suite with a comparable composite, and count the distinct surfaces and boards they reach
+    --visibility current|next|expected: whether the built-in policy uses the next piece, see preview; other than current, the composite of the greedy policy (current) is shown for comparison (default current)
+    --observation full|heights: the built-in policy sees the board or only column heights and holes (default full)
+    --schedule game|candidate: each game is a task that idle threads steal, or all games of a weight vector run in turn on one thread; core utilization is shown at the end (default game)
+    --randomizer uniform|bag|history|adversarial|file:<path>: the cases keep their seeds and lengths but draw pieces this way; the composite only compares with runs on the same randomizer (default uniform)
+    --mode endless|dig[=<rows>]|sprint[=<lines>]: play every case as a dig, on garbage drawn from its seed, or a sprint and report how far it got, the pieces used and a sprint's simulated time instead of the composite (default endless)
+  quantize [weights file]: Quantize weights to fixed-point integers and compare the integer-only policy with the float one on the benchmark suite
+    --bits <n>: bits per weight, 2 to 24 (default 16)
+  determinism verify [file]: Replay a reference game and compare it placement by placement to catch platform-dependent float differences; exits with 1 on a mismatch (default reference/determinism-float.txt)
+  determinism record <file>: Record a reference game with the built-in weights
+    --mode float|integer: float evaluation, or 16-bit fixed point with no floating point at all, which plays the same on every platform and suits grading (default float)
+    --seed <n>, --pieces <n>: seed and length of the piece sequence (default 42, 500)
+  geometry [simple|srs]: Print each piece's cells, widths and valid x ranges per rotation as JSON
+    --out <file>: write to a file instead of stdout
+  check <executable>: Check the AI's performance against a given executable; it may send 'R' instead of a move to resign
+    --summary: expect a final '<score> <lines> <pieces>' line after the end marker
+    --negotiate: offer 'P <n>' before the first piece; the program answers 'P <m>' with m <= n
+    --legal-count: move lines end with the number of legal placements the program saw
+    --board-every <k>: every k pieces, expect the program's board encoding after the score
+    --observation heights: follow every piece line with 'O <10 column heights> <holes>'
+    --pieces <n>: length of the piece sequence; the last piece is only a preview (default 1000000)
+    --piece-set tetromino|pentomino: the piece set; pentominoes are sent as upper case letters and their mirror images as lower case, in the simple rotation system only (default tetromino)
+    --randomizer uniform|bag|history|file:<path>: how the piece sequence is drawn, see preview; it is drawn before the game, so adversarial is not available (default uniform)
+    --hold: the program may send 'H' before a move to hold the current piece and place the one it swaps for; holding into an empty slot swaps in the preview piece, and the next preview piece line follows; once per piece
+    --search depth=<n>,beam=<k>: send n-1 preview pieces instead of one, for programs that search the queue such as serve --search; the last n-1 pieces of the sequence are only previews
+    --scoring guideline|<file>: score by the guideline rules, with level multipliers, hard drop points and T-spin, combo, back-to-back and all clear bonuses, or by a rules file, and expect the program's scores to match; a file has one '<key> <values>' line per rule, the keys being lines, level, drop, spin-mini, spin, combo, back-to-back and all-clear (default 100/300/500/800 points for 1 to 4 lines)
+    --hidden <rows>: rows at the top above the playfield that pieces spawn in; the game tops out when the spawn position is taken or a piece locks entirely in them, so --width 10 --height 22 --hidden 2 is the 20-row guideline well (default 0)
+    --time-limit <secs>: end the game after this long (default 10)
+    --move-time <secs>: how long the program may take over each line it owes, a move, a score or a board, before it is at fault on that piece (default 5)
+    --end marker|close: signal the end with an 'E' line or by closing stdin (default marker)
+    --rotation simple|srs: coordinate convention of the program's moves
+    --image <file.png|file.svg>: save the final board as an image
+    --keyframes <n>: with --image, also save the board every n pieces
+    --seed <n>: seed of the piece sequence, to replay a game (default a fresh one, shown in the log)
+    --mode endless|sprint[=<lines>]: with sprint, end the game once that many lines are cleared (40 by default) and report the pieces and simulated time it took, see preview (default endless)
+  grade <executable>...: Check each submission on the same seeds, grade it by its mean score and archive every game's result and final board
+    --games <n>: games per submission (default 5)
+    --seed <n>: seed of the first game; game i uses n+i (default 1)
+    --out <dir>: archive with grades.csv, grades.html and a folder per submission (default runs/grade-<time>)
+    --scale <grade>=<score>,...: a mean reaching a threshold earns that grade; below every threshold earns the lowest (default A=200000,B=100000,C=50000,D=10000,F=0)
+    other check options apply to every game
+  serve [weights file]: The inverse of check, played by the first weight vector or network of the file: read piece letters from stdin line by line, keep a board of our own and answer each with '<rotation> <x>' and the score, or 'R' when nothing fits; stops at 'E' or the end of input
+    --preview <n>: pieces to wait for beyond the current one before placing it (default 1, as check sends them; 0 places each on arrival)
+    --visibility current|next|expected: whether to use the next piece when it is known, or with expected to average over every piece that may come (default current)
+    --piece-set tetromino|pentomino: piece set (default tetromino)
+    --hold: play with a hold slot and send 'H' before the move when holding, for check --hold
+    --search depth=<n>,beam=<k>: place by a beam search over the whole queue, ignoring --visibility; without --preview, wait for n-1 preview pieces, as check --search sends them
+    --mcts nodes=<n>|time=<ms>ms[,depth=<d>,c=<x>]: place by a Monte Carlo tree search over paths of d pieces, drawing the pieces after the queue and placing greedily past the tree; takes precedence over --search and ignores --visibility (default nodes=400,depth=2,c=1)
+    --scoring guideline|<file>: how the score lines count, as for check --scoring
+    --hidden <rows>: rows hidden above the playfield, as for check --hidden
+    --legal-count, --board-every <k>, --summary: answer check's options of the same name
+    a 'G <rows> <hole>' line pushes an opponent's garbage into our board, see versus
+  versus [weights file A] [weights file B]: Both sides, each played by the first weight vector or network of its file, take turns on the same piece sequence, with clears sending garbage to the other through an attack table after cancelling their own incoming garbage; garbage comes in after a placement that clears nothing (or after a delay), and a side with nowhere to place or pushed out by garbage loses; the sides take turns at moving first, and each game, the lines and garbage per side, their attack per piece, share of received garbage dug out and mean stack height under garbage, and the overall winner are printed (default built-in weights)
+    --games <n>: number of games (default 10)
+    --pieces <n>: length of each game's piece sequence (default 1000)
+    --piece-set tetromino|pentomino: piece set (default tetromino)
+    --attack <single>,<double>,...[/<delay>]: garbage rows sent for clearing 1, 2, ... lines; larger clears send the last value; with /n the garbage only comes in once the receiver has placed n more pieces, and can be cancelled until then (default 0,1,2,4)
+    --program <executable>: play side B with a program speaking the check protocol, started for every game; it must push the garbage of a 'G <rows> <hole>' line into its own board, as serve does
+    --move-time <secs>: how long the program may take over a move before it loses the game, as for check (default 5)
+    --visibility current|next|expected, --randomizer <spec>: see preview
+    --seed <n>: seed of the piece sequences and garbage holes (default random)
+  label [weights file]: Play fast games with the one-piece policy and label every few positions with the move of a deep beam search as the teacher, writing a '<board> <pieces> <rotation> <x> <value>' dataset for distilling the search into the fast policy; the log gives how often the student agreed with the teacher
+    --search depth=<n>,beam=<k>: the teacher's search, see preview (default depth=3,beam=64)
+    --games <n>, --seed <n>: number of games and the seed of the first; game i uses n+i (default 8, 1)
+    --every <k>: label one position every k pieces (default 10)
+    --max-pieces <n>: cap on the pieces of a game (default 2000)
+    --piece-set tetromino|pentomino, --randomizer <spec>: see preview
+    --out <file>: where the dataset goes (default labels.txt)
+  plots compare <run dir>...: Read the evaluations.txt of several training runs and overlay their best fitness per generation and best fitness so far per evaluation in one image; prints each run's generations, evaluations and best fitness
+    --out <file>: where the PNG goes (default compare.png)
+    --labels <name>,...: names for the runs in order (default their directory names)
+";
+
+/// The argument following `flag`, if present.
+fn flag_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|a| a == flag)
+        .and_then(|i| args.get(i + 1))
+        .map(String::as_str)
 }
 
-fn display_game_with_next_piece(
-    board: &Board,
-    current_piece: PieceType,
-    next_piece: PieceType,
-    best_action: (usize, usize, f64),
-    piece_symbols: [char; 7],
-    piece_colors: [&str; 7],
-) {
-    let grid = board.get_grid();
-    let color_grid = board.get_color_grid();
-
-    let next_piece_shape = &ROTATIONS[next_piece as usize][0];
-    let next_piece_color = piece_colors[next_piece as usize];
-
-    let mut next_preview = [[false; 4]; 4];
-
-    let offset_x = (4 - next_piece_shape.width) / 2;
-    let offset_y = 1;
-
-    for y in 0..next_piece_shape.height {
-        for x in 0..next_piece_shape.width {
-            if y + offset_y < 4 && x + offset_x < 4 && next_piece_shape.shape[y][x] != 0 {
-                next_preview[y + offset_y][x + offset_x] = true;
+/// Parses `--visibility current|next|expected`, `None` after reporting a bad value.
+fn visibility(args: &[String]) -> Option<Visibility> {
+    match flag_value(args, "--visibility") {
+        None => Some(Visibility::default()),
+        Some(name) => {
+            let parsed = Visibility::parse(name);
+            if parsed.is_none() {
+                log_error!(
+                    "--visibility 需要 current、next 或 expected",
+                    "--visibility expects current, next or expected"
+                );
             }
+            parsed
         }
     }
+}
 
-    println!("╔{}╗    ╔══════╗", "═".repeat(BOARD_WIDTH));
-    println!("║{}║    ║ NEXT ║", " ".repeat(BOARD_WIDTH));
-    println!("║{}║    ╠══════╣", " ".repeat(BOARD_WIDTH));
-    println!("║{}║    ║      ║", " ".repeat(BOARD_WIDTH));
-    println!("║{}║    ║      ║", " ".repeat(BOARD_WIDTH));
-    println!("║{}║    ║      ║", " ".repeat(BOARD_WIDTH));
-
-    for y in (0..BOARD_HEIGHT).rev() {
-        print!("║");
-
-        for x in 0..BOARD_WIDTH {
-            if grid[y][x] {
-                let color_index = color_grid[y][x].unwrap_or(0) as usize;
-                let color_code = if color_index < piece_colors.len() {
-                    piece_colors[color_index]
-                } else {
-                    "\x1B[37m"
-                };
-                print!("{}\u{25A0}\x1B[0m", color_code);
-            } else {
-                print!(" ");
+/// Parses `--piece-set tetromino|pentomino`, `None` after reporting a bad
+/// value.
+fn piece_set(args: &[String]) -> Option<BuiltinSet> {
+    match flag_value(args, "--piece-set") {
+        None => Some(BuiltinSet::default()),
+        Some(name) => {
+            let parsed = BuiltinSet::parse(name);
+            if parsed.is_none() {
+                log_error!(
+                    "--piece-set 需要 tetromino 或 pentomino",
+                    "--piece-set expects tetromino or pentomino"
+                );
             }
+            parsed
         }
+    }
+}
 
-        let preview_row = BOARD_HEIGHT - y - 1;
-        if preview_row < 6 {
-            print!("║    ║ ");
+/// Parses `--search depth=<n>,beam=<k>`, `Some(None)` when it is not
+/// given and `None` after reporting a bad value.
+fn search(args: &[String]) -> Option<Option<SearchConfig>> {
+    match flag_value(args, "--search") {
+        None => Some(None),
+        Some(spec) => match SearchConfig::parse(spec) {
+            Ok(config) => Some(Some(config)),
+            Err(e) => {
+                log_error!("--search: {}", "--search: {}", e);
+                None
+            }
+        },
+    }
+}
 
-            if (1..=4).contains(&preview_row) {
-                let row_idx = preview_row - 1;
-                for col in 0..4 {
-                    if next_preview[row_idx][col] {
-                        print!("{}\u{25A0}\x1B[0m", next_piece_color);
-                    } else {
-                        print!(" ");
-                    }
-                }
-            } else {
-                print!("    ");
+/// Parses `--garbage every=<n>,lines=<k>`, `Some(None)` when it is not
+/// given and `None` after reporting a bad value.
+fn garbage(args: &[String]) -> Option<Option<GarbageConfig>> {
+    match flag_value(args, "--garbage") {
+        None => Some(None),
+        Some(spec) => match GarbageConfig::parse(spec) {
+            Ok(config) => Some(Some(config)),
+            Err(e) => {
+                log_error!("--garbage: {}", "--garbage: {}", e);
+                None
             }
+        },
+    }
+}
 
-            print!(" ║");
-        } else {
-            print!("║    ║      ║");
+/// Parses `--mode endless|dig[=<rows>]|sprint[=<lines>]|marathon[=<lines>]` for a board of `dimensions`,
+/// `None` after reporting a bad value.
+fn mode(args: &[String], dimensions: Dimensions) -> Option<GameMode> {
+    let mode = match flag_value(args, "--mode") {
+        None => return Some(GameMode::default()),
+        Some(spec) => GameMode::parse(spec),
+    };
+    match mode.and_then(|mode| mode.check(dimensions).map(|()| mode)) {
+        Ok(mode) => Some(mode),
+        Err(e) => {
+            log_error!("--mode: {}", "--mode: {}", e);
+            None
         }
-
-        println!();
     }
-
-    println!("╚{}╝    ╚══════╝", "═".repeat(BOARD_WIDTH));
-
-    println!(
-        "当前: {}{}\x1B[0m(旋转: {}, 位置: {})",
-        piece_colors[current_piece as usize],
-        piece_symbols[current_piece as usize],
-        best_action.0,
-        best_action.1
-    );
 }
 
-fn check(executable_path: String) {
-    use std::io::{BufRead, BufReader, Write};
-    use std::process::{Command, Stdio};
-    let start_time = std::time::Instant::now();
-
-    let mut child = Command::new(&executable_path)
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .spawn()
-        .expect("无法启动目标程序");
-
-    let mut stdin = child.stdin.take().expect("无法获取子进程stdin");
-    let stdout = child.stdout.take().expect("无法获取子进程stdout");
-    let stdout_reader = BufReader::new(stdout);
-    let mut stdout_lines = stdout_reader.lines();
-
-    let mut rng = rand::rng();
-    let piece_types = ['I', 'T', 'O', 'J', 'L', 'S', 'Z'];
-    let mut board = Board::new();
-
-    let mut pieces = Vec::with_capacity(1_000_000);
-    for _ in 0..1_000_000 {
-        pieces.push(*piece_types.choose(&mut rng).unwrap());
+/// Loads the first weight vector or network of the `--weights <path>`
+/// file, the built-in `WEIGHTS` without one; `None` after reporting a
+/// file that cannot be used.
+fn model(args: &[String], allow_stale: bool) -> Option<Model> {
+    let Some(path) = flag_value(args, "--weights") else {
+        return Some(Model::Linear(WEIGHTS));
+    };
+    match weights::load_models(path, allow_stale) {
+        Ok(mut models) if !models.is_empty() => Some(models.swap_remove(0)),
+        Ok(_) => {
+            log_error!("权重文件为空: {}", "Weights file is empty: {}", path);
+            None
+        }
+        Err(e) => {
+            log::error!("{}", e);
+            None
+        }
     }
+}
 
-    let initial_input = format!("{}{}\n", pieces[0], pieces[1]);
-    stdin
-        .write_all(initial_input.as_bytes())
-        .expect("写入初始输入失败");
-
-    let max_pieces = 1_000_000;
-
-    let mut current_idx = 0;
-    let mut next_idx = 1;
-
-    while current_idx < max_pieces && next_idx < pieces.len() {
-        let current_char = pieces[current_idx];
-
-        let current_piece = match current_char {
-            'I' => PieceType::I,
-            'T' => PieceType::T,
-            'O' => PieceType::O,
-            'J' => PieceType::J,
-            'L' => PieceType::L,
-            'S' => PieceType::S,
-            'Z' => PieceType::Z,
-            _ => panic!("未知方块类型"),
-        };
-
-        let response = match stdout_lines.next() {
-            Some(Ok(line)) => line,
-            Some(Err(e)) => {
-                println!("读取程序输出错误: {}", e);
-                break;
-            }
-            None => {
-                println!("程序已退出，游戏结束");
-                break;
+/// Parses `--moves drop|full|full-180`, `None` after reporting a bad value.
+fn moves(args: &[String]) -> Option<Moves> {
+    match flag_value(args, "--moves") {
+        None => Some(Moves::default()),
+        Some(name) => {
+            let parsed = Moves::parse(name);
+            if parsed.is_none() {
+                log_error!(
+                    "--moves 需要 drop、full 或 full-180",
+                    "--moves expects drop, full or full-180"
+                );
             }
-        };
-
-        println!("{}", response);
-
-        let parts: Vec<&str> = response.split_whitespace().collect();
-        if parts.len() < 2 {
-            println!("程序输出格式错误: {}", response);
-            break;
+            parsed
         }
+    }
+}
 
-        let rotation_degrees = parts[0].parse::<usize>().unwrap_or(0);
-        let x_position = parts[1].parse::<usize>().unwrap_or(0);
-        let rotation = rotation_degrees;
-
-        // 从目标程序读取当前分数
-        let score_line = match stdout_lines.next() {
-            Some(Ok(line)) => line,
-            Some(Err(e)) => {
-                println!("读取分数错误: {}", e);
-                break;
-            }
-            None => {
-                println!("程序在提供分数前退出，游戏结束");
-                break;
-            }
-        };
-
-        let program_score = score_line.parse::<i32>().unwrap_or(0);
-
-        if board.check(current_piece, x_position, rotation).is_ok() {
-            board.apply(current_piece, x_position, rotation).unwrap();
-
-            if board.get_score() != program_score {
-                println!(
-                    "警告: 分数不匹配！程序={}, 实际={}",
-                    program_score,
-                    board.get_score()
-                );
+/// Parses `--randomizer <spec>` for pieces of `set`, `None` after
+/// reporting a bad value.
+fn randomizer(args: &[String], set: &PieceSet) -> Option<Randomizer> {
+    match flag_value(args, "--randomizer") {
+        None => Some(Randomizer::default()),
+        Some(spec) => match Randomizer::parse(spec, set) {
+            Ok(randomizer) => Some(randomizer),
+            Err(e) => {
+                log_error!("--randomizer: {}", "--randomizer: {}", e);
+                None
             }
+        },
+    }
+}
 
-            current_idx += 1;
-            next_idx += 1;
-
-            let now = std::time::Instant::now();
-            let elapsed = now.duration_since(start_time);
-            if elapsed.as_secs_f64() > 10.0 {
-                let pieces_per_second = current_idx as f64 / elapsed.as_secs_f64();
-                println!(
-                    "当前放置了 {} 个方块，平均速度: {:.2} 个方块/秒",
-                    current_idx, pieces_per_second
+/// Parses `--temperature <t>` for sampled placements, `Some(None)` for the
+/// usual best placement and `None` after reporting a bad value.
+fn temperature(args: &[String]) -> Option<Option<f64>> {
+    match flag_value(args, "--temperature") {
+        None => Some(None),
+        Some(value) => match value.parse::<f64>() {
+            Ok(t) if t >= 0.0 => Some(Some(t)),
+            _ => {
+                log_error!(
+                    "--temperature 需要非负数",
+                    "--temperature expects a non-negative number"
                 );
-                println!("正在发送结束标记...");
-                stdin.write_all(b"E\n").expect("写入下一方块失败");
-                stdin.flush().expect("刷新stdin失败");
-            }
-
-            if next_idx < pieces.len() {
-                if let Err(e) = stdin.write_all(format!("{}\n", pieces[next_idx]).as_bytes()) {
-                    println!("写入下一方块失败: {}", e);
-                    println!("程序可能已退出，游戏结束");
-                    break;
-                }
-
-                if let Err(e) = stdin.flush() {
-                    println!("刷新stdin失败: {}", e);
-                    println!("程序可能已退出，游戏结束");
-                    break;
-                }
-            } else {
-                if let Err(e) = stdin.write_all(b"E\n") {
-                    println!("写入结束标记失败: {}", e);
-                    break;
-                }
-
-                if let Err(e) = stdin.flush() {
-                    println!("刷新stdin失败: {}", e);
-                    break;
-                }
-
-                println!("已发送游戏结束标记");
+                None
             }
-        } else {
-            println!(
-                "警告: 程序选择了无效的行动 (旋转={}, 位置={})",
-                rotation, x_position
-            );
-            stdin.write_all(b"E\n").expect("写入结束标记失败");
-            println!("已发送游戏结束标记");
-            break;
-        }
+        },
     }
+}
 
-    sleep(Duration::from_secs(1));
-    println!("正在检查目标程序状态...");
-
-    match child.try_wait() {
-        Ok(Some(status)) => println!("目标程序已退出，状态码: {}", status),
-        Ok(None) => {
-            println!("目标程序仍在运行，正在终止...");
-            let _ = child.kill();
-            let _ = child.wait();
-            println!("目标程序已终止");
-        }
-        Err(e) => println!("检查目标程序状态时出错: {}", e),
+fn print_usage() {
+    match i18n::lang() {
+        Lang::Zh => print!("{}", USAGE_ZH),
+        Lang::En => print!("{}", USAGE_EN),
     }
-
-    println!("验证完成！总共放置了 {} 个方块", current_idx);
-    println!("最终分数: {}", board.get_score());
 }
@@ -0,0 +1,353 @@
+//! Monte Carlo tree search over placements, with the evaluator as the
+//! heuristic.
+//!
+//! Every node is a board and a piece to place; its edges are the
+//! placements, each costing its evaluation. The pieces after the known
+//! queue are drawn uniformly from the set on every pass through the tree,
+//! so an edge leads to one child per piece that has come up there.
+//!
+//! Every pass sums the evaluations of `depth` placements: those down the
+//! tree to a placement not visited before, then greedy ones for the rest.
+//! Paths of one length keep shallow and deep parts of the tree comparable,
+//! since each evaluation rates a whole board rather than the change. A
+//! node is worth its best placement and a placement its own evaluation
+//! plus what follows, averaged over the pieces that came next, so poor
+//! placements tried while exploring do not drag down the good ones above
+//! them as plain means of the passes would.
+//!
+//! Placements are picked by UCT, lowest cost first, on values normalized
+//! to the range of the node's own placements, since evaluations have no
+//! fixed scale. The move played is the most visited placement.
+
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+
+use crate::board::Board;
+use crate::evaluator::Evaluator;
+use crate::i18n::tr;
+use crate::piece::PieceSet;
+use crate::policy::best_action_in;
+
+/// Cost of a path that tops out, far past any sum of evaluations, so that
+/// a value with a top-out in it reads as the worst there is.
+const TOP_OUT: f64 = 1e15;
+
+/// When the search stops.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Budget {
+    /// After this many passes through the tree. The same seed then always
+    /// gives the same move.
+    Nodes(usize),
+    /// After this long, however many passes that was.
+    Time(Duration),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MctsConfig {
+    pub budget: Budget,
+    /// Pieces placed along every path, the current one included. The
+    /// default stays within the preview `check` sends: with the built-in
+    /// weights, paths into drawn pieces play worse, as `expected`
+    /// visibility does.
+    pub depth: usize,
+    /// The UCT exploration constant, on normalized costs.
+    pub exploration: f64,
+}
+
+impl Default for MctsConfig {
+    fn default() -> Self {
+        MctsConfig {
+            budget: Budget::Nodes(400),
+            depth: 2,
+            exploration: 1.0,
+        }
+    }
+}
+
+impl MctsConfig {
+    /// Parses `nodes=<n>` or `time=<ms>ms`, and `depth=<d>` and
+    /// `c=<x>`, comma separated; any part may be left out for its default.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut config = MctsConfig::default();
+        for part in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let invalid = || {
+                tr!(
+                    "需要 nodes=<n> 或 time=<毫秒>ms, 以及 depth=<d>、c=<x>, 实际为 '{}'",
+                    "expected nodes=<n> or time=<ms>ms, depth=<d> and c=<x>, got '{}'",
+                    spec
+                )
+            };
+            let (key, value) = part.split_once('=').ok_or_else(invalid)?;
+            let value = value.trim();
+            match key.trim() {
+                "nodes" => {
+                    let nodes = value.parse().ok().filter(|&n| n > 0).ok_or_else(invalid)?;
+                    config.budget = Budget::Nodes(nodes);
+                }
+                "time" => {
+                    let ms: u64 = value
+                        .strip_suffix("ms")
+                        .and_then(|ms| ms.parse().ok())
+                        .filter(|&ms| ms > 0)
+                        .ok_or_else(invalid)?;
+                    config.budget = Budget::Time(Duration::from_millis(ms));
+                }
+                "depth" => {
+                    config.depth = value.parse().ok().filter(|&d| d > 0).ok_or_else(invalid)?
+                }
+                "c" => {
+                    config.exploration = value
+                        .parse()
+                        .ok()
+                        .filter(|c: &f64| c.is_finite() && *c >= 0.0)
+                        .ok_or_else(invalid)?
+                }
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(config)
+    }
+
+    /// The config in the form `parse` reads.
+    pub fn to_spec(&self) -> String {
+        let budget = match self.budget {
+            Budget::Nodes(n) => format!("nodes={}", n),
+            Budget::Time(t) => format!("time={}ms", t.as_millis()),
+        };
+        format!("{},depth={},c={}", budget, self.depth, self.exploration)
+    }
+}
+
+/// The pieces of one search: the known queue, then draws from `rng`.
+struct Pieces<'a, R> {
+    queue: &'a [usize],
+    count: usize,
+    rng: &'a mut R,
+}
+
+impl<R: Rng> Pieces<'_, R> {
+    /// The piece at `depth`, 0 being the one to place now.
+    fn at(&mut self, depth: usize) -> usize {
+        match self.queue.get(depth) {
+            Some(&piece) => piece,
+            None => self.rng.random_range(0..self.count),
+        }
+    }
+}
+
+struct Edge<const H: usize, const W: usize> {
+    rotation: usize,
+    x: usize,
+    /// The placement's own evaluation.
+    cost: f64,
+    /// The board it leaves, once it was visited.
+    after: Option<Board<H, W>>,
+    /// Passes that rolled out right after it, and the sum of their
+    /// rollouts.
+    rollouts: u32,
+    rollout_total: f64,
+    /// The nodes after it, by the piece that came next.
+    children: Vec<(usize, Node<H, W>)>,
+}
+
+impl<const H: usize, const W: usize> Edge<H, W> {
+    fn visits(&self) -> u32 {
+        self.rollouts + self.children.iter().map(|(_, c)| c.visits).sum::<u32>()
+    }
+
+    /// Its own evaluation plus what follows, averaged over the pieces
+    /// that came next as often as they came.
+    fn value(&self) -> f64 {
+        let below: f64 = self
+            .children
+            .iter()
+            .map(|(_, c)| c.visits as f64 * c.value)
+            .sum();
+        self.cost + (self.rollout_total + below) / self.visits() as f64
+    }
+}
+
+struct Node<const H: usize, const W: usize> {
+    board: Board<H, W>,
+    piece: usize,
+    /// The placements, once the node was visited.
+    edges: Option<Vec<Edge<H, W>>>,
+    visits: u32,
+    /// The value of its best placement, `TOP_OUT` when nothing fits.
+    value: f64,
+}
+
+impl<const H: usize, const W: usize> Node<H, W> {
+    fn new(board: Board<H, W>, piece: usize) -> Self {
+        Node {
+            board,
+            piece,
+            edges: None,
+            visits: 0,
+            value: 0.0,
+        }
+    }
+
+    /// One pass from this node at `depth`: picks a placement, goes on
+    /// below it or rolls out past it when it is new or the path ends, and
+    /// brings the values on the way back up to date.
+    fn visit<E: Evaluator<H, W> + ?Sized, R: Rng>(
+        &mut self,
+        depth: usize,
+        set: &PieceSet,
+        evaluator: &E,
+        config: &MctsConfig,
+        pieces: &mut Pieces<R>,
+    ) {
+        let (board, piece) = (&self.board, self.piece);
+        let edges = self.edges.get_or_insert_with(|| {
+            board
+                .legal_placements_with_features_in(set, piece)
+                .map(|(p, features)| Edge {
+                    rotation: p.rotation,
+                    x: p.x,
+                    cost: evaluator.evaluate(board, &features),
+                    after: None,
+                    rollouts: 0,
+                    rollout_total: 0.0,
+                    children: Vec::new(),
+                })
+                .collect()
+        });
+        self.visits += 1;
+        if edges.is_empty() {
+            self.value = TOP_OUT;
+            return;
+        }
+        let i = pick(edges, self.visits, config.exploration);
+        let edge = &mut edges[i];
+        if let Some(after) = &edge.after
+            && depth + 1 < config.depth
+        {
+            let next = pieces.at(depth + 1);
+            let child = match edge.children.iter().position(|(p, _)| *p == next) {
+                Some(c) => c,
+                None => {
+                    edge.children.push((next, Node::new(after.clone(), next)));
+                    edge.children.len() - 1
+                }
+            };
+            edge.children[child]
+                .1
+                .visit(depth + 1, set, evaluator, config, pieces);
+        } else {
+            let after = edge.after.get_or_insert_with(|| {
+                let mut after = self.board.clone();
+                after.apply_in(set, piece, edge.x, edge.rotation).unwrap();
+                after
+            });
+            edge.rollouts += 1;
+            edge.rollout_total += rollout(after.clone(), depth + 1, set, evaluator, config, pieces);
+        }
+        self.value = edges
+            .iter()
+            .filter(|e| e.visits() > 0)
+            .map(Edge::value)
+            .fold(f64::INFINITY, f64::min);
+    }
+}
+
+/// The edge to follow: the cheapest new placement while there are any,
+/// then the lowest UCT bound on values normalized to the range of the
+/// finite ones, the first on ties.
+fn pick<const H: usize, const W: usize>(
+    edges: &[Edge<H, W>],
+    visits: u32,
+    exploration: f64,
+) -> usize {
+    let fresh = edges
+        .iter()
+        .enumerate()
+        .filter(|(_, e)| e.visits() == 0)
+        .min_by(|a, b| a.1.cost.total_cmp(&b.1.cost));
+    if let Some((i, _)) = fresh {
+        return i;
+    }
+    let values: Vec<f64> = edges.iter().map(Edge::value).collect();
+    let finite = || values.iter().copied().filter(|v| *v < TOP_OUT);
+    let low = finite().fold(f64::INFINITY, f64::min);
+    let high = finite().fold(f64::NEG_INFINITY, f64::max);
+    let spread = if high > low { high - low } else { 1.0 };
+    let log_visits = (visits as f64).ln();
+    let bound = |i: usize| {
+        let normalized = ((values[i] - low) / spread).clamp(0.0, 1.0);
+        normalized - exploration * (log_visits / edges[i].visits() as f64).sqrt()
+    };
+    let mut best = 0;
+    for i in 1..edges.len() {
+        if bound(i) < bound(best) {
+            best = i;
+        }
+    }
+    best
+}
+
+/// The summed evaluations of greedy placements on `board` from `depth` to
+/// the end of the path, with `TOP_OUT` added if one does not fit.
+fn rollout<const H: usize, const W: usize, E: Evaluator<H, W> + ?Sized, R: Rng>(
+    mut board: Board<H, W>,
+    depth: usize,
+    set: &PieceSet,
+    evaluator: &E,
+    config: &MctsConfig,
+    pieces: &mut Pieces<R>,
+) -> f64 {
+    let mut cost = 0.0;
+    for depth in depth..config.depth {
+        let piece = pieces.at(depth);
+        let Some((rotation, x, score)) = best_action_in(&board, set, piece, evaluator) else {
+            return cost + TOP_OUT;
+        };
+        cost += score;
+        board.apply_in(set, piece, x, rotation).unwrap();
+    }
+    cost
+}
+
+/// The placement of `queue[0]` the search visits most, as `(rotate, x,
+/// value)`, the one with the lower value on ties. The rest of `queue`
+/// is taken as the next pieces; later ones are drawn from `rng`. `None`
+/// when `queue` is empty or the piece fits nowhere.
+pub fn mcts_action_in<const H: usize, const W: usize, E: Evaluator<H, W> + ?Sized>(
+    board: &Board<H, W>,
+    set: &PieceSet,
+    queue: &[usize],
+    evaluator: &E,
+    config: &MctsConfig,
+    rng: &mut impl Rng,
+) -> Option<(usize, usize, f64)> {
+    let &piece = queue.first()?;
+    let mut pieces = Pieces {
+        queue,
+        count: set.len(),
+        rng,
+    };
+    let mut root = Node::new(board.clone(), piece);
+    let start = Instant::now();
+    let mut passes = 0;
+    loop {
+        let done = match config.budget {
+            Budget::Nodes(n) => passes >= n,
+            // At least one pass, so that there is a move
+            Budget::Time(limit) => passes > 0 && start.elapsed() >= limit,
+        };
+        if done {
+            break;
+        }
+        root.visit(0, set, evaluator, config, &mut pieces);
+        passes += 1;
+    }
+
+    let edges = root.edges?;
+    edges
+        .iter()
+        .filter(|e| e.visits() > 0)
+        .min_by(|a, b| (b.visits().cmp(&a.visits())).then(a.value().total_cmp(&b.value())))
+        .map(|e| (e.rotation, e.x, e.value()))
+}
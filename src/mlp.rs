@@ -0,0 +1,92 @@
+//! A small neural network as an evaluator: the features of a placement go
+//! through one hidden layer of `HIDDEN` tanh units into a single output,
+//! which rates the placement as the dot product of the linear evaluator
+//! does, lower being better. Its parameters are a flat vector, so
+//! training searches them with the same CMA-ES loop as the weights; see
+//! `train --model mlp`.
+//!
+//! The counts among the features are divided by the width of the board
+//! before they go in. The heights summed over the columns then come out
+//! at about the height of one column, which keeps the hidden units off
+//! the flat ends of tanh for the small weights a search starts from.
+
+use crate::board::{Board, COUNT_FEATURES, FEATURES};
+use crate::evaluator::Evaluator;
+use crate::i18n::tr;
+
+/// Units of the hidden layer.
+pub const HIDDEN: usize = 32;
+
+/// Length of the parameter vector: the weights and bias of every hidden
+/// unit, then the output weights and the output bias.
+pub const PARAMETERS: usize = HIDDEN * FEATURES + HIDDEN + HIDDEN + 1;
+
+/// A `FEATURES`-`HIDDEN`-1 network with tanh hidden units.
+///
+/// ```
+/// use mortis::board::{Board, FEATURES};
+/// use mortis::evaluator::Evaluator;
+/// use mortis::mlp::{MlpEvaluator, PARAMETERS};
+///
+/// let board = Board::new();
+/// let features = [1.0; FEATURES];
+/// // Nothing but the output bias
+/// let mut parameters = vec![0.0; PARAMETERS];
+/// parameters[PARAMETERS - 1] = 2.5;
+/// let network = MlpEvaluator::from_parameters(&parameters).unwrap();
+/// assert_eq!(network.evaluate(&board, &features), 2.5);
+/// assert!(MlpEvaluator::from_parameters(&[0.0; FEATURES]).is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct MlpEvaluator {
+    parameters: Vec<f64>,
+}
+
+impl MlpEvaluator {
+    /// The network with `parameters` laid out as `PARAMETERS` says.
+    pub fn from_parameters(parameters: &[f64]) -> Result<Self, String> {
+        if parameters.len() != PARAMETERS {
+            return Err(tr!(
+                "神经网络参数数量应为{}, 实际为{}",
+                "Expected {} network parameters, got {}",
+                PARAMETERS,
+                parameters.len()
+            ));
+        }
+        Ok(MlpEvaluator {
+            parameters: parameters.to_vec(),
+        })
+    }
+
+    pub fn parameters(&self) -> &[f64] {
+        &self.parameters
+    }
+
+    pub fn parameters_mut(&mut self) -> &mut [f64] {
+        &mut self.parameters
+    }
+}
+
+impl<const H: usize, const W: usize> Evaluator<H, W> for MlpEvaluator {
+    fn evaluate(&self, _board: &Board<H, W>, features: &[f64; FEATURES]) -> f64 {
+        let mut inputs = *features;
+        for input in &mut inputs[..COUNT_FEATURES] {
+            *input /= W as f64;
+        }
+        let (hidden, rest) = self.parameters.split_at(HIDDEN * FEATURES);
+        let (biases, rest) = rest.split_at(HIDDEN);
+        let (output, output_bias) = rest.split_at(HIDDEN);
+        let mut value = output_bias[0];
+        for unit in 0..HIDDEN {
+            let weights = &hidden[unit * FEATURES..(unit + 1) * FEATURES];
+            let activation: f64 = biases[unit]
+                + weights
+                    .iter()
+                    .zip(inputs.iter())
+                    .map(|(w, x)| w * x)
+                    .sum::<f64>();
+            value += output[unit] * activation.tanh();
+        }
+        value
+    }
+}
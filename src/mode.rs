@@ -0,0 +1,343 @@
+//! What a game is played for. The endless game plays for score until it
+//! tops out, and so does the marathon, where the level goes up with the
+//! lines and the gravity with the level; the other modes set a goal and
+//! end when it is reached, and are won by reaching it in as few pieces as
+//! possible.
+
+use std::time::Duration;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::board::{Board, PlacementResult};
+use crate::dimensions::Dimensions;
+use crate::garbage::{Garbage, GarbageConfig};
+use crate::i18n::tr;
+use crate::piece::PIECE_SIZE;
+use crate::scoring::ScoringRules;
+use crate::timing::{FRAME, Timing, gravity};
+
+/// Garbage rows a dig game starts on unless `dig=<rows>` says otherwise.
+pub const DIG_ROWS: usize = 10;
+
+/// Lines a sprint clears unless `sprint=<lines>` says otherwise.
+pub const SPRINT_LINES: usize = 40;
+
+/// Lines a marathon level lasts unless `marathon=<lines>` says otherwise.
+pub const LINES_PER_LEVEL: usize = 10;
+
+/// Frames the simulated clock gives a piece to be moved and dropped into
+/// place, about three key presses at the pace of `preview live`. The
+/// pauses of `Timing::NES` come on top.
+pub const PLACEMENT_FRAMES: u32 = 24;
+
+/// What a goal mode's game is worth to training for each unit of
+/// progress, such as a garbage row dug out. Every piece costs a point, so
+/// the games that get further rank first, and among those that reach the
+/// goal the shortest ones.
+pub const GOAL_POINTS: i64 = 1000;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GameMode {
+    /// Play for score until the stack tops out or the pieces run out.
+    #[default]
+    Endless,
+    /// Cheese race: the board starts with `rows` garbage rows, each with
+    /// one hole in a random column, never the column of the row below.
+    /// The game ends once they are all cleared.
+    Dig { rows: usize },
+    /// Sprint: the game ends once `lines` lines are cleared.
+    Sprint { lines: usize },
+    /// Marathon: play for score from level 0, a level up every
+    /// `lines_per_level` lines, with the gravity of `timing::GRAVITY` for
+    /// the level. The gravity only limits the placements of
+    /// `Moves::Full`, which moves the piece as it falls.
+    Marathon { lines_per_level: usize },
+}
+
+impl GameMode {
+    /// Accepts `endless`, `dig`, `dig=<rows>`, `sprint`, `sprint=<lines>`,
+    /// `marathon` or `marathon=<lines per level>`.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let invalid = || {
+            tr!(
+                "需要 endless、dig[=<行数>]、sprint[=<行数>] 或 marathon[=<每级行数>], 实际为 '{}'",
+                "expected endless, dig[=<rows>], sprint[=<lines>] or marathon[=<lines per level>], got '{}'",
+                spec
+            )
+        };
+        let (name, value) = match spec.split_once('=') {
+            Some((name, value)) => (name.trim(), Some(value.trim())),
+            None => (spec.trim(), None),
+        };
+        match (name, value) {
+            ("endless", None) => Ok(GameMode::Endless),
+            ("dig", None) => Ok(GameMode::Dig { rows: DIG_ROWS }),
+            ("dig", Some(rows)) => match rows.parse() {
+                Ok(rows) if rows > 0 => Ok(GameMode::Dig { rows }),
+                _ => Err(invalid()),
+            },
+            ("sprint", None) => Ok(GameMode::Sprint {
+                lines: SPRINT_LINES,
+            }),
+            ("sprint", Some(lines)) => match lines.parse() {
+                Ok(lines) if lines > 0 => Ok(GameMode::Sprint { lines }),
+                _ => Err(invalid()),
+            },
+            ("marathon", None) => Ok(GameMode::Marathon {
+                lines_per_level: LINES_PER_LEVEL,
+            }),
+            ("marathon", Some(lines)) => match lines.parse() {
+                Ok(lines_per_level) if lines_per_level > 0 => {
+                    Ok(GameMode::Marathon { lines_per_level })
+                }
+                _ => Err(invalid()),
+            },
+            _ => Err(invalid()),
+        }
+    }
+
+    /// The mode in the form `parse` reads.
+    pub fn to_spec(&self) -> String {
+        match self {
+            GameMode::Endless => "endless".to_string(),
+            GameMode::Dig { rows } => format!("dig={}", rows),
+            GameMode::Sprint { lines } => format!("sprint={}", lines),
+            GameMode::Marathon { lines_per_level } => format!("marathon={}", lines_per_level),
+        }
+    }
+
+    /// Whether the game ends at a goal rather than at a top out.
+    pub fn has_goal(&self) -> bool {
+        matches!(self, GameMode::Dig { .. } | GameMode::Sprint { .. })
+    }
+
+    /// Checks that the mode can be played on a board of `dimensions`: a
+    /// dig must leave room for a piece above its garbage.
+    pub fn check(&self, dimensions: Dimensions) -> Result<(), String> {
+        match *self {
+            GameMode::Dig { rows } if rows + PIECE_SIZE > dimensions.height => Err(tr!(
+                "{} 行垃圾放不进 {} 的棋盘",
+                "{} garbage rows do not fit a {} board",
+                rows,
+                dimensions
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// One game's way towards the goal of its mode, and the garbage pushed
+/// into it. The garbage rows are always at the bottom of the stack, so
+/// counting the cleared rows below the top one tells how many are left.
+pub struct Progress {
+    mode: GameMode,
+    garbage: Option<Garbage>,
+    rng: StdRng,
+    pieces: usize,
+    lines: usize,
+    /// The simulated clock, see `PLACEMENT_FRAMES`.
+    frames: u64,
+    garbage_left: usize,
+    garbage_cleared: usize,
+    scoring: ScoringRules,
+}
+
+impl Progress {
+    /// A game of `mode` with `garbage` pushed into it. The dig rows and
+    /// the garbage holes are drawn from `seed`, apart from the pieces.
+    pub fn new(mode: GameMode, garbage: Option<GarbageConfig>, seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let garbage = garbage.map(|config| Garbage::new(config, rng.random()));
+        Progress {
+            mode,
+            garbage,
+            rng,
+            pieces: 0,
+            lines: 0,
+            frames: 0,
+            garbage_left: 0,
+            garbage_cleared: 0,
+            scoring: ScoringRules::default(),
+        }
+    }
+
+    /// The game scored by `rules` rather than the default rules.
+    pub fn with_scoring(mut self, rules: ScoringRules) -> Self {
+        self.scoring = rules;
+        self
+    }
+
+    /// The plain endless game, which draws nothing.
+    pub fn endless() -> Self {
+        Self::new(GameMode::Endless, None, 0)
+    }
+
+    /// Lays out the starting board of the mode on the empty `board` and
+    /// gives it the scoring rules of the game. A dig lays no more rows
+    /// than leave room for a piece, see `GameMode::check`.
+    pub fn start<const H: usize, const W: usize>(&mut self, board: &mut Board<H, W>) {
+        *board = std::mem::take(board).with_scoring(self.scoring);
+        if let GameMode::Dig { rows } = self.mode {
+            let rows = rows.min(H.saturating_sub(PIECE_SIZE));
+            // Rows go in from the bottom, so each is below the one before
+            let mut hole = self.rng.random_range(0..W);
+            for _ in 0..rows {
+                board.add_garbage(1, &[hole]).unwrap();
+                hole = (hole + self.rng.random_range(1..W)) % W;
+            }
+            self.garbage_left = rows;
+        }
+    }
+
+    /// Counts the placement `placed` and pushes in the garbage due after
+    /// it. False when the game is over: its goal is reached, or the
+    /// garbage topped the stack out.
+    pub fn after_piece<const H: usize, const W: usize>(
+        &mut self,
+        placed: &PlacementResult,
+        board: &mut Board<H, W>,
+    ) -> bool {
+        self.pieces += 1;
+        self.lines += placed.lines;
+        self.frames += (PLACEMENT_FRAMES + Timing::NES.after_lock(placed.lines)) as u64;
+        let dug = placed
+            .cleared_rows()
+            .iter()
+            .filter(|&&y| y < self.garbage_left)
+            .count();
+        self.garbage_left -= dug;
+        self.garbage_cleared += dug;
+        if self.finished() {
+            return false;
+        }
+        match self
+            .garbage
+            .as_mut()
+            .map(|garbage| garbage.after_piece(board))
+        {
+            Some(Ok(rows)) => {
+                self.garbage_left += rows;
+                true
+            }
+            Some(Err(_)) => false,
+            None => true,
+        }
+    }
+
+    pub fn mode(&self) -> GameMode {
+        self.mode
+    }
+
+    /// Pieces placed so far.
+    pub fn pieces(&self) -> usize {
+        self.pieces
+    }
+
+    /// Lines cleared so far.
+    pub fn lines(&self) -> usize {
+        self.lines
+    }
+
+    /// Time the game took so far on the simulated clock: every piece is
+    /// placed in `PLACEMENT_FRAMES` and waits as `Timing::NES` says after
+    /// it locks.
+    pub fn time(&self) -> Duration {
+        FRAME * self.frames as u32
+    }
+
+    /// Garbage rows cleared so far, dug out or pushed in.
+    pub fn garbage_cleared(&self) -> usize {
+        self.garbage_cleared
+    }
+
+    /// The marathon level the game is on, 0 in the other modes.
+    pub fn level(&self) -> usize {
+        match self.mode {
+            GameMode::Marathon { lines_per_level } => self.lines / lines_per_level,
+            _ => 0,
+        }
+    }
+
+    /// Frames per row the piece falls at on the current level of a
+    /// marathon, `None` in the other modes, which have no gravity.
+    pub fn gravity(&self) -> Option<u32> {
+        match self.mode {
+            GameMode::Marathon { .. } => Some(gravity(self.level())),
+            _ => None,
+        }
+    }
+
+    /// Whether the goal of the mode is reached; never for the modes
+    /// without one.
+    pub fn finished(&self) -> bool {
+        match self.mode {
+            GameMode::Endless | GameMode::Marathon { .. } => false,
+            GameMode::Dig { .. } => self.garbage_left == 0,
+            GameMode::Sprint { lines } => self.lines >= lines,
+        }
+    }
+
+    /// How far the game got towards its goal, or up the levels of a
+    /// marathon, for the summaries; `None` for the endless game.
+    pub fn summary(&self) -> Option<String> {
+        match self.mode {
+            GameMode::Endless => None,
+            GameMode::Marathon { .. } => Some(tr!(
+                "到达第 {} 级 (每行 {} 帧), 消除 {} 行, 用了 {} 个方块",
+                "Reached level {} ({} frames per row) with {} lines in {} pieces",
+                self.level(),
+                gravity(self.level()),
+                self.lines,
+                self.pieces
+            )),
+            GameMode::Dig { .. } if self.finished() => Some(tr!(
+                "挖完全部 {} 行垃圾, 用了 {} 个方块",
+                "Dug out all {} garbage rows in {} pieces",
+                self.garbage_cleared,
+                self.pieces
+            )),
+            GameMode::Dig { .. } => Some(tr!(
+                "挖掉 {}/{} 行垃圾, 用了 {} 个方块",
+                "Dug out {} of {} garbage rows in {} pieces",
+                self.garbage_cleared,
+                self.garbage_cleared + self.garbage_left,
+                self.pieces
+            )),
+            GameMode::Sprint { lines } if self.finished() => Some(tr!(
+                "消除 {} 行, 用了 {} 个方块, 模拟用时 {}",
+                "Cleared {} lines in {} pieces, {} on the simulated clock",
+                lines,
+                self.pieces,
+                clock(self.time())
+            )),
+            GameMode::Sprint { lines } => Some(tr!(
+                "消除 {}/{} 行, 用了 {} 个方块, 模拟用时 {}",
+                "Cleared {} of {} lines in {} pieces, {} on the simulated clock",
+                self.lines,
+                lines,
+                self.pieces,
+                clock(self.time())
+            )),
+        }
+    }
+
+    /// What the game on `board` is worth to training, the higher the
+    /// better and never negative: the score of an endless game or a
+    /// marathon, or `GOAL_POINTS` for each step towards the goal less a
+    /// point for every piece.
+    pub fn fitness<const H: usize, const W: usize>(&self, board: &Board<H, W>) -> i64 {
+        let progress = match self.mode {
+            GameMode::Endless | GameMode::Marathon { .. } => return board.get_score(),
+            GameMode::Dig { .. } => self.garbage_cleared,
+            GameMode::Sprint { lines } => self.lines.min(lines),
+        };
+        (progress as i64 * GOAL_POINTS - self.pieces as i64).max(0)
+    }
+}
+
+/// `m:ss.ss`, as sprint times are usually given.
+fn clock(time: Duration) -> String {
+    let seconds = time.as_secs_f64();
+    format!("{}:{:05.2}", (seconds / 60.0) as u64, seconds % 60.0)
+}
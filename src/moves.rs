@@ -0,0 +1,285 @@
+//! Which placements the policy chooses from.
+//!
+//! The engine drops every piece straight down from above the stack, so a
+//! placement is a rotation and a column and the piece can never end up
+//! under an overhang. With [`Moves::Full`] the piece is instead moved as
+//! a player would move it: from where it spawns at the top of the well,
+//! or in the hidden rows of a board that has them, one column left or
+//! right, one row down or a quarter turn at a time, never up except as
+//! a kick lifts it. [`Moves::FullHalfTurns`] also turns it 180° in one
+//! input, as `live --rotate-180` does. Every position it can rest in is a placement, tucks
+//! under overhangs, slides along the stack and spins into slots
+//! included, and where it rests takes the row too. A position
+//! a turn leads into is marked as `turned`, so that a T locking there
+//! may score as a T-spin. Under the gravity of a marathon level the
+//! piece falls while it is moved, so fewer of those positions are in
+//! reach; see [`reachable_placements_under_in`].
+//!
+//! The built-in tetrominoes turn with the SRS kicks, as `live` turns
+//! them, and half turns with the SRS+ ones; the pieces of other sets turn
+//! in place.
+
+use std::collections::{HashSet, VecDeque};
+
+use crate::board::{Board, FEATURES, Placement};
+use crate::piece::{ALL_PIECES, PIECE_SIZE, PieceSet};
+use crate::rotation::Transition;
+use crate::timing::FRAMES_PER_INPUT;
+
+/// The placements a policy chooses from. `Full` finds more of them, so
+/// scores and trained weights from the two do not compare.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Moves {
+    /// Straight drops, one per rotation and column that fits.
+    #[default]
+    Drop,
+    /// Every resting position the piece can be moved to from its spawn.
+    Full,
+    /// `Full` with 180° turns as well as quarter turns.
+    FullHalfTurns,
+}
+
+impl Moves {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "drop" => Some(Moves::Drop),
+            "full" => Some(Moves::Full),
+            "full-180" => Some(Moves::FullHalfTurns),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Moves::Drop => "drop",
+            Moves::Full => "full",
+            Moves::FullHalfTurns => "full-180",
+        }
+    }
+
+    /// Whether the piece is moved from its spawn rather than dropped.
+    pub fn is_full(self) -> bool {
+        self != Moves::Drop
+    }
+
+    /// The placements of `piece` these moves allow, with the features
+    /// `simulate` reports for each.
+    pub fn placements_with_features_in<const H: usize, const W: usize>(
+        self,
+        board: &Board<H, W>,
+        set: &PieceSet,
+        piece: usize,
+    ) -> Vec<(Placement, [f64; FEATURES])> {
+        self.placements_with_features_under_in(board, set, piece, None)
+    }
+
+    /// `placements_with_features_in` under `gravity` frames per row, which
+    /// only limits `Full`, see `reachable_placements_under_in`. A straight
+    /// drop is taken to be lined up before the piece falls.
+    pub fn placements_with_features_under_in<const H: usize, const W: usize>(
+        self,
+        board: &Board<H, W>,
+        set: &PieceSet,
+        piece: usize,
+        gravity: Option<u32>,
+    ) -> Vec<(Placement, [f64; FEATURES])> {
+        match self {
+            Moves::Drop => board
+                .legal_placements_with_features_in(set, piece)
+                .collect(),
+            Moves::Full | Moves::FullHalfTurns => {
+                let half_turns = self == Moves::FullHalfTurns;
+                reachable_placements_turning_in(board, set, piece, gravity, half_turns)
+            }
+            .into_iter()
+            .filter_map(|p| Some((p, board.simulate_at_in(set, piece, p)?.1)))
+            .collect(),
+        }
+    }
+}
+
+/// Every position `piece` can be moved to from its spawn and rest in,
+/// by rotation, then from left to right and then from the top down, so
+/// the straight drop of a rotation and column comes before the tucks
+/// under it. Positions covering the same cells in another rotation are
+/// left out. Empty when the spawn position is taken.
+pub fn reachable_placements_in<const H: usize, const W: usize>(
+    board: &Board<H, W>,
+    set: &PieceSet,
+    piece: usize,
+) -> Vec<Placement> {
+    reachable_placements_under_in(board, set, piece, None)
+}
+
+/// `reachable_placements_in` with the piece falling a row every `gravity`
+/// frames, as in `timing::GRAVITY`, while the shifts and turns are pressed
+/// one every `FRAMES_PER_INPUT` frames. A position is only reached if the
+/// piece has not been pulled past it by then, so the faster the gravity
+/// the fewer columns and slots are in reach. Soft drops take no time.
+/// With `None` the piece only falls as it is moved.
+///
+/// ```
+/// use mortis::board::Board;
+/// use mortis::moves::{reachable_placements_in, reachable_placements_under_in};
+/// use mortis::piece::{PieceSet, PieceType};
+///
+/// let (board, set) = (Board::new(), PieceSet::standard());
+/// let piece = PieceType::T as usize;
+/// let free = reachable_placements_in(&board, set, piece).len();
+/// assert_eq!(reachable_placements_under_in(&board, set, piece, Some(48)).len(), free);
+/// assert!(reachable_placements_under_in(&board, set, piece, Some(1)).len() < free);
+/// ```
+pub fn reachable_placements_under_in<const H: usize, const W: usize>(
+    board: &Board<H, W>,
+    set: &PieceSet,
+    piece: usize,
+    gravity: Option<u32>,
+) -> Vec<Placement> {
+    reachable_placements_turning_in(board, set, piece, gravity, false)
+}
+
+/// `reachable_placements_under_in` with `half_turns` letting the piece
+/// also turn 180° in one input, with the SRS+ kicks for the tetrominoes.
+/// A position a half turn leads into is `turned` as after a quarter turn.
+pub fn reachable_placements_turning_in<const H: usize, const W: usize>(
+    board: &Board<H, W>,
+    set: &PieceSet,
+    piece: usize,
+    gravity: Option<u32>,
+    half_turns: bool,
+) -> Vec<Placement> {
+    let grid = board.get_grid();
+    let fits = |(rotation, x, y): (usize, i32, i32)| {
+        let shape = set.rotation(piece, rotation);
+        x >= 0
+            && y >= 0
+            && x as usize + shape.width <= W
+            && y as usize + shape.height <= H
+            && (0..shape.height).all(|i| {
+                (0..shape.width)
+                    .all(|j| shape.shape[i][j] == 0 || !grid[y as usize + i][x as usize + j])
+            })
+    };
+    let kicks = set.is_standard().then(|| ALL_PIECES[piece]);
+    // A turn at the top is pushed down first, as the well has no rows
+    // above it to turn into
+    let turn = |(rotation, x, y): (usize, i32, i32), quarters: usize| {
+        let to = (rotation + quarters) % 4;
+        let top = (H - set.rotation(piece, to).height) as i32;
+        match kicks {
+            Some(piece_type) => Transition::find(piece_type, rotation, to)?
+                .positions(piece_type, x, y)
+                .map(|(x, y)| (to, x, y.min(top)))
+                .find(|&state| fits(state)),
+            None => Some((to, x, y.min(top))).filter(|&state| fits(state)),
+        }
+    };
+
+    let spawn = set.rotation(piece, 0);
+    let start = (
+        0,
+        ((W - spawn.width) / 2) as i32,
+        board.spawn_y(spawn.height) as i32,
+    );
+    if !fits(start) {
+        return Vec::new();
+    }
+    let index = |(rotation, x, y): (usize, i32, i32)| (rotation * H + y as usize) * W + x as usize;
+    // By the `inputs`-th shift or turn the gravity has pulled the piece
+    // at least `inputs * FRAMES_PER_INPUT / gravity` rows below its spawn
+    let in_time = |(_, _, y): (usize, i32, i32), inputs: u32| {
+        gravity.is_none_or(|gravity| {
+            (inputs * FRAMES_PER_INPUT) as i64 / gravity as i64 <= (start.2 - y) as i64
+        })
+    };
+    // The fewest shifts and turns each state takes, which leave the most
+    // time for the moves after it
+    let mut inputs = vec![u32::MAX; 4 * H * W];
+    // Whether some turn leads into the state, which may only turn up
+    // after the state itself was reached otherwise
+    let mut turned = vec![false; 4 * H * W];
+    inputs[index(start)] = 0;
+    let mut queue = VecDeque::from([(start, 0)]);
+    let mut resting = Vec::new();
+    while let Some((state @ (rotation, x, y), used)) = queue.pop_front() {
+        if used > inputs[index(state)] {
+            continue;
+        }
+        if !fits((rotation, x, y - 1)) {
+            resting.push(state);
+        } else if used < inputs[index((rotation, x, y - 1))] {
+            // Dropping a row costs no input, so it goes first in line
+            inputs[index((rotation, x, y - 1))] = used;
+            queue.push_front(((rotation, x, y - 1), used));
+        }
+        let shifts = [(rotation, x - 1, y), (rotation, x + 1, y)];
+        let turns = [1, 2, 3].map(|quarters| {
+            (quarters != 2 || half_turns)
+                .then(|| turn(state, quarters))
+                .flatten()
+                .filter(|&next| in_time(next, used + 1))
+        });
+        for next in turns.iter().flatten() {
+            turned[index(*next)] = true;
+        }
+        let next = shifts
+            .into_iter()
+            .filter(|&next| fits(next) && in_time(next, used + 1))
+            .chain(turns.into_iter().flatten());
+        for next in next {
+            if used + 1 < inputs[index(next)] {
+                inputs[index(next)] = used + 1;
+                queue.push_back((next, used + 1));
+            }
+        }
+    }
+
+    let mut placements: Vec<Placement> = resting
+        .into_iter()
+        .map(|state @ (rotation, x, y)| Placement {
+            rotation,
+            x: x as usize,
+            landing_y: y as usize,
+            turned: turned[index(state)],
+        })
+        .collect();
+
+    placements.sort_by_key(|p| (p.rotation, p.x, std::cmp::Reverse(p.landing_y)));
+    let mut covered = HashSet::new();
+    placements.retain(|p| {
+        let shape = set.rotation(piece, p.rotation);
+        let mut cells = [(0, 0); PIECE_SIZE];
+        let mut n = 0;
+        for i in 0..shape.height {
+            for j in 0..shape.width {
+                if shape.shape[i][j] != 0 {
+                    cells[n] = (p.landing_y + i, p.x + j);
+                    n += 1;
+                }
+            }
+        }
+        covered.insert(cells)
+    });
+    placements
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::piece::PieceType;
+
+    #[test]
+    fn half_turns_reach_more_placements() {
+        let board =
+            Board::<15, 10>::from_rows(&["###.#..##.", ".#....#..#", "#..#......"]).unwrap();
+        let (set, piece) = (PieceSet::standard(), PieceType::L as usize);
+        let quarter = reachable_placements_turning_in(&board, set, piece, None, false);
+        let half = reachable_placements_turning_in(&board, set, piece, None, true);
+        let at = |p: &Placement| (p.rotation, p.x, p.landing_y);
+        // Upside down in the gap under the overhang, which only a half
+        // turn gets into
+        assert!(!quarter.iter().any(|p| at(p) == (2, 2, 0)));
+        assert!(half.iter().any(|p| at(p) == (2, 2, 0) && p.turned));
+        assert!(quarter.iter().all(|q| half.iter().any(|p| at(p) == at(q))));
+    }
+}
@@ -0,0 +1,170 @@
+use std::{thread, time::Duration};
+
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+
+use crate::board::{BOARD_HEIGHT, BOARD_WIDTH, Board, FEATURES};
+use crate::i18n::{say, tr};
+use crate::piece::get_random_piece;
+use crate::policy::select_action;
+use crate::terminal;
+use crate::theme::palette;
+
+/// One of the games shown side by side.
+struct Game {
+    board: Board,
+    rng: StdRng,
+    weights: [f64; FEATURES],
+    label: String,
+    pieces: usize,
+    over: bool,
+    temperature: Option<f64>,
+}
+
+impl Game {
+    fn step(&mut self) {
+        if self.over {
+            return;
+        }
+        let piece = get_random_piece(&mut self.rng);
+        match select_action(
+            &self.board,
+            piece,
+            &self.weights,
+            self.temperature,
+            &mut self.rng,
+        ) {
+            Some((rotation, x, _)) => {
+                self.board.apply(piece, x, rotation).unwrap();
+                self.pieces += 1;
+            }
+            None => self.over = true,
+        }
+    }
+
+    /// The board as text lines, each exactly `BOARD_WIDTH + 2` columns
+    /// wide on screen. A `compact` board takes half the lines.
+    fn render(&self, compact: bool) -> Vec<String> {
+        let width = BOARD_WIDTH;
+        let status = if self.over {
+            tr!("结束", "over")
+        } else {
+            String::new()
+        };
+        let mut lines = vec![
+            fit(&self.label, width + 2),
+            fit(&format!("{} {}", self.board.get_score(), status), width + 2),
+            format!("╔{}╗", "═".repeat(width)),
+        ];
+        let grid = self.board.get_grid();
+        let colors = self.board.get_color_grid();
+        if compact {
+            let rows = terminal::half_blocks(width, BOARD_HEIGHT, |y, x| {
+                grid[y][x].then_some(colors[y][x])
+            });
+            lines.extend(rows.into_iter().map(|row| format!("║{}║", row)));
+            lines.push(format!("╚{}╝", "═".repeat(width)));
+            return lines;
+        }
+        for y in (0..BOARD_HEIGHT).rev() {
+            let mut line = String::from("║");
+            for x in 0..width {
+                if grid[y][x] {
+                    let color = palette().ansi(colors[y][x]);
+                    line.push_str(&format!("{}\u{25A0}\x1B[0m", color));
+                } else {
+                    line.push(' ');
+                }
+            }
+            line.push('║');
+            lines.push(line);
+        }
+        lines.push(format!("╚{}╝", "═".repeat(width)));
+        lines
+    }
+}
+
+/// Pads or cuts `text` to `width` terminal columns, counting CJK
+/// characters as two.
+fn fit(text: &str, width: usize) -> String {
+    let mut out = String::new();
+    let mut used = 0;
+    for c in text.chars() {
+        let w = if c.is_ascii() { 1 } else { 2 };
+        if used + w > width {
+            break;
+        }
+        out.push(c);
+        used += w;
+    }
+    out.push_str(&" ".repeat(width - used));
+    out
+}
+
+/// Plays `count` games in lockstep and draws them as a grid. Game `i`
+/// uses seed `i` and weight vector `i % weights.len()`, so with a single
+/// vector the boards differ only in their piece sequence. With a
+/// `temperature` the placements are sampled, see `sample_action`.
+pub fn multiview(count: usize, weights: &[[f64; FEATURES]], temperature: Option<f64>) {
+    let count = count.clamp(1, 9);
+    let mut games: Vec<Game> = (0..count)
+        .map(|i| {
+            let label = if weights.len() > 1 {
+                tr!("#{} 权重{}", "#{} weights {}", i + 1, i % weights.len() + 1)
+            } else {
+                tr!("#{} 种子{}", "#{} seed {}", i + 1, i)
+            };
+            Game {
+                board: Board::new(),
+                rng: StdRng::seed_from_u64(i as u64),
+                weights: weights[i % weights.len()],
+                label,
+                pieces: 0,
+                over: false,
+                temperature,
+            }
+        })
+        .collect();
+    let columns = (1..=3).find(|c| c * c >= count).unwrap_or(3);
+    let rows = count.div_ceil(columns);
+    // The title and the total around the tiles, each tile its label and
+    // score over the framed board
+    let width = columns * (BOARD_WIDTH + 2) + (columns - 1) * 2;
+    let height = 2 + rows * (BOARD_HEIGHT + 4);
+
+    loop {
+        for game in &mut games {
+            game.step();
+        }
+
+        let mut frame = String::from("\x1B[2J\x1B[1;1H");
+        frame.push_str(&tr!(
+            "Tetris AI Preview - 多局 (按Ctrl+C退出)\n",
+            "Tetris AI Preview - multiple games (Ctrl+C to quit)\n"
+        ));
+        let compact = terminal::half(width, height);
+        for row in games.chunks(columns) {
+            let tiles: Vec<Vec<String>> = row.iter().map(|game| game.render(compact)).collect();
+            for line in 0..tiles[0].len() {
+                let parts: Vec<&str> = tiles.iter().map(|t| t[line].as_str()).collect();
+                frame.push_str(&parts.join("  "));
+                frame.push('\n');
+            }
+        }
+        let pieces: usize = games.iter().map(|g| g.pieces).sum();
+        let scores: i64 = games.iter().map(|g| g.board.get_score()).sum();
+        frame.push_str(&tr!(
+            "已放置 {} 个方块, 平均分数 {:.1}\n",
+            "{} pieces placed, average score {:.1}\n",
+            pieces,
+            scores as f64 / games.len() as f64
+        ));
+        print!("{}", frame);
+
+        if games.iter().all(|g| g.over) {
+            say!("所有对局均已结束", "All games are over");
+            break;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+}
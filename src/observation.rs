@@ -0,0 +1,123 @@
+use std::borrow::Cow;
+
+use crate::board::{BOARD_HEIGHT, BOARD_WIDTH, Board};
+
+/// What a controller is shown of the board.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ObservationMode {
+    /// The whole grid, or for external programs their own copy of it.
+    #[default]
+    Full,
+    /// Only the column heights and the number of holes.
+    Heights,
+}
+
+impl ObservationMode {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "full" => Some(ObservationMode::Full),
+            "heights" => Some(ObservationMode::Heights),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ObservationMode::Full => "full",
+            ObservationMode::Heights => "heights",
+        }
+    }
+}
+
+/// The reduced observation: the height of every column and how many empty
+/// cells lie below the column tops. On the wire it is one line,
+/// `O <h0> ... <h9> <holes>` on the default board, with a height for
+/// every column on others.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HeightMap<const H: usize = BOARD_HEIGHT, const W: usize = BOARD_WIDTH> {
+    pub heights: [usize; W],
+    pub holes: usize,
+}
+
+impl<const H: usize, const W: usize> HeightMap<H, W> {
+    pub fn from_board(board: &Board<H, W>) -> Self {
+        let grid = board.get_grid();
+        let holes = (0..W)
+            .map(|x| (0..board.get_height(x)).filter(|&y| !grid[y][x]).count())
+            .sum();
+        HeightMap {
+            heights: *board.get_heights(),
+            holes,
+        }
+    }
+
+    pub fn to_line(&self) -> String {
+        let heights: Vec<String> = self.heights.iter().map(|h| h.to_string()).collect();
+        format!("O {} {}", heights.join(" "), self.holes)
+    }
+
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut parts = line.split_whitespace();
+        if parts.next() != Some("O") {
+            return None;
+        }
+        let numbers: Vec<usize> = parts.map(|p| p.parse().ok()).collect::<Option<_>>()?;
+        let (&holes, heights) = numbers.split_last()?;
+        let heights: [usize; W] = heights.try_into().ok()?;
+        heights
+            .iter()
+            .all(|&h| h <= H)
+            .then_some(HeightMap { heights, holes })
+    }
+
+    /// A board that matches the observation: every column filled up to
+    /// its height, with the holes carved out of it. Each row that would
+    /// otherwise be full gets one hole, under the tallest column since the
+    /// real board must have one there; the remaining holes go in from the
+    /// bottom row up, left to right. The policy can play on this board in
+    /// place of the real one, as landing rows only depend on the heights.
+    pub fn to_board(&self) -> Board<H, W> {
+        let mut holes = Vec::new();
+        let mut left = self.holes;
+        let tallest = (0..W).max_by_key(|&x| self.heights[x]).unwrap_or(0);
+        // A full top row cannot be carved without lowering a column; such
+        // an observation does not come from a real board anyway
+        let full_rows = self
+            .heights
+            .iter()
+            .copied()
+            .min()
+            .unwrap_or(0)
+            .min(self.heights[tallest].saturating_sub(1));
+        for y in 0..full_rows {
+            holes.push((tallest, y));
+            left = left.saturating_sub(1);
+        }
+        'rows: for y in 0..H {
+            for x in 0..W {
+                if left == 0 {
+                    break 'rows;
+                }
+                // Keep the top cell so the height stays the same
+                if y + 1 < self.heights[x] && !(x == tallest && y < full_rows) {
+                    holes.push((x, y));
+                    left -= 1;
+                }
+            }
+        }
+        // `parse` keeps the heights within the board and every hole is
+        // under the top of its column
+        Board::from_heights_with_holes(&self.heights, &holes).unwrap()
+    }
+}
+
+/// The board the built-in policy gets to see in `mode`.
+pub fn observed<const H: usize, const W: usize>(
+    board: &Board<H, W>,
+    mode: ObservationMode,
+) -> Cow<'_, Board<H, W>> {
+    match mode {
+        ObservationMode::Full => Cow::Borrowed(board),
+        ObservationMode::Heights => Cow::Owned(HeightMap::from_board(board).to_board()),
+    }
+}
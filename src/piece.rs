@@ -1,317 +1,47 @@
-#[rustfmt::skip]
-pub const ROTATIONS: [[Piece; 4]; 7] = [
-    // I
-    [
-        Piece {
-            shape: [[1, 1, 1, 1], 
-                    [0, 0, 0, 0], 
-                    [0, 0, 0, 0], 
-                    [0, 0, 0, 0]],
-            width: 4,
-            height: 1,
-            leftmost: [0, 0, 0, 0],
-            rightmost: [3, 0, 0, 0],
-        },
-        Piece {
-            shape: [[1, 0, 0, 0], 
-                    [1, 0, 0, 0], 
-                    [1, 0, 0, 0], 
-                    [1, 0, 0, 0]],
-            width: 1,
-            height: 4,
-            leftmost: [0, 0, 0, 0],
-            rightmost: [0, 0, 0, 0],
-        },
-        Piece {
-            shape: [[1, 1, 1, 1], 
-                    [0, 0, 0, 0], 
-                    [0, 0, 0, 0], 
-                    [0, 0, 0, 0]],
-            width: 4,
-            height: 1,
-            leftmost: [0, 0, 0, 0],
-            rightmost: [3, 0, 0, 0],
-        },
-        Piece {
-            shape: [[1, 0, 0, 0], 
-                    [1, 0, 0, 0], 
-                    [1, 0, 0, 0], 
-                    [1, 0, 0, 0]],
-            width: 1,
-            height: 4,
-            leftmost: [0, 0, 0, 0],
-            rightmost: [0, 0, 0, 0],
-        },
-    ],
-    // T
-    [
-        Piece {
-            shape: [[1, 1, 1, 0], 
-                    [0, 1, 0, 0], 
-                    [0, 0, 0, 0], 
-                    [0, 0, 0, 0]],
-            width: 3,
-            height: 2,
-            leftmost: [0, 1, 0, 0],
-            rightmost: [2, 1, 0, 0],
-        },
-        Piece {
-            shape: [[1, 0, 0, 0], 
-                    [1, 1, 0, 0], 
-                    [1, 0, 0, 0], 
-                    [0, 0, 0, 0]],
-            width: 2,
-            height: 3,
-            leftmost: [0, 0, 0, 0],
-            rightmost: [1, 0, 0, 0],
-        },
-        Piece {
-            shape: [[0, 1, 0, 0], 
-                    [1, 1, 1, 0], 
-                    [0, 0, 0, 0], 
-                    [0, 0, 0, 0]],
-            width: 3,
-            height: 2,
-            leftmost: [1, 0, 0, 0],
-            rightmost: [1, 2, 0, 0],
-        },
-        Piece {
-            shape: [[0, 1, 0, 0], 
-                    [1, 1, 0, 0], 
-                    [0, 1, 0, 0], 
-                    [0, 0, 0, 0]],
-            width: 2,
-            height: 3,
-            leftmost: [0, 0, 0, 0],
-            rightmost: [1, 1, 0, 0],
-        },
-    ],
-    // O
-    [
-        Piece {
-            shape: [[1, 1, 0, 0], 
-                    [1, 1, 0, 0], 
-                    [0, 0, 0, 0], 
-                    [0, 0, 0, 0]],
-            width: 2,
-            height: 2,
-            leftmost: [0, 0, 0, 0],
-            rightmost: [1, 1, 0, 0],
-        },
-        Piece {
-            shape: [[1, 1, 0, 0], 
-                    [1, 1, 0, 0], 
-                    [0, 0, 0, 0], 
-                    [0, 0, 0, 0]],
-            width: 2,
-            height: 2,
-            leftmost: [0, 0, 0, 0],
-            rightmost: [1, 1, 0, 0],
-        },
-        Piece {
-            shape: [[1, 1, 0, 0], 
-                    [1, 1, 0, 0], 
-                    [0, 0, 0, 0], 
-                    [0, 0, 0, 0]],
-            width: 2,
-            height: 2,
-            leftmost: [0, 0, 0, 0],
-            rightmost: [1, 1, 0, 0],
-        },
-        Piece {
-            shape: [[1, 1, 0, 0], 
-                    [1, 1, 0, 0], 
-                    [0, 0, 0, 0], 
-                    [0, 0, 0, 0]],
-            width: 2,
-            height: 2,
-            leftmost: [0, 0, 0, 0],
-            rightmost: [1, 1, 0, 0],
-        },
-    ],
-    // J
-    [
-        Piece {
-            shape: [[1, 1, 1, 0], 
-                    [1, 0, 0, 0], 
-                    [0, 0, 0, 0], 
-                    [0, 0, 0, 0]],
-            width: 3,
-            height: 2,
-            leftmost: [0, 0, 0, 0],
-            rightmost: [2, 0, 0, 0],
-        },
-        Piece {
-            shape: [[1, 0, 0, 0], 
-                    [1, 0, 0, 0], 
-                    [1, 1, 0, 0], 
-                    [0, 0, 0, 0]],
-            width: 2,
-            height: 3,
-            leftmost: [0, 0, 0, 0],
-            rightmost: [1, 1, 0, 0],
-        },
-        Piece {
-            shape: [[0, 0, 1, 0], 
-                    [1, 1, 1, 0], 
-                    [0, 0, 0, 0], 
-                    [0, 0, 0, 0]],
-            width: 3,
-            height: 2,
-            leftmost: [2, 0, 0, 0],
-            rightmost: [2, 2, 0, 0],
-        },
-        Piece {
-            shape: [[1, 1, 0, 0], 
-                    [0, 1, 0, 0], 
-                    [0, 1, 0, 0], 
-                    [0, 0, 0, 0]],
-            width: 2,
-            height: 3,
-            leftmost: [0, 0, 0, 0],
-            rightmost: [1, 1, 0, 0],
-        },
-    ],
-    // L
-    [
-        Piece {
-            shape: [[1, 1, 1, 0], 
-                    [0, 0, 1, 0], 
-                    [0, 0, 0, 0], 
-                    [0, 0, 0, 0]],
-            width: 3,
-            height: 2,
-            leftmost: [0, 0, 0, 0],
-            rightmost: [2, 0, 0, 0],
-        },
-        Piece {
-            shape: [[1, 1, 0, 0], 
-                    [1, 0, 0, 0], 
-                    [1, 0, 0, 0], 
-                    [0, 0, 0, 0]],
-            width: 2,
-            height: 3,
-            leftmost: [0, 0, 0, 0],
-            rightmost: [1, 0, 0, 0],
-        },
-        Piece {
-            shape: [[1, 0, 0, 0], 
-                    [1, 1, 1, 0], 
-                    [0, 0, 0, 0], 
-                    [0, 0, 0, 0]],
-            width: 3,
-            height: 2,
-            leftmost: [0, 0, 0, 0],
-            rightmost: [2, 0, 0, 0],
-        },
-        Piece {
-            shape: [[0, 1, 0, 0], 
-                    [0, 1, 0, 0], 
-                    [1, 1, 0, 0], 
-                    [0, 0, 0, 0]],
-            width: 2,
-            height: 3,
-            leftmost: [0, 0, 0, 0],
-            rightmost: [1, 1, 0, 0],
-        },
-    ],
-    // S
-    [
-        Piece {
-            shape: [[1, 1, 0, 0], 
-                    [0, 1, 1, 0], 
-                    [0, 0, 0, 0], 
-                    [0, 0, 0, 0]],
-            width: 3,
-            height: 2,
-            leftmost: [0, 0, 0, 0],
-            rightmost: [1, 2, 0, 0],
-        },
-        Piece {
-            shape: [[0, 1, 0, 0], 
-                    [1, 1, 0, 0], 
-                    [1, 0, 0, 0], 
-                    [0, 0, 0, 0]],
-            width: 2,
-            height: 3,
-            leftmost: [0, 0, 0, 0],
-            rightmost: [1, 1, 0, 0],
-        },
-        Piece {
-            shape: [[1, 1, 0, 0], 
-                    [0, 1, 1, 0], 
-                    [0, 0, 0, 0], 
-                    [0, 0, 0, 0]],
-            width: 3,
-            height: 2,
-            leftmost: [0, 0, 0, 0],
-            rightmost: [1, 2, 0, 0],
-        },
-        Piece {
-            shape: [[0, 1, 0, 0], 
-                    [1, 1, 0, 0], 
-                    [1, 0, 0, 0], 
-                    [0, 0, 0, 0]],
-            width: 2,
-            height: 3,
-            leftmost: [0, 0, 0, 0],
-            rightmost: [1, 1, 0, 0],
-        },
-    ],
-    // Z
-    [
-        Piece {
-            shape: [[0, 1, 1, 0], 
-                    [1, 1, 0, 0], 
-                    [0, 0, 0, 0], 
-                    [0, 0, 0, 0]],
-            width: 3,
-            height: 2,
-            leftmost: [1, 0, 0, 0],
-            rightmost: [2, 1, 0, 0],
-        },
-        Piece {
-            shape: [[1, 0, 0, 0], 
-                    [1, 1, 0, 0], 
-                    [0, 1, 0, 0], 
-                    [0, 0, 0, 0]],
-            width: 2,
-            height: 3,
-            leftmost: [0, 0, 0, 0],
-            rightmost: [0, 1, 0, 0],
-        },
-        Piece {
-            shape: [[0, 1, 1, 0], 
-                    [1, 1, 0, 0], 
-                    [0, 0, 0, 0], 
-                    [0, 0, 0, 0]],
-            width: 3,
-            height: 2,
-            leftmost: [1, 0, 0, 0],
-            rightmost: [2, 1, 0, 0],
-        },
-        Piece {
-            shape: [[1, 0, 0, 0], 
-                    [1, 1, 0, 0], 
-                    [0, 1, 0, 0], 
-                    [0, 0, 0, 0]],
-            width: 2,
-            height: 3,
-            leftmost: [0, 0, 0, 0],
-            rightmost: [0, 1, 0, 0],
-        },
-    ],
-];
+use std::borrow::Cow;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::LazyLock;
+
+use rand::Rng;
+
+use crate::board::PLAIN_COLOR;
+use crate::i18n::tr;
+use crate::rotation::SRS_SHAPES;
+
+/// The tetrominoes' rotation states as the board plays them: the SRS
+/// states of [`SRS_SHAPES`] moved to the bottom-left corner, so a
+/// placement's column is that of its leftmost cell. Indexed like the SRS
+/// states, 0 spawn, 1 R, 2 180 and 3 L.
+pub const ROTATIONS: [[Piece; 4]; 7] = {
+    let mut table = [[Piece::EMPTY; 4]; 7];
+    let mut p = 0;
+    while p < 7 {
+        let mut r = 0;
+        while r < 4 {
+            table[p][r] = Piece::from_rows(SRS_SHAPES[p][r]);
+            r += 1;
+        }
+        p += 1;
+    }
+    table
+};
+
+/// Side of the box every rotation fits in, and the most cells a piece may
+/// have: five, for the pentominoes.
+pub const PIECE_SIZE: usize = 5;
 
 #[derive(Debug, Clone, Copy)]
 pub struct Piece {
-    pub shape: [[u8; 4]; 4],
+    /// Cells by row from the bottom, then by column from the left.
+    pub shape: [[u8; PIECE_SIZE]; PIECE_SIZE],
     pub width: usize,
     pub height: usize,
-    pub leftmost: [i32; 4],
-    pub rightmost: [i32; 4],
+    pub leftmost: [i32; PIECE_SIZE],
+    pub rightmost: [i32; PIECE_SIZE],
 }
 
+/// The seven tetrominoes; the value indexes `ROTATIONS`.
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum PieceType {
     I = 0,
@@ -322,3 +52,506 @@ pub enum PieceType {
     S = 5,
     Z = 6,
 }
+
+/// Every piece, in `PieceType` order.
+pub const ALL_PIECES: [PieceType; 7] = [
+    PieceType::I,
+    PieceType::T,
+    PieceType::O,
+    PieceType::J,
+    PieceType::L,
+    PieceType::S,
+    PieceType::Z,
+];
+
+impl PieceType {
+    /// The letter the grading protocol and every text format use.
+    pub const fn letter(self) -> char {
+        match self {
+            PieceType::I => 'I',
+            PieceType::T => 'T',
+            PieceType::O => 'O',
+            PieceType::J => 'J',
+            PieceType::L => 'L',
+            PieceType::S => 'S',
+            PieceType::Z => 'Z',
+        }
+    }
+}
+
+impl fmt::Display for PieceType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.letter())
+    }
+}
+
+/// The piece with this letter, upper case only.
+impl TryFrom<char> for PieceType {
+    type Error = String;
+
+    fn try_from(c: char) -> Result<Self, String> {
+        ALL_PIECES
+            .into_iter()
+            .find(|p| p.letter() == c)
+            .ok_or_else(|| tr!("未知方块: {}", "Unknown piece: {}", c))
+    }
+}
+
+/// A single piece letter, as `TryFrom<char>`.
+impl FromStr for PieceType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let mut chars = s.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => PieceType::try_from(c),
+            _ => Err(tr!("未知方块: {}", "Unknown piece: {}", s)),
+        }
+    }
+}
+
+/// Lowest occupied row of each column of every rotation, so the landing row
+/// of a drop is `max(heights[x + dx] - DROP_OFFSETS[piece][rotate][dx])`
+/// over the surface under the piece instead of a scan of the shape.
+pub const DROP_OFFSETS: [[[usize; PIECE_SIZE]; 4]; 7] = {
+    let mut table = [[[0; PIECE_SIZE]; 4]; 7];
+    let mut p = 0;
+    while p < 7 {
+        let mut r = 0;
+        while r < 4 {
+            table[p][r] = drop_offsets(&ROTATIONS[p][r]);
+            r += 1;
+        }
+        p += 1;
+    }
+    table
+};
+
+/// The `DROP_OFFSETS` entry of one rotation.
+const fn drop_offsets(piece: &Piece) -> [usize; PIECE_SIZE] {
+    let mut offsets = [0; PIECE_SIZE];
+    let mut dx = 0;
+    while dx < piece.width {
+        let mut i = 0;
+        while i < piece.height && piece.shape[i][dx] == 0 {
+            i += 1;
+        }
+        offsets[dx] = i;
+        dx += 1;
+    }
+    offsets
+}
+
+/// Whether every cell can be reached from the first through cells that
+/// share an edge.
+fn is_connected(cells: &[(usize, usize)]) -> bool {
+    let mut reached = vec![false; cells.len()];
+    let mut stack = vec![0];
+    reached[0] = true;
+    while let Some(i) = stack.pop() {
+        let (x, y) = cells[i];
+        for (j, &(u, v)) in cells.iter().enumerate() {
+            if !reached[j] && x.abs_diff(u) + y.abs_diff(v) == 1 {
+                reached[j] = true;
+                stack.push(j);
+            }
+        }
+    }
+    reached.into_iter().all(|r| r)
+}
+
+const LETTERS: [char; 7] = {
+    let mut letters = [' '; 7];
+    let mut i = 0;
+    while i < ALL_PIECES.len() {
+        letters[i] = ALL_PIECES[i].letter();
+        i += 1;
+    }
+    letters
+};
+
+/// The pieces a board plays with, for contests that do not use the seven
+/// tetrominoes. A piece is an index into the set, its rotations are
+/// indexed like `ROTATIONS` and its cells get the index as their color.
+/// `PieceSet::standard` is `ROTATIONS` itself, and the `Board` methods
+/// that take a `PieceType` are the `_in` methods on it.
+///
+/// A piece has at most `PIECE_SIZE` cells and fits a box of that side,
+/// so anything from a single cell up to the pentominoes works.
+#[derive(Debug, Clone)]
+pub struct PieceSet {
+    letters: Cow<'static, [char]>,
+    rotations: Cow<'static, [[Piece; 4]]>,
+    drop_offsets: Cow<'static, [[[usize; PIECE_SIZE]; 4]]>,
+}
+
+static STANDARD: PieceSet = PieceSet {
+    letters: Cow::Borrowed(&LETTERS),
+    rotations: Cow::Borrowed(&ROTATIONS),
+    drop_offsets: Cow::Borrowed(&DROP_OFFSETS),
+};
+
+impl PieceSet {
+    /// Most pieces a set may hold: a cell's color is its piece's index,
+    /// kept in a `u8`.
+    pub const MAX_PIECES: usize = 256;
+
+    /// The seven tetrominoes, indexed by `PieceType`.
+    pub fn standard() -> &'static PieceSet {
+        &STANDARD
+    }
+
+    /// Whether this is `PieceSet::standard` itself, whose pieces are the
+    /// `PieceType`s and turn with the SRS kicks.
+    pub fn is_standard(&self) -> bool {
+        std::ptr::eq(self, &STANDARD)
+    }
+
+    /// Reads a set from its text form: each piece is a `piece <letter>`
+    /// line followed by its shape, top row first, with `#` for a cell and
+    /// `.` for a gap. The cells must be joined edge to edge, as the drops
+    /// need a piece without empty columns. The shape as drawn is rotation 0
+    /// and the others turn it clockwise. Blank lines and `//` comments are
+    /// ignored.
+    ///
+    /// ```text
+    /// piece I
+    /// ###
+    ///
+    /// piece L
+    /// #.
+    /// ##
+    /// ```
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut shapes: Vec<(char, Vec<&str>)> = Vec::new();
+        for line in text.lines().map(str::trim) {
+            if let Some(letter) = line.strip_prefix("piece ") {
+                let mut chars = letter.trim().chars();
+                let letter = match (chars.next(), chars.next()) {
+                    (Some(c), None) if c != '.' && c != '#' => c,
+                    _ => {
+                        return Err(tr!(
+                            "方块名应为单个字符: {}",
+                            "A piece name is one character: {}",
+                            line
+                        ));
+                    }
+                };
+                if shapes.iter().any(|(l, _)| *l == letter) {
+                    return Err(tr!("方块 {} 重复定义", "Piece {} is defined twice", letter));
+                }
+                shapes.push((letter, Vec::new()));
+            } else if line.is_empty() || line.starts_with("//") {
+                continue;
+            } else if let Some((_, rows)) = shapes.last_mut() {
+                rows.push(line);
+            } else {
+                return Err(tr!(
+                    "形状之前缺少 piece 行: {}",
+                    "Shape before any piece line: {}",
+                    line
+                ));
+            }
+        }
+        if shapes.is_empty() {
+            return Err(tr!("没有定义任何方块", "No pieces defined"));
+        }
+        if shapes.len() > Self::MAX_PIECES {
+            return Err(tr!("最多 {} 种方块", "At most {} pieces", Self::MAX_PIECES));
+        }
+
+        let mut set = PieceSet {
+            letters: Cow::Owned(Vec::new()),
+            rotations: Cow::Owned(Vec::new()),
+            drop_offsets: Cow::Owned(Vec::new()),
+        };
+        for (letter, rows) in shapes {
+            let mut cells = Vec::new();
+            for (i, row) in rows.iter().rev().enumerate() {
+                for (x, c) in row.chars().enumerate() {
+                    match c {
+                        '#' => cells.push((x, i)),
+                        '.' => {}
+                        _ => return Err(tr!("未知格子 '{}': {}", "Unknown cell '{}': {}", c, row)),
+                    }
+                }
+            }
+            if cells.is_empty()
+                || cells.len() > PIECE_SIZE
+                || rows.len() > PIECE_SIZE
+                || rows.iter().any(|r| r.len() > PIECE_SIZE)
+            {
+                return Err(tr!(
+                    "方块 {} 应有 1 到 5 个格子并且不超过 5×5",
+                    "Piece {} needs 1 to 5 cells within 5×5",
+                    letter
+                ));
+            }
+            if !is_connected(&cells) {
+                return Err(tr!(
+                    "方块 {} 的格子不相连",
+                    "The cells of piece {} are not joined edge to edge",
+                    letter
+                ));
+            }
+            let mut rotations = [Piece::from_cells(&cells); 4];
+            for r in 1..4 {
+                // Clockwise: (x, y) -> (y, -x)
+                cells = cells
+                    .iter()
+                    .map(|&(x, y)| (y, PIECE_SIZE - 1 - x))
+                    .collect();
+                rotations[r] = Piece::from_cells(&cells);
+            }
+            set.letters.to_mut().push(letter);
+            set.rotations.to_mut().push(rotations);
+            set.drop_offsets
+                .to_mut()
+                .push(rotations.map(|p| drop_offsets(&p)));
+        }
+        Ok(set)
+    }
+
+    pub fn len(&self) -> usize {
+        self.rotations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rotations.is_empty()
+    }
+
+    pub fn letter(&self, piece: usize) -> char {
+        self.letters[piece]
+    }
+
+    /// The color `piece` locks with on a board: its `PieceType` for the
+    /// tetrominoes and `PLAIN_COLOR` for the pieces of any other set.
+    pub fn color(&self, piece: usize) -> u8 {
+        if self.is_standard() {
+            piece as u8
+        } else {
+            PLAIN_COLOR
+        }
+    }
+
+    /// The piece named `letter`.
+    pub fn find(&self, letter: char) -> Option<usize> {
+        self.letters.iter().position(|&l| l == letter)
+    }
+
+    pub fn rotation(&self, piece: usize, rotate: usize) -> &Piece {
+        &self.rotations[piece][rotate]
+    }
+
+    /// The `DROP_OFFSETS` entry of a rotation.
+    pub fn drop_offsets(&self, piece: usize, rotate: usize) -> &[usize; PIECE_SIZE] {
+        &self.drop_offsets[piece][rotate]
+    }
+
+    /// A piece drawn uniformly from the set.
+    pub fn random(&self, rng: &mut impl Rng) -> usize {
+        rng.random_range(0..self.len())
+    }
+}
+
+/// The 18 one-sided pentominoes: upper case letters for the twelve free
+/// ones and lower case for the mirror images of the six that have one.
+const PENTOMINOES: &str = "
+piece F
+.##
+##.
+.#.
+
+piece f
+##.
+.##
+.#.
+
+piece I
+#####
+
+piece L
+...#
+####
+
+piece l
+#...
+####
+
+piece N
+.###
+##..
+
+piece n
+###.
+..##
+
+piece P
+##
+##
+#.
+
+piece p
+##
+##
+.#
+
+piece T
+###
+.#.
+.#.
+
+piece U
+#.#
+###
+
+piece V
+#..
+#..
+###
+
+piece W
+#..
+##.
+.##
+
+piece X
+.#.
+###
+.#.
+
+piece Y
+.#..
+####
+
+piece y
+..#.
+####
+
+piece Z
+##.
+.#.
+.##
+
+piece z
+.##
+.#.
+##.
+";
+
+static PENTOMINO: LazyLock<PieceSet> = LazyLock::new(|| PieceSet::parse(PENTOMINOES).unwrap());
+
+/// The piece sets built in, as `--piece-set` names them.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum BuiltinSet {
+    /// The seven tetrominoes of `ROTATIONS`.
+    #[default]
+    Tetromino,
+    /// The 18 one-sided pentominoes.
+    Pentomino,
+}
+
+impl BuiltinSet {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "tetromino" => Some(BuiltinSet::Tetromino),
+            "pentomino" => Some(BuiltinSet::Pentomino),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            BuiltinSet::Tetromino => "tetromino",
+            BuiltinSet::Pentomino => "pentomino",
+        }
+    }
+
+    pub fn set(self) -> &'static PieceSet {
+        match self {
+            BuiltinSet::Tetromino => PieceSet::standard(),
+            BuiltinSet::Pentomino => &PENTOMINO,
+        }
+    }
+}
+
+impl Piece {
+    const EMPTY: Piece = Piece {
+        shape: [[0; PIECE_SIZE]; PIECE_SIZE],
+        width: 0,
+        height: 0,
+        leftmost: [0; PIECE_SIZE],
+        rightmost: [0; PIECE_SIZE],
+    };
+
+    /// The piece drawn by `rows`, top row first, `#` for a cell.
+    const fn from_rows(rows: &[&str]) -> Piece {
+        let mut cells = [(0, 0); PIECE_SIZE];
+        let mut count = 0;
+        let mut top = 0;
+        while top < rows.len() {
+            let row = rows[top].as_bytes();
+            let mut x = 0;
+            while x < row.len() {
+                if row[x] == b'#' {
+                    cells[count] = (x, rows.len() - 1 - top);
+                    count += 1;
+                }
+                x += 1;
+            }
+            top += 1;
+        }
+        Piece::from_cells(cells.split_at(count).0)
+    }
+
+    /// The piece covering `cells`, given as `(x, y)` with `y` up. The
+    /// cells are moved to the bottom left corner; they must fit the box.
+    const fn from_cells(cells: &[(usize, usize)]) -> Piece {
+        let mut left = usize::MAX;
+        let mut bottom = usize::MAX;
+        let mut i = 0;
+        while i < cells.len() {
+            let (x, y) = cells[i];
+            if x < left {
+                left = x;
+            }
+            if y < bottom {
+                bottom = y;
+            }
+            i += 1;
+        }
+        let mut piece = Piece::EMPTY;
+        let mut i = 0;
+        while i < cells.len() {
+            let (x, y) = (cells[i].0 - left, cells[i].1 - bottom);
+            piece.shape[y][x] = 1;
+            if x + 1 > piece.width {
+                piece.width = x + 1;
+            }
+            if y + 1 > piece.height {
+                piece.height = y + 1;
+            }
+            i += 1;
+        }
+        let mut y = 0;
+        while y < piece.height {
+            let row = &piece.shape[y];
+            let mut x = 0;
+            while x < PIECE_SIZE && row[x] == 0 {
+                x += 1;
+            }
+            piece.leftmost[y] = if x < PIECE_SIZE { x as i32 } else { 0 };
+            let mut x = PIECE_SIZE;
+            while x > 0 && row[x - 1] == 0 {
+                x -= 1;
+            }
+            piece.rightmost[y] = x.saturating_sub(1) as i32;
+            y += 1;
+        }
+        piece
+    }
+}
+
+pub fn get_random_piece(rng: &mut impl Rng) -> PieceType {
+    ALL_PIECES[rng.random_range(0..ALL_PIECES.len())]
+}
@@ -0,0 +1,218 @@
+//! Plots across training runs, for comparing optimizers and
+//! hyperparameters without exporting the logs elsewhere.
+//!
+//! Every run is read from the `evaluations.txt` of its run directory,
+//! which holds each fitness in the order it was evaluated. The log does
+//! not mark generations, so they are cut at every `population` entries; a
+//! run that was stopped mid-generation ends with a short one.
+
+use std::path::{Path, PathBuf};
+
+use plotters::prelude::*;
+
+use crate::checkpoint::EvaluationLog;
+use crate::i18n::{say, tr};
+use crate::logging::{log_info, log_warn};
+
+const WIDTH: u32 = 1600;
+const HEIGHT: u32 = 600;
+
+/// `(x, fitness)` points of one run's curve.
+pub type Curve = Vec<(usize, f64)>;
+
+/// The fitness history of one training run.
+#[derive(Debug, Clone)]
+pub struct Run {
+    pub label: String,
+    pub population: usize,
+    pub history: Vec<f64>,
+}
+
+impl Run {
+    /// Reads a run directory, or an evaluation log given directly.
+    pub fn load(path: &Path, label: Option<&str>) -> Result<Self, String> {
+        let log_path = if path.is_dir() {
+            path.join("evaluations.txt")
+        } else {
+            path.to_path_buf()
+        };
+        let log = EvaluationLog::load(&log_path)?;
+        // A log is named after the run directory it is in
+        let named = if path.is_dir() {
+            Some(path)
+        } else {
+            path.parent()
+        };
+        let label = match (label, named.and_then(Path::file_name)) {
+            (Some(label), _) => label.to_string(),
+            (None, Some(name)) => name.to_string_lossy().into_owned(),
+            (None, None) => path.display().to_string(),
+        };
+        Ok(Run {
+            label,
+            population: log.population.max(1),
+            history: log.history,
+        })
+    }
+
+    /// `(generation, best fitness of the generation)`, from generation 1.
+    pub fn generations(&self) -> Curve {
+        self.history
+            .chunks(self.population)
+            .enumerate()
+            .map(|(i, chunk)| (i + 1, chunk.iter().copied().fold(f64::NAN, f64::max)))
+            .collect()
+    }
+
+    /// `(evaluations so far, best fitness so far)` after every evaluation.
+    pub fn best_so_far(&self) -> Curve {
+        let mut best = f64::NAN;
+        self.history
+            .iter()
+            .enumerate()
+            .map(|(i, &fitness)| {
+                best = best.max(fitness);
+                (i + 1, best)
+            })
+            .collect()
+    }
+
+    pub fn best(&self) -> f64 {
+        self.history.iter().copied().fold(f64::NAN, f64::max)
+    }
+}
+
+pub struct CompareOptions {
+    pub out: PathBuf,
+    /// Labels for the runs in order; runs past the end use their
+    /// directory name.
+    pub labels: Vec<String>,
+}
+
+impl CompareOptions {
+    /// Parses `<run dir>...` followed by optional flags.
+    pub fn from_args(args: &[String]) -> (Vec<PathBuf>, Self) {
+        let mut options = CompareOptions {
+            out: PathBuf::from("compare.png"),
+            labels: Vec::new(),
+        };
+        let runs: Vec<PathBuf> = args
+            .iter()
+            .take_while(|a| !a.starts_with("--"))
+            .map(PathBuf::from)
+            .collect();
+        let mut args = args[runs.len()..].iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--out" => match args.next() {
+                    Some(path) => options.out = PathBuf::from(path),
+                    None => log_warn!("--out 需要一个文件", "--out expects a file"),
+                },
+                "--labels" => match args.next() {
+                    Some(labels) => {
+                        options.labels = labels.split(',').map(|l| l.trim().to_string()).collect()
+                    }
+                    None => log_warn!("--labels 需要 <名称>,...", "--labels expects <name>,..."),
+                },
+                _ => log_warn!("忽略未知参数: {}", "Ignoring unknown argument: {}", arg),
+            }
+        }
+        (runs, options)
+    }
+}
+
+/// Loads every run, prints how far each got and draws the best fitness
+/// per generation and the best so far per evaluation, one curve per run,
+/// into a PNG.
+pub fn compare(paths: &[PathBuf], options: &CompareOptions) -> Result<Vec<Run>, String> {
+    let runs = paths
+        .iter()
+        .enumerate()
+        .map(|(i, path)| Run::load(path, options.labels.get(i).map(String::as_str)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    say!(
+        "{:<24} {:>8} {:>10} {:>12}",
+        "{:<24} {:>8} {:>10} {:>12}",
+        tr!("运行", "Run"),
+        tr!("代数", "Gens"),
+        tr!("评估次数", "Evals"),
+        tr!("最佳", "Best")
+    );
+    for run in &runs {
+        say!(
+            "{:<24} {:>8} {:>10} {:>12.0}",
+            "{:<24} {:>8} {:>10} {:>12.0}",
+            run.label,
+            run.generations().len(),
+            run.history.len(),
+            run.best()
+        );
+    }
+
+    draw(&runs, &options.out)?;
+    log_info!("已写入 {}", "Wrote {}", options.out.display());
+    Ok(runs)
+}
+
+fn draw(runs: &[Run], path: &Path) -> Result<(), String> {
+    let draw_error =
+        |e: DrawingAreaErrorKind<_>| tr!("绘制图片失败: {}", "Failed to draw image: {}", e);
+    let root = BitMapBackend::new(path, (WIDTH, HEIGHT)).into_drawing_area();
+    root.fill(&WHITE).map_err(draw_error)?;
+    let panels = root.split_evenly((1, 2));
+
+    // In English whatever the language: the fonts plotters finds rarely
+    // cover Chinese, as with the board images
+    let curves: [(&str, &str, Vec<Curve>); 2] = [
+        (
+            "Best fitness per generation",
+            "Generation",
+            runs.iter().map(Run::generations).collect(),
+        ),
+        (
+            "Best fitness so far",
+            "Evaluations",
+            runs.iter().map(Run::best_so_far).collect(),
+        ),
+    ];
+    for (panel, (caption, x_label, series)) in panels.iter().zip(curves) {
+        let points = series.iter().flatten().filter(|p| p.1.is_finite());
+        let x_max = points.clone().map(|p| p.0).max().unwrap_or(1);
+        let y_max = points.map(|p| p.1).fold(0.0, f64::max);
+        let mut chart = ChartBuilder::on(panel)
+            .caption(caption, ("sans-serif", 20))
+            .margin(12)
+            .x_label_area_size(36)
+            .y_label_area_size(72)
+            .build_cartesian_2d(0..x_max, 0.0..y_max * 1.05 + 1.0)
+            .map_err(draw_error)?;
+        chart
+            .configure_mesh()
+            .x_desc(x_label)
+            .y_desc("Fitness")
+            .draw()
+            .map_err(draw_error)?;
+        for (i, (run, points)) in runs.iter().zip(series).enumerate() {
+            let color = Palette99::pick(i).to_rgba();
+            chart
+                .draw_series(LineSeries::new(
+                    points.into_iter().filter(|p| p.1.is_finite()),
+                    color.stroke_width(2),
+                ))
+                .map_err(draw_error)?
+                .label(run.label.clone())
+                .legend(move |(x, y)| {
+                    PathElement::new(vec![(x, y), (x + 20, y)], color.stroke_width(2))
+                });
+        }
+        chart
+            .configure_series_labels()
+            .position(SeriesLabelPosition::LowerRight)
+            .background_style(WHITE.mix(0.8))
+            .border_style(BLACK)
+            .draw()
+            .map_err(draw_error)?;
+    }
+    root.present().map_err(draw_error)
+}
@@ -0,0 +1,481 @@
+use rand::Rng;
+use rayon::prelude::*;
+
+use crate::board::{Board, Placement};
+use crate::evaluator::Evaluator;
+use crate::hold::HoldSlot;
+use crate::moves::Moves;
+use crate::piece::{PieceSet, PieceType};
+
+/// Lookahead searches with at least this many placements of the current
+/// piece rate them on the rayon pool. Each one costs a full search for the
+/// next piece, which is enough work to pay for the split; a plain
+/// `best_action` candidate is a single `simulate` and stays serial.
+const PARALLEL_CANDIDATES: usize = 8;
+
+/// Returns the `(rotate, x, score)` with the lowest evaluation, keeping the
+/// first one found on ties. Runs once per piece in every rollout, so it only
+/// tracks the running best instead of collecting candidates.
+pub fn best_action<const H: usize, const W: usize, E: Evaluator<H, W> + ?Sized>(
+    board: &Board<H, W>,
+    piece_type: PieceType,
+    evaluator: &E,
+) -> Option<(usize, usize, f64)> {
+    best_action_in(board, PieceSet::standard(), piece_type as usize, evaluator)
+}
+
+/// `best_action` for a piece of `set`.
+pub fn best_action_in<const H: usize, const W: usize, E: Evaluator<H, W> + ?Sized>(
+    board: &Board<H, W>,
+    set: &PieceSet,
+    piece: usize,
+    evaluator: &E,
+) -> Option<(usize, usize, f64)> {
+    let mut best: Option<(usize, usize, f64)> = None;
+    for (p, features) in board.legal_placements_with_features_in(set, piece) {
+        let action_score = evaluator.evaluate(board, &features);
+        if best.is_none_or(|(_, _, s)| action_score < s) {
+            best = Some((p.rotation, p.x, action_score));
+        }
+    }
+    best
+}
+
+/// `best_action_in` among the placements `moves` allows, returning the
+/// whole placement: with `Moves::Full` the rotation and column do not
+/// say where the piece ends up.
+pub fn best_move_in<const H: usize, const W: usize, E: Evaluator<H, W> + ?Sized>(
+    board: &Board<H, W>,
+    set: &PieceSet,
+    piece: usize,
+    evaluator: &E,
+    moves: Moves,
+) -> Option<(Placement, f64)> {
+    best_move_under_in(board, set, piece, evaluator, moves, None)
+}
+
+/// `best_move_in` under `gravity` frames per row, see
+/// `Moves::placements_with_features_under_in`.
+pub fn best_move_under_in<const H: usize, const W: usize, E: Evaluator<H, W> + ?Sized>(
+    board: &Board<H, W>,
+    set: &PieceSet,
+    piece: usize,
+    evaluator: &E,
+    moves: Moves,
+    gravity: Option<u32>,
+) -> Option<(Placement, f64)> {
+    let mut best: Option<(Placement, f64)> = None;
+    for (p, features) in moves.placements_with_features_under_in(board, set, piece, gravity) {
+        let score = evaluator.evaluate(board, &features);
+        if best.is_none_or(|(_, s)| score < s) {
+            best = Some((p, score));
+        }
+    }
+    best
+}
+
+/// Every legal `(rotate, x, score)` for the piece, in search order. Meant
+/// for analysis tools; rollouts should use `best_action`.
+pub fn evaluate_all<const H: usize, const W: usize, E: Evaluator<H, W> + ?Sized>(
+    board: &Board<H, W>,
+    piece_type: PieceType,
+    evaluator: &E,
+) -> Vec<(usize, usize, f64)> {
+    evaluate_all_in(board, PieceSet::standard(), piece_type as usize, evaluator)
+}
+
+/// `evaluate_all` for a piece of `set`.
+pub fn evaluate_all_in<const H: usize, const W: usize, E: Evaluator<H, W> + ?Sized>(
+    board: &Board<H, W>,
+    set: &PieceSet,
+    piece: usize,
+    evaluator: &E,
+) -> Vec<(usize, usize, f64)> {
+    board
+        .legal_placements_with_features_in(set, piece)
+        .map(|(p, features)| (p.rotation, p.x, evaluator.evaluate(board, &features)))
+        .collect()
+}
+
+/// Draws a placement at random instead of taking the best, for exploring
+/// and for generating varied games. Each candidate gets the weight
+/// `exp(-(score - best) / (temperature * spread))`, where `spread` is the
+/// standard deviation of the candidates' scores, so the temperature means
+/// the same for any weight vector. At temperature 0 only the best
+/// placements are left, and ties between them go either way.
+pub fn sample_action<const H: usize, const W: usize, E: Evaluator<H, W> + ?Sized>(
+    board: &Board<H, W>,
+    piece_type: PieceType,
+    evaluator: &E,
+    temperature: f64,
+    rng: &mut impl Rng,
+) -> Option<(usize, usize, f64)> {
+    sample_action_in(
+        board,
+        PieceSet::standard(),
+        piece_type as usize,
+        evaluator,
+        temperature,
+        rng,
+    )
+}
+
+/// `sample_action` for a piece of `set`.
+pub fn sample_action_in<const H: usize, const W: usize, E: Evaluator<H, W> + ?Sized>(
+    board: &Board<H, W>,
+    set: &PieceSet,
+    piece: usize,
+    evaluator: &E,
+    temperature: f64,
+    rng: &mut impl Rng,
+) -> Option<(usize, usize, f64)> {
+    sample_from(
+        evaluate_all_in(board, set, piece, evaluator),
+        temperature,
+        rng,
+    )
+}
+
+/// The draw of `sample_action_in` among `candidates`.
+fn sample_from(
+    candidates: Vec<(usize, usize, f64)>,
+    temperature: f64,
+    rng: &mut impl Rng,
+) -> Option<(usize, usize, f64)> {
+    let best = candidates
+        .iter()
+        .map(|&(_, _, s)| s)
+        .min_by(f64::total_cmp)?;
+
+    let n = candidates.len() as f64;
+    let mean = candidates.iter().map(|&(_, _, s)| s).sum::<f64>() / n;
+    let spread = (candidates
+        .iter()
+        .map(|&(_, _, s)| (s - mean).powi(2))
+        .sum::<f64>()
+        / n)
+        .sqrt();
+    let weights: Vec<f64> = candidates
+        .iter()
+        .map(|&(_, _, s)| {
+            if s == best {
+                1.0
+            } else if temperature > 0.0 && spread.is_finite() && spread > 0.0 {
+                (-(s - best) / (temperature * spread)).exp()
+            } else {
+                0.0
+            }
+        })
+        .collect();
+
+    let mut pick = rng.random::<f64>() * weights.iter().sum::<f64>();
+    for (candidate, weight) in candidates.iter().zip(&weights) {
+        if pick < *weight {
+            return Some(*candidate);
+        }
+        pick -= weight;
+    }
+    // Rounding left a sliver past the last weight
+    candidates
+        .iter()
+        .zip(&weights)
+        .rev()
+        .find(|(_, w)| **w > 0.0)
+        .map(|(c, _)| *c)
+}
+
+/// `best_action`, or `sample_action` when a temperature is given.
+pub fn select_action<const H: usize, const W: usize, E: Evaluator<H, W> + ?Sized>(
+    board: &Board<H, W>,
+    piece_type: PieceType,
+    evaluator: &E,
+    temperature: Option<f64>,
+    rng: &mut impl Rng,
+) -> Option<(usize, usize, f64)> {
+    select_action_in(
+        board,
+        PieceSet::standard(),
+        piece_type as usize,
+        evaluator,
+        temperature,
+        rng,
+    )
+}
+
+/// `select_action` for a piece of `set`.
+pub fn select_action_in<const H: usize, const W: usize, E: Evaluator<H, W> + ?Sized>(
+    board: &Board<H, W>,
+    set: &PieceSet,
+    piece: usize,
+    evaluator: &E,
+    temperature: Option<f64>,
+    rng: &mut impl Rng,
+) -> Option<(usize, usize, f64)> {
+    match temperature {
+        None => best_action_in(board, set, piece, evaluator),
+        Some(t) => sample_action_in(board, set, piece, evaluator, t, rng),
+    }
+}
+
+/// How much of the piece queue the built-in policy may look at. Scores
+/// from the two settings are not comparable, so evaluations say which one
+/// they used.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Visibility {
+    /// Only the piece to place, as training does by default.
+    #[default]
+    Current,
+    /// The piece to place and the next one, as the grading protocol gives.
+    Next,
+    /// Only the piece to place, but each placement is rated as if any
+    /// piece of the set could follow it: expectimax over the unknown next
+    /// piece. Costs a search per piece of the set for every placement.
+    Expected,
+}
+
+impl Visibility {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "current" => Some(Visibility::Current),
+            "next" => Some(Visibility::Next),
+            "expected" => Some(Visibility::Expected),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Visibility::Current => "current",
+            Visibility::Next => "next",
+            Visibility::Expected => "expected",
+        }
+    }
+}
+
+/// Like `best_action`, but rates each placement of `piece_type` by its own
+/// evaluation plus that of the best placement of `next` that follows it.
+/// Placements after which `next` does not fit are only taken when there is
+/// nothing else.
+pub fn best_action_with_next<const H: usize, const W: usize, E: Evaluator<H, W> + ?Sized>(
+    board: &Board<H, W>,
+    piece_type: PieceType,
+    next: PieceType,
+    evaluator: &E,
+) -> Option<(usize, usize, f64)> {
+    best_action_with_next_in(
+        board,
+        PieceSet::standard(),
+        piece_type as usize,
+        next as usize,
+        evaluator,
+    )
+}
+
+/// `best_action_with_next` for pieces of `set`.
+pub fn best_action_with_next_in<const H: usize, const W: usize, E: Evaluator<H, W> + ?Sized>(
+    board: &Board<H, W>,
+    set: &PieceSet,
+    piece: usize,
+    next: usize,
+    evaluator: &E,
+) -> Option<(usize, usize, f64)> {
+    lowest(evaluate_all_with_next_in(
+        board, set, piece, next, evaluator,
+    ))
+}
+
+/// The first of the lowest-rated `candidates`.
+fn lowest(candidates: Vec<(usize, usize, f64)>) -> Option<(usize, usize, f64)> {
+    let mut best: Option<(usize, usize, f64)> = None;
+    for (rotate, x, score) in candidates {
+        if best.is_none_or(|(_, _, s)| score < s) {
+            best = Some((rotate, x, score));
+        }
+    }
+    best
+}
+
+/// Every legal `(rotate, x, score)` for `piece` rated as
+/// `best_action_with_next_in` rates them, in search order. The score is
+/// infinite where `next` does not fit afterwards.
+pub fn evaluate_all_with_next_in<const H: usize, const W: usize, E: Evaluator<H, W> + ?Sized>(
+    board: &Board<H, W>,
+    set: &PieceSet,
+    piece: usize,
+    next: usize,
+    evaluator: &E,
+) -> Vec<(usize, usize, f64)> {
+    evaluate_all_then_in(board, set, piece, evaluator, |after| {
+        best_action_in(after, set, next, evaluator).map_or(f64::INFINITY, |(_, _, s)| s)
+    })
+}
+
+/// Every legal `(rotate, x, score)` for `piece`, in search order, each
+/// rated by its own evaluation plus `follow` of the board it leaves.
+fn evaluate_all_then_in<const H: usize, const W: usize, E: Evaluator<H, W> + ?Sized>(
+    board: &Board<H, W>,
+    set: &PieceSet,
+    piece: usize,
+    evaluator: &E,
+    follow: impl Fn(&Board<H, W>) -> f64 + Sync,
+) -> Vec<(usize, usize, f64)> {
+    let candidates = evaluate_all_in(board, set, piece, evaluator);
+
+    let rate = |&(rotate, x, first): &(usize, usize, f64)| {
+        let mut after = board.clone();
+        after.apply_in(set, piece, x, rotate).ok()?;
+        Some((rotate, x, first + follow(&after)))
+    };
+    // Both paths keep search order, so ties go the same way
+    let rated: Vec<Option<(usize, usize, f64)>> = if candidates.len() >= PARALLEL_CANDIDATES {
+        candidates.par_iter().map(rate).collect()
+    } else {
+        candidates.iter().map(rate).collect()
+    };
+    rated.into_iter().flatten().collect()
+}
+
+/// `best_action_with_next_in`, or with a temperature a draw as in
+/// `sample_action` among the same ratings. Placements after which `next`
+/// does not fit are only drawn when there is nothing else.
+pub fn select_action_with_next_in<const H: usize, const W: usize, E: Evaluator<H, W> + ?Sized>(
+    board: &Board<H, W>,
+    set: &PieceSet,
+    piece: usize,
+    next: usize,
+    evaluator: &E,
+    temperature: Option<f64>,
+    rng: &mut impl Rng,
+) -> Option<(usize, usize, f64)> {
+    let Some(t) = temperature else {
+        return best_action_with_next_in(board, set, piece, next, evaluator);
+    };
+    sample_finite(
+        evaluate_all_with_next_in(board, set, piece, next, evaluator),
+        t,
+        rng,
+    )
+}
+
+/// `sample_from`, leaving out infinite ratings unless there is nothing
+/// else.
+fn sample_finite(
+    mut candidates: Vec<(usize, usize, f64)>,
+    temperature: f64,
+    rng: &mut impl Rng,
+) -> Option<(usize, usize, f64)> {
+    if candidates.iter().any(|c| c.2.is_finite()) {
+        candidates.retain(|c| c.2.is_finite());
+    }
+    sample_from(candidates, temperature, rng)
+}
+
+/// Like `best_action_in`, but rates each placement of `piece` by its own
+/// evaluation plus the mean, over every piece of `set`, of the best
+/// placement of that piece afterwards. Placements after which some piece
+/// does not fit are only taken when there is nothing else.
+pub fn best_action_expected_in<const H: usize, const W: usize, E: Evaluator<H, W> + ?Sized>(
+    board: &Board<H, W>,
+    set: &PieceSet,
+    piece: usize,
+    evaluator: &E,
+) -> Option<(usize, usize, f64)> {
+    lowest(evaluate_all_expected_in(board, set, piece, evaluator))
+}
+
+/// Every legal `(rotate, x, score)` for `piece` rated as
+/// `best_action_expected_in` rates them, in search order. The score is
+/// infinite where some piece does not fit afterwards.
+pub fn evaluate_all_expected_in<const H: usize, const W: usize, E: Evaluator<H, W> + ?Sized>(
+    board: &Board<H, W>,
+    set: &PieceSet,
+    piece: usize,
+    evaluator: &E,
+) -> Vec<(usize, usize, f64)> {
+    evaluate_all_then_in(board, set, piece, evaluator, |after| {
+        let total: f64 = (0..set.len())
+            .map(|next| {
+                best_action_in(after, set, next, evaluator).map_or(f64::INFINITY, |(_, _, s)| s)
+            })
+            .sum();
+        total / set.len() as f64
+    })
+}
+
+/// `best_action_expected_in`, or with a temperature a draw as in
+/// `sample_action` among the same ratings. Placements after which some
+/// piece does not fit are only drawn when there is nothing else.
+pub fn select_action_expected_in<const H: usize, const W: usize, E: Evaluator<H, W> + ?Sized>(
+    board: &Board<H, W>,
+    set: &PieceSet,
+    piece: usize,
+    evaluator: &E,
+    temperature: Option<f64>,
+    rng: &mut impl Rng,
+) -> Option<(usize, usize, f64)> {
+    let Some(t) = temperature else {
+        return best_action_expected_in(board, set, piece, evaluator);
+    };
+    sample_finite(
+        evaluate_all_expected_in(board, set, piece, evaluator),
+        t,
+        rng,
+    )
+}
+
+/// The best move with a hold slot: `(hold, rotate, x, score)`, where
+/// `hold` says to place the piece `slot.alternative(next)` instead of
+/// `piece`. Each piece is judged by its own best placement, and holding
+/// only wins when it is strictly better.
+pub fn best_action_with_hold_in<const H: usize, const W: usize, E: Evaluator<H, W> + ?Sized>(
+    board: &Board<H, W>,
+    set: &PieceSet,
+    piece: usize,
+    slot: HoldSlot,
+    next: Option<usize>,
+    evaluator: &E,
+) -> Option<(bool, usize, usize, f64)> {
+    let keep = best_action_in(board, set, piece, evaluator);
+    let swap = slot
+        .alternative(next)
+        .and_then(|other| best_action_in(board, set, other, evaluator));
+    match (keep, swap) {
+        (Some(keep), Some(swap)) if swap.2 < keep.2 => Some((true, swap.0, swap.1, swap.2)),
+        (None, Some(swap)) => Some((true, swap.0, swap.1, swap.2)),
+        (keep, _) => keep.map(|(rotate, x, score)| (false, rotate, x, score)),
+    }
+}
+
+/// The policy's placement for `piece_type` given what it may see.
+pub fn choose<const H: usize, const W: usize, E: Evaluator<H, W> + ?Sized>(
+    board: &Board<H, W>,
+    piece_type: PieceType,
+    next: PieceType,
+    evaluator: &E,
+    visibility: Visibility,
+) -> Option<(usize, usize, f64)> {
+    choose_in(
+        board,
+        PieceSet::standard(),
+        piece_type as usize,
+        next as usize,
+        evaluator,
+        visibility,
+    )
+}
+
+/// `choose` for pieces of `set`.
+pub fn choose_in<const H: usize, const W: usize, E: Evaluator<H, W> + ?Sized>(
+    board: &Board<H, W>,
+    set: &PieceSet,
+    piece: usize,
+    next: usize,
+    evaluator: &E,
+    visibility: Visibility,
+) -> Option<(usize, usize, f64)> {
+    match visibility {
+        Visibility::Current => best_action_in(board, set, piece, evaluator),
+        Visibility::Next => best_action_with_next_in(board, set, piece, next, evaluator),
+        Visibility::Expected => best_action_expected_in(board, set, piece, evaluator),
+    }
+}
@@ -0,0 +1,107 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use rayon::prelude::*;
+
+/// How the games of a batch are spread over the rayon workers.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Schedule {
+    /// Every game is a task of its own, and workers that run out steal
+    /// games from the others. A candidate that survives a million pieces
+    /// then no longer keeps one core busy while the rest wait.
+    #[default]
+    Game,
+    /// One task per candidate, playing all of its games in turn. Kept to
+    /// compare against.
+    Candidate,
+}
+
+impl Schedule {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "game" => Some(Schedule::Game),
+            "candidate" => Some(Schedule::Candidate),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Schedule::Game => "game",
+            Schedule::Candidate => "candidate",
+        }
+    }
+}
+
+/// How busy the workers were during a batch.
+#[derive(Debug, Clone, Copy)]
+pub struct Utilization {
+    pub threads: usize,
+    pub wall: Duration,
+    /// Time spent inside games, summed over all workers.
+    pub busy: Duration,
+}
+
+impl Utilization {
+    /// Share of the available core time spent playing, from 0 to 1.
+    pub fn ratio(&self) -> f64 {
+        let available = self.wall.as_secs_f64() * self.threads as f64;
+        if available > 0.0 {
+            (self.busy.as_secs_f64() / available).min(1.0)
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Plays `games` games for every candidate on the rayon pool and returns
+/// the results per candidate, in game order, whatever the schedule.
+/// `play` gets the candidate and the index of the game.
+pub fn play_all<C, T, F>(
+    candidates: &[C],
+    games: usize,
+    schedule: Schedule,
+    play: F,
+) -> (Vec<Vec<T>>, Utilization)
+where
+    C: Sync,
+    T: Send,
+    F: Fn(&C, usize) -> T + Sync,
+{
+    let busy = AtomicU64::new(0);
+    let timed = |candidate: &C, game: usize| {
+        let start = Instant::now();
+        let result = play(candidate, game);
+        busy.fetch_add(start.elapsed().as_nanos() as u64, Ordering::Relaxed);
+        result
+    };
+
+    let start = Instant::now();
+    let results = match schedule {
+        Schedule::Game => {
+            let mut flat: Vec<T> = (0..candidates.len() * games)
+                .into_par_iter()
+                .with_max_len(1)
+                .map(|task| timed(&candidates[task / games], task % games))
+                .collect();
+            let mut results = Vec::with_capacity(candidates.len());
+            for _ in 0..candidates.len() {
+                let rest = flat.split_off(games);
+                results.push(flat);
+                flat = rest;
+            }
+            results
+        }
+        Schedule::Candidate => candidates
+            .par_iter()
+            .with_max_len(1)
+            .map(|candidate| (0..games).map(|game| timed(candidate, game)).collect())
+            .collect(),
+    };
+    let utilization = Utilization {
+        threads: rayon::current_num_threads(),
+        wall: start.elapsed(),
+        busy: Duration::from_nanos(busy.into_inner()),
+    };
+    (results, utilization)
+}
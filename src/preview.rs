@@ -0,0 +1,436 @@
+//! The `preview` command: one game played by a policy in the terminal,
+//! drawn after every move with the next and held pieces beside the board,
+//! and a summary of the game when it ends or is interrupted.
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::{thread, time::Duration};
+
+use crate::board::Board;
+use crate::evaluator::Model;
+use crate::generator::Randomizer;
+use crate::hold::HoldSlot;
+use crate::i18n::say;
+use crate::logging::{log_info, log_warn};
+use crate::mode::Progress;
+use crate::piece::{PIECE_SIZE, PieceSet};
+use crate::policy::{
+    Visibility, best_action_with_hold_in, best_move_under_in, evaluate_all_expected_in,
+    evaluate_all_in, evaluate_all_with_next_in, select_action_expected_in, select_action_in,
+    select_action_with_next_in,
+};
+use crate::search::{
+    SearchConfig, TranspositionTable, beam_search_with_table_in, best_search_action_with_table_in,
+};
+use crate::stats::{GameStats, decision_margin, sparkline};
+use crate::train::GameConfig;
+use crate::{terminal, theme};
+
+/// With `Visibility::Next` every move looks ahead to the best placement of
+/// the next piece, see `best_action_with_next_in`, and with
+/// `Visibility::Expected` to the mean over every piece that may come, see
+/// `best_action_expected_in`.
+pub fn preview<const H: usize, const W: usize>(
+    model: &Model,
+    temperature: Option<f64>,
+    set: &PieceSet,
+    randomizer: &Randomizer,
+    config: &GameConfig,
+    search: Option<SearchConfig>,
+    mut progress: Progress,
+) {
+    let GameConfig {
+        max_pieces,
+        visibility,
+        hold,
+        moves,
+    } = *config;
+    // Full moves only see the current piece and always take the best
+    // placement, as in training
+    let full = moves.is_full();
+    let (hold, search) = if full { (false, None) } else { (hold, search) };
+    let mut board = Board::<H, W>::empty();
+    progress.start(&mut board);
+    let mut rng = rand::rng();
+    let mut pieces = randomizer.generator(set, rand::rng());
+
+    let running = Arc::new(AtomicBool::new(true));
+    let r = running.clone();
+    if let Err(e) = ctrlc::set_handler(move || r.store(false, Ordering::SeqCst)) {
+        log_warn!(
+            "无法设置Ctrl+C处理: {}",
+            "Cannot set the Ctrl+C handler: {}",
+            e
+        );
+    }
+
+    say!(
+        "Tetris AI Preview (按Ctrl+C退出)",
+        "Tetris AI Preview (Ctrl+C to quit)"
+    );
+
+    if full {
+        log_info!(
+            "落点包括从出生位置移动、旋转可达的所有位置; 不使用暂存、搜索和温度",
+            "Placements include every position the piece can be moved and turned into from its spawn; there is no hold, search or temperature"
+        );
+    } else if search.is_some() && temperature.is_some() {
+        log_warn!(
+            "--search 总是选择最佳落点, 忽略 --temperature",
+            "--search always plays the best placement, ignoring --temperature"
+        );
+    }
+
+    // The pieces known beyond the current one: the next piece, and as
+    // many more as the search looks ahead
+    let lookahead = search.map_or(1, |config| config.lookahead().max(1));
+    let mut current_piece = pieces.next_piece(&board);
+    let mut queue: VecDeque<usize> = (0..lookahead).map(|_| pieces.next_piece(&board)).collect();
+
+    // Each move searches twice, and the next one again most of this one
+    let mut table = TranspositionTable::new(search.map_or(0, |config| config.table));
+    let mut slot = HoldSlot::new();
+    let mut last_score = 0;
+    let mut stats = GameStats::default();
+    let mut heights = Vec::new();
+    let mut margins = Vec::new();
+
+    while running.load(Ordering::SeqCst) && stats.pieces < max_pieces {
+        // The hold decision is always the policy's best; the temperature
+        // only applies to where the chosen piece goes
+        if hold
+            && let Some((true, ..)) =
+                best_action_with_hold_in(&board, set, current_piece, slot, Some(queue[0]), model)
+        {
+            let took_next;
+            (current_piece, took_next) = slot.hold(current_piece, Some(queue[0]));
+            if took_next {
+                queue.pop_front();
+                queue.push_back(pieces.next_piece(&board));
+            }
+        }
+        let next_piece = queue[0];
+        let path: Vec<usize> = std::iter::once(current_piece)
+            .chain(queue.iter().copied())
+            .collect();
+        // A full move may end up below where its rotation and column drop
+        let mut moved = None;
+        let best_action = match (search, visibility) {
+            _ if full => {
+                best_move_under_in(&board, set, current_piece, model, moves, progress.gravity())
+                    .map(|(placement, score)| {
+                        moved = Some(placement);
+                        (placement.rotation, placement.x, score)
+                    })
+            }
+            (Some(config), _) => {
+                best_search_action_with_table_in(&board, set, &path, model, config, &mut table)
+            }
+            (None, Visibility::Current) => {
+                select_action_in(&board, set, current_piece, model, temperature, &mut rng)
+            }
+            (None, Visibility::Next) => select_action_with_next_in(
+                &board,
+                set,
+                current_piece,
+                next_piece,
+                model,
+                temperature,
+                &mut rng,
+            ),
+            (None, Visibility::Expected) => {
+                select_action_expected_in(&board, set, current_piece, model, temperature, &mut rng)
+            }
+        };
+        let Some(best_action) = best_action else {
+            say!(
+                "游戏结束！无法放置方块: {}",
+                "Game over! Cannot place piece: {}",
+                set.letter(current_piece)
+            );
+            break;
+        };
+        let candidates = match (search, visibility) {
+            _ if full => Vec::new(),
+            (Some(config), _) => {
+                beam_search_with_table_in(&board, set, &path, model, config, &mut table)
+            }
+            (None, Visibility::Current) => evaluate_all_in(&board, set, current_piece, model),
+            (None, Visibility::Next) => {
+                evaluate_all_with_next_in(&board, set, current_piece, next_piece, model)
+            }
+            (None, Visibility::Expected) => {
+                evaluate_all_expected_in(&board, set, current_piece, model)
+            }
+        };
+        margins.extend(decision_margin(candidates.into_iter().map(|a| a.2)));
+
+        let placed = match moved {
+            Some(placement) => board.apply_at_in(set, current_piece, placement),
+            None => board.apply_in(set, current_piece, best_action.1, best_action.0),
+        }
+        .unwrap();
+        stats.record(&placed);
+        let going = progress.after_piece(&placed, &mut board);
+        heights.push(board.max_height());
+
+        // print!("\x1B[2J\x1B[1;1H");
+
+        let score = board.get_score();
+
+        let held = hold.then_some(slot.piece());
+        if !terminal::half(PREVIEW_COLUMNS, H + PREVIEW_MARGIN) {
+            println!("╔══════════════════════════════════════╗");
+            println!("║ Tetris AI Preview - Score: {:<9} ║", score);
+            println!("╚══════════════════════════════════════╝");
+            display_game_with_next_piece(&board, set, current_piece, next_piece, held, best_action);
+        } else {
+            say!("分数: {}", "Score: {}", score);
+            display_compact(&board, set, current_piece, next_piece, held, best_action);
+        }
+
+        if progress.finished() {
+            say!("目标达成！", "Goal reached!");
+            break;
+        }
+        if !going {
+            say!(
+                "游戏结束！垃圾行把方块推出了棋盘",
+                "Game over! The garbage pushed the stack out of the well"
+            );
+            break;
+        }
+
+        current_piece = queue.pop_front().unwrap();
+        queue.push_back(pieces.next_piece(&board));
+
+        if score - last_score > 100 {
+            break;
+        }
+        last_score = score;
+
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    preview_summary(&board, &progress, &stats, &heights, &margins);
+    let table_stats = table.stats();
+    if table_stats.lookups > 0 {
+        log_info!(
+            "置换表: {} 次查找, 命中 {:.1}%, {} 次覆盖",
+            "Transposition table: {} lookups, {:.1}% hits, {} replaced",
+            table_stats.lookups,
+            100.0 * table_stats.hit_rate(),
+            table_stats.replaced
+        );
+    }
+}
+
+/// What a preview session amounted to, printed however it ended.
+fn preview_summary<const H: usize, const W: usize>(
+    board: &Board<H, W>,
+    progress: &Progress,
+    stats: &GameStats,
+    heights: &[usize],
+    margins: &[f64],
+) {
+    say!("\n== 预览总结 ==", "\n== Preview summary ==");
+    say!(
+        "  方块: {}  分数: {}  消行: {}",
+        "  Pieces: {}  Score: {}  Lines: {}",
+        stats.pieces,
+        board.get_score(),
+        board.get_lines()
+    );
+    if let Some(summary) = progress.summary() {
+        say!("  {}: {}", "  {}: {}", progress.mode().to_spec(), summary);
+    }
+    for (name, count, points) in stats.components() {
+        if count > 0 {
+            say!(
+                "  {}: {} 次, {} 分",
+                "  {}: {} times, {} points",
+                name,
+                count,
+                points
+            );
+        }
+    }
+    if !heights.is_empty() {
+        say!(
+            "  最大高度: {} (最高 {}, 平均 {:.1})",
+            "  Max height: {} (peak {}, mean {:.1})",
+            sparkline(heights, 60, H),
+            heights.iter().max().unwrap(),
+            heights.iter().sum::<usize>() as f64 / heights.len() as f64
+        );
+    }
+    if !margins.is_empty() {
+        // A few placements next to a game-over penalty would swamp a mean
+        let mut sorted = margins.to_vec();
+        sorted.sort_by(f64::total_cmp);
+        say!(
+            "  决策差距中位数: {:.3} ({} 步中的 {} 步)",
+            "  Median decision margin: {:.3} ({} of {} moves)",
+            sorted[sorted.len() / 2],
+            margins.len(),
+            stats.pieces
+        );
+    }
+}
+
+/// Terminal cells the full preview of a move takes: the score banner
+/// over the board with its panels, and the line about the current piece,
+/// which are the rows on top of the board's own.
+const PREVIEW_COLUMNS: usize = 40;
+const PREVIEW_MARGIN: usize = 6;
+
+/// `piece` in its spawn rotation, top row first, centered in a box that
+/// fits any piece.
+fn piece_box(set: &PieceSet, piece: usize) -> [[bool; PIECE_SIZE]; PIECE_SIZE] {
+    let shape = set.rotation(piece, 0);
+    let mut cells = [[false; PIECE_SIZE]; PIECE_SIZE];
+
+    let offset_x = (PIECE_SIZE - shape.width) / 2;
+    let offset_y = (PIECE_SIZE - shape.height) / 2;
+
+    for y in 0..shape.height {
+        for x in 0..shape.width {
+            if shape.shape[y][x] != 0 {
+                cells[offset_y + shape.height - 1 - y][offset_x + x] = true;
+            }
+        }
+    }
+    cells
+}
+
+/// `held` is the hold slot when the game has one.
+fn display_game_with_next_piece<const H: usize, const W: usize>(
+    board: &Board<H, W>,
+    set: &PieceSet,
+    current_piece: usize,
+    next_piece: usize,
+    held: Option<Option<usize>>,
+    best_action: (usize, usize, f64),
+) {
+    let palette = theme::palette();
+    let grid = board.get_grid();
+    let color_grid = board.get_color_grid();
+
+    let next_preview = piece_box(set, next_piece);
+    let next_piece_color = palette.ansi(Some(set.color(next_piece)));
+    let held_preview = held.flatten().map(|piece| {
+        (
+            piece_box(set, piece),
+            palette.ansi(Some(set.color(piece))).to_string(),
+        )
+    });
+
+    let bar = "═".repeat(PIECE_SIZE + 2);
+    let blank = " ".repeat(PIECE_SIZE + 2);
+    println!("╔{}╗    ╔{}╗", "═".repeat(W), bar);
+    println!("║{}║    ║{:^2$}║", " ".repeat(W), "NEXT", PIECE_SIZE + 2);
+    println!("║{}║    ╠{}╣", " ".repeat(W), bar);
+    println!("║{}║    ║{}║", " ".repeat(W), blank);
+    println!("║{}║    ║{}║", " ".repeat(W), blank);
+    println!("║{}║    ║{}║", " ".repeat(W), blank);
+
+    // With a hold slot, its box takes the rows below the next piece
+    let hold_top = PIECE_SIZE + 2;
+    let print_cells = |cells: &[bool; PIECE_SIZE], color: &str| {
+        print!("║    ║ ");
+        for &cell in cells {
+            if cell {
+                print!("{}\u{25A0}\x1B[0m", color);
+            } else {
+                print!(" ");
+            }
+        }
+        print!(" ║");
+    };
+
+    for y in (0..H).rev() {
+        print!("║");
+
+        for x in 0..W {
+            if grid[y][x] {
+                let color_code = palette.ansi(Some(color_grid[y][x].unwrap_or(0)));
+                print!("{}\u{25A0}\x1B[0m", color_code);
+            } else {
+                print!(" ");
+            }
+        }
+
+        let preview_row = H - y - 1;
+        if (1..=PIECE_SIZE).contains(&preview_row) {
+            print_cells(&next_preview[preview_row - 1], next_piece_color);
+        } else if held.is_some() && (preview_row == hold_top || preview_row == hold_top + 2) {
+            print!("║    ╠{}╣", bar);
+        } else if held.is_some() && preview_row == hold_top + 1 {
+            print!("║    ║{:^1$}║", "HOLD", PIECE_SIZE + 2);
+        } else if let Some((cells, color)) = &held_preview
+            && (hold_top + 3..hold_top + 3 + PIECE_SIZE).contains(&preview_row)
+        {
+            print_cells(&cells[preview_row - hold_top - 3], color);
+        } else {
+            print!("║    ║{}║", blank);
+        }
+
+        println!();
+    }
+
+    println!("╚{}╝    ╚{}╝", "═".repeat(W), bar);
+
+    say!(
+        "当前: {}{}\x1B[0m(旋转: {}, 位置: {})",
+        "Current: {}{}\x1B[0m(rotation: {}, x: {})",
+        palette.ansi(Some(set.color(current_piece))),
+        set.letter(current_piece),
+        best_action.0,
+        best_action.1
+    );
+}
+
+/// `display_game_with_next_piece` in half blocks: the board at half
+/// height, and the next and held pieces by letter.
+fn display_compact<const H: usize, const W: usize>(
+    board: &Board<H, W>,
+    set: &PieceSet,
+    current_piece: usize,
+    next_piece: usize,
+    held: Option<Option<usize>>,
+    best_action: (usize, usize, f64),
+) {
+    let palette = theme::palette();
+    let grid = board.get_grid();
+    let color_grid = board.get_color_grid();
+    let letter = |piece: usize| {
+        format!(
+            "{}{}\x1B[0m",
+            palette.ansi(Some(set.color(piece))),
+            set.letter(piece)
+        )
+    };
+
+    println!("╔{}╗", "═".repeat(W));
+    let lines = terminal::half_blocks(W, H, |y, x| {
+        grid[y][x].then_some(Some(color_grid[y][x].unwrap_or(0)))
+    });
+    for line in lines {
+        println!("║{}║", line);
+    }
+    println!("╚{}╝", "═".repeat(W));
+
+    let mut panels = format!("NEXT {}", letter(next_piece));
+    if let Some(held) = held {
+        panels.push_str(&format!("  HOLD {}", held.map_or("-".to_string(), letter)));
+    }
+    println!("{}", panels);
+    say!(
+        "当前: {} (旋转: {}, 位置: {})",
+        "Current: {} (rotation: {}, x: {})",
+        letter(current_piece),
+        best_action.0,
+        best_action.1
+    );
+}
@@ -0,0 +1,291 @@
+use std::collections::HashSet;
+use std::fs;
+
+use crate::board::{BOARD_WIDTH, Board};
+use crate::evaluator::Evaluator;
+use crate::i18n::{say, tr};
+use crate::logging::{log_error, log_info};
+use crate::piece::{PieceType, get_random_piece};
+use crate::policy::{best_action, evaluate_all};
+
+/// A curated position together with the placements considered correct.
+///
+/// Puzzle files are plain text, one block per puzzle:
+///
+/// ```text
+/// puzzle I into the well
+/// piece I
+/// expect 1 9
+/// expect 3 9
+/// #########.
+/// #########.
+/// ```
+///
+/// `expect` lines are `<rotation> <x>` and any of them counts as solved.
+/// Board rows are listed top to bottom with `#` for filled cells and `.`
+/// for empty ones; the last row is the bottom of the well. Lines starting
+/// with `//` are comments.
+pub struct Puzzle {
+    pub name: String,
+    pub board: Board,
+    pub piece: PieceType,
+    pub expected: Vec<(usize, usize)>,
+}
+
+/// A board from puzzle rows, top to bottom.
+pub fn board_from_rows(rows: &[&str]) -> Result<Board, String> {
+    Board::from_rows(rows)
+}
+
+/// Inverse of `board_from_rows`: the rows up to the highest filled cell,
+/// top to bottom.
+pub fn board_to_rows(board: &Board) -> Vec<String> {
+    let top = board.max_height();
+    let grid = board.get_grid();
+    (0..top)
+        .rev()
+        .map(|y| {
+            (0..BOARD_WIDTH)
+                .map(|x| if grid[y][x] { '#' } else { '.' })
+                .collect()
+        })
+        .collect()
+}
+
+/// A puzzle block while its lines are still being read.
+struct Draft<'a> {
+    name: String,
+    piece: Option<PieceType>,
+    expected: Vec<(usize, usize)>,
+    rows: Vec<&'a str>,
+}
+
+impl Draft<'_> {
+    fn finish(self) -> Result<Puzzle, String> {
+        let name = self.name;
+        let piece = self
+            .piece
+            .ok_or_else(|| tr!("谜题 '{}' 缺少 piece", "Puzzle '{}' is missing piece", name))?;
+        if self.expected.is_empty() {
+            return Err(tr!(
+                "谜题 '{}' 缺少 expect",
+                "Puzzle '{}' is missing expect",
+                name
+            ));
+        }
+        let board = board_from_rows(&self.rows)
+            .map_err(|e| tr!("谜题 '{}': {}", "Puzzle '{}': {}", name, e))?;
+        Ok(Puzzle {
+            name,
+            board,
+            piece,
+            expected: self.expected,
+        })
+    }
+}
+
+pub fn parse_puzzles(text: &str) -> Result<Vec<Puzzle>, String> {
+    let mut puzzles = Vec::new();
+    let mut current: Option<Draft> = None;
+
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with("//") {
+            continue;
+        }
+
+        if let Some(name) = line.strip_prefix("puzzle") {
+            if let Some(draft) = current.take() {
+                puzzles.push(draft.finish()?);
+            }
+            current = Some(Draft {
+                name: name.trim().to_string(),
+                piece: None,
+                expected: Vec::new(),
+                rows: Vec::new(),
+            });
+            continue;
+        }
+
+        let Some(draft) = current.as_mut() else {
+            return Err(tr!(
+                "谜题定义之前出现内容: {}",
+                "Content before the first puzzle: {}",
+                line
+            ));
+        };
+
+        if let Some(p) = line.strip_prefix("piece") {
+            draft.piece = Some(p.trim().parse()?);
+        } else if let Some(e) = line.strip_prefix("expect") {
+            let mut parts = e.split_whitespace().map(|v| v.parse::<usize>());
+            match (parts.next(), parts.next()) {
+                (Some(Ok(rotation)), Some(Ok(x))) => draft.expected.push((rotation, x)),
+                _ => return Err(tr!("expect 格式错误: {}", "Malformed expect: {}", line)),
+            }
+        } else if line.chars().all(|c| c == '#' || c == '.') {
+            draft.rows.push(line);
+        } else {
+            return Err(tr!("无法识别的行: {}", "Unrecognized line: {}", line));
+        }
+    }
+
+    if let Some(draft) = current.take() {
+        puzzles.push(draft.finish()?);
+    }
+    Ok(puzzles)
+}
+
+/// Runs every puzzle in `path` against the policy and reports the results.
+pub fn puzzle<E: Evaluator + ?Sized>(path: &str, evaluator: &E) {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) => {
+            log_error!(
+                "无法读取谜题文件 {}: {}",
+                "Cannot read puzzle file {}: {}",
+                path,
+                e
+            );
+            return;
+        }
+    };
+    let puzzles = match parse_puzzles(&text) {
+        Ok(puzzles) => puzzles,
+        Err(e) => {
+            log_error!("谜题文件格式错误: {}", "Malformed puzzle file: {}", e);
+            return;
+        }
+    };
+
+    let mut solved = 0;
+    for puzzle in &puzzles {
+        match best_action(&puzzle.board, puzzle.piece, evaluator) {
+            Some((rotation, x, _)) if puzzle.expected.contains(&(rotation, x)) => {
+                solved += 1;
+                say!(
+                    "[通过] {} (旋转={}, 位置={})",
+                    "[pass] {} (rotation={}, x={})",
+                    puzzle.name,
+                    rotation,
+                    x
+                );
+            }
+            Some((rotation, x, _)) => say!(
+                "[失败] {} 选择了 (旋转={}, 位置={}), 期望 {:?}",
+                "[fail] {} chose (rotation={}, x={}), expected {:?}",
+                puzzle.name,
+                rotation,
+                x,
+                puzzle.expected
+            ),
+            None => say!(
+                "[失败] {} 无法放置方块",
+                "[fail] {} cannot place the piece",
+                puzzle.name
+            ),
+        }
+    }
+
+    say!(
+        "通过 {}/{} 个谜题",
+        "Passed {}/{} puzzles",
+        solved,
+        puzzles.len()
+    );
+}
+
+/// How clearly the best move stands out: the gap between the best and the
+/// second best distinct evaluation, relative to the spread of all of them.
+/// Placements that evaluate exactly like the best one (symmetric rotations)
+/// are all accepted as answers.
+fn decision_margin(actions: &[(usize, usize, f64)]) -> Option<(f64, Vec<(usize, usize)>)> {
+    let best = actions.iter().map(|a| a.2).fold(f64::INFINITY, f64::min);
+    let worst = actions
+        .iter()
+        .map(|a| a.2)
+        .fold(f64::NEG_INFINITY, f64::max);
+    let second = actions
+        .iter()
+        .map(|a| a.2)
+        .filter(|&s| s > best)
+        .fold(f64::INFINITY, f64::min);
+    // With only two distinct values every position looks clear-cut
+    if !second.is_finite() || second >= worst {
+        return None;
+    }
+    let answers = actions
+        .iter()
+        .filter(|a| a.2 == best)
+        .map(|a| (a.0, a.1))
+        .collect();
+    Some(((second - best) / (worst - best), answers))
+}
+
+/// Plays `games` games with the policy and writes the `count` decision
+/// points with the clearest best move to `out` in puzzle format.
+pub fn generate<E: Evaluator + ?Sized>(out: &str, games: usize, count: usize, evaluator: &E) {
+    let mut rng = rand::rng();
+    let mut seen = HashSet::new();
+    let mut candidates = Vec::new();
+
+    for _ in 0..games {
+        let mut board = Board::new();
+        loop {
+            let piece = get_random_piece(&mut rng);
+            let actions = evaluate_all(&board, piece, evaluator);
+            let Some(&(rotation, x, _)) = actions.iter().min_by(|a, b| a.2.total_cmp(&b.2)) else {
+                break;
+            };
+
+            let rows = board_to_rows(&board);
+            if !rows.is_empty()
+                && seen.insert((rows.clone(), piece as usize))
+                && let Some((margin, answers)) = decision_margin(&actions)
+            {
+                candidates.push((margin, piece, answers, rows));
+            }
+
+            board.apply(piece, x, rotation).unwrap();
+        }
+    }
+
+    candidates.sort_by(|a, b| b.0.total_cmp(&a.0));
+    candidates.truncate(count);
+
+    let mut text = tr!(
+        "// 由 puzzle generate 从对局中挖掘的局面\n",
+        "// Positions mined from games by puzzle generate\n"
+    );
+    for (i, (margin, piece, answers, rows)) in candidates.iter().enumerate() {
+        text.push_str(&tr!(
+            "\npuzzle 自动生成 #{} (差距 {:.2})\n",
+            "\npuzzle generated #{} (margin {:.2})\n",
+            i + 1,
+            margin
+        ));
+        text.push_str(&format!("piece {}\n", piece));
+        for (rotation, x) in answers {
+            text.push_str(&format!("expect {} {}\n", rotation, x));
+        }
+        for row in rows {
+            text.push_str(row);
+            text.push('\n');
+        }
+    }
+
+    match fs::write(out, text) {
+        Ok(()) => log_info!(
+            "已生成 {} 个谜题到 {}",
+            "Generated {} puzzles into {}",
+            candidates.len(),
+            out
+        ),
+        Err(e) => log_error!(
+            "无法写入谜题文件 {}: {}",
+            "Cannot write puzzle file {}: {}",
+            out,
+            e
+        ),
+    }
+}
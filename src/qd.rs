@@ -0,0 +1,313 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rand::Rng;
+
+use crate::board::{BOARD_HEIGHT, BOARD_WIDTH, FEATURES};
+use crate::episode::MAX_PIECES;
+use crate::generator::Uniform;
+use crate::i18n::tr;
+use crate::logging::{log_error, log_info, log_warn};
+use crate::mode::Progress;
+use crate::moves::Moves;
+use crate::piece::PieceSet;
+use crate::policy::Visibility;
+use crate::pool::{Schedule, play_all};
+use crate::train::{Behavior, GameConfig, normalize, play_game_with_behavior};
+use crate::weights::{compatibility_line, format_weights};
+
+pub struct QdOptions {
+    pub iterations: usize,
+    pub games: usize,
+    pub resolution: usize,
+    pub out_dir: PathBuf,
+}
+
+impl QdOptions {
+    /// Parses `[iterations]` followed by optional flags.
+    pub fn from_args(args: &[String]) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let mut options = QdOptions {
+            iterations: 50,
+            games: 20,
+            resolution: 10,
+            out_dir: PathBuf::from(format!("runs/qd-{}", timestamp)),
+        };
+
+        let mut args = args.iter();
+        let mut positional = 0;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--out" => {
+                    if let Some(dir) = args.next() {
+                        options.out_dir = PathBuf::from(dir);
+                    }
+                }
+                "--games" => {
+                    if let Some(n) = args.next().and_then(|n| n.parse().ok()) {
+                        options.games = n;
+                    }
+                }
+                "--resolution" => {
+                    if let Some(n) = args.next().and_then(|n| n.parse().ok()) {
+                        options.resolution = n;
+                    }
+                }
+                _ => {
+                    match positional {
+                        0 => options.iterations = arg.parse().unwrap_or(50),
+                        _ => log_warn!("忽略未知参数: {}", "Ignoring unknown argument: {}", arg),
+                    }
+                    positional += 1;
+                }
+            }
+        }
+        options.resolution = options.resolution.max(1);
+        options
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Elite {
+    score: f64,
+    behavior: Behavior,
+    weights: [f64; FEATURES],
+}
+
+/// MAP-Elites archive: the best policy found for each combination of mean
+/// stack height and tetris rate, both split into `resolution` bins.
+struct Grid {
+    resolution: usize,
+    cells: Vec<Option<Elite>>,
+}
+
+impl Grid {
+    fn new(resolution: usize) -> Self {
+        Grid {
+            resolution,
+            cells: vec![None; resolution * resolution],
+        }
+    }
+
+    fn bin(&self, value: f64) -> usize {
+        ((value * self.resolution as f64) as usize).min(self.resolution - 1)
+    }
+
+    /// Returns `(height bin, tetris bin)` for a behavior.
+    fn cell(&self, behavior: &Behavior) -> (usize, usize) {
+        (
+            self.bin(behavior.stack_height),
+            self.bin(behavior.clears[3]),
+        )
+    }
+
+    /// Puts `elite` in its cell if the cell is empty or holds a worse one.
+    fn insert(&mut self, elite: Elite) -> bool {
+        let (h, t) = self.cell(&elite.behavior);
+        let slot = &mut self.cells[h * self.resolution + t];
+        if slot.is_none_or(|e| e.score < elite.score) {
+            *slot = Some(elite);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn elites(&self) -> Vec<&Elite> {
+        self.cells.iter().flatten().collect()
+    }
+}
+
+fn gaussian(rng: &mut impl Rng) -> f64 {
+    // Box-Muller
+    let u: f64 = 1.0 - rng.random::<f64>();
+    let v: f64 = rng.random();
+    (-2.0 * u.ln()).sqrt() * (2.0 * std::f64::consts::PI * v).cos()
+}
+
+/// Evaluates a batch of candidates on all cores, one game per task, so
+/// that candidates whose games run long do not hold up the batch.
+fn evaluate_batch(candidates: Vec<[f64; FEATURES]>, games: usize) -> Vec<Elite> {
+    let (results, _) = play_all(&candidates, games, Schedule::Game, |weights, _| {
+        play_game_with_behavior::<BOARD_HEIGHT, BOARD_WIDTH, _>(
+            weights,
+            &mut Uniform::new(rand::rng(), PieceSet::standard().len()),
+            PieceSet::standard(),
+            &GameConfig {
+                max_pieces: MAX_PIECES,
+                visibility: Visibility::Current,
+                hold: false,
+                moves: Moves::Drop,
+            },
+            &mut Progress::endless(),
+        )
+    });
+    candidates
+        .into_iter()
+        .zip(results)
+        .map(|(weights, games)| {
+            let total: f64 = games.iter().map(|&(score, _)| score as f64).sum();
+            let behaviors: Vec<Behavior> = games.into_iter().map(|(_, b)| b).collect();
+            Elite {
+                score: total / behaviors.len().max(1) as f64,
+                behavior: Behavior::mean(&behaviors),
+                weights,
+            }
+        })
+        .collect()
+}
+
+/// Quality-diversity training: instead of one best policy, fills a grid of
+/// playing styles with the best policy found for each.
+pub fn qd(options: QdOptions) {
+    let QdOptions {
+        iterations,
+        games,
+        resolution,
+        ref out_dir,
+    } = options;
+    log_info!(
+        "开始MAP-Elites训练, 共{}轮, 网格 {}x{}",
+        "Starting MAP-Elites, {} iterations, {}x{} grid",
+        iterations,
+        resolution,
+        resolution
+    );
+
+    if let Err(e) = fs::create_dir_all(out_dir) {
+        log_error!(
+            "无法创建输出目录 {}: {}",
+            "Cannot create output directory {}: {}",
+            out_dir.display(),
+            e
+        );
+        return;
+    }
+
+    let running = Arc::new(AtomicBool::new(true));
+    let r = running.clone();
+    ctrlc::set_handler(move || {
+        log_info!(
+            "\n接收到Ctrl+C, 正在结束训练...",
+            "\nReceived Ctrl+C, stopping training..."
+        );
+        r.store(false, Ordering::SeqCst);
+    })
+    .expect("Error setting Ctrl+C handler");
+
+    let mut rng = rand::rng();
+    let batch = thread::available_parallelism().map_or(1, |n| n.get()) * 8;
+    let mut grid = Grid::new(resolution);
+
+    for iteration in 0..iterations {
+        if !running.load(Ordering::SeqCst) {
+            break;
+        }
+
+        let elites = grid.elites();
+        let candidates: Vec<[f64; FEATURES]> = (0..batch)
+            .map(|_| {
+                let mut weights = [0.0; FEATURES];
+                if elites.is_empty() {
+                    // Bootstrap with random directions
+                    for w in &mut weights {
+                        *w = gaussian(&mut rng);
+                    }
+                } else {
+                    let parent = elites[rng.random_range(0..elites.len())];
+                    for (w, p) in weights.iter_mut().zip(parent.weights.iter()) {
+                        *w = p + 0.1 * gaussian(&mut rng);
+                    }
+                }
+                normalize(&weights)
+            })
+            .collect();
+
+        let mut improved = 0;
+        for elite in evaluate_batch(candidates, games) {
+            if grid.insert(elite) {
+                improved += 1;
+            }
+        }
+
+        let best = grid.elites().iter().map(|e| e.score).fold(0.0, f64::max);
+        log_info!(
+            "第{}轮: 已覆盖 {} 格, 本轮更新 {} 格, 最佳分数 {:.2}",
+            "Iteration {}: {} cells filled, {} improved, best score {:.2}",
+            iteration + 1,
+            grid.elites().len(),
+            improved,
+            best
+        );
+    }
+
+    save(&grid, out_dir);
+}
+
+/// Writes `grid.txt` with the score of every cell plus one weights file per
+/// filled cell, loadable by `hof`.
+fn save(grid: &Grid, out_dir: &std::path::Path) {
+    let n = grid.resolution;
+    let mut report = tr!(
+        "# MAP-Elites 网格: 行为平均堆叠高度, 列为四消占比\n",
+        "# MAP-Elites grid: rows are mean stack height, columns tetris share\n"
+    );
+    report.push_str(&tr!(
+        "# 每格宽度 {:.3}, '.' 表示空格\n",
+        "# bin width {:.3}, '.' marks an empty cell\n",
+        1.0 / n as f64
+    ));
+    report.push_str(&tr!("高度\\四消", "height\\tetris"));
+    for t in 0..n {
+        report.push_str(&format!(" {:>9}", t));
+    }
+    report.push('\n');
+
+    for h in 0..n {
+        report.push_str(&format!("{:>9}", h));
+        for t in 0..n {
+            match &grid.cells[h * n + t] {
+                Some(elite) => {
+                    report.push_str(&format!(" {:>9.0}", elite.score));
+                    let text = tr!(
+                        "{}# 平均高度 {:.3}, 四消占比 {:.3}, 训练分数 {:.2}\n{}\n",
+                        "{}# mean height {:.3}, tetris share {:.3}, training score {:.2}\n{}\n",
+                        compatibility_line(),
+                        elite.behavior.stack_height,
+                        elite.behavior.clears[3],
+                        elite.score,
+                        format_weights(&elite.weights)
+                    );
+                    let path = out_dir.join(format!("cell_{}_{}.txt", h, t));
+                    if let Err(e) = fs::write(&path, text) {
+                        log_error!("无法保存 {}: {}", "Cannot save {}: {}", path.display(), e);
+                    }
+                }
+                None => report.push_str(&format!(" {:>9}", ".")),
+            }
+        }
+        report.push('\n');
+    }
+
+    print!("{}", report);
+    let path = out_dir.join("grid.txt");
+    match fs::write(&path, report) {
+        Ok(()) => log_info!(
+            "网格报告已保存到 {}",
+            "Grid report saved to {}",
+            path.display()
+        ),
+        Err(e) => log_error!(
+            "无法保存网格报告 {}: {}",
+            "Cannot save grid report {}: {}",
+            path.display(),
+            e
+        ),
+    }
+}
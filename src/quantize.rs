@@ -0,0 +1,188 @@
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+
+use crate::benchmark::{Case, SUITE, SUITE_VERSION, composite, play_case};
+use crate::board::{BOARD_HEIGHT, BOARD_WIDTH, Board, COUNT_FEATURES, FEATURES};
+use crate::i18n::say;
+use crate::piece::{PieceType, get_random_piece};
+use crate::policy::Visibility;
+
+/// Fractional bits of the fixed-point features. The counts are whole
+/// numbers, so this only sets the resolution of the height RBF terms.
+pub const FRACTION_BITS: u32 = 16;
+
+const ONE: f64 = (1u64 << FRACTION_BITS) as f64;
+
+/// `e^-x` for `x >= 0` from halving and a Taylor series, using nothing
+/// but basic arithmetic so that the result is the same everywhere.
+const fn exp_neg(x: f64) -> f64 {
+    let mut halvings = 0;
+    let mut y = x;
+    while y > 0.5 {
+        y /= 2.0;
+        halvings += 1;
+    }
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let mut k = 1;
+    while k < 20 {
+        term = term * -y / k as f64;
+        sum += term;
+        k += 1;
+    }
+    while halvings > 0 {
+        sum *= sum;
+        halvings -= 1;
+    }
+    sum
+}
+
+/// `height_rbf` in fixed point for every possible sum of column heights,
+/// computed at compile time.
+const RBF_TABLE: [[i64; FEATURES - COUNT_FEATURES]; BOARD_WIDTH * BOARD_HEIGHT + 1] = {
+    let mut table = [[0; FEATURES - COUNT_FEATURES]; BOARD_WIDTH * BOARD_HEIGHT + 1];
+    let h = BOARD_HEIGHT as f64;
+    let mut sum = 0;
+    while sum < table.len() {
+        let c = sum as f64 / BOARD_WIDTH as f64;
+        let mut i = 0;
+        while i < FEATURES - COUNT_FEATURES {
+            let term = c - (i as f64 * h / 3.0);
+            let value = exp_neg(term * term / (2.0 * (h / 5.0) * (h / 5.0)));
+            table[sum][i] = (value * ONE + 0.5) as i64;
+            i += 1;
+        }
+        sum += 1;
+    }
+    table
+};
+
+/// The features of a placement in fixed point with `FRACTION_BITS`
+/// fractional bits, computed without floating point at run time.
+pub fn integer_features(
+    board: &Board,
+    piece_type: PieceType,
+    x: usize,
+    rotate: usize,
+) -> Option<[i64; FEATURES]> {
+    let (_, counts, height_sum) = board.simulate_counts(piece_type, x, rotate)?;
+    let mut features = [0; FEATURES];
+    for i in 0..COUNT_FEATURES {
+        features[i] = counts[i] << FRACTION_BITS;
+    }
+    features[COUNT_FEATURES..].copy_from_slice(&RBF_TABLE[height_sum]);
+    Some(features)
+}
+
+/// Weights rounded to signed integers of `bits` bits. Only the order of
+/// the evaluations matters to the policy, so the weights are scaled to
+/// use the whole range and the scale is dropped.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QuantizedWeights {
+    pub bits: u32,
+    pub weights: [i64; FEATURES],
+}
+
+impl QuantizedWeights {
+    /// Largest supported width; wider weights could overflow the sum.
+    pub const MAX_BITS: u32 = 24;
+
+    /// `bits` is clamped to `2..=MAX_BITS`.
+    pub fn quantize(weights: &[f64; FEATURES], bits: u32) -> Self {
+        let bits = bits.clamp(2, Self::MAX_BITS);
+        let limit = ((1i64 << (bits - 1)) - 1) as f64;
+        let largest = weights.iter().map(|w| w.abs()).fold(0.0, f64::max);
+        let scale = if largest > 0.0 { limit / largest } else { 0.0 };
+        QuantizedWeights {
+            bits,
+            weights: weights.map(|w| (w * scale).round() as i64),
+        }
+    }
+
+    pub fn evaluate(&self, features: &[i64; FEATURES]) -> i64 {
+        features
+            .iter()
+            .zip(self.weights.iter())
+            .map(|(f, w)| f * w)
+            .sum()
+    }
+}
+
+/// `policy::best_action` on the integer path: same search order, same tie
+/// breaking, no floating point.
+pub fn best_action_quantized(
+    board: &Board,
+    piece_type: PieceType,
+    weights: &QuantizedWeights,
+) -> Option<(usize, usize, i64)> {
+    let mut best: Option<(usize, usize, i64)> = None;
+    for p in board.legal_placements(piece_type) {
+        if let Some(features) = integer_features(board, piece_type, p.x, p.rotation) {
+            let action_score = weights.evaluate(&features);
+            if best.is_none_or(|(_, _, s)| action_score < s) {
+                best = Some((p.rotation, p.x, action_score));
+            }
+        }
+    }
+    best
+}
+
+/// `benchmark::play_case` with the integer policy, seeing the current
+/// piece only.
+pub fn play_case_quantized(case: &Case, weights: &QuantizedWeights) -> i64 {
+    let mut rng = StdRng::seed_from_u64(case.seed);
+    let mut board = Board::new();
+    let mut next = get_random_piece(&mut rng);
+    for _ in 0..case.pieces {
+        let piece = next;
+        next = get_random_piece(&mut rng);
+        let Some((rotation, x, _)) = best_action_quantized(&board, piece, weights) else {
+            break;
+        };
+        board.apply(piece, x, rotation).unwrap();
+    }
+    board.get_score()
+}
+
+/// Quantizes every weight vector to `bits` bits and compares the integer
+/// policy with the float one on the benchmark suite.
+pub fn report(entries: &[[f64; FEATURES]], bits: u32) {
+    for (i, weights) in entries.iter().enumerate() {
+        if entries.len() > 1 {
+            say!("#{}", "#{}", i + 1);
+        }
+        let quantized = QuantizedWeights::quantize(weights, bits);
+        say!(
+            "{} 位整数权重: {}",
+            "{}-bit integer weights: {}",
+            quantized.bits,
+            format!("{:?}", quantized.weights)
+        );
+
+        let float: Vec<i64> = SUITE
+            .iter()
+            .map(|case| play_case(case, weights, Default::default()))
+            .collect();
+        let integer: Vec<i64> = SUITE
+            .iter()
+            .map(|case| play_case_quantized(case, &quantized))
+            .collect();
+        say!(
+            "  用例            浮点       整数",
+            "  case            float    integer"
+        );
+        for ((case, f), n) in SUITE.iter().zip(&float).zip(&integer) {
+            println!("  {:<12} {:>10} {:>10}", case.name, f, n);
+        }
+        let (float_composite, integer_composite) = (composite(&float), composite(&integer));
+        say!(
+            "  综合得分 (v{}, {}): 浮点 {:.1}, 整数 {:.1}, 变化 {:+.1}%",
+            "  Composite (v{}, {}): float {:.1}, integer {:.1}, change {:+.1}%",
+            SUITE_VERSION,
+            Visibility::Current.name(),
+            float_composite,
+            integer_composite,
+            100.0 * (integer_composite / float_composite - 1.0)
+        );
+    }
+}
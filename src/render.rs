@@ -0,0 +1,279 @@
+use std::fs;
+use std::path::Path;
+
+use plotters::prelude::*;
+
+use crate::board::Board;
+use crate::i18n::tr;
+use crate::logging::log_warn;
+use crate::theme::palette;
+
+const CELL: u32 = 24;
+const BANNER: u32 = 32;
+/// Pixel width and height of the image of a board of `H` rows and `W`
+/// columns.
+fn image_size<const H: usize, const W: usize>() -> (u32, u32) {
+    (CELL * W as u32, BANNER + CELL * H as u32)
+}
+
+/// Top-left pixel of the cell at column `x`, row `y` (row 0 is the bottom)
+/// of a board of `H` rows.
+fn cell_origin<const H: usize>(x: usize, y: usize) -> (u32, u32) {
+    (x as u32 * CELL, BANNER + (H - 1 - y) as u32 * CELL)
+}
+
+fn banner<const H: usize, const W: usize>(board: &Board<H, W>, title: &str) -> String {
+    format!(
+        "{}  score {}  lines {}",
+        title,
+        board.get_score(),
+        board.get_lines()
+    )
+}
+
+/// Renders the board as a standalone SVG document.
+pub fn board_svg<const H: usize, const W: usize>(board: &Board<H, W>, title: &str) -> String {
+    let (width, height) = image_size::<H, W>();
+    let palette = palette();
+    let rgb = |(r, g, b): (u8, u8, u8)| format!("rgb({},{},{})", r, g, b);
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">\n",
+        w = width,
+        h = height
+    );
+    svg.push_str(&format!(
+        "<rect width=\"{}\" height=\"{}\" fill=\"{}\"/>\n",
+        width,
+        height,
+        rgb(palette.background)
+    ));
+    svg.push_str(&format!(
+        "<text x=\"6\" y=\"{}\" font-family=\"sans-serif\" font-size=\"14\" fill=\"white\">{}</text>\n",
+        BANNER - 11,
+        escape(&banner(board, title))
+    ));
+
+    let grid = board.get_grid();
+    let colors = board.get_color_grid();
+    for y in 0..H {
+        for x in 0..W {
+            let (px, py) = cell_origin::<H>(x, y);
+            let fill = if grid[y][x] {
+                rgb(palette.rgb(colors[y][x]))
+            } else {
+                "none".to_string()
+            };
+            svg.push_str(&format!(
+                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\" stroke=\"{}\"/>\n",
+                px,
+                py,
+                CELL,
+                CELL,
+                fill,
+                rgb(palette.grid)
+            ));
+        }
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Renders the board into a PNG file.
+pub fn save_board_png<const H: usize, const W: usize>(
+    board: &Board<H, W>,
+    title: &str,
+    path: &Path,
+) -> Result<(), String> {
+    let root = BitMapBackend::new(path, image_size::<H, W>()).into_drawing_area();
+    let palette = palette();
+    let to_color = |(r, g, b): (u8, u8, u8)| RGBColor(r, g, b);
+    let draw_error =
+        |e: DrawingAreaErrorKind<_>| tr!("绘制图片失败: {}", "Failed to draw image: {}", e);
+
+    root.fill(&to_color(palette.background))
+        .map_err(draw_error)?;
+
+    let grid = board.get_grid();
+    let colors = board.get_color_grid();
+    for y in 0..H {
+        for x in 0..W {
+            let (px, py) = cell_origin::<H>(x, y);
+            let corners = [
+                (px as i32, py as i32),
+                ((px + CELL) as i32, (py + CELL) as i32),
+            ];
+            if grid[y][x] {
+                root.draw(&Rectangle::new(
+                    corners,
+                    to_color(palette.rgb(colors[y][x])).filled(),
+                ))
+                .map_err(draw_error)?;
+            }
+            root.draw(&Rectangle::new(corners, to_color(palette.grid)))
+                .map_err(draw_error)?;
+        }
+    }
+
+    // Text needs a system font; without one the image is still useful
+    let style = ("sans-serif", 16).into_font().color(&WHITE);
+    if let Err(e) = root.draw(&Text::new(banner(board, title), (6, 8), style)) {
+        log_warn!(
+            "警告: 无法绘制标题文字: {}",
+            "Warning: cannot draw banner text: {}",
+            e
+        );
+    }
+
+    root.present().map_err(draw_error)
+}
+
+/// Saves the board as SVG or PNG depending on the file extension.
+pub fn save_board<const H: usize, const W: usize>(
+    board: &Board<H, W>,
+    title: &str,
+    path: &Path,
+) -> Result<(), String> {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("svg") => fs::write(path, board_svg(board, title))
+            .map_err(|e| tr!("无法写入 {}: {}", "Cannot write {}: {}", path.display(), e)),
+        Some("png") => save_board_png(board, title, path),
+        _ => Err(tr!(
+            "不支持的图片格式: {}",
+            "Unsupported image format: {}",
+            path.display()
+        )),
+    }
+}
+
+/// Pixels per cell in GIFs, fewer than in stills: a clip has hundreds of
+/// frames.
+const GIF_CELL: usize = 12;
+/// Hundredths of a second each GIF frame shows.
+const GIF_DELAY: u16 = 8;
+/// Bits per pixel of the GIF color table: background, grid, the seven
+/// piece colors and `other` fit in 16.
+const GIF_BITS: u8 = 4;
+
+/// Saves `boards` as the frames of a looping GIF, without a banner since
+/// the frames have no font to draw it with. Each frame only stores the
+/// rows that changed since the one before.
+pub fn save_boards_gif<const H: usize, const W: usize>(
+    boards: &[Board<H, W>],
+    path: &Path,
+) -> Result<(), String> {
+    let palette = palette();
+    let mut colors = vec![palette.background, palette.grid];
+    colors.extend(palette.pieces);
+    colors.push(palette.other);
+    let (width, height) = (GIF_CELL * W, GIF_CELL * H);
+
+    let mut gif = b"GIF89a".to_vec();
+    gif.extend((width as u16).to_le_bytes());
+    gif.extend((height as u16).to_le_bytes());
+    gif.extend([0xF0 | (GIF_BITS - 1), 0, 0]);
+    for i in 0..1 << GIF_BITS {
+        let (r, g, b) = colors.get(i).copied().unwrap_or((0, 0, 0));
+        gif.extend([r, g, b]);
+    }
+    // Loop forever
+    gif.extend(b"\x21\xFF\x0BNETSCAPE2.0\x03\x01\x00\x00\x00");
+
+    let mut previous: Option<Vec<u8>> = None;
+    for board in boards {
+        let pixels = gif_pixels(board, width, height);
+        let changed = |row: &usize| {
+            previous.as_ref().is_none_or(|p| {
+                p[row * width..(row + 1) * width] != pixels[row * width..(row + 1) * width]
+            })
+        };
+        // An unchanged frame still takes its turn, as one row
+        let top = (0..height).find(changed).unwrap_or(0);
+        let bottom = (0..height).rev().find(changed).unwrap_or(top) + 1;
+
+        // Graphic control: keep the frame below, show this one for a while
+        gif.extend([0x21, 0xF9, 0x04, 0x04]);
+        gif.extend(GIF_DELAY.to_le_bytes());
+        gif.extend([0, 0]);
+        gif.push(0x2C);
+        gif.extend(0u16.to_le_bytes());
+        gif.extend((top as u16).to_le_bytes());
+        gif.extend((width as u16).to_le_bytes());
+        gif.extend(((bottom - top) as u16).to_le_bytes());
+        gif.push(0);
+        gif.push(GIF_BITS);
+        for block in lzw_literals(&pixels[top * width..bottom * width]).chunks(255) {
+            gif.push(block.len() as u8);
+            gif.extend(block);
+        }
+        gif.push(0);
+        previous = Some(pixels);
+    }
+    gif.push(0x3B);
+
+    fs::write(path, gif)
+        .map_err(|e| tr!("无法写入 {}: {}", "Cannot write {}: {}", path.display(), e))
+}
+
+/// The board as GIF color indices, row by row from the top.
+fn gif_pixels<const H: usize, const W: usize>(
+    board: &Board<H, W>,
+    width: usize,
+    height: usize,
+) -> Vec<u8> {
+    let grid = board.get_grid();
+    let colors = board.get_color_grid();
+    let mut pixels = vec![0; width * height];
+    for (i, pixel) in pixels.iter_mut().enumerate() {
+        let (px, py) = (i % width, i / width);
+        let (x, y) = (px / GIF_CELL, H - 1 - py / GIF_CELL);
+        *pixel = if px % GIF_CELL == 0 || py % GIF_CELL == 0 {
+            1
+        } else if grid[y][x] {
+            // The piece colors, then `other`
+            2 + colors[y][x].map_or(7, |c| c.min(7))
+        } else {
+            0
+        };
+    }
+    pixels
+}
+
+/// GIF image data that sends every pixel as a literal code, clearing the
+/// table before the decoder would widen its codes past `GIF_BITS + 1`
+/// bits. Larger than real LZW, but a board is mostly runs of few colors
+/// and the frames only hold what changed.
+fn lzw_literals(pixels: &[u8]) -> Vec<u8> {
+    let clear = 1u32 << GIF_BITS;
+    let code_bits = GIF_BITS as u32 + 1;
+    // Every literal after the first adds a table entry from `clear + 2`
+    // on; stop two short of the entry that widens the codes
+    let run = (1 << code_bits) - clear as usize - 3;
+    let mut bytes = Vec::new();
+    let (mut acc, mut filled) = (0u32, 0);
+    let mut write = |code: u32| {
+        acc |= code << filled;
+        filled += code_bits;
+        while filled >= 8 {
+            bytes.push(acc as u8);
+            acc >>= 8;
+            filled -= 8;
+        }
+    };
+    for chunk in pixels.chunks(run) {
+        write(clear);
+        for &pixel in chunk {
+            write(pixel as u32);
+        }
+    }
+    write(clear + 1);
+    if filled > 0 {
+        bytes.push(acc as u8);
+    }
+    bytes
+}
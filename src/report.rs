@@ -0,0 +1,266 @@
+use std::collections::VecDeque;
+
+use rand::Rng;
+
+use crate::board::Board;
+use crate::episode::MAX_PIECES;
+use crate::evaluator::{Evaluator, Model};
+use crate::i18n::{Lang, lang, tr};
+use crate::piece::{PieceType, get_random_piece};
+use crate::policy::{Visibility, choose};
+use crate::render::board_svg;
+use crate::stats::GameStats;
+use crate::weights::format_model;
+
+/// How many of the last moves of a game are kept for the drill-down.
+const MOVE_LOG: usize = 200;
+
+/// One game played for a report, with enough detail to show how it ended.
+pub struct GameRecord {
+    pub score: i64,
+    pub pieces: usize,
+    pub board: Board,
+    pub stats: GameStats,
+    /// The last `MOVE_LOG` placements as `(piece, rotation, x)`.
+    pub last_moves: VecDeque<(PieceType, usize, usize)>,
+}
+
+/// Plays one game like `train::play_game_seeing`, keeping the final board and the
+/// tail of the move list.
+pub fn play_recorded<E: Evaluator + ?Sized>(
+    evaluator: &E,
+    rng: &mut impl Rng,
+    visibility: Visibility,
+) -> GameRecord {
+    let mut board = Board::new();
+    let mut pieces = 0;
+    let mut stats = GameStats::default();
+    let mut last_moves = VecDeque::with_capacity(MOVE_LOG);
+
+    let mut next = get_random_piece(rng);
+    for _ in 0..MAX_PIECES {
+        let piece_type = next;
+        next = get_random_piece(rng);
+        let Some((rotation, x, _)) = choose(&board, piece_type, next, evaluator, visibility) else {
+            break;
+        };
+        let placed = board.apply(piece_type, x, rotation).unwrap();
+        stats.record(&placed);
+        pieces += 1;
+        if last_moves.len() == MOVE_LOG {
+            last_moves.pop_front();
+        }
+        last_moves.push_back((piece_type, rotation, x));
+    }
+
+    GameRecord {
+        score: board.get_score(),
+        pieces,
+        board,
+        stats,
+        last_moves,
+    }
+}
+
+/// All games one weight vector played in a tournament.
+pub struct EntryResult {
+    /// Position of the vector in the weights file, from 0.
+    pub index: usize,
+    pub model: Model,
+    pub games: Vec<GameRecord>,
+}
+
+impl EntryResult {
+    pub fn mean(&self) -> f64 {
+        let total: f64 = self.games.iter().map(|g| g.score as f64).sum();
+        total / self.games.len().max(1) as f64
+    }
+
+    fn worst(&self) -> Option<(usize, &GameRecord)> {
+        self.games.iter().enumerate().min_by_key(|(_, g)| g.score)
+    }
+
+    fn best(&self) -> Option<(usize, &GameRecord)> {
+        self.games.iter().enumerate().max_by_key(|(_, g)| g.score)
+    }
+}
+
+pub(crate) fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Inline SVG bar chart of the scores, split into ten equal bins.
+fn histogram_svg(scores: &[i64]) -> String {
+    const BINS: usize = 10;
+    let (width, height) = (300, 80);
+    let min = scores.iter().copied().min().unwrap_or(0);
+    let max = scores.iter().copied().max().unwrap_or(0);
+    let span = (max - min).max(1) as f64;
+
+    let mut counts = [0usize; BINS];
+    for &s in scores {
+        let bin = (((s - min) as f64 / span) * BINS as f64) as usize;
+        counts[bin.min(BINS - 1)] += 1;
+    }
+    let top = counts.iter().copied().max().unwrap_or(1).max(1);
+
+    let bar = width / BINS;
+    let mut svg = format!(
+        "<svg width=\"{}\" height=\"{}\" class=\"hist\">",
+        width,
+        height + 16
+    );
+    for (i, &c) in counts.iter().enumerate() {
+        let h = c * height / top;
+        svg.push_str(&format!(
+            "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\"><title>{}</title></rect>",
+            i * bar + 1,
+            height - h,
+            bar - 2,
+            h,
+            c
+        ));
+    }
+    svg.push_str(&format!(
+        "<text x=\"0\" y=\"{y}\">{}</text><text x=\"{w}\" y=\"{y}\" text-anchor=\"end\">{}</text></svg>",
+        min,
+        max,
+        y = height + 14,
+        w = width
+    ));
+    svg
+}
+
+/// A self-contained HTML page for a `hof` tournament: the standings, each
+/// entry's score distribution and best and worst final boards, and the last
+/// moves of each entry's worst game.
+pub fn tournament_html(source: &str, entries: &[EntryResult], visibility: Visibility) -> String {
+    let games = entries.first().map_or(0, |e| e.games.len());
+    let (code, title) = match lang() {
+        Lang::Zh => ("zh", "名人堂评估报告"),
+        Lang::En => ("en", "Hall of fame report"),
+    };
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html lang=\"{}\">\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n",
+        code, title
+    );
+    html.push_str(
+        "<style>\n\
+         body { font-family: sans-serif; margin: 2em; }\n\
+         table { border-collapse: collapse; }\n\
+         td, th { border: 1px solid #ccc; padding: 4px 8px; text-align: right; }\n\
+         .hist rect { fill: #4a7bd0; }\n\
+         .hist text { font-size: 11px; }\n\
+         .boards { display: flex; gap: 1em; }\n\
+         code { font-size: 12px; }\n\
+         </style>\n</head>\n<body>\n",
+    );
+    html.push_str(&tr!(
+        "<h1>名人堂评估报告</h1>\n<p>来源 <code>{}</code>, 每组 {} 局, 使用固定验证种子, 可见方块: {}</p>\n", "<h1>Hall of fame report</h1>\n<p>Source <code>{}</code>, {} games per vector on the fixed validation seeds, visible pieces: {}</p>\n",
+        escape(source),
+        games,
+        visibility.name()
+    ));
+
+    html.push_str(&tr!(
+        "<h2>排名</h2>\n<table>\n<tr><th>名次</th><th>组</th><th>平均</th><th>最低</th><th>最高</th><th>平均方块数</th></tr>\n",
+        "<h2>Standings</h2>\n<table>\n<tr><th>Rank</th><th>Vector</th><th>Mean</th><th>Min</th><th>Max</th><th>Mean pieces</th></tr>\n"
+    ));
+    for (rank, entry) in entries.iter().enumerate() {
+        let min = entry.games.iter().map(|g| g.score).min().unwrap_or(0);
+        let max = entry.games.iter().map(|g| g.score).max().unwrap_or(0);
+        let pieces: usize = entry.games.iter().map(|g| g.pieces).sum();
+        html.push_str(&tr!(
+            "<tr><td>{}</td><td><a href=\"#entry{}\">第{}组</a></td><td>{:.2}</td><td>{}</td><td>{}</td><td>{:.1}</td></tr>\n", "<tr><td>{}</td><td><a href=\"#entry{}\">vector {}</a></td><td>{:.2}</td><td>{}</td><td>{}</td><td>{:.1}</td></tr>\n",
+            rank + 1,
+            entry.index + 1,
+            entry.index + 1,
+            entry.mean(),
+            min,
+            max,
+            pieces as f64 / entry.games.len().max(1) as f64
+        ));
+    }
+    html.push_str("</table>\n");
+
+    html.push_str(&tr!(
+        "<h2>得分构成</h2>\n<p>每局平均次数与分数</p>\n<table>\n<tr><th>组</th>",
+        "<h2>Score components</h2>\n<p>Mean count and points per game</p>\n<table>\n<tr><th>Vector</th>"
+    ));
+    for (name, _, _) in GameStats::default().components() {
+        html.push_str(&format!("<th>{}</th>", name));
+    }
+    html.push_str("</tr>\n");
+    for entry in entries {
+        let mut total = GameStats::default();
+        for game in &entry.games {
+            total.add(&game.stats);
+        }
+        let n = entry.games.len().max(1) as f64;
+        html.push_str(&format!("<tr><td>{}</td>", entry.index + 1));
+        for (_, count, points) in total.components() {
+            html.push_str(&format!(
+                "<td>{:.1} / {:.0}</td>",
+                count as f64 / n,
+                points as f64 / n
+            ));
+        }
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</table>\n");
+
+    for entry in entries {
+        html.push_str(&tr!(
+            "<h2 id=\"entry{}\">第{}组</h2>\n<p><code>{}</code></p>\n",
+            "<h2 id=\"entry{}\">Vector {}</h2>\n<p><code>{}</code></p>\n",
+            entry.index + 1,
+            entry.index + 1,
+            format_model(&entry.model)
+        ));
+        let scores: Vec<i64> = entry.games.iter().map(|g| g.score).collect();
+        html.push_str(&histogram_svg(&scores));
+        html.push('\n');
+
+        let (Some((best_i, best)), Some((worst_i, worst))) = (entry.best(), entry.worst()) else {
+            continue;
+        };
+        html.push_str("<div class=\"boards\">\n");
+        for (label, i, game) in [
+            (tr!("最佳", "Best"), best_i, best),
+            (tr!("最差", "Worst"), worst_i, worst),
+        ] {
+            let title = format!("{} #{}", label, i + 1);
+            html.push_str(&tr!(
+                "<figure>{}<figcaption>{} 第{}局: {} 分, {} 个方块</figcaption></figure>\n",
+                "<figure>{}<figcaption>{} game {}: {} points, {} pieces</figcaption></figure>\n",
+                board_svg(&game.board, &title),
+                label,
+                i + 1,
+                game.score,
+                game.pieces
+            ));
+        }
+        html.push_str("</div>\n");
+
+        html.push_str(&tr!(
+            "<details><summary>最差对局的最后 {} 步</summary>\n<table>\n<tr><th>步</th><th>方块</th><th>旋转</th><th>位置</th></tr>\n", "<details><summary>Last {} moves of the worst game</summary>\n<table>\n<tr><th>Move</th><th>Piece</th><th>Rotation</th><th>x</th></tr>\n",
+            worst.last_moves.len()
+        ));
+        let first = worst.pieces - worst.last_moves.len();
+        for (n, (piece, rotation, x)) in worst.last_moves.iter().enumerate() {
+            html.push_str(&format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                first + n + 1,
+                piece,
+                rotation,
+                x
+            ));
+        }
+        html.push_str("</table>\n</details>\n");
+    }
+
+    html.push_str("</body>\n</html>\n");
+    html
+}
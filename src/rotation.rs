@@ -0,0 +1,335 @@
+use crate::board::BOARD_WIDTH;
+use crate::piece::{PieceType, ROTATIONS};
+
+/// Coordinate convention a program uses when reporting placements.
+///
+/// `Simple` is our own: an index into `ROTATIONS` and the column of the
+/// piece's leftmost cell. `Srs` is the guideline one: the SRS rotation state
+/// (0 spawn, 1 R, 2 180, 3 L) and the column of the left edge of the SRS
+/// bounding box, which may be negative when the box hangs over the wall.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum RotationSystem {
+    #[default]
+    Simple,
+    Srs,
+}
+
+/// The placement exists in the source system but has no counterpart on
+/// our board.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Unrepresentable;
+
+/// SRS shapes in their bounding boxes, rows listed top to bottom, by
+/// piece type and rotation state. The only definition of the tetrominoes:
+/// `ROTATIONS` is built from it, and the kicks of `Transition` are
+/// offsets of these boxes.
+#[rustfmt::skip]
+pub const SRS_SHAPES: [[&[&str]; 4]; 7] = [
+    // I
+    [
+        &["....", "####", "....", "...."],
+        &["..#.", "..#.", "..#.", "..#."],
+        &["....", "....", "####", "...."],
+        &[".#..", ".#..", ".#..", ".#.."],
+    ],
+    // T
+    [
+        &[".#.", "###", "..."],
+        &[".#.", ".##", ".#."],
+        &["...", "###", ".#."],
+        &[".#.", "##.", ".#."],
+    ],
+    // O
+    [
+        &[".##", ".##", "..."],
+        &[".##", ".##", "..."],
+        &[".##", ".##", "..."],
+        &[".##", ".##", "..."],
+    ],
+    // J
+    [
+        &["#..", "###", "..."],
+        &[".##", ".#.", ".#."],
+        &["...", "###", "..#"],
+        &[".#.", ".#.", "##."],
+    ],
+    // L
+    [
+        &["..#", "###", "..."],
+        &[".#.", ".#.", ".##"],
+        &["...", "###", "#.."],
+        &["##.", ".#.", ".#."],
+    ],
+    // S
+    [
+        &[".##", "##.", "..."],
+        &[".#.", ".##", "..#"],
+        &["...", ".##", "##."],
+        &["#..", "##.", ".#."],
+    ],
+    // Z
+    [
+        &["##.", ".##", "..."],
+        &["..#", ".##", ".#."],
+        &["...", "##.", ".##"],
+        &[".#.", "##.", "#.."],
+    ],
+];
+
+/// A turn from one rotation state to another, with the `(dx, dy)` offsets
+/// of the SRS box tried in order until one fits, y pointing up.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transition {
+    pub from: usize,
+    pub to: usize,
+    pub kicks: &'static [(i32, i32)],
+}
+
+const fn turn(from: usize, to: usize, kicks: &'static [(i32, i32)]) -> Transition {
+    Transition { from, to, kicks }
+}
+
+/// The SRS kicks of J, L, S, T and Z for quarter turns.
+#[rustfmt::skip]
+pub const JLSTZ_KICKS: [Transition; 8] = [
+    turn(0, 1, &[(0, 0), (-1, 0), (-1, 1), (0, -2), (-1, -2)]),
+    turn(1, 0, &[(0, 0), (1, 0), (1, -1), (0, 2), (1, 2)]),
+    turn(1, 2, &[(0, 0), (1, 0), (1, -1), (0, 2), (1, 2)]),
+    turn(2, 1, &[(0, 0), (-1, 0), (-1, 1), (0, -2), (-1, -2)]),
+    turn(2, 3, &[(0, 0), (1, 0), (1, 1), (0, -2), (1, -2)]),
+    turn(3, 2, &[(0, 0), (-1, 0), (-1, -1), (0, 2), (-1, 2)]),
+    turn(3, 0, &[(0, 0), (-1, 0), (-1, -1), (0, 2), (-1, 2)]),
+    turn(0, 3, &[(0, 0), (1, 0), (1, 1), (0, -2), (1, -2)]),
+];
+
+/// The SRS kicks of I for quarter turns.
+#[rustfmt::skip]
+pub const I_KICKS: [Transition; 8] = [
+    turn(0, 1, &[(0, 0), (-2, 0), (1, 0), (-2, -1), (1, 2)]),
+    turn(1, 0, &[(0, 0), (2, 0), (-1, 0), (2, 1), (-1, -2)]),
+    turn(1, 2, &[(0, 0), (-1, 0), (2, 0), (-1, 2), (2, -1)]),
+    turn(2, 1, &[(0, 0), (1, 0), (-2, 0), (1, -2), (-2, 1)]),
+    turn(2, 3, &[(0, 0), (2, 0), (-1, 0), (2, 1), (-1, -2)]),
+    turn(3, 2, &[(0, 0), (-2, 0), (1, 0), (-2, -1), (1, 2)]),
+    turn(3, 0, &[(0, 0), (1, 0), (-2, 0), (1, -2), (-2, 1)]),
+    turn(0, 3, &[(0, 0), (-1, 0), (2, 0), (-1, 2), (2, -1)]),
+];
+
+/// The SRS+ kicks for 180° turns, the same for every piece.
+#[rustfmt::skip]
+pub const HALF_TURN_KICKS: [Transition; 4] = [
+    turn(0, 2, &[(0, 0), (0, 1), (1, 1), (-1, 1), (1, 0), (-1, 0)]),
+    turn(1, 3, &[(0, 0), (1, 0), (1, 2), (1, 1), (0, 2), (0, 1)]),
+    turn(2, 0, &[(0, 0), (0, -1), (-1, -1), (1, -1), (-1, 0), (1, 0)]),
+    turn(3, 1, &[(0, 0), (-1, 0), (-1, 2), (-1, 1), (0, 2), (0, 1)]),
+];
+
+/// O turns in place; its states differ only in name.
+const O_KICKS: [Transition; 12] = {
+    let mut table = [turn(0, 0, &[(0, 0)]); 12];
+    let mut i = 0;
+    while i < 12 {
+        table[i].from = i / 3;
+        table[i].to = (i / 3 + 1 + i % 3) % 4;
+        i += 1;
+    }
+    table
+};
+
+/// Where each SRS state's cells start in its box: `(x, y)` of the leftmost
+/// column and the lowest row, y up, by piece type and state. A piece at
+/// column `x` and row `y` in `ROTATIONS` terms has its box at this much
+/// less.
+const BOX_OFFSETS: [[(i32, i32); 4]; 7] = {
+    let mut table = [[(0, 0); 4]; 7];
+    let mut p = 0;
+    while p < 7 {
+        let mut r = 0;
+        while r < 4 {
+            let rows = SRS_SHAPES[p][r];
+            let (mut left, mut bottom) = (i32::MAX, i32::MAX);
+            let mut top = 0;
+            while top < rows.len() {
+                let row = rows[top].as_bytes();
+                let mut x = 0;
+                while x < row.len() {
+                    if row[x] == b'#' {
+                        let y = (rows.len() - 1 - top) as i32;
+                        if (x as i32) < left {
+                            left = x as i32;
+                        }
+                        if y < bottom {
+                            bottom = y;
+                        }
+                    }
+                    x += 1;
+                }
+                top += 1;
+            }
+            table[p][r] = (left, bottom);
+            r += 1;
+        }
+        p += 1;
+    }
+    table
+};
+
+impl Transition {
+    /// The turn of `piece_type` from state `from` to state `to`, `None`
+    /// when they are the same.
+    pub fn find(piece_type: PieceType, from: usize, to: usize) -> Option<&'static Transition> {
+        let table: &'static [Transition] = match (piece_type, (to % 4 + 4 - from % 4) % 4) {
+            (_, 0) => return None,
+            (PieceType::O, _) => &O_KICKS,
+            (_, 2) => &HALF_TURN_KICKS,
+            (PieceType::I, _) => &I_KICKS,
+            _ => &JLSTZ_KICKS,
+        };
+        table.iter().find(|t| t.from == from % 4 && t.to == to % 4)
+    }
+
+    /// The positions to try, in order, for `piece_type` at column `x` and
+    /// row `y` in `ROTATIONS` terms: every kick moved from the SRS box to
+    /// the leftmost column and lowest row of the new state.
+    pub fn positions(
+        &self,
+        piece_type: PieceType,
+        x: i32,
+        y: i32,
+    ) -> impl Iterator<Item = (i32, i32)> + '_ {
+        let from = BOX_OFFSETS[piece_type as usize][self.from];
+        let to = BOX_OFFSETS[piece_type as usize][self.to];
+        self.kicks
+            .iter()
+            .map(move |&(dx, dy)| (x - from.0 + dx + to.0, y - from.1 + dy + to.1))
+    }
+}
+
+/// A rotation state as a program reporting in some system sees it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Shape {
+    /// Occupied `(dx, dy)` offsets from the reported column and the lowest
+    /// occupied row, bottom row first.
+    pub cells: Vec<(usize, usize)>,
+    /// Width and height of the occupied cells.
+    pub width: usize,
+    pub height: usize,
+    /// Reported columns for which the piece lies on the board, inclusive.
+    pub x_range: (i32, i32),
+}
+
+impl Shape {
+    /// Bit `4 * dy + dx` set for every occupied cell.
+    pub fn mask(&self) -> u16 {
+        self.cells
+            .iter()
+            .fold(0, |mask, &(dx, dy)| mask | 1 << (dy * 4 + dx))
+    }
+}
+
+impl RotationSystem {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "simple" => Some(RotationSystem::Simple),
+            "srs" => Some(RotationSystem::Srs),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            RotationSystem::Simple => "simple",
+            RotationSystem::Srs => "srs",
+        }
+    }
+
+    /// The geometry of `rotation` of `piece_type` in this system, derived
+    /// from `ROTATIONS` and `SRS_SHAPES`.
+    pub fn shape(self, piece_type: PieceType, rotation: usize) -> Shape {
+        let (mask, left) = match self {
+            RotationSystem::Simple => (simple_cells(piece_type, rotation), 0),
+            RotationSystem::Srs => srs_cells(piece_type, rotation),
+        };
+        let cells: Vec<(usize, usize)> = (0..16)
+            .filter(|bit| mask & 1 << bit != 0)
+            .map(|bit| (bit % 4 + left, bit / 4))
+            .collect();
+        let width = cells
+            .iter()
+            .map(|&(dx, _)| dx - left + 1)
+            .max()
+            .unwrap_or(0);
+        let height = cells.iter().map(|&(_, dy)| dy + 1).max().unwrap_or(0);
+        let left = left as i32;
+        Shape {
+            cells,
+            width,
+            height,
+            x_range: (-left, (BOARD_WIDTH - width) as i32 - left),
+        }
+    }
+
+    /// Maps a placement reported in this system onto `(rotate, x)` for
+    /// `ROTATIONS`. Only the shape is translated; whether the column is on
+    /// the board is left to the rules check.
+    pub fn to_simple(
+        self,
+        piece_type: PieceType,
+        rotation: usize,
+        x: i32,
+    ) -> Result<(usize, i32), Unrepresentable> {
+        match self {
+            RotationSystem::Simple => Ok((rotation, x)),
+            RotationSystem::Srs => {
+                if rotation >= 4 {
+                    return Err(Unrepresentable);
+                }
+                let (cells, left) = srs_cells(piece_type, rotation);
+                // Prefer the same rotation index so that symmetric pieces
+                // keep the number the program sent.
+                let rotate = std::iter::once(rotation)
+                    .chain(0..4)
+                    .find(|&r| simple_cells(piece_type, r) == cells)
+                    .ok_or(Unrepresentable)?;
+                Ok((rotate, x + left as i32))
+            }
+        }
+    }
+}
+
+/// Occupied cells as a bitmask over a 4x4 box, bottom row first, shifted to
+/// the bottom-left corner.
+type CellMask = u16;
+
+fn simple_cells(piece_type: PieceType, rotate: usize) -> CellMask {
+    let piece = &ROTATIONS[piece_type as usize][rotate];
+    let mut mask = 0;
+    for i in 0..piece.height {
+        for j in 0..piece.width {
+            if piece.shape[i][j] != 0 {
+                mask |= 1 << (i * 4 + j);
+            }
+        }
+    }
+    mask
+}
+
+/// Returns the normalized cells of an SRS state and how many empty
+/// columns its bounding box has on the left.
+fn srs_cells(piece_type: PieceType, rotation: usize) -> (CellMask, usize) {
+    let rows = SRS_SHAPES[piece_type as usize][rotation];
+    let mut cells = Vec::new();
+    for (top, row) in rows.iter().enumerate() {
+        for (col, c) in row.chars().enumerate() {
+            if c == '#' {
+                cells.push((rows.len() - 1 - top, col));
+            }
+        }
+    }
+    let bottom = cells.iter().map(|&(y, _)| y).min().unwrap_or(0);
+    let left = cells.iter().map(|&(_, x)| x).min().unwrap_or(0);
+    let mask = cells
+        .iter()
+        .fold(0, |mask, &(y, x)| mask | 1 << ((y - bottom) * 4 + x - left));
+    (mask, left)
+}
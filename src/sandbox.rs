@@ -0,0 +1,233 @@
+use std::fs;
+use std::io::{self, BufRead, Write};
+
+use crate::board::{Board, BoardSnapshot, Placement, Spin};
+use crate::evaluator::Evaluator;
+use crate::features::BoardFeatures;
+use crate::i18n::{say, tr};
+use crate::logging::{log_error, log_info};
+use crate::piece::{PieceSet, PieceType};
+use crate::policy::best_action;
+use crate::puzzle::board_to_rows;
+
+/// What one line typed into the sandbox asks for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Command<'a> {
+    /// Let the policy place the piece.
+    Auto(PieceType),
+    /// Place the piece at `(rotation, x)`, with its bottom row on the
+    /// given row rather than where it drops to, as under an overhang, and
+    /// turned into it last, as for a T-spin.
+    Place(PieceType, usize, usize, Option<(usize, bool)>),
+    Undo,
+    Export(Option<&'a str>),
+    /// Keep the game, score included, to pick it up again with `Load`.
+    Save(&'a str),
+    Load(&'a str),
+    Quit,
+}
+
+fn parse_command(line: &str) -> Option<Command<'_>> {
+    let mut parts = line.split_whitespace();
+    let first = parts.next()?;
+    let command = match first {
+        "undo" | "u" => Command::Undo,
+        "export" | "e" => Command::Export(parts.next()),
+        "save" => Command::Save(parts.next()?),
+        "load" => Command::Load(parts.next()?),
+        "quit" | "q" => Command::Quit,
+        _ => {
+            let piece = first.to_uppercase().parse::<PieceType>().ok()?;
+            match (parts.next(), parts.next()) {
+                (None, _) => Command::Auto(piece),
+                (Some(rotation), Some(x)) => {
+                    let row = match parts.next() {
+                        Some(row) => {
+                            let turned = match parts.next() {
+                                Some("spin") => true,
+                                Some(_) => return None,
+                                None => false,
+                            };
+                            Some((row.parse().ok()?, turned))
+                        }
+                        None => None,
+                    };
+                    Command::Place(piece, rotation.parse().ok()?, x.parse().ok()?, row)
+                }
+                (Some(_), None) => return None,
+            }
+        }
+    };
+    parts.next().is_none().then_some(command)
+}
+
+/// The board in puzzle notation, ready to go under a `puzzle` and `piece`
+/// header.
+fn notation(board: &Board) -> String {
+    let mut text = tr!(
+        "// 沙盒局面, 分数 {}\n",
+        "// Sandbox position, score {}\n",
+        board.get_score()
+    );
+    for row in board_to_rows(board) {
+        text.push_str(&row);
+        text.push('\n');
+    }
+    text
+}
+
+fn show(board: &Board, sequence: &str, message: &str) {
+    board.draw_colored();
+    say!(
+        "行数 {}  已放置 {}",
+        "Lines {}  pieces {}",
+        board.get_lines(),
+        sequence
+    );
+    if !message.is_empty() {
+        println!("{}", message);
+    }
+    say!(
+        "输入方块字母由AI放置, 或 <字母> <旋转> <位置> [<行> [spin]] 手动放置; undo 撤销, export [文件] 导出, save/load <文件> 保存或继续对局, quit 退出",
+        "Type a piece letter for the AI to place it, or <letter> <rotation> <x> [<row> [spin]] to place it yourself; undo, export [file], save/load <file> to keep a game for later, quit"
+    );
+    print!("> ");
+    let _ = io::stdout().flush();
+}
+
+/// Freeplay mode: the user picks every piece and either places it or has
+/// the policy do it, so that floods, droughts and other sequences can be
+/// set up by hand and the policy's answers watched. The position can be
+/// exported in puzzle notation, and the game saved to go on with later.
+pub fn sandbox<E: Evaluator + ?Sized>(evaluator: &E) {
+    let mut board = Board::new();
+    let mut history: Vec<BoardSnapshot> = Vec::new();
+    let mut sequence = String::new();
+    show(&board, &sequence, "");
+
+    for line in io::stdin().lock().lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            show(&board, &sequence, "");
+            continue;
+        }
+        // Comments, as in the clips of a training run
+        if line.starts_with('#') {
+            continue;
+        }
+        let message = match parse_command(line) {
+            None => tr!("无法识别的命令: {}", "Unrecognized command: {}", line),
+            Some(Command::Quit) => break,
+            Some(Command::Undo) => match history.pop() {
+                Some(previous) => {
+                    board.restore(&previous);
+                    sequence.pop();
+                    tr!("已撤销", "Undone")
+                }
+                None => tr!("没有可撤销的放置", "Nothing to undo"),
+            },
+            Some(Command::Export(None)) => notation(&board),
+            Some(Command::Export(Some(path))) => match fs::write(path, notation(&board)) {
+                Ok(()) => {
+                    log_info!("局面已保存到 {}", "Position saved to {}", path);
+                    String::new()
+                }
+                Err(e) => {
+                    log_error!(
+                        "无法保存局面 {}: {}",
+                        "Cannot save position {}: {}",
+                        path,
+                        e
+                    );
+                    String::new()
+                }
+            },
+            Some(Command::Save(path)) => match fs::write(path, board.snapshot().to_text()) {
+                Ok(()) => tr!("对局已保存到 {}", "Game saved to {}", path),
+                Err(e) => tr!("无法保存对局 {}: {}", "Cannot save game {}: {}", path, e),
+            },
+            Some(Command::Load(path)) => match fs::read_to_string(path)
+                .map_err(|e| e.to_string())
+                .and_then(|text| BoardSnapshot::parse(&text))
+            {
+                Ok(snapshot) => {
+                    history.push(board.snapshot());
+                    board.restore(&snapshot);
+                    sequence.clear();
+                    tr!("已载入 {}", "Loaded {}", path)
+                }
+                Err(e) => tr!("无法读取对局 {}: {}", "Cannot read game {}: {}", path, e),
+            },
+            Some(Command::Auto(piece)) => match best_action(&board, piece, evaluator) {
+                Some((rotation, x, _)) => {
+                    let (_, features) = board.simulate(piece, x, rotation).unwrap();
+                    history.push(board.snapshot());
+                    board.apply(piece, x, rotation).unwrap();
+                    sequence.push(piece.letter());
+                    tr!(
+                        "AI 将 {} 放在 旋转={}, 位置={}\n{}",
+                        "AI placed {} at rotation={}, x={}\n{}",
+                        piece,
+                        rotation,
+                        x,
+                        BoardFeatures::from(features)
+                    )
+                }
+                None => tr!(
+                    "{} 无处可放, 可以撤销后重试",
+                    "{} does not fit anywhere; undo to try something else",
+                    piece
+                ),
+            },
+            Some(Command::Place(piece, rotation, x, row)) => {
+                let snapshot = board.snapshot();
+                let place = |board: &mut Board| match row {
+                    None => board.apply(piece, x, rotation),
+                    Some((landing_y, turned)) => board.apply_at_in(
+                        PieceSet::standard(),
+                        piece as usize,
+                        Placement {
+                            rotation,
+                            x,
+                            landing_y,
+                            turned,
+                        },
+                    ),
+                };
+                match (rotation < 4).then(|| place(&mut board)) {
+                    Some(Ok(placed)) => {
+                        history.push(snapshot);
+                        sequence.push(piece.letter());
+                        let mut message = match placed.spin {
+                            Spin::None => String::new(),
+                            Spin::Mini => tr!("T 旋 (迷你)! ", "T-spin mini! "),
+                            Spin::Full => tr!("T 旋! ", "T-spin! "),
+                        };
+                        if placed.back_to_back {
+                            message += &tr!("背靠背! ", "Back-to-back! ");
+                        }
+                        if placed.combo >= 2 {
+                            message += &tr!("{} 连击! ", "{} combo! ", placed.combo - 1);
+                        }
+                        if placed.all_clear {
+                            message += &tr!("全清!", "All clear!");
+                        }
+                        message.trim_end().to_string()
+                    }
+                    _ => tr!(
+                        "不合法的放置: {} 旋转={}, 位置={}",
+                        "Illegal placement: {} rotation={}, x={}",
+                        piece,
+                        rotation,
+                        x
+                    ),
+                }
+            }
+        };
+        show(&board, &sequence, &message);
+    }
+    println!();
+}
@@ -0,0 +1,289 @@
+use std::fs;
+
+use crate::board::Board;
+use crate::evaluator::Evaluator;
+use crate::i18n::{say, tr};
+use crate::logging::log_error;
+use crate::observation::HeightMap;
+use crate::piece::PieceType;
+use crate::policy::best_action;
+use crate::puzzle::board_from_rows;
+
+/// Something the policy must do while playing a scenario.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Assertion {
+    /// Every piece of the sequence is placed.
+    Survives,
+    /// The board never has more holes than at the start.
+    NoNewHoles,
+    /// At least `lines` lines are cleared within the first `by` pieces, or
+    /// the whole sequence when `by` is `None`.
+    Clears { lines: usize, by: Option<usize> },
+    /// No column ever grows above `rows`.
+    MaxHeight(usize),
+}
+
+impl Assertion {
+    /// Parses what follows `expect`: `survives`, `no-new-holes`,
+    /// `clears >= <n> [by <m>]` or `height <= <n>`.
+    pub fn parse(text: &str) -> Option<Self> {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        match words.as_slice() {
+            ["survives"] => Some(Assertion::Survives),
+            ["no-new-holes"] => Some(Assertion::NoNewHoles),
+            ["clears", ">=", lines] => Some(Assertion::Clears {
+                lines: lines.parse().ok()?,
+                by: None,
+            }),
+            ["clears", ">=", lines, "by", by] => Some(Assertion::Clears {
+                lines: lines.parse().ok()?,
+                by: Some(by.parse().ok()?),
+            }),
+            ["height", "<=", rows] => Some(Assertion::MaxHeight(rows.parse().ok()?)),
+            _ => None,
+        }
+    }
+}
+
+/// A start position, a forced piece sequence and what the policy has to
+/// achieve with it.
+///
+/// Scenario files look like puzzle files:
+///
+/// ```text
+/// scenario S flood on a flat stack
+/// pieces SSSSSSSS
+/// expect survives
+/// expect no-new-holes
+/// expect clears >= 2 by 5
+/// expect height <= 8
+/// ##..######
+/// ```
+///
+/// Board rows are optional and listed top to bottom; without them the
+/// scenario starts on an empty board. Lines starting with `//` are
+/// comments.
+pub struct Scenario {
+    pub name: String,
+    pub board: Board,
+    pub pieces: Vec<PieceType>,
+    pub assertions: Vec<Assertion>,
+}
+
+/// A scenario block while its lines are still being read.
+struct Draft<'a> {
+    name: String,
+    pieces: Vec<PieceType>,
+    assertions: Vec<Assertion>,
+    rows: Vec<&'a str>,
+}
+
+impl Draft<'_> {
+    fn finish(self) -> Result<Scenario, String> {
+        let name = self.name;
+        if self.pieces.is_empty() {
+            return Err(tr!(
+                "场景 '{}' 缺少 pieces",
+                "Scenario '{}' is missing pieces",
+                name
+            ));
+        }
+        if self.assertions.is_empty() {
+            return Err(tr!(
+                "场景 '{}' 缺少 expect",
+                "Scenario '{}' is missing expect",
+                name
+            ));
+        }
+        let board = board_from_rows(&self.rows)
+            .map_err(|e| tr!("场景 '{}': {}", "Scenario '{}': {}", name, e))?;
+        Ok(Scenario {
+            name,
+            board,
+            pieces: self.pieces,
+            assertions: self.assertions,
+        })
+    }
+}
+
+pub fn parse_scenarios(text: &str) -> Result<Vec<Scenario>, String> {
+    let mut scenarios = Vec::new();
+    let mut current: Option<Draft> = None;
+
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with("//") {
+            continue;
+        }
+
+        if let Some(name) = line.strip_prefix("scenario") {
+            if let Some(draft) = current.take() {
+                scenarios.push(draft.finish()?);
+            }
+            current = Some(Draft {
+                name: name.trim().to_string(),
+                pieces: Vec::new(),
+                assertions: Vec::new(),
+                rows: Vec::new(),
+            });
+            continue;
+        }
+
+        let Some(draft) = current.as_mut() else {
+            return Err(tr!(
+                "场景定义之前出现内容: {}",
+                "Content before the first scenario: {}",
+                line
+            ));
+        };
+
+        if let Some(pieces) = line.strip_prefix("pieces") {
+            for c in pieces.chars().filter(|c| !c.is_whitespace()) {
+                draft.pieces.push(PieceType::try_from(c)?);
+            }
+        } else if let Some(e) = line.strip_prefix("expect") {
+            draft.assertions.push(
+                Assertion::parse(e)
+                    .ok_or_else(|| tr!("expect 格式错误: {}", "Malformed expect: {}", line))?,
+            );
+        } else if line.chars().all(|c| c == '#' || c == '.') {
+            draft.rows.push(line);
+        } else {
+            return Err(tr!("无法识别的行: {}", "Unrecognized line: {}", line));
+        }
+    }
+
+    if let Some(draft) = current.take() {
+        scenarios.push(draft.finish()?);
+    }
+    Ok(scenarios)
+}
+
+/// What happened when the policy played a scenario.
+struct Playthrough {
+    placed: usize,
+    start_holes: usize,
+    max_holes: usize,
+    max_height: usize,
+    /// Lines cleared so far after each placement.
+    lines: Vec<usize>,
+}
+
+fn play<E: Evaluator + ?Sized>(scenario: &Scenario, evaluator: &E) -> Playthrough {
+    let mut board = scenario.board.clone();
+    let start_holes = HeightMap::from_board(&board).holes;
+    let mut run = Playthrough {
+        placed: 0,
+        start_holes,
+        max_holes: start_holes,
+        max_height: board.max_height(),
+        lines: Vec::new(),
+    };
+    for &piece in &scenario.pieces {
+        let Some((rotation, x, _)) = best_action(&board, piece, evaluator) else {
+            break;
+        };
+        board.apply(piece, x, rotation).unwrap();
+        run.placed += 1;
+        run.max_holes = run.max_holes.max(HeightMap::from_board(&board).holes);
+        run.max_height = run.max_height.max(board.max_height());
+        run.lines.push(board.get_lines());
+    }
+    run
+}
+
+/// `Err` with the reason when `run` breaks `assertion`.
+fn verify(assertion: Assertion, scenario: &Scenario, run: &Playthrough) -> Result<(), String> {
+    match assertion {
+        Assertion::Survives if run.placed < scenario.pieces.len() => Err(tr!(
+            "第 {} 块无处可放",
+            "piece {} did not fit",
+            run.placed + 1
+        )),
+        Assertion::NoNewHoles if run.max_holes > run.start_holes => Err(tr!(
+            "空洞从 {} 个增加到 {} 个",
+            "holes went from {} to {}",
+            run.start_holes,
+            run.max_holes
+        )),
+        Assertion::Clears { lines, by } => {
+            let by = by.unwrap_or(scenario.pieces.len());
+            let cleared = run.lines[..by.min(run.lines.len())]
+                .last()
+                .copied()
+                .unwrap_or(0);
+            if cleared >= lines {
+                Ok(())
+            } else {
+                Err(tr!(
+                    "前 {} 块只消除了 {} 行, 需要 {} 行",
+                    "the first {} pieces cleared only {} lines, needed {}",
+                    by,
+                    cleared,
+                    lines
+                ))
+            }
+        }
+        Assertion::MaxHeight(rows) if run.max_height > rows => Err(tr!(
+            "堆叠高度达到 {}, 上限 {}",
+            "stack reached height {}, limit {}",
+            run.max_height,
+            rows
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Plays every scenario in `path` with the policy and reports each broken
+/// assertion. Returns whether all of them held, so that scripts can fail
+/// on a behavioral regression.
+pub fn run<E: Evaluator + ?Sized>(path: &str, evaluator: &E) -> bool {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) => {
+            log_error!(
+                "无法读取场景文件 {}: {}",
+                "Cannot read scenario file {}: {}",
+                path,
+                e
+            );
+            return false;
+        }
+    };
+    let scenarios = match parse_scenarios(&text) {
+        Ok(scenarios) => scenarios,
+        Err(e) => {
+            log_error!("场景文件格式错误: {}", "Malformed scenario file: {}", e);
+            return false;
+        }
+    };
+
+    let mut passed = 0;
+    for scenario in &scenarios {
+        let run = play(scenario, evaluator);
+        let failures: Vec<String> = scenario
+            .assertions
+            .iter()
+            .filter_map(|&a| verify(a, scenario, &run).err())
+            .collect();
+        if failures.is_empty() {
+            passed += 1;
+            say!("[通过] {}", "[pass] {}", scenario.name);
+        } else {
+            say!(
+                "[失败] {}: {}",
+                "[fail] {}: {}",
+                scenario.name,
+                failures.join("; ")
+            );
+        }
+    }
+
+    say!(
+        "通过 {}/{} 个场景",
+        "Passed {}/{} scenarios",
+        passed,
+        scenarios.len()
+    );
+    passed == scenarios.len()
+}
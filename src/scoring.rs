@@ -0,0 +1,411 @@
+//! How a board scores its placements, so that the engine can match the
+//! scoring of other games and contests exactly.
+//!
+//! A [`ScoringRules`] is handed to a board with `Board::with_scoring`. The
+//! default rules are the ones the built-in weights, the benchmarks and
+//! every recorded score were made with: 100, 300, 500 and 800 points for
+//! one to four lines, T-spin bonuses and nothing else. `--scoring` takes
+//! `guideline` for the rules of the guideline games or a file of
+//! `<key> <values>` lines:
+//!
+//! ```text
+//! # lines cleared with one piece, 0 to 5
+//! lines 0 100 300 500 800 1200
+//! # starting level, lines per level up, 0 for a level that never rises
+//! level 1 10
+//! drop 2
+//! spin-mini 100 100 100
+//! spin 400 700 900 1100
+//! combo 50 100 150
+//! back-to-back 50
+//! all-clear 0 800 1200 1800 2000 3000
+//! ```
+//!
+//! Keys that are left out keep their default. A key given fewer values
+//! than its table has only sets the first ones, except `combo`, whose
+//! last value goes on for every longer combo. Lines starting with `#`
+//! are comments. No value may be above [`MAX_RULE_VALUE`].
+
+#[cfg(feature = "io")]
+use std::fs;
+
+use crate::board::{LINE_CLEAR_SCORES, Spin};
+use crate::i18n::tr;
+
+/// The largest value a rules file may give. Far more than any game
+/// awards, and small enough that the products the board takes of points,
+/// levels and percents stay well inside an `i64`.
+pub const MAX_RULE_VALUE: i64 = 1_000_000_000;
+
+/// Points a T-spin adds to those of the lines it clears, by the number of
+/// lines.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpinBonus {
+    pub mini: [i64; 3],
+    pub full: [i64; 4],
+}
+
+impl SpinBonus {
+    /// The bonuses that make the totals those of the guideline games: 100,
+    /// 200 and 400 for a mini clearing 0 to 2 lines, and 400, 800, 1200 and
+    /// 1600 for a T-spin clearing 0 to 3.
+    pub const GUIDELINE: SpinBonus = SpinBonus {
+        mini: [100, 100, 100],
+        full: [400, 700, 900, 1100],
+    };
+
+    /// Spins are still reported, but score like any other placement.
+    pub const NONE: SpinBonus = SpinBonus {
+        mini: [0; 3],
+        full: [0; 4],
+    };
+
+    pub(crate) fn points(&self, spin: Spin, lines: usize) -> i64 {
+        let bonus: &[i64] = match spin {
+            Spin::None => &[],
+            Spin::Mini => &self.mini,
+            Spin::Full => &self.full,
+        };
+        bonus.get(lines).copied().unwrap_or(0)
+    }
+}
+
+impl Default for SpinBonus {
+    fn default() -> Self {
+        SpinBonus::GUIDELINE
+    }
+}
+
+/// Points for keeping up a chain of clears, added to those of the lines.
+/// A combo is a run of placements that each clear lines; a clear is
+/// back to back when it is difficult, four lines or more or a T-spin
+/// that clears any, and the clear before it was difficult too. Clears
+/// that are not difficult break a back-to-back chain, placements that
+/// clear nothing only break a combo.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChainBonus {
+    /// Points for the second clear in a row, the third and so on. The
+    /// last entry stands for every later one.
+    pub combo: [i64; 12],
+    /// Percent of a back-to-back clear's points, T-spin bonus included,
+    /// added on top of them.
+    pub back_to_back: i64,
+}
+
+impl ChainBonus {
+    /// The guideline bonuses: 50 points per clear of the combo before
+    /// this one, and half again for a back-to-back clear.
+    pub const GUIDELINE: ChainBonus = ChainBonus {
+        combo: [50, 100, 150, 200, 250, 300, 350, 400, 450, 500, 550, 600],
+        back_to_back: 50,
+    };
+
+    /// Chains are still tracked, but score nothing.
+    pub const NONE: ChainBonus = ChainBonus {
+        combo: [0; 12],
+        back_to_back: 0,
+    };
+
+    /// Points for the `combo`th clear in a row.
+    pub(crate) fn combo_points(&self, combo: usize) -> i64 {
+        combo
+            .checked_sub(2)
+            .map_or(0, |n| self.combo[n.min(self.combo.len() - 1)])
+    }
+
+    /// Points for a clear worth `clear` points, if it is back to back.
+    pub(crate) fn back_to_back_points(&self, back_to_back: bool, clear: i64) -> i64 {
+        if back_to_back {
+            clear.saturating_mul(self.back_to_back) / 100
+        } else {
+            0
+        }
+    }
+}
+
+/// No bonus: the built-in weights, the benchmarks and every recorded
+/// score count the lines alone, and straight drops make combos all the
+/// time.
+impl Default for ChainBonus {
+    fn default() -> Self {
+        ChainBonus::NONE
+    }
+}
+
+/// Points a clear that leaves the board empty adds to all the others, by
+/// the number of lines.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AllClearBonus {
+    pub lines: [i64; 6],
+}
+
+impl AllClearBonus {
+    /// The guideline bonuses for 1 to 4 lines, and half again the four
+    /// line one for the five only pentominoes clear.
+    pub const GUIDELINE: AllClearBonus = AllClearBonus {
+        lines: [0, 800, 1200, 1800, 2000, 3000],
+    };
+
+    /// All clears are still reported, but score nothing extra.
+    pub const NONE: AllClearBonus = AllClearBonus { lines: [0; 6] };
+}
+
+/// No bonus, as for `ChainBonus`.
+impl Default for AllClearBonus {
+    fn default() -> Self {
+        AllClearBonus::NONE
+    }
+}
+
+/// The points of one placement by the rule that gave them, those of the
+/// clear multiplied by the level as in the score.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ScoreParts {
+    pub lines: i64,
+    pub spin: i64,
+    pub combo: i64,
+    pub back_to_back: i64,
+    pub all_clear: i64,
+    pub drop: i64,
+}
+
+impl ScoreParts {
+    pub fn total(&self) -> i64 {
+        [
+            self.lines,
+            self.spin,
+            self.combo,
+            self.back_to_back,
+            self.all_clear,
+            self.drop,
+        ]
+        .into_iter()
+        .fold(0, i64::saturating_add)
+    }
+}
+
+/// Everything a placement scores for. The points of a clear, with all
+/// its bonuses, are multiplied by the level it was made on; drop points
+/// are not.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScoringRules {
+    /// Points for clearing 0 to 5 lines with one piece.
+    pub lines: [i64; 6],
+    /// Level of a game that has cleared no lines yet.
+    pub start_level: i64,
+    /// Lines that take the level up by one, 0 for a level that never
+    /// rises.
+    pub lines_per_level: usize,
+    /// Points per row the piece came down from the top of the well.
+    pub drop: i64,
+    pub spin: SpinBonus,
+    pub chain: ChainBonus,
+    pub all_clear: AllClearBonus,
+}
+
+impl ScoringRules {
+    /// The rules of the guideline games, with every piece hard dropped
+    /// from the top.
+    pub const GUIDELINE: ScoringRules = ScoringRules {
+        lines: LINE_CLEAR_SCORES,
+        start_level: 1,
+        lines_per_level: 10,
+        drop: 2,
+        spin: SpinBonus::GUIDELINE,
+        chain: ChainBonus::GUIDELINE,
+        all_clear: AllClearBonus::GUIDELINE,
+    };
+
+    /// The level of a game that has cleared `lines` lines.
+    pub fn level(&self, lines: usize) -> i64 {
+        let ups = lines.checked_div(self.lines_per_level).unwrap_or(0);
+        self.start_level.saturating_add(ups as i64)
+    }
+
+    /// `default`, `guideline` or the path of a rules file.
+    #[cfg(feature = "io")]
+    pub fn load(spec: &str) -> Result<Self, String> {
+        match spec {
+            "default" => Ok(ScoringRules::default()),
+            "guideline" => Ok(ScoringRules::GUIDELINE),
+            path => {
+                let text = fs::read_to_string(path)
+                    .map_err(|e| tr!("无法读取 {}: {}", "Cannot read {}: {}", path, e))?;
+                Self::parse(&text)
+            }
+        }
+    }
+
+    /// The rules as a rules file that gives every key, which `parse`
+    /// reads back as the same rules.
+    pub fn to_text(&self) -> String {
+        let values = |values: &[i64]| {
+            values
+                .iter()
+                .map(|v| v.to_string())
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        format!(
+            "lines {}\nlevel {} {}\ndrop {}\nspin-mini {}\nspin {}\ncombo {}\nback-to-back {}\nall-clear {}\n",
+            values(&self.lines),
+            self.start_level,
+            self.lines_per_level,
+            self.drop,
+            values(&self.spin.mini),
+            values(&self.spin.full),
+            values(&self.chain.combo),
+            self.chain.back_to_back,
+            values(&self.all_clear.lines)
+        )
+    }
+
+    /// Parses a rules file, see the module documentation.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut rules = ScoringRules::default();
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, rest) = line.split_once(' ').unwrap_or((line, ""));
+            let values: Vec<i64> = rest
+                .split_whitespace()
+                .map(|v| match v.parse() {
+                    Ok(points) if (0..=MAX_RULE_VALUE).contains(&points) => Ok(points),
+                    _ => Err(tr!(
+                        "无效的分数: {} (需要 0 到 {} 的整数)",
+                        "Invalid points: {} (expected an integer from 0 to {})",
+                        v,
+                        MAX_RULE_VALUE
+                    )),
+                })
+                .collect::<Result<_, _>>()?;
+            // Fills the first entries of `table` with the values
+            let fill = |table: &mut [i64]| {
+                if values.is_empty() || values.len() > table.len() {
+                    return Err(tr!(
+                        "{} 需要 1 到 {} 个数",
+                        "{} expects 1 to {} numbers",
+                        key,
+                        table.len()
+                    ));
+                }
+                table[..values.len()].copy_from_slice(&values);
+                Ok(())
+            };
+            match key {
+                "lines" => fill(&mut rules.lines)?,
+                "level" => match values.as_slice() {
+                    &[start, per_level] => {
+                        rules.start_level = start;
+                        rules.lines_per_level = per_level as usize;
+                    }
+                    _ => {
+                        return Err(tr!(
+                            "level 需要 <起始等级> <每级行数>",
+                            "level expects <start level> <lines per level>"
+                        ));
+                    }
+                },
+                "drop" => fill(std::slice::from_mut(&mut rules.drop))?,
+                "spin-mini" => fill(&mut rules.spin.mini)?,
+                "spin" => fill(&mut rules.spin.full)?,
+                "combo" => {
+                    fill(&mut rules.chain.combo)?;
+                    let last = values[values.len() - 1];
+                    rules.chain.combo[values.len()..].fill(last);
+                }
+                "back-to-back" => fill(std::slice::from_mut(&mut rules.chain.back_to_back))?,
+                "all-clear" => fill(&mut rules.all_clear.lines)?,
+                _ => return Err(tr!("无法识别的行: {}", "Unrecognized line: {}", line)),
+            }
+        }
+        Ok(rules)
+    }
+}
+
+/// The rules of the built-in weights: line points at level 1 forever and
+/// the T-spin bonuses, which only placements reached by a turn can score.
+impl Default for ScoringRules {
+    fn default() -> Self {
+        ScoringRules {
+            lines: LINE_CLEAR_SCORES,
+            start_level: 1,
+            lines_per_level: 0,
+            drop: 0,
+            spin: SpinBonus::default(),
+            chain: ChainBonus::default(),
+            all_clear: AllClearBonus::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn guideline_spin_bonuses_make_the_guideline_totals() {
+        let total = |spin, lines: usize| {
+            LINE_CLEAR_SCORES[lines] + SpinBonus::GUIDELINE.points(spin, lines)
+        };
+        let minis: Vec<i64> = (0..3).map(|lines| total(Spin::Mini, lines)).collect();
+        assert_eq!(minis, [100, 200, 400]);
+        let fulls: Vec<i64> = (0..4).map(|lines| total(Spin::Full, lines)).collect();
+        assert_eq!(fulls, [400, 800, 1200, 1600]);
+        // Past the end of a table a spin adds nothing
+        assert_eq!(SpinBonus::GUIDELINE.points(Spin::Mini, 3), 0);
+        assert_eq!(SpinBonus::GUIDELINE.points(Spin::None, 1), 0);
+    }
+
+    #[test]
+    fn combos_score_from_the_second_clear_and_keep_the_last_value() {
+        let chain = ChainBonus::GUIDELINE;
+        assert_eq!(chain.combo_points(0), 0);
+        assert_eq!(chain.combo_points(1), 0);
+        assert_eq!(chain.combo_points(2), 50);
+        assert_eq!(chain.combo_points(3), 100);
+        assert_eq!(chain.combo_points(13), 600);
+        assert_eq!(chain.combo_points(100), 600);
+    }
+
+    #[test]
+    fn back_to_back_is_a_percent_of_the_clear() {
+        let chain = ChainBonus::GUIDELINE;
+        assert_eq!(chain.back_to_back_points(true, 1200), 600);
+        assert_eq!(chain.back_to_back_points(false, 1200), 0);
+        let chain = ChainBonus {
+            back_to_back: 33,
+            ..ChainBonus::GUIDELINE
+        };
+        assert_eq!(chain.back_to_back_points(true, 100), 33);
+        assert_eq!(chain.back_to_back_points(true, i64::MAX), i64::MAX / 100);
+    }
+
+    #[test]
+    fn rules_read_back_from_their_text() {
+        for rules in [ScoringRules::default(), ScoringRules::GUIDELINE] {
+            assert_eq!(ScoringRules::parse(&rules.to_text()), Ok(rules));
+        }
+        let rules = ScoringRules::parse(
+            "# a comment\nlines 0 40 100 300 1200\nlevel 0 10\ncombo 10 20\nall-clear 0 1\n",
+        )
+        .unwrap();
+        assert_eq!(rules.lines, [0, 40, 100, 300, 1200, 1200]);
+        assert_eq!(rules.start_level, 0);
+        assert_eq!(rules.chain.combo[..3], [10, 20, 20]);
+        assert_eq!(rules.chain.combo[11], 20);
+        assert_eq!(rules.all_clear.lines, [0, 1, 0, 0, 0, 0]);
+        assert_eq!(ScoringRules::parse(&rules.to_text()), Ok(rules));
+    }
+
+    #[test]
+    fn bad_rules_are_refused() {
+        assert!(ScoringRules::parse("lines 0 1 2 3 4 5 6").is_err());
+        assert!(ScoringRules::parse("drop -1").is_err());
+        assert!(ScoringRules::parse("level 1").is_err());
+        assert!(ScoringRules::parse("gravity 1").is_err());
+        assert!(ScoringRules::parse(&format!("drop {}", MAX_RULE_VALUE + 1)).is_err());
+    }
+}
@@ -0,0 +1,352 @@
+//! Beam search over the known part of the piece queue.
+//!
+//! The first ply rates every placement of the current piece. Each later
+//! ply places the next queued piece on the `beam_width` best boards so
+//! far, a board's score being the sum of the evaluations along its path,
+//! and keeps the best `beam_width` of the results. The move played is the
+//! first placement of the best path at the last ply the queue allows.
+//!
+//! Depth 1 is `best_action`. Depth 2 with a beam at least as wide as the
+//! placements of the current piece rates moves as `best_action_with_next`
+//! does; a narrower beam trades strength for time.
+//!
+//! Boards recur: two placement orders can build the same stack, and the
+//! search for the next move expands again the boards the search for this
+//! one kept. A `TranspositionTable` kept across moves remembers the rated
+//! placements of every board and piece it expanded, so that only boards
+//! it has not seen cost evaluations. It changes no result.
+
+use std::sync::Arc;
+
+use rayon::prelude::*;
+
+use crate::board::{Board, zobrist_key};
+use crate::evaluator::Evaluator;
+use crate::i18n::tr;
+use crate::piece::PieceSet;
+
+/// Boards per ply from which the expansion runs on the rayon pool, as
+/// with the lookahead of `best_action_with_next`.
+const PARALLEL_NODES: usize = 8;
+
+/// How far and how wide the search looks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SearchConfig {
+    /// Pieces to place along each path, the current one included.
+    pub depth: usize,
+    /// Boards kept after each ply.
+    pub beam_width: usize,
+    /// Entries of the transposition table the callers keep for it, 0 for
+    /// none.
+    pub table: usize,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        SearchConfig {
+            depth: 1,
+            beam_width: 16,
+            table: 4096,
+        }
+    }
+}
+
+impl SearchConfig {
+    /// Parses `depth=<n>,beam=<k>,table=<t>`; any part may be left out
+    /// for its default, and the depth and beam must be at least 1.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut config = SearchConfig::default();
+        for part in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let invalid = || {
+                tr!(
+                    "需要 depth=<n>,beam=<k>,table=<t>, 实际为 '{}'",
+                    "expected depth=<n>,beam=<k>,table=<t>, got '{}'",
+                    spec
+                )
+            };
+            let (key, value) = part.split_once('=').ok_or_else(invalid)?;
+            let value: usize = value.trim().parse().map_err(|_| invalid())?;
+            match key.trim() {
+                "depth" if value > 0 => config.depth = value,
+                "beam" if value > 0 => config.beam_width = value,
+                "table" => config.table = value,
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(config)
+    }
+
+    /// The config in the form `parse` reads.
+    pub fn to_spec(&self) -> String {
+        format!(
+            "depth={},beam={},table={}",
+            self.depth, self.beam_width, self.table
+        )
+    }
+
+    /// Pieces the search wants to see beyond the current one.
+    pub fn lookahead(&self) -> usize {
+        self.depth - 1
+    }
+}
+
+/// The rated placements of a piece on a board, as `(rotate, x, score)`
+/// in search order.
+type Rated = Arc<[(usize, usize, f64)]>;
+
+/// What a `TranspositionTable` did so far.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TableStats {
+    /// Boards the search looked up.
+    pub lookups: u64,
+    /// Lookups that found the board, sparing its evaluations.
+    pub hits: u64,
+    /// Entries stored over an entry for another board.
+    pub replaced: u64,
+}
+
+impl TableStats {
+    pub fn hit_rate(&self) -> f64 {
+        self.hits as f64 / self.lookups.max(1) as f64
+    }
+}
+
+/// The rated placements of boards already expanded, by the Zobrist hash of
+/// the board and the piece. The table has a fixed number of slots, and an
+/// entry goes into the slot its hash picks, over whatever was there: the
+/// boards of the last few searches are the ones that come back. Two boards
+/// with one hash would share an entry, which at 64 bits does not happen in
+/// practice.
+pub struct TranspositionTable {
+    slots: Vec<Option<(u64, Rated)>>,
+    stats: TableStats,
+}
+
+impl TranspositionTable {
+    /// A table of `entries` slots; with none it stores nothing.
+    pub fn new(entries: usize) -> Self {
+        TranspositionTable {
+            slots: vec![None; entries],
+            stats: TableStats::default(),
+        }
+    }
+
+    pub fn stats(&self) -> TableStats {
+        self.stats
+    }
+
+    fn key<const H: usize, const W: usize>(board: &Board<H, W>, piece: usize) -> u64 {
+        board.zobrist() ^ zobrist_key(1 << 61 | piece as u64)
+    }
+
+    fn slot(&self, key: u64) -> Option<usize> {
+        (!self.slots.is_empty()).then(|| (key % self.slots.len() as u64) as usize)
+    }
+
+    fn get(&mut self, key: u64) -> Option<Rated> {
+        let slot = self.slot(key)?;
+        self.stats.lookups += 1;
+        match &self.slots[slot] {
+            Some((k, rated)) if *k == key => {
+                self.stats.hits += 1;
+                Some(rated.clone())
+            }
+            _ => None,
+        }
+    }
+
+    fn insert(&mut self, key: u64, rated: Rated) {
+        let Some(slot) = self.slot(key) else {
+            return;
+        };
+        if self.slots[slot].as_ref().is_some_and(|(k, _)| *k != key) {
+            self.stats.replaced += 1;
+        }
+        self.slots[slot] = Some((key, rated));
+    }
+}
+
+/// A board reached along a path, the index of the path's first placement
+/// and the summed evaluation.
+struct Node<const H: usize, const W: usize> {
+    board: Board<H, W>,
+    first: usize,
+    score: f64,
+}
+
+/// The rated placements of `piece` on every board of `nodes`, from `table`
+/// where it has them. The others are rated on the rayon pool when there
+/// are enough of them, as with the lookahead of `best_action_with_next`,
+/// and stored.
+fn rate<const H: usize, const W: usize, E: Evaluator<H, W> + ?Sized>(
+    nodes: &[Node<H, W>],
+    set: &PieceSet,
+    piece: usize,
+    evaluator: &E,
+    table: &mut TranspositionTable,
+) -> Vec<Rated> {
+    let keys: Vec<u64> = nodes
+        .iter()
+        .map(|node| TranspositionTable::key(&node.board, piece))
+        .collect();
+    let found: Vec<Option<Rated>> = keys.iter().map(|&key| table.get(key)).collect();
+    let missing: Vec<usize> = (0..nodes.len()).filter(|&i| found[i].is_none()).collect();
+    let expand = |&i: &usize| -> Rated {
+        let board = &nodes[i].board;
+        board
+            .legal_placements_with_features_in(set, piece)
+            .map(|(p, features)| (p.rotation, p.x, evaluator.evaluate(board, &features)))
+            .collect()
+    };
+    let mut fresh: Vec<Rated> = if missing.len() >= PARALLEL_NODES {
+        missing.par_iter().map(expand).collect()
+    } else {
+        missing.iter().map(expand).collect()
+    };
+    for (&i, rated) in missing.iter().zip(&fresh) {
+        table.insert(keys[i], rated.clone());
+    }
+    fresh.reverse();
+    found
+        .into_iter()
+        .map(|rated| rated.unwrap_or_else(|| fresh.pop().unwrap()))
+        .collect()
+}
+
+/// Every first placement of `queue[0]` that is still on the beam at the
+/// last ply, as `(rotate, x, score)` in search order, with the score of
+/// its best path. The search goes `config.depth` pieces deep, or as deep
+/// as `queue` goes; a ply where no board takes the piece ends it early.
+/// Empty when `queue` is or the first piece fits nowhere.
+pub fn beam_search_in<const H: usize, const W: usize, E: Evaluator<H, W> + ?Sized>(
+    board: &Board<H, W>,
+    set: &PieceSet,
+    queue: &[usize],
+    evaluator: &E,
+    config: SearchConfig,
+) -> Vec<(usize, usize, f64)> {
+    let mut table = TranspositionTable::new(0);
+    beam_search_with_table_in(board, set, queue, evaluator, config, &mut table)
+}
+
+/// `beam_search_in` with the boards `table` has seen taken from it, and
+/// the ones it has not stored in it. The table's size is the caller's;
+/// `config.table` is only what it asked for.
+pub fn beam_search_with_table_in<const H: usize, const W: usize, E: Evaluator<H, W> + ?Sized>(
+    board: &Board<H, W>,
+    set: &PieceSet,
+    queue: &[usize],
+    evaluator: &E,
+    config: SearchConfig,
+    table: &mut TranspositionTable,
+) -> Vec<(usize, usize, f64)> {
+    let Some(&piece) = queue.first() else {
+        return Vec::new();
+    };
+    let root = [Node {
+        board: board.clone(),
+        first: 0,
+        score: 0.0,
+    }];
+    let firsts = rate(&root, set, piece, evaluator, table).remove(0);
+    let children = firsts
+        .iter()
+        .enumerate()
+        .map(|(i, &(rotate, x, score))| (0, i, rotate, x, score))
+        .collect();
+    let mut nodes = grow(&root, children, set, piece, config.beam_width);
+
+    for &piece in queue.iter().take(config.depth).skip(1) {
+        let rated = rate(&nodes, set, piece, evaluator, table);
+        let children: Vec<_> = nodes
+            .iter()
+            .zip(&rated)
+            .enumerate()
+            .flat_map(|(parent, (node, rated))| {
+                rated.iter().map(move |&(rotate, x, score)| {
+                    (parent, node.first, rotate, x, node.score + score)
+                })
+            })
+            .collect();
+        if children.is_empty() {
+            break;
+        }
+        nodes = grow(&nodes, children, set, piece, config.beam_width);
+    }
+
+    let mut best = vec![None; firsts.len()];
+    for node in &nodes {
+        let slot: &mut Option<f64> = &mut best[node.first];
+        if slot.is_none_or(|s| node.score < s) {
+            *slot = Some(node.score);
+        }
+    }
+    firsts
+        .iter()
+        .zip(best)
+        .filter_map(|(&(rotate, x, _), score)| Some((rotate, x, score?)))
+        .collect()
+}
+
+/// Keeps the `beam_width` best of `children`, given as `(parent, first,
+/// rotate, x, score)`, earlier ones winning ties, and plays them out on
+/// their parents' boards. Only the survivors get a board of their own.
+fn grow<const H: usize, const W: usize>(
+    parents: &[Node<H, W>],
+    mut children: Vec<(usize, usize, usize, usize, f64)>,
+    set: &PieceSet,
+    piece: usize,
+    beam_width: usize,
+) -> Vec<Node<H, W>> {
+    children.sort_by(|a, b| a.4.total_cmp(&b.4));
+    children.truncate(beam_width);
+    children
+        .into_iter()
+        .map(|(parent, first, rotate, x, score)| {
+            let mut board = parents[parent].board.clone();
+            board.apply_in(set, piece, x, rotate).unwrap();
+            Node {
+                board,
+                first,
+                score,
+            }
+        })
+        .collect()
+}
+
+/// The move `beam_search_in` rates best, as `(rotate, x, score)`; the first
+/// in search order on ties, like `best_action`.
+pub fn best_search_action_in<const H: usize, const W: usize, E: Evaluator<H, W> + ?Sized>(
+    board: &Board<H, W>,
+    set: &PieceSet,
+    queue: &[usize],
+    evaluator: &E,
+    config: SearchConfig,
+) -> Option<(usize, usize, f64)> {
+    let mut table = TranspositionTable::new(0);
+    best_search_action_with_table_in(board, set, queue, evaluator, config, &mut table)
+}
+
+/// `best_search_action_in` through `table`, see
+/// `beam_search_with_table_in`.
+pub fn best_search_action_with_table_in<
+    const H: usize,
+    const W: usize,
+    E: Evaluator<H, W> + ?Sized,
+>(
+    board: &Board<H, W>,
+    set: &PieceSet,
+    queue: &[usize],
+    evaluator: &E,
+    config: SearchConfig,
+    table: &mut TranspositionTable,
+) -> Option<(usize, usize, f64)> {
+    let mut best: Option<(usize, usize, f64)> = None;
+    for (rotate, x, score) in beam_search_with_table_in(board, set, queue, evaluator, config, table)
+    {
+        if best.is_none_or(|(_, _, s)| score < s) {
+            best = Some((rotate, x, score));
+        }
+    }
+    best
+}
@@ -0,0 +1,358 @@
+//! The other side of `check`: pieces come in on stdin, placements go out
+//! on stdout, so the built-in policy can be plugged into any harness that
+//! speaks the same line protocol.
+//!
+//! Every input line is one of
+//!
+//! - one or more piece letters, queued in order; `check` opens with the
+//!   current piece and its preview and then sends one per move;
+//! - `P <n>`, the length offer of `check --negotiate`, accepted as is;
+//! - `O ...`, the observation line of `check --observation heights`,
+//!   which is ignored since we keep our own board;
+//! - `G <rows> <hole>`, garbage from the opponent of a `versus` match:
+//!   `rows` rows pushed in from below, open in column `hole`;
+//! - `E`, the end of the game, as is the end of the input.
+//!
+//! Once the queue holds the current piece and `preview` more, the current
+//! piece is placed and answered with a `<rotation> <x>` line in the
+//! `simple` rotation system and a line with the score. When nothing fits,
+//! the answer is `R`, and the game is over. With `--hold` the move line
+//! may be preceded by `H`, as `check --hold` expects. With `--search` the
+//! placement is the best of a beam search over the whole queue, which
+//! then waits for the lookahead of the search, as `check --search` deals.
+//! With `--mcts` it is the most visited of a tree search that takes the
+//! queue as known and draws the pieces after it.
+
+use std::io::{self, BufRead, Write};
+
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+
+use crate::board::Board;
+use crate::check::encode_board;
+use crate::dimensions::{Dimensions, with_dimensions};
+use crate::evaluator::{Evaluator, Model};
+use crate::hold::HoldSlot;
+use crate::logging::{log_info, log_warn};
+use crate::mcts::{MctsConfig, mcts_action_in};
+use crate::piece::BuiltinSet;
+use crate::policy::{Visibility, best_action_with_hold_in, choose_in};
+use crate::scoring::ScoringRules;
+use crate::search::{SearchConfig, TranspositionTable, best_search_action_with_table_in};
+use crate::stats::GameStats;
+
+#[derive(Debug, Clone)]
+pub struct ServeOptions {
+    /// Pieces to wait for beyond the current one before placing it: 1 for
+    /// `check`, 0 for feeds that expect an answer to every piece at once.
+    pub preview: usize,
+    /// Whether the policy uses the next piece when it is known.
+    pub visibility: Visibility,
+    pub piece_set: BuiltinSet,
+    /// End move lines with the number of legal placements, for
+    /// `check --legal-count`.
+    pub legal_count: bool,
+    /// Every this many pieces, follow the score with the board encoding,
+    /// for `check --board-every`.
+    pub board_every: Option<usize>,
+    /// After the end, print a `<score> <lines> <pieces>` line, for
+    /// `check --summary`.
+    pub summary: bool,
+    /// Hold when the other piece has the better placement, for
+    /// `check --hold`. Each piece is then judged on its own, whatever the
+    /// visibility.
+    pub hold: bool,
+    /// Place by a beam search over the queue, whatever the visibility.
+    pub search: Option<SearchConfig>,
+    /// Place by a tree search instead, ahead of `search`. The pieces it
+    /// draws are seeded, so a node budget plays the same game every time.
+    pub mcts: Option<MctsConfig>,
+    /// How the score lines count, as `check --scoring` does.
+    pub scoring: ScoringRules,
+    /// Size of our board, as `check` is told it. Set from the global
+    /// `--width` and `--height`.
+    pub dimensions: Dimensions,
+    /// Rows hidden above the playfield, as `check --hidden` is told.
+    pub hidden: usize,
+}
+
+impl Default for ServeOptions {
+    fn default() -> Self {
+        ServeOptions {
+            preview: 1,
+            visibility: Visibility::default(),
+            piece_set: BuiltinSet::default(),
+            legal_count: false,
+            board_every: None,
+            summary: false,
+            hold: false,
+            search: None,
+            mcts: None,
+            scoring: ScoringRules::default(),
+            dimensions: Dimensions::default(),
+            hidden: 0,
+        }
+    }
+}
+
+impl ServeOptions {
+    pub fn from_args(args: &[String]) -> Self {
+        let mut options = ServeOptions::default();
+        let mut preview = None;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--summary" => options.summary = true,
+                "--legal-count" => options.legal_count = true,
+                "--hold" => options.hold = true,
+                "--board-every" => {
+                    options.board_every =
+                        args.next().and_then(|n| n.parse().ok()).filter(|&n| n > 0)
+                }
+                "--preview" => match args.next().and_then(|n| n.parse().ok()) {
+                    Some(n) => preview = Some(n),
+                    None => log_warn!("--preview 需要一个整数", "--preview expects an integer"),
+                },
+                "--visibility" => match args.next().and_then(|v| Visibility::parse(v)) {
+                    Some(visibility) => options.visibility = visibility,
+                    None => log_warn!(
+                        "--visibility 需要 current、next 或 expected",
+                        "--visibility expects current, next or expected"
+                    ),
+                },
+                "--search" => match args.next().map(|s| SearchConfig::parse(s)) {
+                    Some(Ok(search)) => options.search = Some(search),
+                    Some(Err(e)) => log_warn!("忽略 --search: {}", "Ignoring --search: {}", e),
+                    None => log_warn!(
+                        "--search 需要 depth=<n>,beam=<k>",
+                        "--search expects depth=<n>,beam=<k>"
+                    ),
+                },
+                "--mcts" => match args.next().map(|s| MctsConfig::parse(s)) {
+                    Some(Ok(mcts)) => options.mcts = Some(mcts),
+                    Some(Err(e)) => log_warn!("忽略 --mcts: {}", "Ignoring --mcts: {}", e),
+                    None => log_warn!(
+                        "--mcts 需要 nodes=<n> 或 time=<毫秒>ms",
+                        "--mcts expects nodes=<n> or time=<ms>ms"
+                    ),
+                },
+                "--scoring" => match args.next().map(|s| ScoringRules::load(s)) {
+                    Some(Ok(scoring)) => options.scoring = scoring,
+                    Some(Err(e)) => log_warn!("忽略 --scoring: {}", "Ignoring --scoring: {}", e),
+                    None => log_warn!(
+                        "--scoring 需要 guideline 或规则文件",
+                        "--scoring expects guideline or a rules file"
+                    ),
+                },
+                "--hidden" => match args.next().and_then(|n| n.parse().ok()) {
+                    Some(rows) => options.hidden = rows,
+                    None => log_warn!("--hidden 需要一个整数", "--hidden expects an integer"),
+                },
+                "--piece-set" => match args.next().and_then(|p| BuiltinSet::parse(p)) {
+                    Some(set) => options.piece_set = set,
+                    None => log_warn!(
+                        "--piece-set 需要 tetromino 或 pentomino",
+                        "--piece-set expects tetromino or pentomino"
+                    ),
+                },
+                _ => log_warn!("忽略未知参数: {}", "Ignoring unknown argument: {}", arg),
+            }
+        }
+        // A search waits for its lookahead unless told otherwise, as
+        // `check --search` deals it
+        options.preview = preview.unwrap_or_else(|| {
+            options
+                .search
+                .map_or(options.preview, |search| search.lookahead().max(1))
+        });
+        options
+    }
+}
+
+/// Plays the pieces read from stdin until the input ends, an `E` line
+/// arrives or nothing fits, and returns where the points came from.
+/// Lines that are none of the above are reported and skipped.
+pub fn serve(model: &Model, options: &ServeOptions) -> io::Result<GameStats> {
+    with_dimensions!(options.dimensions, |H, W| serve_in::<H, W, _>(
+        model, options
+    ))
+}
+
+/// `serve` with any evaluator, on a board of `H` rows and `W` columns;
+/// `options.dimensions` is not looked at.
+pub fn serve_in<const H: usize, const W: usize, E: Evaluator<H, W> + ?Sized>(
+    evaluator: &E,
+    options: &ServeOptions,
+) -> io::Result<GameStats> {
+    let set = options.piece_set.set();
+    let mut board = Board::<H, W>::empty().with_scoring(options.scoring);
+    if options.hidden + 4 <= H {
+        board = board.with_hidden_rows(options.hidden);
+    } else {
+        log_warn!(
+            "忽略 --hidden: {} 行的棋盘至少要露出 4 行",
+            "Ignoring --hidden: a board of {} rows must show at least 4",
+            H
+        );
+    }
+    let mut stats = GameStats::default();
+    let mut queue = Vec::new();
+    let mut slot = HoldSlot::new();
+    let mut out = io::stdout().lock();
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut table = TranspositionTable::new(options.search.map_or(0, |search| search.table));
+
+    'game: for line in io::stdin().lock().lines() {
+        let line = line?;
+        let line = line.trim();
+        if line == "E" {
+            break;
+        }
+        if let Some(offer) = line.strip_prefix("P ") {
+            writeln!(out, "P {}", offer.trim())?;
+            out.flush()?;
+            continue;
+        }
+        if line.is_empty() || line.starts_with("O ") {
+            continue;
+        }
+        if let Some(garbage) = line.strip_prefix("G ") {
+            let fields: Vec<Option<usize>> =
+                garbage.split_whitespace().map(|f| f.parse().ok()).collect();
+            match fields[..] {
+                [Some(rows), Some(hole)] => {
+                    if let Err(e) = board.add_garbage(rows, &[hole]) {
+                        log_warn!("无法加入垃圾行: {}", "Cannot add the garbage: {}", e);
+                    }
+                }
+                _ => log_warn!(
+                    "忽略无法识别的行: {}",
+                    "Ignoring unrecognized line: {}",
+                    line
+                ),
+            }
+            continue;
+        }
+        let Some(pieces) = line
+            .chars()
+            .map(|c| set.find(c))
+            .collect::<Option<Vec<_>>>()
+        else {
+            log_warn!(
+                "忽略无法识别的行: {}",
+                "Ignoring unrecognized line: {}",
+                line
+            );
+            continue;
+        };
+        queue.extend(pieces);
+
+        while queue.len() > options.preview {
+            // The best placement of `piece` followed by the queue, by
+            // whichever search was asked for
+            let mut search = |piece: usize, queue: &[usize]| {
+                let path: Vec<usize> = std::iter::once(piece)
+                    .chain(queue.iter().copied())
+                    .collect();
+                match (options.mcts, options.search) {
+                    (Some(config), _) => {
+                        mcts_action_in(&board, set, &path, evaluator, &config, &mut rng)
+                    }
+                    (None, Some(config)) => best_search_action_with_table_in(
+                        &board, set, &path, evaluator, config, &mut table,
+                    ),
+                    (None, None) => None,
+                }
+            };
+            let searching = options.mcts.is_some() || options.search.is_some();
+            let mut piece = queue.remove(0);
+            let next = queue.first().copied().unwrap_or(piece);
+            let visibility = if queue.is_empty() {
+                Visibility::Current
+            } else {
+                options.visibility
+            };
+            let action = if options.hold {
+                best_action_with_hold_in(
+                    &board,
+                    set,
+                    piece,
+                    slot,
+                    queue.first().copied(),
+                    evaluator,
+                )
+            } else if searching {
+                search(piece, &queue).map(|(rotation, x, score)| (false, rotation, x, score))
+            } else {
+                choose_in(&board, set, piece, next, evaluator, visibility)
+                    .map(|(rotation, x, score)| (false, rotation, x, score))
+            };
+            let Some((hold, mut rotation, mut x, _)) = action else {
+                log_info!(
+                    "{} 无处可放, 认输",
+                    "{} does not fit anywhere, resigning",
+                    set.letter(piece)
+                );
+                writeln!(out, "R")?;
+                out.flush()?;
+                break 'game;
+            };
+            if hold {
+                let (held, took_next) = slot.hold(piece, queue.first().copied());
+                if took_next {
+                    queue.remove(0);
+                }
+                piece = held;
+                writeln!(out, "H")?;
+            }
+            // The hold decision judges each piece on its own; where the
+            // piece it settled on goes is still up to the search
+            if options.hold
+                && searching
+                && let Some(searched) = search(piece, &queue)
+            {
+                (rotation, x, _) = searched;
+            }
+            let legal = options
+                .legal_count
+                .then(|| board.legal_placements_in(set, piece).count());
+            let placed = board.apply_in(set, piece, x, rotation).unwrap();
+            stats.record(&placed);
+
+            match legal {
+                Some(count) => writeln!(out, "{} {} {}", rotation, x, count)?,
+                None => writeln!(out, "{} {}", rotation, x)?,
+            }
+            writeln!(out, "{}", board.get_score())?;
+            if options
+                .board_every
+                .is_some_and(|k| stats.pieces.is_multiple_of(k))
+            {
+                writeln!(out, "{}", encode_board(&board))?;
+            }
+            out.flush()?;
+        }
+    }
+
+    let table_stats = table.stats();
+    if table_stats.lookups > 0 {
+        log_info!(
+            "置换表: {} 次查找, 命中 {:.1}%, {} 次覆盖",
+            "Transposition table: {} lookups, {:.1}% hits, {} replaced",
+            table_stats.lookups,
+            100.0 * table_stats.hit_rate(),
+            table_stats.replaced
+        );
+    }
+    if options.summary {
+        writeln!(
+            out,
+            "{} {} {}",
+            board.get_score(),
+            board.get_lines(),
+            stats.pieces
+        )?;
+        out.flush()?;
+    }
+    Ok(stats)
+}
@@ -0,0 +1,150 @@
+//! The serialized form of a `Board`, behind the `serde` feature.
+//!
+//! `Board` goes through `BoardState`, whose JSON is versioned and
+//! described by `schema/board.v2.json`:
+//!
+//! ```text
+//! {"version": 2, "width": 10, "height": 15,
+//!  "rows": ["IIII##ZZ.L", "...", ...], "score": 100, "lines": 1,
+//!  "combo": 1, "back_to_back": false}
+//! ```
+//!
+//! `rows` lists the rows bottom first, so `rows[y]` is row `y`, one
+//! character per cell: `.` for empty, the piece letter for a cell left by
+//! that piece and `#` for a filled cell of no known piece. Missing top
+//! rows are empty. Heights are not stored; they are recomputed on load.
+//! `combo` and `back_to_back` are the chain the next clear scores on.
+//! Version 1, `schema/board.v1.json`, had no chain; such boards are
+//! still read, as boards in no chain.
+
+use serde::{Deserialize, Serialize};
+
+use crate::board::{Board, CELL_CHARS};
+use crate::i18n::tr;
+
+/// Version written into every serialized board. Readers reject other
+/// versions than this one and 1 instead of guessing.
+pub const BOARD_STATE_VERSION: u32 = 2;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BoardState {
+    pub version: u32,
+    pub width: usize,
+    pub height: usize,
+    pub rows: Vec<String>,
+    pub score: i64,
+    pub lines: usize,
+    /// Clears in a row up to the last placement, absent in version 1.
+    #[serde(default)]
+    pub combo: usize,
+    /// Whether the last clear was difficult, absent in version 1.
+    #[serde(default)]
+    pub back_to_back: bool,
+}
+
+impl<const H: usize, const W: usize> From<&Board<H, W>> for BoardState {
+    fn from(board: &Board<H, W>) -> Self {
+        let (grid, colors) = (board.get_grid(), board.get_color_grid());
+        let rows = (0..H)
+            .map(|y| {
+                (0..W)
+                    .map(|x| match colors[y][x] {
+                        _ if !grid[y][x] => '.',
+                        Some(c) => CELL_CHARS.get(c as usize).copied().unwrap_or('#'),
+                        None => '#',
+                    })
+                    .collect()
+            })
+            .collect();
+        BoardState {
+            version: BOARD_STATE_VERSION,
+            width: W,
+            height: H,
+            rows,
+            score: board.get_score(),
+            lines: board.get_lines(),
+            combo: board.get_combo(),
+            back_to_back: board.get_back_to_back(),
+        }
+    }
+}
+
+impl<const H: usize, const W: usize> From<Board<H, W>> for BoardState {
+    fn from(board: Board<H, W>) -> Self {
+        BoardState::from(&board)
+    }
+}
+
+impl<const H: usize, const W: usize> TryFrom<BoardState> for Board<H, W> {
+    type Error = String;
+
+    fn try_from(state: BoardState) -> Result<Self, String> {
+        if !(1..=BOARD_STATE_VERSION).contains(&state.version) {
+            return Err(tr!(
+                "不支持的棋盘版本 {}, 应为 {}",
+                "unsupported board version {}, expected {}",
+                state.version,
+                BOARD_STATE_VERSION
+            ));
+        }
+        if (state.width, state.height) != (W, H) {
+            return Err(tr!(
+                "棋盘为 {}x{}, 应为 {}x{}",
+                "board is {}x{}, expected {}x{}",
+                state.width,
+                state.height,
+                W,
+                H
+            ));
+        }
+        if state.rows.len() > H {
+            return Err(tr!(
+                "{} 行超出棋盘高度 {}",
+                "{} rows for a board of height {}",
+                state.rows.len(),
+                H
+            ));
+        }
+
+        for (y, row) in state.rows.iter().enumerate() {
+            if row.chars().count() != W {
+                return Err(tr!(
+                    "第 {} 行不是 {} 格宽: {}",
+                    "row {} is not {} cells wide: {}",
+                    y,
+                    W,
+                    row
+                ));
+            }
+            if let Some(c) = row.chars().find(|&c| c != '.' && !CELL_CHARS.contains(&c)) {
+                return Err(tr!(
+                    "未知格子 '{}', 位于第 {} 行",
+                    "unknown cell '{}' in row {}",
+                    c,
+                    y
+                ));
+            }
+        }
+
+        let rows: Vec<&str> = state.rows.iter().rev().map(String::as_str).collect();
+        let board = Self::from_rows(&rows)?
+            .with_progress(state.score, state.lines)
+            .with_chain(state.combo, state.back_to_back);
+        board.check_invariants(0)?;
+        Ok(board)
+    }
+}
+
+impl<const H: usize, const W: usize> Serialize for Board<H, W> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        BoardState::from(self).serialize(serializer)
+    }
+}
+
+impl<'de, const H: usize, const W: usize> Deserialize<'de> for Board<H, W> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let state = BoardState::deserialize(deserializer)?;
+        Board::try_from(state).map_err(serde::de::Error::custom)
+    }
+}
@@ -0,0 +1,158 @@
+use crate::board::{PlacementResult, Spin};
+use crate::i18n::tr;
+
+/// Where a game's points came from. Every scoring rule gets its own field,
+/// so that a change in the total can be told apart as a change in play or
+/// a change in the rules. The counts of the bonuses are of the placements
+/// that earned them, which the default rules give no points for.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct GameStats {
+    pub pieces: usize,
+    /// Number of single, double, triple, tetris and, with pentominoes,
+    /// five-line clears.
+    pub clears: [usize; 5],
+    /// Points from line clears, by the same sizes.
+    pub clear_points: [i64; 5],
+    /// T-spins, minis included, and their bonus points.
+    pub spins: usize,
+    pub spin_points: i64,
+    /// Clears that kept a combo going, and the combo bonus points.
+    pub combos: usize,
+    pub combo_points: i64,
+    /// Back-to-back clears, and the back-to-back bonus points.
+    pub back_to_backs: usize,
+    pub back_to_back_points: i64,
+    /// Clears that left the board empty, and the all clear bonus points.
+    pub all_clears: usize,
+    pub all_clear_points: i64,
+    pub drop_points: i64,
+    /// Points no rule explains, which only a score that saturated has.
+    pub other: i64,
+}
+
+impl GameStats {
+    /// Accounts for the placement `placed`.
+    pub fn record(&mut self, placed: &PlacementResult) {
+        self.pieces += 1;
+        let points = placed.points;
+        if let Some(size) = placed.lines.checked_sub(1).filter(|&s| s < 5) {
+            self.clears[size] += 1;
+            self.clear_points[size] = self.clear_points[size].saturating_add(points.lines);
+        }
+        self.spins += (placed.spin != Spin::None) as usize;
+        self.spin_points = self.spin_points.saturating_add(points.spin);
+        self.combos += (placed.combo >= 2) as usize;
+        self.combo_points = self.combo_points.saturating_add(points.combo);
+        self.back_to_backs += placed.back_to_back as usize;
+        self.back_to_back_points = self.back_to_back_points.saturating_add(points.back_to_back);
+        self.all_clears += placed.all_clear as usize;
+        self.all_clear_points = self.all_clear_points.saturating_add(points.all_clear);
+        self.drop_points = self.drop_points.saturating_add(points.drop);
+        self.other = self
+            .other
+            .saturating_add(placed.score_delta.saturating_sub(points.total()));
+    }
+
+    pub fn total(&self) -> i64 {
+        [
+            self.spin_points,
+            self.combo_points,
+            self.back_to_back_points,
+            self.all_clear_points,
+            self.drop_points,
+            self.other,
+        ]
+        .into_iter()
+        .chain(self.clear_points)
+        .fold(0, i64::saturating_add)
+    }
+
+    pub fn add(&mut self, other: &GameStats) {
+        self.pieces += other.pieces;
+        for i in 0..5 {
+            self.clears[i] += other.clears[i];
+            self.clear_points[i] = self.clear_points[i].saturating_add(other.clear_points[i]);
+        }
+        self.spins += other.spins;
+        self.spin_points = self.spin_points.saturating_add(other.spin_points);
+        self.combos += other.combos;
+        self.combo_points = self.combo_points.saturating_add(other.combo_points);
+        self.back_to_backs += other.back_to_backs;
+        self.back_to_back_points = self
+            .back_to_back_points
+            .saturating_add(other.back_to_back_points);
+        self.all_clears += other.all_clears;
+        self.all_clear_points = self.all_clear_points.saturating_add(other.all_clear_points);
+        self.drop_points = self.drop_points.saturating_add(other.drop_points);
+        self.other = self.other.saturating_add(other.other);
+    }
+
+    /// `(name, count, points)` rows for reports, the line clears first,
+    /// then the bonuses and the drops. Five-line clears only get a row
+    /// when there were some, so games of tetrominoes report as before.
+    pub fn components(&self) -> Vec<(String, usize, i64)> {
+        let names = [
+            tr!("单消", "Single"),
+            tr!("双消", "Double"),
+            tr!("三消", "Triple"),
+            tr!("四消", "Tetris"),
+            tr!("五消", "Pentris"),
+        ];
+        let mut rows: Vec<(String, usize, i64)> = names
+            .into_iter()
+            .enumerate()
+            .filter(|&(i, _)| i < 4 || self.clears[i] > 0)
+            .map(|(i, name)| (name, self.clears[i], self.clear_points[i]))
+            .collect();
+        rows.extend([
+            (tr!("T 旋", "T-spin"), self.spins, self.spin_points),
+            (tr!("连击", "Combo"), self.combos, self.combo_points),
+            (
+                tr!("背靠背", "Back-to-back"),
+                self.back_to_backs,
+                self.back_to_back_points,
+            ),
+            (
+                tr!("全清", "All clear"),
+                self.all_clears,
+                self.all_clear_points,
+            ),
+            (tr!("硬降", "Drop"), 0, self.drop_points),
+            (tr!("其他", "Other"), 0, self.other),
+        ]);
+        rows
+    }
+}
+
+/// `values` squeezed into at most `width` block characters, each showing
+/// the largest value of its stretch against `ceiling`.
+pub fn sparkline(values: &[usize], width: usize, ceiling: usize) -> String {
+    const BLOCKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    if values.is_empty() || width == 0 {
+        return String::new();
+    }
+    let columns = width.min(values.len());
+    (0..columns)
+        .map(|i| {
+            let stretch = &values[i * values.len() / columns..(i + 1) * values.len() / columns];
+            let value = stretch.iter().copied().max().unwrap_or(0).min(ceiling);
+            BLOCKS[value * (BLOCKS.len() - 1) / ceiling.max(1)]
+        })
+        .collect()
+}
+
+/// How clearly the policy preferred its best placement: the gap between
+/// the best and the second best distinct evaluation, `None` when all
+/// placements evaluate the same.
+pub fn decision_margin(scores: impl IntoIterator<Item = f64>) -> Option<f64> {
+    let (mut best, mut second) = (f64::INFINITY, f64::INFINITY);
+    for score in scores {
+        if score < best {
+            second = best;
+            best = score;
+        } else if score > best && score < second {
+            second = score;
+        }
+    }
+    second.is_finite().then_some(second - best)
+}
@@ -0,0 +1,156 @@
+//! The size of the terminal and how the board views use it.
+//!
+//! A board can be drawn one row per line, or two rows per line with half
+//! block characters, which fits the board and its stats in a 24-line
+//! terminal and leaves room beside it. The choice is made once for the
+//! whole process, like the theme: from `MORTIS_RENDER` and then the
+//! global `--render` flag. By default the views draw full rows and only
+//! switch to half blocks when the terminal is too small for them, instead
+//! of wrapping their lines and scrolling on every frame.
+
+use std::env;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use terminal_size::{Height, Width, terminal_size};
+
+use crate::theme::{Theme, palette, theme};
+
+/// A terminal size in character cells.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Size {
+    pub columns: usize,
+    pub rows: usize,
+}
+
+/// The size of the terminal stdout goes to, or what `COLUMNS` and `LINES`
+/// say when stdout is not one. `None` when neither tells.
+pub fn size() -> Option<Size> {
+    if let Some((Width(columns), Height(rows))) = terminal_size() {
+        return Some(Size {
+            columns: columns as usize,
+            rows: rows as usize,
+        });
+    }
+    let var = |name| env::var(name).ok()?.parse().ok().filter(|&n| n > 0);
+    Some(Size {
+        columns: var("COLUMNS")?,
+        rows: var("LINES")?,
+    })
+}
+
+/// Whether a view of `columns` by `rows` cells fits the terminal. Output
+/// of unknown size, such as a pipe, counts as fitting, so that logs keep
+/// the full rendering.
+pub fn fits(columns: usize, rows: usize) -> bool {
+    size().is_none_or(|size| size.columns >= columns && size.rows >= rows)
+}
+
+/// How the board views draw boards.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Rendering {
+    /// Full rows while they fit, half blocks otherwise.
+    #[default]
+    Auto,
+    /// One board row per line, whatever the terminal.
+    Full,
+    /// Two board rows per line.
+    Half,
+}
+
+pub const RENDERINGS: [Rendering; 3] = [Rendering::Auto, Rendering::Full, Rendering::Half];
+
+static RENDERING: AtomicU8 = AtomicU8::new(Rendering::Auto as u8);
+
+impl Rendering {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "auto" => Some(Rendering::Auto),
+            "full" => Some(Rendering::Full),
+            "half" => Some(Rendering::Half),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Rendering::Auto => "auto",
+            Rendering::Full => "full",
+            Rendering::Half => "half",
+        }
+    }
+
+    /// The rendering named by `MORTIS_RENDER`, if it names one.
+    pub fn from_env() -> Option<Self> {
+        env::var("MORTIS_RENDER").ok().and_then(|v| Self::parse(&v))
+    }
+}
+
+pub fn set_rendering(rendering: Rendering) {
+    RENDERING.store(rendering as u8, Ordering::Relaxed);
+}
+
+pub fn rendering() -> Rendering {
+    RENDERINGS
+        .get(RENDERING.load(Ordering::Relaxed) as usize)
+        .copied()
+        .unwrap_or_default()
+}
+
+/// Whether a view whose full rendering takes `columns` by `rows` cells
+/// draws its boards with `half_blocks` this time.
+pub fn half(columns: usize, rows: usize) -> bool {
+    match rendering() {
+        Rendering::Auto => !fits(columns, rows),
+        Rendering::Full => false,
+        Rendering::Half => true,
+    }
+}
+
+/// A `width` by `height` grid two rows to a line, top line first, each
+/// line `width` columns wide on screen. `cell(y, x)` is the color of the
+/// cell in row `y` from the bottom as a `color_grid` value, or `None`
+/// when it is empty. Two filled cells of different colors share a line
+/// as an upper half on a lower background; in the monochrome theme,
+/// which has no colors, as a full block.
+pub fn half_blocks(
+    width: usize,
+    height: usize,
+    cell: impl Fn(usize, usize) -> Option<Option<u8>>,
+) -> Vec<String> {
+    let palette = palette();
+    (0..height.div_ceil(2))
+        .rev()
+        .map(|line| {
+            let lower = 2 * line;
+            let mut text = String::new();
+            for x in 0..width {
+                let upper = (lower + 1 < height).then(|| cell(lower + 1, x)).flatten();
+                match (upper, cell(lower, x)) {
+                    (None, None) => {
+                        text.push(' ');
+                        continue;
+                    }
+                    (Some(color), None) => {
+                        text.push_str(palette.ansi(color));
+                        text.push('\u{2580}');
+                    }
+                    (None, Some(color)) => {
+                        text.push_str(palette.ansi(color));
+                        text.push('\u{2584}');
+                    }
+                    (Some(top), Some(bottom)) if top == bottom || theme() == Theme::Monochrome => {
+                        text.push_str(palette.ansi(top));
+                        text.push('\u{2588}');
+                    }
+                    (Some(top), Some(bottom)) => {
+                        let (r, g, b) = palette.rgb(bottom);
+                        text.push_str(palette.ansi(top));
+                        text.push_str(&format!("\x1B[48;2;{};{};{}m\u{2580}", r, g, b));
+                    }
+                }
+                text.push_str("\x1B[0m");
+            }
+            text
+        })
+        .collect()
+}
@@ -0,0 +1,199 @@
+//! Colors for drawing boards, in the terminal and in exported images.
+//!
+//! The theme is chosen once for the whole process, like the language:
+//! from `MORTIS_THEME` and then the global `--theme` flag. Everything that
+//! draws cells asks the current palette, so the previews and the image
+//! export always agree.
+
+use std::env;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+pub type Rgb = (u8, u8, u8);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Theme {
+    #[default]
+    Classic,
+    /// Saturated colors on black with a light grid.
+    HighContrast,
+    /// The Okabe-Ito palette, which stays distinguishable under the
+    /// common forms of color blindness.
+    Colorblind,
+    /// Shades of grey; the terminal output uses no colors at all.
+    Monochrome,
+}
+
+pub const THEMES: [Theme; 4] = [
+    Theme::Classic,
+    Theme::HighContrast,
+    Theme::Colorblind,
+    Theme::Monochrome,
+];
+
+static THEME: AtomicU8 = AtomicU8::new(Theme::Classic as u8);
+
+impl Theme {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "classic" => Some(Theme::Classic),
+            "high-contrast" => Some(Theme::HighContrast),
+            "colorblind" => Some(Theme::Colorblind),
+            "monochrome" => Some(Theme::Monochrome),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Theme::Classic => "classic",
+            Theme::HighContrast => "high-contrast",
+            Theme::Colorblind => "colorblind",
+            Theme::Monochrome => "monochrome",
+        }
+    }
+
+    /// The theme named by `MORTIS_THEME`, if it names one.
+    pub fn from_env() -> Option<Self> {
+        env::var("MORTIS_THEME").ok().and_then(|v| Self::parse(&v))
+    }
+
+    pub fn palette(self) -> &'static Palette {
+        match self {
+            Theme::Classic => &CLASSIC,
+            Theme::HighContrast => &HIGH_CONTRAST,
+            Theme::Colorblind => &COLORBLIND,
+            Theme::Monochrome => &MONOCHROME,
+        }
+    }
+}
+
+pub fn set_theme(theme: Theme) {
+    THEME.store(theme as u8, Ordering::Relaxed);
+}
+
+pub fn theme() -> Theme {
+    THEMES
+        .get(THEME.load(Ordering::Relaxed) as usize)
+        .copied()
+        .unwrap_or_default()
+}
+
+/// The palette of the current theme.
+pub fn palette() -> &'static Palette {
+    theme().palette()
+}
+
+/// Cell colors by `color_grid` value, in `PieceType` order, for images
+/// and for the terminal. Any other value (e.g. puzzle filler) gets the
+/// `other` color.
+#[derive(Debug)]
+pub struct Palette {
+    pub pieces: [Rgb; 7],
+    pub other: Rgb,
+    pub background: Rgb,
+    pub grid: Rgb,
+    pub ansi: [&'static str; 7],
+    pub ansi_other: &'static str,
+}
+
+impl Palette {
+    pub fn rgb(&self, color: Option<u8>) -> Rgb {
+        color
+            .and_then(|c| self.pieces.get(c as usize).copied())
+            .unwrap_or(self.other)
+    }
+
+    /// The escape sequence that starts a cell of this color; reset with
+    /// `\x1B[0m` afterwards.
+    pub fn ansi(&self, color: Option<u8>) -> &'static str {
+        color
+            .and_then(|c| self.ansi.get(c as usize).copied())
+            .unwrap_or(self.ansi_other)
+    }
+}
+
+const CLASSIC: Palette = Palette {
+    pieces: [
+        (0, 200, 220),
+        (160, 60, 220),
+        (230, 210, 0),
+        (40, 80, 230),
+        (240, 150, 0),
+        (40, 190, 60),
+        (220, 40, 40),
+    ],
+    other: (140, 140, 140),
+    background: (24, 24, 32),
+    grid: (60, 60, 72),
+    ansi: [
+        "\x1B[36m", "\x1B[35m", "\x1B[33m", "\x1B[34m", "\x1B[31m", "\x1B[32m", "\x1B[91m",
+    ],
+    ansi_other: "\x1B[37m",
+};
+
+const HIGH_CONTRAST: Palette = Palette {
+    pieces: [
+        (0, 255, 255),
+        (255, 0, 255),
+        (255, 255, 0),
+        (0, 110, 255),
+        (255, 140, 0),
+        (0, 255, 0),
+        (255, 0, 0),
+    ],
+    other: (255, 255, 255),
+    background: (0, 0, 0),
+    grid: (128, 128, 128),
+    ansi: [
+        "\x1B[1;96m",
+        "\x1B[1;95m",
+        "\x1B[1;93m",
+        "\x1B[1;94m",
+        "\x1B[1;38;5;208m",
+        "\x1B[1;92m",
+        "\x1B[1;91m",
+    ],
+    ansi_other: "\x1B[1;97m",
+};
+
+const COLORBLIND: Palette = Palette {
+    pieces: [
+        (86, 180, 233),
+        (204, 121, 167),
+        (240, 228, 66),
+        (0, 114, 178),
+        (230, 159, 0),
+        (0, 158, 115),
+        (213, 94, 0),
+    ],
+    other: (153, 153, 153),
+    background: (24, 24, 32),
+    grid: (60, 60, 72),
+    ansi: [
+        "\x1B[38;2;86;180;233m",
+        "\x1B[38;2;204;121;167m",
+        "\x1B[38;2;240;228;66m",
+        "\x1B[38;2;0;114;178m",
+        "\x1B[38;2;230;159;0m",
+        "\x1B[38;2;0;158;115m",
+        "\x1B[38;2;213;94;0m",
+    ],
+    ansi_other: "\x1B[38;2;153;153;153m",
+};
+
+const MONOCHROME: Palette = Palette {
+    pieces: [
+        (240, 240, 240),
+        (200, 200, 200),
+        (225, 225, 225),
+        (110, 110, 110),
+        (160, 160, 160),
+        (185, 185, 185),
+        (135, 135, 135),
+    ],
+    other: (80, 80, 80),
+    background: (0, 0, 0),
+    grid: (48, 48, 48),
+    ansi: [""; 7],
+    ansi_other: "",
+};
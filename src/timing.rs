@@ -0,0 +1,80 @@
+use std::time::Duration;
+
+/// One frame at the NTSC refresh rate the classic gravity table assumes.
+pub const FRAME: Duration = Duration::from_micros(16_639);
+
+/// Frames between two simulated key presses, about 7 presses a second.
+pub const FRAMES_PER_INPUT: u32 = 8;
+
+/// Frames per row of gravity at each level, as in the NES version. Level
+/// 29 and up drop one row every frame.
+pub const GRAVITY: [u32; 29] = [
+    48, 43, 38, 33, 28, 23, 18, 13, 8, 6, 5, 5, 5, 4, 4, 4, 3, 3, 3, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2,
+];
+
+/// Frames per row of gravity at `level`.
+pub fn gravity(level: usize) -> u32 {
+    GRAVITY.get(level).copied().unwrap_or(1)
+}
+
+/// Pauses a game engine adds between pieces, in frames. Rollouts place the
+/// next piece the moment the last one locks, which real games never do, so
+/// anything that talks about pieces per second or a time budget per piece
+/// should go through one of these.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Timing {
+    /// Entry delay (ARE): frames from a lock until the next piece spawns.
+    pub entry_delay: u32,
+    /// Extra frames a lock that clears lines waits for the rows to vanish.
+    pub line_clear_delay: u32,
+}
+
+impl Timing {
+    /// The rollout model: no pauses at all.
+    pub const INSTANT: Timing = Timing {
+        entry_delay: 0,
+        line_clear_delay: 0,
+    };
+
+    /// Close to the NES version, which waits 10 to 18 frames depending on
+    /// the lock height and about 18 more when lines clear. The lowest entry
+    /// delay is used, as the board here is shorter.
+    pub const NES: Timing = Timing {
+        entry_delay: 10,
+        line_clear_delay: 18,
+    };
+
+    /// Accepts `instant`, `nes` or `<entry>,<line clear>` in frames.
+    pub fn parse(spec: &str) -> Option<Self> {
+        match spec {
+            "instant" => Some(Timing::INSTANT),
+            "nes" => Some(Timing::NES),
+            _ => {
+                let (entry, clear) = spec.split_once(',')?;
+                Some(Timing {
+                    entry_delay: entry.trim().parse().ok()?,
+                    line_clear_delay: clear.trim().parse().ok()?,
+                })
+            }
+        }
+    }
+
+    /// Frames between a lock that cleared `cleared` lines and the next
+    /// spawn.
+    pub fn after_lock(&self, cleared: usize) -> u32 {
+        if cleared > 0 {
+            self.entry_delay + self.line_clear_delay
+        } else {
+            self.entry_delay
+        }
+    }
+}
+
+/// Pieces per second over `frames` frames of play.
+pub fn pieces_per_second(pieces: usize, frames: u64) -> f64 {
+    if frames == 0 {
+        0.0
+    } else {
+        pieces as f64 / (frames as f64 * FRAME.as_secs_f64())
+    }
+}
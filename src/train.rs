@@ -0,0 +1,2042 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, Once, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use cmaes::{CMAES, CMAESOptions, DVector, Individual, Mode, PlotOptions};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::anomaly::{AnomalyAction, AnomalyDetector, report};
+use crate::board::{BOARD_HEIGHT, BOARD_WIDTH, Board, FEATURES, PlacementResult, WEIGHTS};
+use crate::checkpoint::{Checkpoint, EvaluationLog, SamplerState, WarmStart};
+use crate::clips::{ClipRecorder, Clips};
+use crate::dimensions::{Dimensions, with_dimensions};
+use crate::episode::MAX_PIECES;
+use crate::evaluator::{Evaluator, Model, ModelKind, NonFinite, NonFiniteCounts, NonFiniteGuard};
+use crate::features::BONUS_FEATURE_NAMES;
+use crate::garbage::GarbageConfig;
+use crate::generator::{PieceGenerator, Randomizer};
+use crate::hold::HoldSlot;
+use crate::i18n::{say, tr};
+use crate::logging;
+use crate::logging::{log_debug, log_error, log_info, log_warn};
+use crate::mlp::{HIDDEN, MlpEvaluator};
+use crate::mode::{GameMode, Progress};
+use crate::moves::Moves;
+use crate::piece::{BuiltinSet, PieceSet};
+use crate::policy::{Visibility, best_action_with_hold_in, best_move_under_in, choose_in};
+use crate::pool::{Schedule, play_all};
+use crate::report::{EntryResult, play_recorded, tournament_html};
+use crate::scoring::{AllClearBonus, ChainBonus, ScoringRules};
+use crate::versus::{
+    AttackTable, MATCH_PIECES, Objective, PolicyPlayer, play_match_from, sequence,
+};
+use crate::weights::{compatibility_line, format_model, load_models};
+
+/// Seeds the elite re-evaluations are played on, so that validation
+/// scores from different generations are comparable.
+pub(crate) const VALIDATION_SEED: u64 = 0x4d4f_5254_4953;
+
+/// Default games per candidate in the population: cheap and noisy.
+pub const POPULATION_GAMES: usize = 100;
+
+/// Default games behind the reported best score.
+pub const ELITE_GAMES: usize = 1000;
+
+pub struct TrainOptions {
+    pub generations: usize,
+    pub target: f64,
+    pub run_dir: PathBuf,
+    pub hof_size: usize,
+    /// Games that rank candidates for the hall of fame.
+    pub validation_games: usize,
+    /// Games per fitness evaluation of a candidate in the population.
+    pub population_games: usize,
+    /// Games the best vector of the hall of fame is re-evaluated on. The
+    /// reported best score and the target check use this evaluation.
+    pub elite_games: usize,
+    /// Pieces after which a training or validation game stops.
+    pub max_pieces: usize,
+    /// The pieces the games are played with.
+    pub pieces: BuiltinSet,
+    /// How the games draw their pieces, parsed for `pieces`.
+    pub randomizer: Randomizer,
+    /// Play every game with a hold slot.
+    pub hold: bool,
+    /// Whether the policy looks ahead to the next piece in training and
+    /// validation games. Ignored with `hold`.
+    pub visibility: Visibility,
+    /// The placements the policy chooses from. With `Moves::Full` it sees
+    /// only the current piece and does not hold, whatever `hold` and
+    /// `visibility` say.
+    pub moves: Moves,
+    /// Weight of the novelty bonus in the fitness, 0 disables it.
+    pub novelty: f64,
+    pub bounds: FeatureBounds,
+    /// Checkpoint file or run directory to start the search from.
+    pub warm_start: Option<PathBuf>,
+    /// Also write diagnostics to `train.log` in the run directory.
+    pub save_log: bool,
+    /// Share of the detected cores used to evaluate candidates.
+    pub cpu_fraction: f64,
+    /// Seed of the CMA-ES sampler; the rollouts are seeded from it too.
+    pub seed: Option<u64>,
+    /// Continue the run in `run_dir` from its evaluation log.
+    pub resume: bool,
+    /// What to do when the search blows up, see `AnomalyDetector`.
+    pub on_anomaly: AnomalyAction,
+    /// What to do about NaN and infinite weights and evaluations.
+    pub non_finite: NonFinite,
+    /// What to save of every new best, see `clips`.
+    pub clips: Clips,
+    /// Size of the board the games are played on. Set from the global
+    /// `--width` and `--height`.
+    pub dimensions: Dimensions,
+    /// Garbage pushed into every training and validation game, none by
+    /// default.
+    pub garbage: Option<GarbageConfig>,
+    /// What the games are played for; with a goal the fitness is how fast
+    /// they reach it rather than the score.
+    pub mode: GameMode,
+    /// What the candidates are ranked by. The versus objectives play
+    /// each candidate against the leader of the hall of fame.
+    pub objective: Objective,
+    /// The attack table of the versus objectives.
+    pub attack: AttackTable,
+    /// What kind of evaluator is trained.
+    pub model: ModelKind,
+    /// How the training and validation games score. With rules that give
+    /// bonuses, `ModelKind::Bonus` lets the weights see the chain.
+    pub scoring: ScoringRules,
+}
+
+impl TrainOptions {
+    /// Parses `[generations] [target]` followed by optional flags.
+    pub fn from_args(args: &[String]) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let mut options = TrainOptions {
+            generations: 20,
+            target: 1_000_000.0,
+            run_dir: PathBuf::from(format!("runs/{}", timestamp)),
+            hof_size: 10,
+            validation_games: 100,
+            population_games: POPULATION_GAMES,
+            elite_games: ELITE_GAMES,
+            max_pieces: MAX_PIECES,
+            pieces: BuiltinSet::default(),
+            randomizer: Randomizer::default(),
+            hold: false,
+            visibility: Visibility::default(),
+            moves: Moves::default(),
+            novelty: 0.0,
+            bounds: FeatureBounds::default(),
+            warm_start: None,
+            save_log: false,
+            cpu_fraction: 1.0,
+            seed: None,
+            resume: false,
+            on_anomaly: AnomalyAction::default(),
+            non_finite: NonFinite::default(),
+            clips: Clips::default(),
+            dimensions: Dimensions::default(),
+            garbage: None,
+            mode: GameMode::default(),
+            objective: Objective::default(),
+            attack: AttackTable::default(),
+            model: ModelKind::default(),
+            scoring: ScoringRules::default(),
+        };
+
+        let mut positional = 0;
+        let mut randomizer = None;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--run-dir" => {
+                    if let Some(dir) = args.next() {
+                        options.run_dir = PathBuf::from(dir);
+                    }
+                }
+                "--hof-size" => {
+                    if let Some(k) = args.next().and_then(|k| k.parse().ok()) {
+                        options.hof_size = k;
+                    }
+                }
+                "--validation-games" => {
+                    if let Some(n) = args.next().and_then(|n| n.parse().ok()) {
+                        options.validation_games = n;
+                    }
+                }
+                "--population-games" => {
+                    if let Some(n) = args.next().and_then(|n| n.parse().ok()).filter(|&n| n > 0) {
+                        options.population_games = n;
+                    }
+                }
+                "--elite-games" => {
+                    if let Some(n) = args.next().and_then(|n| n.parse().ok()).filter(|&n| n > 0) {
+                        options.elite_games = n;
+                    }
+                }
+                "--max-pieces" => {
+                    if let Some(n) = args.next().and_then(|n| n.parse().ok()).filter(|&n| n > 0) {
+                        options.max_pieces = n;
+                    }
+                }
+                "--piece-set" => match args.next().and_then(|p| BuiltinSet::parse(p)) {
+                    Some(pieces) => options.pieces = pieces,
+                    None => log_warn!(
+                        "--piece-set 需要 tetromino 或 pentomino",
+                        "--piece-set expects tetromino or pentomino"
+                    ),
+                },
+                "--randomizer" => randomizer = args.next(),
+                "--novelty" => {
+                    if let Some(w) = args.next().and_then(|w| w.parse().ok()) {
+                        options.novelty = w;
+                    }
+                }
+                "--on-anomaly" => match args.next().and_then(|a| AnomalyAction::parse(a)) {
+                    Some(action) => options.on_anomaly = action,
+                    None => log_warn!(
+                        "--on-anomaly 需要 stop、rollback 或 ignore",
+                        "--on-anomaly expects stop, rollback or ignore"
+                    ),
+                },
+                "--non-finite" => match args.next().and_then(|m| NonFinite::parse(m)) {
+                    Some(mode) => options.non_finite = mode,
+                    None => log_warn!(
+                        "--non-finite 需要 skip、clamp 或 error",
+                        "--non-finite expects skip, clamp or error"
+                    ),
+                },
+                "--clips" => match args.next().and_then(|c| Clips::parse(c)) {
+                    Some(clips) => options.clips = clips,
+                    None => log_warn!(
+                        "--clips 需要 off、replay 或 gif",
+                        "--clips expects off, replay or gif"
+                    ),
+                },
+                "--garbage" => match GarbageConfig::parse(args.next().map_or("", |g| g.as_str())) {
+                    Ok(garbage) => options.garbage = Some(garbage),
+                    Err(e) => log_warn!("忽略 --garbage: {}", "Ignoring --garbage: {}", e),
+                },
+                "--mode" => match GameMode::parse(args.next().map_or("", |m| m.as_str())) {
+                    Ok(mode) => options.mode = mode,
+                    Err(e) => log_warn!("忽略 --mode: {}", "Ignoring --mode: {}", e),
+                },
+                "--objective" => match args.next().and_then(|o| Objective::parse(o)) {
+                    Some(objective) => options.objective = objective,
+                    None => log_warn!(
+                        "--objective 需要 score、wins 或 damage",
+                        "--objective expects score, wins or damage"
+                    ),
+                },
+                "--attack" => match AttackTable::parse(args.next().map_or("", |a| a.as_str())) {
+                    Ok(attack) => options.attack = attack,
+                    Err(e) => log_warn!("忽略 --attack: {}", "Ignoring --attack: {}", e),
+                },
+                "--scoring" => match args.next().map(|s| ScoringRules::load(s)) {
+                    Some(Ok(scoring)) => options.scoring = scoring,
+                    Some(Err(e)) => log_warn!("忽略 --scoring: {}", "Ignoring --scoring: {}", e),
+                    None => log_warn!(
+                        "--scoring 需要 guideline 或规则文件",
+                        "--scoring expects guideline or a rules file"
+                    ),
+                },
+                "--model" => match args.next().and_then(|m| ModelKind::parse(m)) {
+                    Some(model) => options.model = model,
+                    None => log_warn!(
+                        "--model 需要 linear、mlp 或 bonus",
+                        "--model expects linear, mlp or bonus"
+                    ),
+                },
+                "--hold" => options.hold = true,
+                "--visibility" => match args.next().and_then(|v| Visibility::parse(v)) {
+                    Some(visibility) => options.visibility = visibility,
+                    None => log_warn!(
+                        "--visibility 需要 current、next 或 expected",
+                        "--visibility expects current, next or expected"
+                    ),
+                },
+                "--moves" => match args.next().and_then(|m| Moves::parse(m)) {
+                    Some(moves) => options.moves = moves,
+                    None => log_warn!(
+                        "--moves 需要 drop、full 或 full-180",
+                        "--moves expects drop, full or full-180"
+                    ),
+                },
+                "--save-log" => options.save_log = true,
+                "--seed" => {
+                    if let Some(seed) = args.next().and_then(|s| s.parse().ok()) {
+                        options.seed = Some(seed);
+                    }
+                }
+                "--resume" => {
+                    if let Some(dir) = args.next() {
+                        options.run_dir = PathBuf::from(dir);
+                        options.resume = true;
+                    }
+                }
+                "--cpu-fraction" => {
+                    if let Some(f) = args
+                        .next()
+                        .and_then(|f| f.parse::<f64>().ok())
+                        .filter(|f| *f > 0.0 && *f <= 1.0)
+                    {
+                        options.cpu_fraction = f;
+                    }
+                }
+                "--warm-start" => {
+                    if let Some(path) = args.next() {
+                        options.warm_start = Some(PathBuf::from(path));
+                    }
+                }
+                "--freeze" | "--sign" | "--bound" => {
+                    let rule = args.next().map_or("", |r| r.as_str());
+                    if let Err(e) = options.bounds.add_rule(arg, rule) {
+                        log_warn!(
+                            "忽略约束 {} {}: {}",
+                            "Ignoring constraint {} {}: {}",
+                            arg,
+                            rule,
+                            e
+                        );
+                    }
+                }
+                _ => {
+                    match positional {
+                        0 => options.generations = arg.parse().unwrap_or(20),
+                        1 => options.target = arg.parse().unwrap_or(1_000_000.0),
+                        _ => log_warn!("忽略未知参数: {}", "Ignoring unknown argument: {}", arg),
+                    }
+                    positional += 1;
+                }
+            }
+        }
+        // The sequence letters depend on the set, wherever --piece-set was
+        if let Some(spec) = randomizer {
+            match Randomizer::parse(spec, options.pieces.set()) {
+                Ok(randomizer) => options.randomizer = randomizer,
+                Err(e) => log_warn!("忽略 --randomizer: {}", "Ignoring --randomizer: {}", e),
+            }
+        }
+        options
+    }
+}
+
+/// Candidates evaluated per worker thread each generation. Scaling the
+/// population with the thread count keeps the wall time of a generation
+/// about the same on every machine; 16 threads give the old 240.
+const CANDIDATES_PER_THREAD: usize = 15;
+
+/// Smallest population worth running, a bit above the CMA-ES default of
+/// `4 + 3 ln n` for 13 features.
+const MIN_POPULATION: usize = 16;
+
+/// Number of worker threads to use given the share of cores allowed.
+fn worker_threads(cpu_fraction: f64) -> usize {
+    let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+    ((cores as f64 * cpu_fraction).round() as usize).clamp(1, cores)
+}
+
+/// Per-feature limits on the weights, applied to every candidate before it
+/// is evaluated. They act on the normalized weights the policy actually
+/// plays with, so a frozen value is relative to a unit-length vector.
+#[derive(Debug, Clone, Copy)]
+pub struct FeatureBounds {
+    ranges: [(f64, f64); FEATURES],
+}
+
+impl Default for FeatureBounds {
+    fn default() -> Self {
+        FeatureBounds {
+            ranges: [(f64::NEG_INFINITY, f64::INFINITY); FEATURES],
+        }
+    }
+}
+
+impl FeatureBounds {
+    /// Adds one command line rule: `--freeze i=v`, `--sign i=+` / `i=-`, or
+    /// `--bound i=lo:hi` where either end may be left empty.
+    pub fn add_rule(&mut self, flag: &str, rule: &str) -> Result<(), String> {
+        let (index, value) = rule
+            .split_once('=')
+            .ok_or_else(|| tr!("格式应为 <特征>=<值>", "Expected <feature>=<value>"))?;
+        let index: usize = index
+            .parse()
+            .ok()
+            .filter(|&i| i < FEATURES)
+            .ok_or_else(|| {
+                tr!(
+                    "特征编号应在 0..{} 之间",
+                    "Feature index should be in 0..{}",
+                    FEATURES
+                )
+            })?;
+        let parse = |v: &str, default: f64| -> Result<f64, String> {
+            if v.is_empty() {
+                Ok(default)
+            } else {
+                v.parse()
+                    .map_err(|_| tr!("无效的数值: {}", "Invalid number: {}", v))
+            }
+        };
+
+        let range = match flag {
+            "--freeze" => {
+                let v = parse(value, f64::NAN)?;
+                (v, v)
+            }
+            "--sign" => match value {
+                "+" => (0.0, f64::INFINITY),
+                "-" => (f64::NEG_INFINITY, 0.0),
+                _ => return Err(tr!("符号应为 + 或 -", "Sign should be + or -")),
+            },
+            _ => {
+                let (lo, hi) = value
+                    .split_once(':')
+                    .ok_or_else(|| tr!("范围格式应为 lo:hi", "Range should be lo:hi"))?;
+                (parse(lo, f64::NEG_INFINITY)?, parse(hi, f64::INFINITY)?)
+            }
+        };
+        if range.0.is_nan() || range.1.is_nan() || range.0 > range.1 {
+            return Err(tr!("无效的范围", "Invalid range"));
+        }
+        self.ranges[index] = range;
+        Ok(())
+    }
+
+    pub fn is_unbounded(&self) -> bool {
+        self.ranges
+            .iter()
+            .all(|&(lo, hi)| lo == f64::NEG_INFINITY && hi == f64::INFINITY)
+    }
+
+    /// Clamps every weight into its range.
+    pub fn project(&self, weights: &[f64; FEATURES]) -> [f64; FEATURES] {
+        let mut projected = *weights;
+        for (w, &(lo, hi)) in projected.iter_mut().zip(self.ranges.iter()) {
+            *w = w.clamp(lo, hi);
+        }
+        projected
+    }
+}
+
+/// How points sampled by CMA-ES become the weights they are played with.
+/// The warm start and the bounds only apply to linear weights; the
+/// parameters of a network or of weights with bonus terms are the point
+/// itself.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SearchSpace {
+    pub warm_start: WarmStart,
+    pub bounds: FeatureBounds,
+    pub kind: ModelKind,
+}
+
+impl SearchSpace {
+    pub fn weights(&self, point: &[f64]) -> [f64; FEATURES] {
+        self.bounds
+            .project(&normalize(&self.warm_start.to_weights(point)))
+    }
+
+    /// The model played for `point`, of `kind`.
+    pub fn model(&self, point: &[f64]) -> Model {
+        match self.kind {
+            ModelKind::Linear => Model::Linear(self.weights(point)),
+            ModelKind::Mlp => Model::Mlp(MlpEvaluator::from_parameters(point).unwrap()),
+            ModelKind::Bonus => Model::Bonus(point.try_into().unwrap()),
+        }
+    }
+
+    /// The search distribution in the space of the models, for saving.
+    pub fn checkpoint(
+        &self,
+        sigma: f64,
+        mean: &[f64],
+        cov: impl Fn(usize, usize) -> f64,
+    ) -> Checkpoint {
+        match self.kind {
+            ModelKind::Linear => self.warm_start.checkpoint(sigma, mean, cov),
+            ModelKind::Mlp | ModelKind::Bonus => Checkpoint {
+                sigma,
+                mean: mean.to_vec(),
+                cov: (0..mean.len())
+                    .map(|i| (0..mean.len()).map(|j| cov(i, j)).collect())
+                    .collect(),
+                sampler: None,
+            },
+        }
+    }
+}
+
+/// Projected number of further generations until the target is reached,
+/// with a rough 95% range. `high` is `None` when the slow end of the range
+/// never gets there.
+#[derive(Debug, Clone, Copy)]
+pub struct Eta {
+    pub generations: f64,
+    pub low: f64,
+    pub high: Option<f64>,
+}
+
+/// Fits a line to the validated best score of the last generations and
+/// extrapolates it to the target.
+pub struct TargetTracker {
+    target: f64,
+    window: usize,
+    history: Vec<f64>,
+}
+
+impl TargetTracker {
+    pub fn new(target: f64, window: usize) -> Self {
+        TargetTracker {
+            target,
+            window: window.max(3),
+            history: Vec::new(),
+        }
+    }
+
+    /// Adds this generation's score and returns the projection, or `None`
+    /// while there are too few points or the trend is not upwards.
+    pub fn record(&mut self, score: f64) -> Option<Eta> {
+        self.history.push(score);
+        let recent = &self.history[self.history.len().saturating_sub(self.window)..];
+        let n = recent.len() as f64;
+        if recent.len() < 3 {
+            return None;
+        }
+
+        // Least squares slope and its standard error
+        let mean_x = (n - 1.0) / 2.0;
+        let mean_y = recent.iter().sum::<f64>() / n;
+        let sxx: f64 = (0..recent.len()).map(|i| (i as f64 - mean_x).powi(2)).sum();
+        let sxy: f64 = recent
+            .iter()
+            .enumerate()
+            .map(|(i, y)| (i as f64 - mean_x) * (y - mean_y))
+            .sum();
+        let slope = sxy / sxx;
+        let residuals: f64 = recent
+            .iter()
+            .enumerate()
+            .map(|(i, y)| (y - mean_y - slope * (i as f64 - mean_x)).powi(2))
+            .sum();
+        let error = (residuals / (n - 2.0) / sxx).sqrt();
+
+        let remaining = self.target - score;
+        if remaining <= 0.0 {
+            return Some(Eta {
+                generations: 0.0,
+                low: 0.0,
+                high: Some(0.0),
+            });
+        }
+        if slope <= 0.0 {
+            return None;
+        }
+        let slow = slope - 2.0 * error;
+        Some(Eta {
+            generations: remaining / slope,
+            low: remaining / (slope + 2.0 * error),
+            high: (slow > 0.0).then(|| remaining / slow),
+        })
+    }
+}
+
+/// The best distinct weight vectors or networks seen during a run, ranked
+/// by a re-evaluation on fixed seeds rather than by the noisy training
+/// fitness.
+pub struct HallOfFame {
+    capacity: usize,
+    validation_games: usize,
+    elite_games: usize,
+    max_pieces: usize,
+    pieces: BuiltinSet,
+    randomizer: Randomizer,
+    hold: bool,
+    visibility: Visibility,
+    moves: Moves,
+    dimensions: Dimensions,
+    garbage: Option<GarbageConfig>,
+    mode: GameMode,
+    scoring: ScoringRules,
+    space: SearchSpace,
+    entries: Vec<(f64, Model)>,
+    /// The leading entry re-evaluated on `elite_games` games.
+    elite: Option<(f64, Model)>,
+}
+
+impl HallOfFame {
+    pub fn new(
+        capacity: usize,
+        validation_games: usize,
+        elite_games: usize,
+        max_pieces: usize,
+        pieces: BuiltinSet,
+        randomizer: Randomizer,
+        space: SearchSpace,
+    ) -> Self {
+        HallOfFame {
+            capacity,
+            validation_games,
+            elite_games,
+            max_pieces,
+            pieces,
+            randomizer,
+            hold: false,
+            visibility: Visibility::Current,
+            moves: Moves::Drop,
+            dimensions: Dimensions::default(),
+            garbage: None,
+            mode: GameMode::Endless,
+            scoring: ScoringRules::default(),
+            space,
+            entries: Vec::new(),
+            elite: None,
+        }
+    }
+
+    /// Validates with a hold slot, as the training games are played.
+    pub fn with_hold(mut self, hold: bool) -> Self {
+        self.hold = hold;
+        self
+    }
+
+    /// Validates with the policy seeing `visibility` of the queue, as the
+    /// training games are played.
+    pub fn with_visibility(mut self, visibility: Visibility) -> Self {
+        self.visibility = visibility;
+        self
+    }
+
+    /// Validates with the policy choosing from `moves`, as the training
+    /// games are played.
+    pub fn with_moves(mut self, moves: Moves) -> Self {
+        self.moves = moves;
+        self
+    }
+
+    /// Validates on a board of `dimensions`, as the training games are
+    /// played.
+    pub fn with_dimensions(mut self, dimensions: Dimensions) -> Self {
+        self.dimensions = dimensions;
+        self
+    }
+
+    /// Validates with `garbage` pushed into every game, as the training
+    /// games are played.
+    pub fn with_garbage(mut self, garbage: Option<GarbageConfig>) -> Self {
+        self.garbage = garbage;
+        self
+    }
+
+    /// Validates games of `mode`, as the training games are played, so
+    /// the entries rank by the training objective.
+    pub fn with_mode(mut self, mode: GameMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Validates games scored by `rules`, as the training games are.
+    pub fn with_scoring(mut self, rules: ScoringRules) -> Self {
+        self.scoring = rules;
+        self
+    }
+
+    /// Average score over `games` games played on the fixed validation
+    /// seeds. The games are independent, so they are spread over the pool
+    /// one by one.
+    fn validation_score(&self, model: &Model, games: usize) -> f64 {
+        let set = self.pieces.set();
+        let config = GameConfig {
+            max_pieces: self.max_pieces,
+            visibility: self.visibility,
+            hold: self.hold,
+            moves: self.moves,
+        };
+        let (scores, _) = play_all(&[model], games, Schedule::Game, |model, i| {
+            with_dimensions!(self.dimensions, |H, W| play_game_seeing::<H, W, _>(
+                *model,
+                &mut *validation_pieces(i, set, &self.randomizer),
+                set,
+                &config,
+                &mut validation_progress(i, self.mode, self.garbage).with_scoring(self.scoring),
+            ))
+        });
+        mean(&scores[0])
+    }
+
+    /// Re-evaluates `individual` and keeps it if it is among the best.
+    /// Weight vectors pointing in nearly the same direction count as one,
+    /// since the policy only depends on the direction of the weights; a
+    /// network only counts as one already there if it is the same.
+    pub fn consider(&mut self, individual: &Individual) {
+        let model = self.space.model(individual.point.as_slice());
+        // Nothing to rank, whatever the training did with them
+        if model.parameters().iter().any(|w| !w.is_finite()) {
+            return;
+        }
+        if self.entries.iter().any(|(_, entry)| match (entry, &model) {
+            (Model::Linear(a), Model::Linear(b)) => dot(a, b) > 0.9999,
+            _ => *entry == model,
+        }) {
+            return;
+        }
+
+        let score = self.validation_score(&model, self.validation_games);
+        log_debug!(
+            "名人堂候选验证分数 {:.2}",
+            "Hall of fame candidate validation score {:.2}",
+            score
+        );
+        self.entries.push((score, model));
+        self.entries.sort_by(|a, b| b.0.total_cmp(&a.0));
+        self.entries.truncate(self.capacity);
+
+        let leader = self.entries[0].1.clone();
+        if self
+            .elite
+            .as_ref()
+            .is_none_or(|(_, elite)| *elite != leader)
+        {
+            let score = self.validation_score(&leader, self.elite_games);
+            log_debug!(
+                "名人堂新的最佳权重, {} 局重新评估分数 {:.2}",
+                "New hall of fame leader, re-evaluated on {} games: {:.2}",
+                self.elite_games,
+                score
+            );
+            self.elite = Some((score, leader));
+        }
+    }
+
+    /// The leader's score on the elite budget, which is what the run
+    /// reports as its best.
+    pub fn best_score(&self) -> Option<f64> {
+        self.elite.as_ref().map(|(score, _)| *score)
+    }
+
+    /// The leader and its score on the elite budget.
+    pub fn elite(&self) -> Option<(f64, &Model)> {
+        self.elite.as_ref().map(|(score, model)| (*score, model))
+    }
+
+    pub fn save(&self, path: &Path) {
+        let mut text = tr!(
+            "# 名人堂: 按验证分数排序的权重\n",
+            "# Hall of fame: weights ranked by validation score\n"
+        );
+        text.push_str(&compatibility_line());
+        if let Some((score, _)) = self.elite() {
+            text.push_str(&tr!(
+                "# 第一组在 {} 局上重新评估的分数 {:.2}\n",
+                "# the first one scores {1:.2} when re-evaluated on {0} games\n",
+                self.elite_games,
+                score
+            ));
+        }
+        for (score, model) in &self.entries {
+            text.push_str(&tr!(
+                "# 验证分数 {:.2}\n",
+                "# validation score {:.2}\n",
+                score
+            ));
+            text.push_str(&format_model(model));
+            text.push('\n');
+        }
+        if let Err(e) = fs::write(path, text) {
+            log_error!(
+                "无法保存名人堂 {}: {}",
+                "Cannot save hall of fame {}: {}",
+                path.display(),
+                e
+            );
+        }
+    }
+}
+
+/// How a policy plays, independent of how well: used to tell playing
+/// styles apart.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Behavior {
+    /// Mean stack height after each placement, as a fraction of the board.
+    pub stack_height: f64,
+    /// Mean height difference between neighbouring columns, as a fraction
+    /// of the board height.
+    pub bumpiness: f64,
+    /// Share of line clears that were singles, doubles, triples and tetrises.
+    pub clears: [f64; 4],
+}
+
+impl Behavior {
+    fn descriptor(&self) -> [f64; 6] {
+        let [c1, c2, c3, c4] = self.clears;
+        [self.stack_height, self.bumpiness, c1, c2, c3, c4]
+    }
+
+    pub fn distance(&self, other: &Behavior) -> f64 {
+        self.descriptor()
+            .iter()
+            .zip(other.descriptor().iter())
+            .map(|(a, b)| (a - b).powi(2))
+            .sum::<f64>()
+            .sqrt()
+    }
+
+    pub fn mean(behaviors: &[Behavior]) -> Behavior {
+        let n = behaviors.len().max(1) as f64;
+        let mut mean = Behavior::default();
+        for b in behaviors {
+            mean.stack_height += b.stack_height / n;
+            mean.bumpiness += b.bumpiness / n;
+            for i in 0..4 {
+                mean.clears[i] += b.clears[i] / n;
+            }
+        }
+        mean
+    }
+}
+
+/// Behaviors of previously evaluated candidates. A candidate's novelty is
+/// its mean distance to the `k` nearest of them.
+pub struct NoveltyArchive {
+    entries: Mutex<Vec<Behavior>>,
+    capacity: usize,
+    k: usize,
+}
+
+impl NoveltyArchive {
+    pub fn new(capacity: usize, k: usize) -> Self {
+        NoveltyArchive {
+            entries: Mutex::new(Vec::new()),
+            capacity,
+            k,
+        }
+    }
+
+    /// Scores `behavior` against the archive, then adds it, evicting the
+    /// oldest entry once full.
+    pub fn novelty_and_insert(&self, behavior: Behavior) -> f64 {
+        let mut entries = self.entries.lock().unwrap();
+        let mut distances: Vec<f64> = entries.iter().map(|b| b.distance(&behavior)).collect();
+        distances.sort_by(f64::total_cmp);
+        let nearest = &distances[..distances.len().min(self.k)];
+        let novelty = if nearest.is_empty() {
+            0.0
+        } else {
+            nearest.iter().sum::<f64>() / nearest.len() as f64
+        };
+
+        if entries.len() >= self.capacity {
+            entries.remove(0);
+        }
+        entries.push(behavior);
+        novelty
+    }
+}
+
+pub(crate) fn normalize(point: &[f64]) -> [f64; FEATURES] {
+    let mut weights_array = [0.0; FEATURES];
+    weights_array.copy_from_slice(&point[..FEATURES]);
+
+    let norm = weights_array.iter().map(|w| w.powi(2)).sum::<f64>().sqrt();
+    if norm > 0.0 {
+        for w in &mut weights_array {
+            *w /= norm;
+        }
+    }
+    weights_array
+}
+
+fn dot(a: &[f64; FEATURES], b: &[f64; FEATURES]) -> f64 {
+    a.iter().zip(b.iter()).map(|(x, y)| x * y).sum()
+}
+
+/// The pieces of the game on the `i`th fixed validation seed.
+fn validation_pieces<'a, const H: usize, const W: usize>(
+    i: usize,
+    set: &'a PieceSet,
+    randomizer: &'a Randomizer,
+) -> Box<dyn PieceGenerator<H, W> + 'a> {
+    randomizer.generator(set, StdRng::seed_from_u64(VALIDATION_SEED + i as u64))
+}
+
+/// The mode and garbage of the game on the `i`th fixed validation seed.
+/// Its garbage is drawn apart from the pieces.
+fn validation_progress(i: usize, mode: GameMode, garbage: Option<GarbageConfig>) -> Progress {
+    Progress::new(mode, garbage, rollout_seed(VALIDATION_SEED, &[i as u64]))
+}
+
+fn mean(scores: &[i64]) -> f64 {
+    scores.iter().map(|&score| score as f64).sum::<f64>() / scores.len().max(1) as f64
+}
+
+pub fn train(options: TrainOptions) {
+    let TrainOptions {
+        generations,
+        target,
+        ref run_dir,
+        ..
+    } = options;
+    log_info!(
+        "开始使用CMAES训练俄罗斯方块AI参数...",
+        "Training Tetris AI weights with CMA-ES..."
+    );
+
+    if let Err(e) = fs::create_dir_all(run_dir) {
+        log_error!(
+            "无法创建运行目录 {}: {}",
+            "Cannot create run directory {}: {}",
+            run_dir.display(),
+            e
+        );
+        return;
+    }
+    if options.save_log
+        && let Err(e) = logging::log_to_file(&run_dir.join("train.log"))
+    {
+        log_error!("无法写入 train.log: {}", "Cannot write train.log: {}", e);
+    }
+    log_info!("运行目录: {}", "Run directory: {}", run_dir.display());
+
+    // The handler can only be set once a process, and the tests train
+    // several times
+    static RUNNING: AtomicBool = AtomicBool::new(true);
+    static HANDLER: Once = Once::new();
+    RUNNING.store(true, Ordering::SeqCst);
+    HANDLER.call_once(|| {
+        ctrlc::set_handler(|| {
+            log_info!(
+                "\n接收到Ctrl+C, 正在结束训练...",
+                "\nReceived Ctrl+C, stopping training..."
+            );
+            RUNNING.store(false, Ordering::SeqCst);
+        })
+        .expect("Error setting Ctrl+C handler");
+    });
+    let running = &RUNNING;
+
+    let threads = worker_threads(options.cpu_fraction);
+    // cmaes evaluates candidates on the global rayon pool
+    if let Err(e) = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build_global()
+    {
+        log_warn!("无法设置线程数: {}", "Cannot set the thread count: {}", e);
+    }
+    let mut population = (threads * CANDIDATES_PER_THREAD).max(MIN_POPULATION);
+    let mut population_games = options.population_games;
+    let mut max_pieces = options.max_pieces;
+    let mut pieces = options.pieces;
+    let mut randomizer = options.randomizer.clone();
+    let mut hold = options.hold;
+    let mut visibility = options.visibility;
+    let mut moves = options.moves;
+    let mut dimensions = options.dimensions;
+    let mut garbage = options.garbage;
+    let mut mode = options.mode;
+    let mut objective = options.objective;
+    let mut attack = options.attack;
+    let mut model = options.model;
+    let mut scoring = options.scoring;
+
+    let log_path = run_dir.join("evaluations.txt");
+    let (seed, replay) = if options.resume {
+        match EvaluationLog::load(&log_path) {
+            Ok(log) => {
+                log_info!(
+                    "从 {} 恢复, 重放{}次评估",
+                    "Resuming from {}, replaying {} evaluations",
+                    log_path.display(),
+                    log.values.len()
+                );
+                // The sampler is not restored from the checkpoint but
+                // redrawn from the seed, which must then be this run's
+                if let Some(sampler) = Checkpoint::load(run_dir)
+                    .ok()
+                    .and_then(|checkpoint| checkpoint.sampler)
+                {
+                    if sampler.seed.wrapping_sub(log.seed) > AnomalyAction::MAX_ROLLBACKS as u64 {
+                        log_error!(
+                            "无法恢复: 检查点的采样器种子 {} 不属于种子为 {} 的运行",
+                            "Cannot resume: the checkpoint's sampler seed {} is not from the run with seed {}",
+                            sampler.seed,
+                            log.seed
+                        );
+                        return;
+                    }
+                    log_info!(
+                        "检查点停在采样器的第{}代",
+                        "The checkpoint stopped at generation {} of its sampler",
+                        sampler.generation
+                    );
+                }
+                // The population decides the samples, so it must not follow
+                // this machine's core count
+                population = log.population;
+                // Fitness values from different budgets would not compare
+                population_games = log.games;
+                max_pieces = log.max_pieces;
+                pieces = log.pieces;
+                // A sequence is read again, with the logged set
+                randomizer = match Randomizer::parse(&log.randomizer, pieces.set()) {
+                    Ok(randomizer) => randomizer,
+                    Err(e) => {
+                        log_error!("无法恢复: {}", "Cannot resume: {}", e);
+                        return;
+                    }
+                };
+                hold = log.hold;
+                visibility = log.visibility;
+                moves = log.moves;
+                dimensions = log.dimensions;
+                garbage = log.garbage;
+                mode = log.mode;
+                objective = log.objective;
+                attack = log.attack;
+                model = log.model;
+                scoring = log.scoring;
+                (log.seed, log.values)
+            }
+            Err(e) => {
+                log_error!("无法恢复: {}", "Cannot resume: {}", e);
+                return;
+            }
+        }
+    } else {
+        let seed = options.seed.unwrap_or_else(rand::random);
+        let header = EvaluationLog {
+            seed,
+            population,
+            games: population_games,
+            max_pieces,
+            pieces,
+            randomizer: randomizer.to_spec(),
+            hold,
+            visibility,
+            moves,
+            dimensions,
+            garbage,
+            mode,
+            objective,
+            attack,
+            model,
+            scoring,
+            values: HashMap::new(),
+            history: Vec::new(),
+        };
+        if let Err(e) = header.create(&log_path) {
+            log_error!("{}", "{}", e);
+            return;
+        }
+        (seed, HashMap::new())
+    };
+    log_info!("随机种子: {}", "Random seed: {}", seed);
+    if pieces != BuiltinSet::Tetromino {
+        log_info!("方块组: {}", "Piece set: {}", pieces.name());
+    }
+    if randomizer != Randomizer::Uniform {
+        log_info!("出块方式: {}", "Randomizer: {}", randomizer.to_spec());
+    }
+    if !dimensions.is_default() {
+        log_info!("棋盘尺寸: {}", "Board size: {}", dimensions);
+    }
+    if let Err(e) = mode.check(dimensions) {
+        log_error!("{}", "{}", e);
+        return;
+    }
+    if mode.has_goal() {
+        log_info!(
+            "模式: {}, 适应度按达成目标的速度计算",
+            "Mode: {}, the fitness is how fast the goal is reached",
+            mode.to_spec()
+        );
+    }
+    if scoring != ScoringRules::default() {
+        log_info!("使用给定的计分规则", "Scoring by the given rules");
+    }
+    if let GameMode::Marathon { lines_per_level } = mode {
+        log_info!(
+            "模式: {}, 每 {} 行升一级, 重力随等级加快",
+            "Mode: {}, a level up every {} lines, with the gravity speeding up",
+            mode.to_spec(),
+            lines_per_level
+        );
+        if !moves.is_full() {
+            log_warn!(
+                "重力只限制 --moves full 的落点, 直落时马拉松与无尽模式相同",
+                "The gravity only limits the placements of --moves full; with drops a marathon plays as the endless game"
+            );
+        }
+    }
+    if objective.is_versus() {
+        log_info!(
+            "目标: {}, 每个候选与名人堂第一名 (最初为内置权重) 对战, 攻击表 {}; 名人堂仍按单人对局验证",
+            "Objective: {}, every candidate plays matches against the hall of fame leader (the built-in weights at first), attack table {}; the hall of fame still validates on games played alone",
+            objective.name(),
+            attack.to_spec()
+        );
+        if randomizer == Randomizer::Adversarial {
+            log_error!(
+                "对战双方的方块序列相同, 不能使用 --randomizer adversarial",
+                "Both sides of a match share the sequence, so --randomizer adversarial cannot be used"
+            );
+            return;
+        }
+        if mode != GameMode::Endless
+            || garbage.is_some()
+            || hold
+            || moves.is_full()
+            || options.novelty > 0.0
+            || scoring != ScoringRules::default()
+        {
+            log_warn!(
+                "对战不使用 --mode、--garbage、--hold、--moves full、--novelty 和 --scoring, 它们只影响验证",
+                "Matches ignore --mode, --garbage, --hold, --moves full, --novelty and --scoring, which only apply to validation"
+            );
+        }
+    }
+    if let Some(garbage) = garbage {
+        log_info!(
+            "每{}个方块加入{}行垃圾",
+            "Adding {} garbage rows every {} pieces",
+            garbage.lines,
+            garbage.every
+        );
+    }
+    if moves.is_full() {
+        log_info!(
+            "落点包括从出生位置移动、旋转可达的所有位置, 每局慢得多; 不使用暂存和下一个方块",
+            "Placements include every position the piece can be moved and turned into from its spawn, so games are much slower; there is no hold or next piece"
+        );
+    } else if hold {
+        log_info!("使用暂存", "Playing with hold");
+    } else if visibility == Visibility::Next {
+        log_info!(
+            "策略参考下一个方块, 每局慢得多",
+            "The policy looks ahead to the next piece, so games are much slower"
+        );
+    } else if visibility == Visibility::Expected {
+        log_info!(
+            "策略对下一个方块的所有可能取平均, 每局慢得多",
+            "The policy averages over every possible next piece, so games are much slower"
+        );
+    }
+    let set = pieces.set();
+    log_info!(
+        "使用{}个线程, 种群大小{}",
+        "Using {} threads, population size {}",
+        threads,
+        population
+    );
+    log_info!(
+        "每个候选评估{}局, 最佳权重重新评估{}局",
+        "{} games per candidate, {} games to re-evaluate the best weights",
+        population_games,
+        options.elite_games
+    );
+    if options.elite_games <= population_games {
+        log_warn!(
+            "最佳权重的评估局数不多于候选的评估局数, 报告的最佳分数不会比训练估计更可靠",
+            "The best weights get no more games than a candidate, so the reported best score is no more reliable than the training estimate"
+        );
+    }
+
+    let novelty_archive = NoveltyArchive::new(5000, 15);
+    let novelty_weight = options.novelty;
+    if novelty_weight > 0.0 {
+        log_info!(
+            "启用新颖性奖励, 权重 {}",
+            "Novelty bonus enabled, weight {}",
+            novelty_weight
+        );
+        log_warn!(
+            "新颖性奖励取决于评估顺序, 本次运行无法精确复现",
+            "The novelty bonus depends on evaluation order, so this run cannot be reproduced exactly"
+        );
+    }
+
+    let mut space = SearchSpace {
+        bounds: options.bounds,
+        kind: model,
+        ..SearchSpace::default()
+    };
+    if model == ModelKind::Mlp {
+        log_info!(
+            "模型: {}-{}-1 神经网络, {} 个参数, 每局慢得多",
+            "Model: a {}-{}-1 network with {} parameters, so games are much slower",
+            FEATURES,
+            HIDDEN,
+            model.parameters()
+        );
+    }
+    if model == ModelKind::Bonus {
+        log_info!(
+            "模型: 线性权重加上 {} 的奖励项",
+            "Model: linear weights with terms for {}",
+            BONUS_FEATURE_NAMES.join(", ")
+        );
+        if scoring.chain == ChainBonus::NONE && scoring.all_clear == AllClearBonus::NONE {
+            log_warn!(
+                "计分规则没有连击、背靠背和全清奖励, 奖励项无从学习; 请加上 --scoring",
+                "The scoring rules give no combo, back-to-back or all clear bonus, so the bonus terms have nothing to learn from; pass --scoring"
+            );
+        }
+    }
+    if model != ModelKind::Linear && (!space.bounds.is_unbounded() || options.warm_start.is_some())
+    {
+        log_error!(
+            "--freeze、--sign、--bound 和 --warm-start 只适用于 --model linear",
+            "--freeze, --sign, --bound and --warm-start only apply to --model linear"
+        );
+        return;
+    }
+    if !space.bounds.is_unbounded() {
+        log_info!("已启用特征权重约束", "Feature weight constraints enabled");
+    }
+    if let Some(path) = &options.warm_start {
+        match Checkpoint::load(path).and_then(|c| WarmStart::from_checkpoint(&c)) {
+            Ok(warm_start) => {
+                log_info!(
+                    "从检查点 {} 热启动",
+                    "Warm-starting from checkpoint {}",
+                    path.display()
+                );
+                space.warm_start = warm_start;
+            }
+            Err(e) => {
+                log_error!("无法热启动: {}", "Cannot warm-start: {}", e);
+                return;
+            }
+        }
+    }
+
+    // Evaluations of the current generation that are not in the log yet
+    let fresh = Mutex::new(Vec::new());
+    let guard = NonFiniteGuard::new(options.non_finite);
+    let mut non_finite = NonFiniteCounts::default();
+    let game = GameConfig {
+        max_pieces,
+        visibility,
+        hold,
+        moves,
+    };
+    // The versus objectives play against the leader of the hall of fame,
+    // which changes between generations: the population evolves against
+    // its own elite. A second population searched alongside would need an
+    // evaluation log, checkpoint, rollbacks and hall of fame of its own,
+    // and each of those holds the one population of a run
+    let opponent = RwLock::new(Model::Linear(WEIGHTS));
+    let versus = objective.is_versus();
+    let match_pieces = max_pieces.min(MATCH_PIECES) + 1;
+    let worst = objective.worst(match_pieces, &attack);
+    // A rollback moves the search to another space, so the objective is
+    // made for a given one
+    let objective = |space: SearchSpace| {
+        let (replay, fresh, novelty_archive, randomizer, guard, game, opponent) = (
+            &replay,
+            &fresh,
+            &novelty_archive,
+            &randomizer,
+            &guard,
+            &game,
+            &opponent,
+        );
+        move |weights: &DVector<f64>| {
+            let key = EvaluationLog::key(weights.as_slice());
+            if let Some(&value) = replay.get(&key) {
+                return value;
+            }
+            let mut model = space.model(weights.as_slice());
+            if !guard.sanitize(model.parameters_mut()) {
+                // Scores are never negative and wins never below none,
+                // so this is the worst fitness
+                fresh.lock().unwrap().push((key, worst));
+                return worst;
+            }
+            let evaluator = guard.guard(&model);
+            // The games depend only on the run seed and the point, so that a
+            // point is scored the same whichever thread evaluates it
+            let mut rng = StdRng::seed_from_u64(rollout_seed(seed, &key));
+
+            let num_games = population_games;
+            let mut total_score = 0.0;
+
+            let value = if versus {
+                let opponent = opponent.read().unwrap().clone();
+                let mut outcomes = Vec::with_capacity(num_games);
+                for game in 0..num_games {
+                    let game_seed: u64 = rng.random();
+                    outcomes.push(with_dimensions!(dimensions, |H, W| {
+                        let pieces = sequence::<H, W>(randomizer, set, match_pieces, game_seed);
+                        let mut candidate = PolicyPlayer::new(&evaluator, visibility);
+                        let mut leader = PolicyPlayer::new(&opponent, visibility);
+                        // The sides take turns to move first
+                        play_match_from::<H, W>(
+                            [&mut candidate, &mut leader],
+                            game % 2,
+                            set,
+                            &pieces,
+                            &attack,
+                            !game_seed,
+                        )
+                    }));
+                }
+                objective.fitness(&outcomes)
+            } else if novelty_weight > 0.0 {
+                let mut behaviors = Vec::with_capacity(num_games);
+                for _ in 0..num_games {
+                    let (score, behavior) = with_dimensions!(dimensions, |H, W| {
+                        let mut progress =
+                            game_progress(mode, garbage, &mut rng).with_scoring(scoring);
+                        let generator = &mut *randomizer.generator::<_, H, W>(set, &mut rng);
+                        play_game_with_behavior(&evaluator, generator, set, game, &mut progress)
+                    });
+                    total_score += score as f64;
+                    behaviors.push(behavior);
+                }
+                let novelty = novelty_archive.novelty_and_insert(Behavior::mean(&behaviors));
+                total_score / num_games as f64 * (1.0 + novelty_weight * novelty)
+            } else {
+                for _ in 0..num_games {
+                    total_score += with_dimensions!(dimensions, |H, W| {
+                        let mut progress =
+                            game_progress(mode, garbage, &mut rng).with_scoring(scoring);
+                        let generator = &mut *randomizer.generator::<_, H, W>(set, &mut rng);
+                        play_game_seeing(&evaluator, generator, set, game, &mut progress)
+                    }) as f64;
+                }
+                total_score / num_games as f64
+            };
+            fresh.lock().unwrap().push((key, value));
+            value
+        }
+    };
+
+    // With a warm start this is the previous run's distribution, see
+    // `WarmStart`
+    let initial_weights = DVector::from_vec(vec![0.0; model.parameters()]);
+    // let initial_weights = WEIGHTS.to_vec();
+    let initial_step_size = 1.0;
+
+    // Every rollback gets a sampler of its own, derived from the run seed
+    // so that a resumed run retraces the rollbacks too
+    let build = |space: SearchSpace, generations: usize, rollbacks: usize| {
+        let mut cmaes_options = CMAESOptions::new(initial_weights.clone(), initial_step_size)
+            .mode(Mode::Maximize)
+            .max_generations(generations)
+            .cm(0.8)
+            .weights(cmaes::Weights::Positive)
+            .parallel_update(true)
+            .population_size(population)
+            .seed(seed.wrapping_add(rollbacks as u64))
+            .enable_plot(PlotOptions::new(0, false));
+        // The crate prints its progress table itself, so only ask for it when
+        // info messages are wanted
+        if log::log_enabled!(log::Level::Info) {
+            cmaes_options = cmaes_options.enable_printing(50);
+        }
+        cmaes_options.build(objective(space)).unwrap()
+    };
+    let mut cmaes_states = build(space, generations, 0);
+
+    let mut hall_of_fame = HallOfFame::new(
+        options.hof_size,
+        options.validation_games,
+        options.elite_games,
+        max_pieces,
+        pieces,
+        randomizer.clone(),
+        space,
+    )
+    .with_hold(hold)
+    .with_visibility(visibility)
+    .with_moves(moves)
+    .with_dimensions(dimensions)
+    .with_garbage(garbage)
+    .with_mode(mode)
+    .with_scoring(scoring);
+    // Clips are played back in the sandbox, which has the default board,
+    // no garbage, no gravity and linear weights
+    let clips_mode = if (dimensions.is_default()
+        && garbage.is_none()
+        && mode == GameMode::Endless
+        && model == ModelKind::Linear)
+        || options.clips == Clips::Off
+    {
+        options.clips
+    } else {
+        log_info!(
+            "片段只支持线性权重在默认尺寸、没有垃圾行的无尽模式下的对局, 不录制",
+            "Clips only play endless games of linear weights on the default board size without garbage, not recording them"
+        );
+        Clips::Off
+    };
+    let mut clips = ClipRecorder::new(
+        clips_mode,
+        run_dir.join("clips"),
+        pieces,
+        randomizer.clone(),
+        hold,
+        visibility,
+        moves,
+    );
+    let mut tracker = TargetTracker::new(target, 10);
+    let hof_path = run_dir.join("hall_of_fame.txt");
+    let checkpoint_path = run_dir.join("checkpoint.txt");
+    let mut detector = AnomalyDetector::new();
+    // The last distribution that passed the anomaly checks
+    let mut healthy: Option<Checkpoint> = None;
+    let mut rollbacks = 0;
+    // Generations played before the last rollback
+    let mut past_generations = 0;
+
+    log_info!(
+        "正在运行CMAES优化, 总共{}代...",
+        "Running CMA-ES for {} generations...",
+        generations
+    );
+
+    'main: loop {
+        let result = loop {
+            let termination = cmaes_states.next_parallel();
+            let evaluations = std::mem::take(&mut *fresh.lock().unwrap());
+            if let Err(e) = EvaluationLog::append(&log_path, &evaluations) {
+                log_error!("{}", "{}", e);
+            }
+
+            let counts = guard.take();
+            if !counts.is_empty() {
+                non_finite.weights += counts.weights;
+                non_finite.evaluations += counts.evaluations;
+                log_warn!(
+                    "第{}代: {}组权重和{}次评估不是有限数 (累计 {} 和 {})",
+                    "Generation {}: {} weight vectors and {} evaluations were not finite ({} and {} so far)",
+                    past_generations + cmaes_states.generation(),
+                    counts.weights,
+                    counts.evaluations,
+                    non_finite.weights,
+                    non_finite.evaluations
+                );
+                if options.non_finite == NonFinite::Error {
+                    log_error!(
+                        "--non-finite error: 停止训练",
+                        "--non-finite error: stopping the training"
+                    );
+                    if let Some(best) = cmaes_states.current_best_individual() {
+                        finish(&cmaes_states, best, &hall_of_fame, &space, run_dir);
+                    }
+                    break 'main;
+                }
+            }
+
+            let covariance = cmaes_states.covariance_matrix();
+            let mut checkpoint = space.checkpoint(
+                cmaes_states.sigma(),
+                cmaes_states.mean().as_slice(),
+                |i, j| covariance[(i, j)],
+            );
+            checkpoint.sampler = Some(SamplerState {
+                seed: seed.wrapping_add(rollbacks as u64),
+                generation: cmaes_states.generation(),
+            });
+            let fitness = cmaes_states
+                .current_best_individual()
+                .map_or(f64::NAN, |best| best.value);
+            let generation = past_generations + cmaes_states.generation();
+            if let Some(anomaly) = detector.check(&checkpoint, fitness) {
+                log_error!(
+                    "第{}代出现异常: {}",
+                    "Anomaly in generation {}: {}",
+                    generation,
+                    anomaly.describe()
+                );
+                checkpoint.save(&run_dir.join("anomaly-checkpoint.txt"));
+                let rollback = match (options.on_anomaly, &healthy) {
+                    (AnomalyAction::Rollback, _) if rollbacks >= AnomalyAction::MAX_ROLLBACKS => {
+                        log_error!(
+                            "已回滚{}次, 不再回滚",
+                            "Already rolled back {} times, not again",
+                            rollbacks
+                        );
+                        None
+                    }
+                    (AnomalyAction::Rollback, None) => {
+                        log_error!(
+                            "还没有健康的检查点可供回滚",
+                            "No healthy checkpoint to roll back to yet"
+                        );
+                        None
+                    }
+                    (AnomalyAction::Rollback, _) if generation >= generations => None,
+                    (AnomalyAction::Rollback, _) if model != ModelKind::Linear => {
+                        log_error!(
+                            "回滚从检查点热启动, 只适用于线性权重",
+                            "A rollback warm-starts from the checkpoint, which only works for linear weights"
+                        );
+                        None
+                    }
+                    (AnomalyAction::Rollback, Some(healthy)) => {
+                        match WarmStart::from_checkpoint(healthy) {
+                            Ok(warm_start) => Some(warm_start),
+                            Err(e) => {
+                                log_error!("无法回滚: {}", "Cannot roll back: {}", e);
+                                None
+                            }
+                        }
+                    }
+                    _ => None,
+                };
+                let action = match (options.on_anomaly, rollback) {
+                    (AnomalyAction::Ignore, _) => AnomalyAction::Ignore,
+                    (_, Some(_)) => AnomalyAction::Rollback,
+                    _ => AnomalyAction::Stop,
+                };
+                let report_path = run_dir.join("anomaly.txt");
+                report(
+                    &report_path,
+                    generation,
+                    &anomaly,
+                    action.name(),
+                    &detector,
+                    &checkpoint,
+                );
+
+                if let Some(warm_start) = rollback {
+                    rollbacks += 1;
+                    log_warn!(
+                        "回滚到上一个健康的检查点 (第{}次), 诊断报告见 {}",
+                        "Rolling back to the last healthy checkpoint (rollback {}), report in {}",
+                        rollbacks,
+                        report_path.display()
+                    );
+                    space.warm_start = warm_start;
+                    hall_of_fame.space = space;
+                    past_generations = generation;
+                    cmaes_states = build(space, generations - generation, rollbacks);
+                    continue;
+                }
+                if action == AnomalyAction::Stop {
+                    log_error!(
+                        "训练已停止, 诊断报告见 {}, 最后一个健康的检查点仍在 {}",
+                        "Training stopped, report in {}; the last healthy checkpoint is still in {}",
+                        report_path.display(),
+                        checkpoint_path.display()
+                    );
+                    let best = cmaes_states.current_best_individual().unwrap();
+                    finish(&cmaes_states, best, &hall_of_fame, &space, run_dir);
+                    break 'main;
+                }
+            } else {
+                if let Some(best) = cmaes_states.current_best_individual() {
+                    let leader = hall_of_fame.elite().map(|(_, model)| model.clone());
+                    hall_of_fame.consider(best);
+                    hall_of_fame.save(&hof_path);
+                    if let Some((score, model)) = hall_of_fame.elite()
+                        && leader.as_ref() != Some(model)
+                    {
+                        if let Model::Linear(weights) = model {
+                            clips.record(generation, score, *weights);
+                        }
+                        if versus {
+                            *opponent.write().unwrap() = model.clone();
+                            // The fitness against the new leader does
+                            // not compare with that against the old one
+                            detector.forget_fitness();
+                        }
+                    }
+                }
+                if let Some(score) = hall_of_fame.best_score() {
+                    match tracker.record(score) {
+                        Some(Eta {
+                            generations,
+                            low,
+                            high: Some(high),
+                        }) => log_info!(
+                            "验证最佳 {:.0}, 预计还需约{:.0}代达到目标 ({:.0}-{:.0})",
+                            "Validated best {:.0}, about {:.0} more generations to target ({:.0}-{:.0})",
+                            score,
+                            generations,
+                            low,
+                            high
+                        ),
+                        Some(Eta {
+                            generations, low, ..
+                        }) => log_info!(
+                            "验证最佳 {:.0}, 预计还需约{:.0}代达到目标 (至少{:.0}, 也可能无法达到)",
+                            "Validated best {:.0}, about {:.0} more generations to target (at least {:.0}, possibly never)",
+                            score,
+                            generations,
+                            low
+                        ),
+                        None => log_debug!(
+                            "验证最佳 {:.0}, 尚无法估计达到目标的时间",
+                            "Validated best {:.0}, no projection to the target yet",
+                            score
+                        ),
+                    }
+                }
+                checkpoint.save(&checkpoint_path);
+                healthy = Some(checkpoint);
+            }
+
+            if let Some(data) = termination {
+                break data;
+            }
+
+            if !running.load(Ordering::SeqCst) {
+                let best = cmaes_states.current_best_individual().unwrap();
+                finish(&cmaes_states, best, &hall_of_fame, &space, run_dir);
+                break 'main;
+            }
+        };
+
+        if !running.load(Ordering::SeqCst) {
+            let best = cmaes_states.current_best_individual().unwrap();
+            finish(&cmaes_states, best, &hall_of_fame, &space, run_dir);
+            break 'main;
+        }
+
+        if past_generations + cmaes_states.generation() > generations {
+            finish(
+                &cmaes_states,
+                &result.current_best.unwrap(),
+                &hall_of_fame,
+                &space,
+                run_dir,
+            );
+            break 'main;
+        }
+
+        // The target is judged on the elite budget, not on the noisy
+        // training fitness
+        if hall_of_fame
+            .best_score()
+            .is_some_and(|score| score > target)
+        {
+            finish(
+                &cmaes_states,
+                &result.current_best.unwrap(),
+                &hall_of_fame,
+                &space,
+                run_dir,
+            );
+            break 'main;
+        };
+    }
+    // The clips of the last new bests may still be playing
+    clips.finish();
+}
+
+fn finish<F>(
+    cmaes_states: &CMAES<F>,
+    best: &Individual,
+    hall_of_fame: &HallOfFame,
+    space: &SearchSpace,
+    run_dir: &Path,
+) {
+    cmaes_states
+        .get_plot()
+        .unwrap()
+        .save_to_file(run_dir.join("plot.png"), true)
+        .unwrap();
+    hall_of_fame.save(&run_dir.join("hall_of_fame.txt"));
+
+    log_info!("优化完成！", "Optimization finished!");
+    print_results(best, hall_of_fame, space);
+    if let Some((score, _)) = hall_of_fame.entries.first() {
+        say!(
+            "名人堂最佳验证分数: {:.2}",
+            "Best validation score in hall of fame: {:.2}",
+            score
+        );
+    }
+}
+
+/// Reports the hall of fame leader with its score on the elite budget.
+/// Only when nothing was re-evaluated yet does it fall back to `best` and
+/// its training fitness, and says so.
+fn print_results(best: &Individual, hall_of_fame: &HallOfFame, space: &SearchSpace) {
+    let model = match hall_of_fame.elite() {
+        Some((score, model)) => {
+            say!(
+                "最佳分数: {:.2} ({} 局重新评估)",
+                "Best score: {:.2} (re-evaluated on {} games)",
+                score,
+                hall_of_fame.elite_games
+            );
+            model.clone()
+        }
+        None => {
+            say!(
+                "最佳分数: {:.2} (训练估计, 尚未重新评估)",
+                "Best score: {:.2} (training estimate, not re-evaluated)",
+                best.value
+            );
+            space.model(best.point.as_slice())
+        }
+    };
+
+    match model {
+        Model::Linear(_) => say!("最佳权重数组形式:", "Best weights as an array:"),
+        Model::Mlp(_) => say!(
+            "最佳网络, 可存入权重文件:",
+            "Best network, in the form of a weights file line:"
+        ),
+        Model::Bonus(_) => say!(
+            "最佳带奖励项的权重, 可存入权重文件:",
+            "Best weights with bonus terms, in the form of a weights file line:"
+        ),
+    }
+    println!("{}", format_model(&model));
+}
+
+/// The mode and garbage of a training game, seeded from the game's
+/// generator. It draws nothing for a game without a goal or garbage, so
+/// the games of runs without either stay the same.
+fn game_progress(mode: GameMode, garbage: Option<GarbageConfig>, rng: &mut StdRng) -> Progress {
+    if mode.has_goal() || garbage.is_some() {
+        Progress::new(mode, garbage, rng.random())
+    } else {
+        Progress::new(mode, None, 0)
+    }
+}
+
+/// Seed for the games that score the point with these bits.
+fn rollout_seed(seed: u64, key: &[u64]) -> u64 {
+    // FNV-1a over the bytes
+    let mut hash = 0xcbf2_9ce4_8422_2325 ^ seed;
+    for byte in key.iter().flat_map(|bits| bits.to_le_bytes()) {
+        hash = (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+/// How the games of a run are played, apart from their pieces.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GameConfig {
+    /// Pieces after which a game stops.
+    pub max_pieces: usize,
+    pub visibility: Visibility,
+    pub hold: bool,
+    pub moves: Moves,
+}
+
+/// Plays one game of at most `config.max_pieces` pieces of `set`, drawn
+/// from `pieces`, with `evaluator` choosing every placement as `config`
+/// says, and returns its fitness: the score, unless `progress` plays for
+/// a goal, see `Progress::fitness`. `progress` also lays out the starting
+/// board and pushes in the garbage, and the game ends when it says.
+/// Training rollouts go through here.
+pub fn play_game_seeing<const H: usize, const W: usize, E: Evaluator<H, W> + ?Sized>(
+    evaluator: &E,
+    pieces: &mut dyn PieceGenerator<H, W>,
+    set: &PieceSet,
+    config: &GameConfig,
+    progress: &mut Progress,
+) -> i64 {
+    let place = chooser(evaluator, set, config);
+    play_game_with(pieces, config.max_pieces, progress, place, None)
+}
+
+/// Like `play_game_seeing`, but also records the policy's `Behavior`.
+pub fn play_game_with_behavior<const H: usize, const W: usize, E: Evaluator<H, W> + ?Sized>(
+    evaluator: &E,
+    pieces: &mut dyn PieceGenerator<H, W>,
+    set: &PieceSet,
+    config: &GameConfig,
+    progress: &mut Progress,
+) -> (i64, Behavior) {
+    let mut moves = 0;
+    let mut height_sum = 0.0;
+    let mut bumpiness_sum = 0.0;
+    let mut clears = [0usize; 4];
+    let mut record = |placed: &PlacementResult, board: &Board<H, W>| {
+        let cleared = placed.lines;
+        if cleared > 0 {
+            clears[cleared.min(4) - 1] += 1;
+        }
+        let heights = board.get_heights();
+        height_sum += board.max_height() as f64;
+        bumpiness_sum += heights
+            .windows(2)
+            .map(|w| w[0].abs_diff(w[1]) as f64)
+            .sum::<f64>()
+            / (W - 1) as f64;
+        moves += 1;
+    };
+    let place = chooser(evaluator, set, config);
+    let fitness = play_game_with(
+        pieces,
+        config.max_pieces,
+        progress,
+        place,
+        Some(&mut record),
+    );
+
+    let moves = moves.max(1) as f64;
+    let total_clears = clears.iter().sum::<usize>().max(1) as f64;
+    let behavior = Behavior {
+        stack_height: height_sum / moves / H as f64,
+        bumpiness: bumpiness_sum / moves / H as f64,
+        clears: clears.map(|c| c as f64 / total_clears),
+    };
+    (fitness, behavior)
+}
+
+/// What `play_game_with` calls with every placement.
+type Observer<'a, const H: usize, const W: usize> =
+    &'a mut dyn FnMut(&PlacementResult, &Board<H, W>);
+
+/// The loop of every training game: `place` puts the next piece from
+/// `pieces` on the board under the gravity of the level, or gives `None`
+/// when it has nowhere to go, and `observe` sees each placement with the
+/// board once `progress` has had it.
+fn play_game_with<const H: usize, const W: usize>(
+    pieces: &mut dyn PieceGenerator<H, W>,
+    max_pieces: usize,
+    progress: &mut Progress,
+    mut place: impl FnMut(
+        &mut Board<H, W>,
+        &mut dyn PieceGenerator<H, W>,
+        Option<u32>,
+    ) -> Option<PlacementResult>,
+    mut observe: Option<Observer<H, W>>,
+) -> i64 {
+    let mut board = Board::<H, W>::empty();
+    progress.start(&mut board);
+
+    for _ in 0..max_pieces {
+        let Some(placed) = place(&mut board, pieces, progress.gravity()) else {
+            break;
+        };
+        let going = progress.after_piece(&placed, &mut board);
+        if let Some(observe) = observe.as_mut() {
+            observe(&placed, &board);
+        }
+        if !going {
+            break;
+        }
+    }
+
+    progress.fitness(&board)
+}
+
+/// How `evaluator` places each piece by `config`, for `play_game_with`.
+/// With full moves the policy chooses from every position the piece can
+/// be moved to, tucks and spins included, see `moves`, and the gravity
+/// limits them in a marathon. Otherwise pieces drop as `visibility`
+/// chooses, except that holding into an empty slot already looks at the
+/// next piece, so a hold game does not use it.
+fn chooser<'a, const H: usize, const W: usize, E: Evaluator<H, W> + ?Sized>(
+    evaluator: &'a E,
+    set: &'a PieceSet,
+    config: &GameConfig,
+) -> impl FnMut(&mut Board<H, W>, &mut dyn PieceGenerator<H, W>, Option<u32>) -> Option<PlacementResult>
++ 'a {
+    let GameConfig {
+        visibility,
+        hold,
+        moves,
+        ..
+    } = *config;
+    let mut slot = HoldSlot::new();
+    // The piece after the one to place, dealt before the first move when
+    // the policy sees it
+    let mut next = None;
+    move |board: &mut Board<H, W>, pieces: &mut dyn PieceGenerator<H, W>, gravity: Option<u32>| {
+        if moves.is_full() {
+            let piece = pieces.next_piece(board);
+            let (placement, _) = best_move_under_in(board, set, piece, evaluator, moves, gravity)?;
+            return Some(board.apply_at_in(set, piece, placement).unwrap());
+        }
+        if !hold && visibility != Visibility::Next {
+            // Nothing is known past `piece`, and no visibility that
+            // leaves `next` unset reads it
+            let piece = pieces.next_piece(board);
+            let (rotation, x, _) = choose_in(board, set, piece, piece, evaluator, visibility)?;
+            return Some(board.apply_in(set, piece, x, rotation).unwrap());
+        }
+        let next = next.get_or_insert_with(|| pieces.next_piece(board));
+        let (piece, rotation, x) = if hold {
+            move_with_hold(board, set, evaluator, pieces, next, &mut slot)?
+        } else {
+            let piece = std::mem::replace(next, pieces.next_piece(board));
+            let (rotation, x, _) =
+                choose_in(board, set, piece, *next, evaluator, Visibility::Next)?;
+            (piece, rotation, x)
+        };
+        Some(board.apply_in(set, piece, x, rotation).unwrap())
+    }
+}
+
+/// Deals the next piece of a game with a hold slot and decides whether to
+/// hold it: returns the piece to place with its `(rotation, x)`. `next`
+/// is the piece after it, drawn again when the slot takes it.
+pub(crate) fn move_with_hold<const H: usize, const W: usize, E: Evaluator<H, W> + ?Sized>(
+    board: &Board<H, W>,
+    set: &PieceSet,
+    evaluator: &E,
+    pieces: &mut dyn PieceGenerator<H, W>,
+    next: &mut usize,
+    slot: &mut HoldSlot,
+) -> Option<(usize, usize, usize)> {
+    let piece = *next;
+    *next = pieces.next_piece(board);
+    let (hold, rotation, x, _) =
+        best_action_with_hold_in(board, set, piece, *slot, Some(*next), evaluator)?;
+    if !hold {
+        return Some((piece, rotation, x));
+    }
+    let (piece, took_next) = slot.hold(piece, Some(*next));
+    if took_next {
+        *next = pieces.next_piece(board);
+    }
+    Some((piece, rotation, x))
+}
+
+/// Re-evaluates every vector in a weights file on the same seeds and
+/// prints them ranked. With `html`, the games are also written out as a
+/// report. `allow_stale` is passed to `load_weights`.
+pub fn tournament(
+    path: &str,
+    games: usize,
+    html: Option<&Path>,
+    visibility: Visibility,
+    allow_stale: bool,
+) {
+    let entries = match load_models(path, allow_stale) {
+        Ok(entries) => entries,
+        Err(e) => {
+            log::error!("{}", e);
+            return;
+        }
+    };
+
+    log_info!(
+        "正在评估 {} 组权重, 每组 {} 局, 可见方块: {}...",
+        "Evaluating {} weight vectors, {} games each, visible pieces: {}...",
+        entries.len(),
+        games,
+        visibility.name()
+    );
+
+    if let Some(html) = html {
+        let (played, _) = play_all(&entries, games, Schedule::Game, |weights, i| {
+            let mut rng = StdRng::seed_from_u64(VALIDATION_SEED + i as u64);
+            play_recorded(weights, &mut rng, visibility)
+        });
+        let mut results: Vec<EntryResult> = entries
+            .iter()
+            .zip(played)
+            .enumerate()
+            .map(|(index, (model, games))| EntryResult {
+                index,
+                model: model.clone(),
+                games,
+            })
+            .collect();
+        results.sort_by(|a, b| b.mean().total_cmp(&a.mean()));
+
+        for (rank, entry) in results.iter().enumerate() {
+            say!(
+                "#{:<3} 第{}组 平均分数: {:.2}",
+                "#{:<3} vector {} mean score: {:.2}",
+                rank + 1,
+                entry.index + 1,
+                entry.mean()
+            );
+        }
+        match fs::write(html, tournament_html(path, &results, visibility)) {
+            Ok(()) => log_info!("报告已保存到 {}", "Report saved to {}", html.display()),
+            Err(e) => log_error!(
+                "无法保存报告 {}: {}",
+                "Cannot save report {}: {}",
+                html.display(),
+                e
+            ),
+        }
+        return;
+    }
+
+    let set = PieceSet::standard();
+    let config = GameConfig {
+        max_pieces: MAX_PIECES,
+        visibility,
+        hold: false,
+        moves: Moves::Drop,
+    };
+    let (scores, _) = play_all(&entries, games, Schedule::Game, |weights, i| {
+        play_game_seeing::<BOARD_HEIGHT, BOARD_WIDTH, _>(
+            weights,
+            &mut *validation_pieces(i, set, &Randomizer::Uniform),
+            set,
+            &config,
+            &mut Progress::endless(),
+        )
+    });
+    let mut results: Vec<(usize, f64)> = scores
+        .iter()
+        .enumerate()
+        .map(|(i, scores)| (i, mean(scores)))
+        .collect();
+    results.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    for (rank, (index, score)) in results.iter().enumerate() {
+        say!(
+            "#{:<3} 第{}组 平均分数: {:.2}",
+            "#{:<3} vector {} mean score: {:.2}",
+            rank + 1,
+            index + 1,
+            score
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(generations: usize, dir: &str, run_dir: &Path) {
+        let args = [
+            &generations.to_string(),
+            "--population-games",
+            "2",
+            "--max-pieces",
+            "30",
+            "--validation-games",
+            "2",
+            "--elite-games",
+            "2",
+            "--hof-size",
+            "2",
+            "--clips",
+            "off",
+            "--seed",
+            "7",
+            dir,
+            &run_dir.display().to_string(),
+        ];
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        train(TrainOptions::from_args(&args));
+    }
+
+    #[test]
+    fn resumed_run_retraces_the_uninterrupted_one() {
+        let root = std::env::temp_dir().join(format!("mortis-resume-{}", std::process::id()));
+        let (full, resumed) = (root.join("full"), root.join("resumed"));
+        run(4, "--run-dir", &full);
+        run(2, "--run-dir", &resumed);
+        run(4, "--resume", &resumed);
+        for file in ["evaluations.txt", "checkpoint.txt", "hall_of_fame.txt"] {
+            let read = |dir: &Path| fs::read_to_string(dir.join(file)).unwrap();
+            assert_eq!(read(&full), read(&resumed), "{} differs", file);
+        }
+        let _ = fs::remove_dir_all(&root);
+    }
+}
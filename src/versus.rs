@@ -0,0 +1,582 @@
+//! Two players on one piece sequence, each clear sending garbage to the
+//! other. The players take turns, piece for piece, so both always place
+//! the same piece on their own board. A clear's attack, from the
+//! [`AttackTable`], first cancels the garbage waiting to come into the
+//! player's own board and sends the rest to the opponent, as a block of
+//! rows sharing a hole in a random column. The waiting garbage comes in
+//! after the next placement that clears nothing, or with a delay only
+//! once the player has placed that many pieces since it was sent, which
+//! leaves time to clear lines against it. A player who has no
+//! placement left, or whose stack the garbage pushes out of the well,
+//! loses.
+//!
+//! Training can play candidates against the best weights so far and rank
+//! them by the matches, see [`Objective`].
+
+use std::collections::VecDeque;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::board::Board;
+use crate::evaluator::Evaluator;
+use crate::generator::Randomizer;
+use crate::i18n::tr;
+use crate::piece::{PIECE_SIZE, PieceSet};
+use crate::policy::{Visibility, choose_in};
+
+/// Length of a match's piece sequence unless told otherwise. Two good
+/// policies can keep each other's garbage down for a long time, so a
+/// match needs an end of its own.
+pub const MATCH_PIECES: usize = 1000;
+
+/// Garbage rows sent for clearing one line, two lines and so on, and how
+/// long they take to arrive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AttackTable {
+    pub clears: [usize; PIECE_SIZE],
+    /// Pieces the receiving player places before sent garbage may come
+    /// in, 0 for the next one that clears nothing.
+    pub delay: usize,
+}
+
+impl Default for AttackTable {
+    /// Nothing for a single, then one, two and four rows as in most
+    /// versus games; a pentomino's five lines send four too.
+    fn default() -> Self {
+        AttackTable {
+            clears: [0, 1, 2, 4, 4],
+            delay: 0,
+        }
+    }
+}
+
+impl AttackTable {
+    /// Parses the rows sent for one, two, ... lines, comma separated, as
+    /// in `0,1,2,4`. Clears past the last value send as much as it. A
+    /// `/<n>` after them delays the garbage by `n` pieces, as in
+    /// `0,1,2,4/3`.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let invalid = || {
+            tr!(
+                "需要用逗号分隔的行数, 可在 / 后加延迟的方块数, 如 0,1,2,4/3, 实际为 '{}'",
+                "expected comma separated row counts, then optionally / and the pieces of delay, such as 0,1,2,4/3, got '{}'",
+                spec
+            )
+        };
+        let (rows, delay) = match spec.split_once('/') {
+            Some((rows, delay)) => (rows, delay.trim().parse().map_err(|_| invalid())?),
+            None => (spec, 0),
+        };
+        let values: Vec<usize> = rows
+            .split(',')
+            .map(|value| value.trim().parse().map_err(|_| invalid()))
+            .collect::<Result<_, _>>()?;
+        if values.is_empty() || values.len() > PIECE_SIZE {
+            return Err(invalid());
+        }
+        let last = values[values.len() - 1];
+        Ok(AttackTable {
+            clears: std::array::from_fn(|i| values.get(i).copied().unwrap_or(last)),
+            delay,
+        })
+    }
+
+    /// The table in the form `parse` reads, without the values at the end
+    /// that only repeat the one before.
+    pub fn to_spec(&self) -> String {
+        let mut clears = &self.clears[..];
+        while let [rest @ .., last, repeated] = clears
+            && last == repeated
+        {
+            clears = &clears[..rest.len() + 1];
+        }
+        let values: Vec<String> = clears.iter().map(|rows| rows.to_string()).collect();
+        match self.delay {
+            0 => values.join(","),
+            delay => format!("{}/{}", values.join(","), delay),
+        }
+    }
+
+    /// Rows a clear of `lines` lines sends.
+    pub fn attack(&self, lines: usize) -> usize {
+        match lines {
+            0 => 0,
+            _ => self.clears[lines.min(PIECE_SIZE) - 1],
+        }
+    }
+}
+
+/// What training ranks a candidate by: its own games, or matches against
+/// the best weights so far.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Objective {
+    /// The fitness of a game played alone, see `Progress::fitness`.
+    #[default]
+    Score,
+    /// The share of matches won, in percent, a match that nobody lost
+    /// counting half.
+    Wins,
+    /// The garbage sent less the garbage received, averaged over the
+    /// matches.
+    Damage,
+}
+
+impl Objective {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "score" => Some(Objective::Score),
+            "wins" => Some(Objective::Wins),
+            "damage" => Some(Objective::Damage),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Objective::Score => "score",
+            Objective::Wins => "wins",
+            Objective::Damage => "damage",
+        }
+    }
+
+    /// Whether the candidates play matches rather than games alone.
+    pub fn is_versus(&self) -> bool {
+        *self != Objective::Score
+    }
+
+    /// The fitness of the first player of `outcomes`, which must not be
+    /// empty; 0 for `Score`, which plays no matches.
+    pub fn fitness(&self, outcomes: &[MatchOutcome]) -> f64 {
+        let games = outcomes.len() as f64;
+        match self {
+            Objective::Score => 0.0,
+            Objective::Wins => {
+                let points: f64 = outcomes
+                    .iter()
+                    .map(|outcome| match outcome.winner {
+                        Some(0) => 1.0,
+                        Some(_) => 0.0,
+                        None => 0.5,
+                    })
+                    .sum();
+                points / games * 100.0
+            }
+            Objective::Damage => {
+                outcomes
+                    .iter()
+                    .map(|outcome| outcome.damage(0))
+                    .sum::<i64>() as f64
+                    / games
+            }
+        }
+    }
+
+    /// The lowest fitness there is for matches of `pieces` pieces: no
+    /// wins, or every row the opponent can send in that many turns.
+    pub fn worst(&self, pieces: usize, attack: &AttackTable) -> f64 {
+        match self {
+            Objective::Score | Objective::Wins => 0.0,
+            Objective::Damage => {
+                let most = attack.clears.iter().copied().max().unwrap_or(0);
+                -((pieces.saturating_sub(1) * most) as f64)
+            }
+        }
+    }
+}
+
+/// A block of garbage rows sharing a hole, on its way into a board.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Block {
+    rows: usize,
+    hole: usize,
+    /// Pieces the receiving player has yet to place before it may come in.
+    wait: usize,
+}
+
+/// The garbage waiting to come into one board, oldest first.
+#[derive(Debug, Clone, Default)]
+struct GarbageMeter {
+    blocks: VecDeque<Block>,
+}
+
+impl GarbageMeter {
+    /// Lets `rows` of attack cancel the waiting garbage, oldest first.
+    /// Returns the rows cancelled and the rows left to send.
+    fn counter(&mut self, mut rows: usize) -> (usize, usize) {
+        let mut cancelled = 0;
+        while rows > 0
+            && let Some(block) = self.blocks.front_mut()
+        {
+            let taken = rows.min(block.rows);
+            block.rows -= taken;
+            rows -= taken;
+            cancelled += taken;
+            if block.rows == 0 {
+                self.blocks.pop_front();
+            }
+        }
+        (cancelled, rows)
+    }
+
+    fn send(&mut self, rows: usize, hole: usize, delay: usize) {
+        self.blocks.push_back(Block {
+            rows,
+            hole,
+            wait: delay,
+        });
+    }
+
+    /// After a placement of the receiving player: the `(rows, hole)`
+    /// blocks that come in now, those done waiting if the placement
+    /// cleared nothing. The others wait a piece less.
+    fn after_piece(&mut self, cleared: bool) -> Vec<(usize, usize)> {
+        let mut landing = Vec::new();
+        while !cleared
+            && let Some(block) = self.blocks.front()
+            && block.wait == 0
+        {
+            landing.push((block.rows, block.hole));
+            self.blocks.pop_front();
+        }
+        for block in &mut self.blocks {
+            block.wait = block.wait.saturating_sub(1);
+        }
+        landing
+    }
+}
+
+/// The piece sequence of a match, `len` pieces drawn by `randomizer` from
+/// `seed`. The pieces are drawn on an empty board, as the sequence is
+/// shared by both sides.
+pub fn sequence<const H: usize, const W: usize>(
+    randomizer: &Randomizer,
+    set: &PieceSet,
+    len: usize,
+    seed: u64,
+) -> Vec<usize> {
+    let mut generator = randomizer.generator::<_, H, W>(set, StdRng::seed_from_u64(seed));
+    let empty = Board::<H, W>::empty();
+    (0..len).map(|_| generator.next_piece(&empty)).collect()
+}
+
+/// One side of a match.
+pub trait Player<const H: usize, const W: usize> {
+    /// The `(rotation, x)` `piece` goes to on `board`, with `next` coming
+    /// after it; `None` gives the game up.
+    fn place(
+        &mut self,
+        board: &Board<H, W>,
+        set: &PieceSet,
+        piece: usize,
+        next: usize,
+    ) -> Option<(usize, usize)>;
+
+    /// Told that `lines` rows of garbage with a hole in column `hole` were
+    /// pushed into the player's board.
+    fn receive(&mut self, _lines: usize, _hole: usize) {}
+}
+
+/// The policy of an evaluator, looking ahead as `visibility` says.
+pub struct PolicyPlayer<'a, E: ?Sized> {
+    evaluator: &'a E,
+    visibility: Visibility,
+}
+
+impl<'a, E: ?Sized> PolicyPlayer<'a, E> {
+    pub fn new(evaluator: &'a E, visibility: Visibility) -> Self {
+        PolicyPlayer {
+            evaluator,
+            visibility,
+        }
+    }
+}
+
+impl<const H: usize, const W: usize, E: Evaluator<H, W> + ?Sized> Player<H, W>
+    for PolicyPlayer<'_, E>
+{
+    fn place(
+        &mut self,
+        board: &Board<H, W>,
+        set: &PieceSet,
+        piece: usize,
+        next: usize,
+    ) -> Option<(usize, usize)> {
+        choose_in(board, set, piece, next, self.evaluator, self.visibility)
+            .map(|(rotation, x, _)| (rotation, x))
+    }
+}
+
+/// How a match went, with the two players in the order they were given.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MatchOutcome {
+    /// The player left standing, `None` when the sequence ran out first.
+    pub winner: Option<usize>,
+    /// Turns the match lasted, the one a player lost on included.
+    pub pieces: usize,
+    pub lines: [usize; 2],
+    /// Garbage rows sent to the opponent, less those cancelled.
+    pub sent: [usize; 2],
+    /// Garbage rows that came into the player's board.
+    pub received: [usize; 2],
+    /// Incoming garbage rows the player's clears cancelled.
+    pub cancelled: [usize; 2],
+    /// Pieces the player placed.
+    pub placed: [usize; 2],
+    /// Garbage rows the player's clears removed from its board.
+    pub dug: [usize; 2],
+    /// Placements the player made with garbage in its board or on its way.
+    pub pressured: [usize; 2],
+    /// Height of the player's stack after those placements, summed.
+    pub pressured_height: [usize; 2],
+}
+
+impl MatchOutcome {
+    /// Garbage rows `player` sent less those that came into its board.
+    pub fn damage(&self, player: usize) -> i64 {
+        self.sent[player] as i64 - self.received[player] as i64
+    }
+
+    /// Garbage rows `player`'s clears made per piece, cancelled ones
+    /// included.
+    pub fn attack_per_piece(&self, player: usize) -> f64 {
+        ratio(
+            self.sent[player] + self.cancelled[player],
+            self.placed[player],
+        )
+    }
+
+    /// Share of the garbage that came into `player`'s board it cleared
+    /// again.
+    pub fn downstack_efficiency(&self, player: usize) -> f64 {
+        ratio(self.dug[player], self.received[player])
+    }
+
+    /// Mean height of `player`'s stack after the placements it made with
+    /// garbage in its board or on its way.
+    pub fn pressured_height(&self, player: usize) -> f64 {
+        ratio(self.pressured_height[player], self.pressured[player])
+    }
+}
+
+/// `part / whole`, 0 when `whole` is.
+pub fn ratio(part: usize, whole: usize) -> f64 {
+    if whole == 0 {
+        0.0
+    } else {
+        part as f64 / whole as f64
+    }
+}
+
+/// `play_match` with player `first` moving first in every turn. The
+/// outcome still has the players in the order they were given.
+pub fn play_match_from<const H: usize, const W: usize>(
+    players: [&mut dyn Player<H, W>; 2],
+    first: usize,
+    set: &PieceSet,
+    pieces: &[usize],
+    attack: &AttackTable,
+    seed: u64,
+) -> MatchOutcome {
+    if first == 0 {
+        return play_match(players, set, pieces, attack, seed);
+    }
+    let [a, b] = players;
+    let outcome = play_match([b, a], set, pieces, attack, seed);
+    let swap = |[a, b]: [usize; 2]| [b, a];
+    MatchOutcome {
+        winner: outcome.winner.map(|winner| 1 - winner),
+        pieces: outcome.pieces,
+        lines: swap(outcome.lines),
+        sent: swap(outcome.sent),
+        received: swap(outcome.received),
+        cancelled: swap(outcome.cancelled),
+        placed: swap(outcome.placed),
+        dug: swap(outcome.dug),
+        pressured: swap(outcome.pressured),
+        pressured_height: swap(outcome.pressured_height),
+    }
+}
+
+/// Plays `players` against each other on `pieces`, the first player
+/// moving first in every turn; `seed` draws the garbage holes. The last
+/// piece is only ever the next one, so a sequence of `n` pieces lasts at
+/// most `n - 1` turns.
+///
+/// ```
+/// use mortis::board::{Board, WEIGHTS};
+/// use mortis::piece::PieceSet;
+/// use mortis::policy::Visibility;
+/// use mortis::versus::{AttackTable, PolicyPlayer, play_match};
+///
+/// let pieces: Vec<usize> = (0..200).map(|i| i * 3 % 7).collect();
+/// let mut a = PolicyPlayer::new(&WEIGHTS, Visibility::Current);
+/// let mut b = PolicyPlayer::new(&WEIGHTS, Visibility::Current);
+/// let outcome = play_match::<15, 10>(
+///     [&mut a, &mut b],
+///     PieceSet::standard(),
+///     &pieces,
+///     &AttackTable::default(),
+///     0,
+/// );
+/// // Whatever one side sends the other receives or cancels, or it is
+/// // still on its way
+/// for side in 0..2 {
+///     assert!(outcome.received[1 - side] + outcome.cancelled[1 - side] <= outcome.sent[side]);
+/// }
+/// ```
+pub fn play_match<const H: usize, const W: usize>(
+    players: [&mut dyn Player<H, W>; 2],
+    set: &PieceSet,
+    pieces: &[usize],
+    attack: &AttackTable,
+    seed: u64,
+) -> MatchOutcome {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut boards = [Board::<H, W>::empty(), Board::<H, W>::empty()];
+    // Garbage on its way into each board
+    let mut incoming = [GarbageMeter::default(), GarbageMeter::default()];
+    // Garbage rows at the bottom of each board. Garbage pushes the stack
+    // up from below, so they are always the lowest ones
+    let mut garbage = [0; 2];
+    let mut outcome = MatchOutcome::default();
+
+    'game: for (turn, pair) in pieces.windows(2).enumerate() {
+        let (piece, next) = (pair[0], pair[1]);
+        outcome.pieces = turn + 1;
+        for side in 0..2 {
+            let board = &mut boards[side];
+            let pressured = garbage[side] > 0 || !incoming[side].blocks.is_empty();
+            let placed = players[side]
+                .place(board, set, piece, next)
+                .filter(|&(rotation, x)| board.check_in(set, piece, x, rotation).is_ok())
+                .and_then(|(rotation, x)| board.apply_in(set, piece, x, rotation).ok());
+            let Some(placed) = placed else {
+                outcome.winner = Some(1 - side);
+                break 'game;
+            };
+            outcome.lines[side] += placed.lines;
+            outcome.placed[side] += 1;
+            let dug = placed.rows[..placed.lines]
+                .iter()
+                .filter(|&&y| y < garbage[side])
+                .count();
+            garbage[side] -= dug;
+            outcome.dug[side] += dug;
+
+            let (cancelled, rows) = incoming[side].counter(attack.attack(placed.lines));
+            outcome.cancelled[side] += cancelled;
+            if rows > 0 {
+                incoming[1 - side].send(rows, rng.random_range(0..W), attack.delay);
+                outcome.sent[side] += rows;
+            }
+
+            for (rows, hole) in incoming[side].after_piece(placed.lines > 0) {
+                if board.add_garbage(rows, &[hole]).is_err() {
+                    outcome.winner = Some(1 - side);
+                    break 'game;
+                }
+                players[side].receive(rows, hole);
+                garbage[side] += rows;
+                outcome.received[side] += rows;
+            }
+            if pressured {
+                outcome.pressured[side] += 1;
+                outcome.pressured_height[side] += board.max_height();
+            }
+        }
+    }
+    outcome
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attack_table_round_trips_the_delay() {
+        let table = AttackTable::parse("0,1,2,4/3").unwrap();
+        assert_eq!(table.delay, 3);
+        assert_eq!(table.attack(4), 4);
+        assert_eq!(AttackTable::parse(&table.to_spec()), Ok(table));
+        assert_eq!(AttackTable::default().to_spec(), "0,1,2,4");
+        assert!(AttackTable::parse("0,1,2,4/").is_err());
+    }
+
+    #[test]
+    fn undelayed_garbage_lands_after_the_next_piece_that_clears_nothing() {
+        let mut meter = GarbageMeter::default();
+        meter.send(2, 5, 0);
+        assert_eq!(meter.after_piece(true), []);
+        assert_eq!(meter.after_piece(false), [(2, 5)]);
+        assert_eq!(meter.after_piece(false), []);
+    }
+
+    #[test]
+    fn delayed_garbage_waits_its_pieces() {
+        let mut meter = GarbageMeter::default();
+        meter.send(2, 5, 2);
+        assert_eq!(meter.after_piece(false), []);
+        meter.send(1, 0, 2);
+        assert_eq!(meter.after_piece(false), []);
+        // The first block is due, the second one piece later
+        assert_eq!(meter.after_piece(false), [(2, 5)]);
+        // A clear holds back even garbage that is due
+        assert_eq!(meter.after_piece(true), []);
+        assert_eq!(meter.after_piece(false), [(1, 0)]);
+    }
+
+    #[test]
+    fn counters_cancel_the_oldest_garbage_first() {
+        let mut meter = GarbageMeter::default();
+        meter.send(2, 5, 1);
+        meter.send(3, 0, 1);
+        assert_eq!(meter.counter(3), (3, 0));
+        assert_eq!(meter.counter(4), (2, 2));
+        assert!(meter.blocks.is_empty());
+    }
+
+    #[test]
+    fn digging_out_garbage_counts_as_downstack() {
+        let outcome = MatchOutcome {
+            sent: [3, 0],
+            cancelled: [1, 0],
+            received: [0, 4],
+            placed: [8, 8],
+            dug: [0, 3],
+            pressured: [0, 4],
+            pressured_height: [0, 18],
+            ..MatchOutcome::default()
+        };
+        assert_eq!(outcome.attack_per_piece(0), 0.5);
+        assert_eq!(outcome.downstack_efficiency(1), 0.75);
+        assert_eq!(outcome.pressured_height(1), 4.5);
+        // Nothing to dig or no pressure reads as 0, not NaN
+        assert_eq!(outcome.downstack_efficiency(0), 0.0);
+        assert_eq!(outcome.pressured_height(0), 0.0);
+    }
+
+    #[test]
+    fn a_long_delay_lets_a_match_run_without_garbage() {
+        let pieces: Vec<usize> = (0..100).map(|i| i * 3 % 7).collect();
+        let play = |delay| {
+            let mut a = PolicyPlayer::new(&crate::board::WEIGHTS, Visibility::Current);
+            let mut b = PolicyPlayer::new(&crate::board::WEIGHTS, Visibility::Next);
+            let attack = AttackTable {
+                clears: [1, 2, 3, 4, 4],
+                delay,
+            };
+            play_match::<15, 10>([&mut a, &mut b], PieceSet::standard(), &pieces, &attack, 0)
+        };
+        let outcome = play(1000);
+        assert_eq!(outcome.received, [0, 0]);
+        assert_eq!(outcome.dug, [0, 0]);
+        assert!(outcome.sent.iter().sum::<usize>() > 0, "{:?}", outcome);
+        let outcome = play(0);
+        assert!(outcome.received.iter().sum::<usize>() > 0);
+        for side in 0..2 {
+            assert!(outcome.dug[side] <= outcome.received[side]);
+            assert!(outcome.pressured[side] <= outcome.placed[side]);
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use std::collections::HashSet;
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+use crate::board::Board;
+
+/// The distinct positions a policy passed through, as a measure of how
+/// varied its play is. Two policies with the same score can differ a lot
+/// here: one that keeps returning to a few favorite shapes visits far
+/// fewer than one that plays each sequence its own way.
+///
+/// Positions are kept as 64-bit hashes, so merging the visits of a whole
+/// batch stays cheap; a collision among a few million positions is
+/// unlikely enough not to matter for a count.
+#[derive(Debug, Clone, Default)]
+pub struct Visits {
+    /// Column heights relative to the lowest column, so that the same
+    /// shape at another altitude counts once.
+    surfaces: HashSet<u64>,
+    /// The occupied cells, colors left out.
+    boards: HashSet<u64>,
+    positions: usize,
+}
+
+impl Visits {
+    pub fn record<const H: usize, const W: usize>(&mut self, board: &Board<H, W>) {
+        let heights = board.get_heights();
+        let floor = heights.iter().copied().min().unwrap_or(0);
+        let mut surface = DefaultHasher::new();
+        for &h in heights {
+            (h - floor).hash(&mut surface);
+        }
+        self.surfaces.insert(surface.finish());
+
+        let mut cells = DefaultHasher::new();
+        board.get_grid().hash(&mut cells);
+        self.boards.insert(cells.finish());
+        self.positions += 1;
+    }
+
+    pub fn merge(&mut self, other: &Visits) {
+        self.surfaces.extend(&other.surfaces);
+        self.boards.extend(&other.boards);
+        self.positions += other.positions;
+    }
+
+    /// Distinct surface profiles.
+    pub fn surfaces(&self) -> usize {
+        self.surfaces.len()
+    }
+
+    /// Distinct boards.
+    pub fn boards(&self) -> usize {
+        self.boards.len()
+    }
+
+    /// Positions recorded, repeats included.
+    pub fn positions(&self) -> usize {
+        self.positions
+    }
+}
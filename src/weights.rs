@@ -0,0 +1,218 @@
+use std::fs;
+
+use crate::board::{
+    BOARD_HEIGHT, BOARD_WIDTH, COUNT_FEATURES, FEATURE_REVISION, FEATURES, LINE_CLEAR_SCORES,
+};
+use crate::evaluator::{BONUS_PARAMETERS, Model};
+use crate::i18n::tr;
+use crate::logging::{log_debug, log_warn};
+use crate::mlp::MlpEvaluator;
+
+/// What a weight vector means to this build: the revision and layout of
+/// the features and the board rules they are measured under. Weights
+/// files record it when written and are checked against it when loaded,
+/// because weights for other features still parse and then play badly.
+pub const COMPATIBILITY: u64 = compatibility();
+
+const fn compatibility() -> u64 {
+    // FNV-1a over the little-endian bytes of every part
+    const fn mix(mut hash: u64, value: u64) -> u64 {
+        let bytes = value.to_le_bytes();
+        let mut i = 0;
+        while i < bytes.len() {
+            hash = (hash ^ bytes[i] as u64).wrapping_mul(0x0100_0000_01b3);
+            i += 1;
+        }
+        hash
+    }
+    let mut hash = 0xcbf2_9ce4_8422_2325;
+    hash = mix(hash, FEATURE_REVISION as u64);
+    hash = mix(hash, FEATURES as u64);
+    hash = mix(hash, COUNT_FEATURES as u64);
+    hash = mix(hash, BOARD_WIDTH as u64);
+    hash = mix(hash, BOARD_HEIGHT as u64);
+    let mut i = 0;
+    while i < LINE_CLEAR_SCORES.len() {
+        hash = mix(hash, LINE_CLEAR_SCORES[i] as u64);
+        i += 1;
+    }
+    hash
+}
+
+/// The comment line that stamps a weights file with `COMPATIBILITY`.
+pub fn compatibility_line() -> String {
+    format!("# compatibility {:016x}\n", COMPATIBILITY)
+}
+
+/// The stamp of a weights file, `None` for files written before stamps.
+fn stamp(text: &str) -> Result<Option<u64>, String> {
+    let Some(value) = text
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("# compatibility "))
+    else {
+        return Ok(None);
+    };
+    u64::from_str_radix(value.trim(), 16)
+        .map(Some)
+        .map_err(|_| {
+            tr!(
+                "无效的兼容性标记: {}",
+                "Invalid compatibility stamp: {}",
+                value
+            )
+        })
+}
+
+/// Formats weights the way training prints them, so the output can be
+/// pasted into `WEIGHTS` or a weights file.
+pub fn format_weights(weights: &[f64]) -> String {
+    let values: Vec<String> = weights.iter().map(|w| format!("{:.6}", w)).collect();
+    format!("[{}]", values.join(", "))
+}
+
+/// The numbers of one line, separated by commas and/or spaces and
+/// optionally wrapped in `[...]`.
+fn parse_values(line: &str) -> Result<Vec<f64>, String> {
+    line.trim_start_matches('[')
+        .trim_end_matches(']')
+        .split([',', ' '])
+        .filter(|v| !v.is_empty())
+        .map(|v| match v.parse::<f64>() {
+            Ok(w) if w.is_finite() => Ok(w),
+            Ok(_) => Err(tr!(
+                "权重不是有限数: {}",
+                "Weight is not a finite number: {}",
+                v
+            )),
+            Err(_) => Err(tr!("无效的权重: {}", "Invalid weight: {}", v)),
+        })
+        .collect()
+}
+
+/// Parses a weights file: one vector of `FEATURES` numbers per line,
+/// separated by commas and/or spaces, optionally wrapped in `[...]`.
+/// Empty lines and lines starting with `#` are ignored.
+pub fn parse_weights(text: &str) -> Result<Vec<[f64; FEATURES]>, String> {
+    parse_models(text)?
+        .into_iter()
+        .map(|model| match model {
+            Model::Linear(weights) => Ok(weights),
+            Model::Mlp(_) => Err(tr!(
+                "文件中是神经网络, 这里只能使用线性权重",
+                "The file holds a network, but only linear weights can be used here"
+            )),
+            Model::Bonus(_) => Err(tr!(
+                "文件中是带奖励项的权重, 这里只能使用线性权重",
+                "The file holds weights with bonus terms, but only linear weights can be used here"
+            )),
+        })
+        .collect()
+}
+
+/// Parses a file of weight vectors and networks, one per line: a weight
+/// vector as `parse_weights` reads it, `mlp` followed by the
+/// `mlp::PARAMETERS` parameters of a network in the same form, or `bonus`
+/// followed by the `evaluator::BONUS_PARAMETERS` of weights with bonus
+/// terms.
+///
+/// ```
+/// use mortis::evaluator::Model;
+/// use mortis::weights::{format_model, parse_models};
+///
+/// let text = "[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13]\n";
+/// let models = parse_models(text).unwrap();
+/// assert!(matches!(models[0], Model::Linear(_)));
+/// assert_eq!(parse_models(&format_model(&models[0])).unwrap(), models);
+/// ```
+pub fn parse_models(text: &str) -> Result<Vec<Model>, String> {
+    let mut models = Vec::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let model =
+            if let Some(parameters) = line.strip_prefix("mlp") {
+                Model::Mlp(MlpEvaluator::from_parameters(&parse_values(
+                    parameters.trim(),
+                )?)?)
+            } else if let Some(parameters) = line.strip_prefix("bonus") {
+                Model::Bonus(parse_values(parameters.trim())?.try_into().map_err(
+                    |v: Vec<f64>| {
+                        tr!(
+                            "带奖励项的权重数量应为{}, 实际为{}",
+                            "Expected {} weights with bonus terms, got {}",
+                            BONUS_PARAMETERS,
+                            v.len()
+                        )
+                    },
+                )?)
+            } else {
+                Model::Linear(parse_values(line)?.try_into().map_err(|v: Vec<f64>| {
+                    tr!(
+                        "权重数量应为{}, 实际为{}",
+                        "Expected {} weights, got {}",
+                        FEATURES,
+                        v.len()
+                    )
+                })?)
+            };
+        models.push(model);
+    }
+    Ok(models)
+}
+
+/// A model in the form `parse_models` reads, on one line.
+pub fn format_model(model: &Model) -> String {
+    match model {
+        Model::Linear(weights) => format_weights(weights),
+        Model::Mlp(network) => format!("mlp {}", format_weights(network.parameters())),
+        Model::Bonus(parameters) => format!("bonus {}", format_weights(parameters)),
+    }
+}
+
+/// Reads a weights file and checks its stamp. Weights stamped for other
+/// features or board rules are an error unless `allow_stale`, when they
+/// are only warned about; unstamped files are trusted.
+pub fn load_weights(path: &str, allow_stale: bool) -> Result<Vec<[f64; FEATURES]>, String> {
+    parse_weights(&read_stamped(path, allow_stale)?)
+}
+
+/// `load_weights` for a file that may hold networks as well, see
+/// `parse_models`. A network is stamped like weights, since it reads the
+/// same features.
+pub fn load_models(path: &str, allow_stale: bool) -> Result<Vec<Model>, String> {
+    parse_models(&read_stamped(path, allow_stale)?)
+}
+
+/// The text of the file at `path`, once its stamp is checked as
+/// `load_weights` says.
+fn read_stamped(path: &str, allow_stale: bool) -> Result<String, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| tr!("无法读取 {}: {}", "Cannot read {}: {}", path, e))?;
+    match stamp(&text)? {
+        Some(stamp) if stamp != COMPATIBILITY && !allow_stale => {
+            return Err(tr!(
+                "{} 中的权重是为特征版本 {:016x} 训练的, 当前程序为 {:016x}: 特征或棋盘规则已经改变, 这些权重多半表现很差。请重新训练, 或加上 --allow-stale-weights 仍然使用",
+                "The weights in {} were trained for feature set {:016x}, this build has {:016x}: the features or board rules have changed and the weights will most likely play badly. Retrain them, or pass --allow-stale-weights to use them anyway",
+                path,
+                stamp,
+                COMPATIBILITY
+            ));
+        }
+        Some(stamp) if stamp != COMPATIBILITY => log_warn!(
+            "仍然使用过时的权重 {} (特征版本 {:016x}, 当前程序为 {:016x})",
+            "Using stale weights from {} anyway (feature set {:016x}, this build has {:016x})",
+            path,
+            stamp,
+            COMPATIBILITY
+        ),
+        Some(_) => {}
+        None => log_debug!(
+            "{} 没有兼容性标记, 无法检查是否过时",
+            "{} has no compatibility stamp, so it cannot be checked for staleness",
+            path
+        ),
+    }
+    Ok(text)
+}